@@ -0,0 +1,90 @@
+use ai_runtime::embed::{Embedder, HashEmbedder, ModelEmbedder};
+use ai_runtime::index::{DocKey, Document, VectorIndex};
+use ai_runtime::model::LocalModel;
+use ai_runtime::{AiError, MODEL_ENV};
+use clap::Parser;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Parser, Debug)]
+#[command(name = "search")]
+struct Args {
+    /// Index file; created if missing
+    #[arg(long)]
+    index: PathBuf,
+
+    /// Add this URL as a history entry (page text from --text-file)
+    #[arg(long, value_name = "URL")]
+    add: Option<String>,
+
+    /// Index the page as this open tab instead of history
+    #[arg(long)]
+    tab: Option<u64>,
+
+    #[arg(long, default_value = "")]
+    title: String,
+
+    /// Page text for --add (- for stdin)
+    #[arg(long, value_name = "FILE")]
+    text_file: Option<PathBuf>,
+
+    /// Remove this URL from history
+    #[arg(long, value_name = "URL")]
+    remove: Option<String>,
+
+    /// Remove all history entries (tabs stay)
+    #[arg(long)]
+    clear_history: bool,
+
+    /// Query to search for
+    #[arg(long)]
+    query: Option<String>,
+
+    /// Results to print
+    #[arg(long, default_value_t = 5)]
+    top_k: usize,
+
+    /// Only pages visited in the last N days
+    #[arg(long)]
+    days: Option<u64>,
+
+    /// Embed with this GGUF model (also read from AI_RUNTIME_MODEL) instead of hashing
+    #[arg(long)]
+    model: Option<PathBuf>,
+}
+
+fn now() -> u64 { SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()) }
+
+fn run(args: Args) -> Result<(), AiError> {
+    let (mut hashed, mut model, mut by_model) = (None, None, None);
+    let embedder: &mut dyn Embedder = match args.model.clone().or_else(|| std::env::var_os(MODEL_ENV).map(PathBuf::from)) {
+        Some(path) => by_model.insert(ModelEmbedder(model.insert(LocalModel::load(path)?))),
+        None => hashed.insert(HashEmbedder::default()),
+    };
+    let mut index = VectorIndex::open(&args.index, embedder.dim())?;
+    if let Some(url) = &args.add {
+        let text = match &args.text_file {
+            Some(p) if p.as_os_str() == "-" => std::io::read_to_string(std::io::stdin())?,
+            Some(p) => std::fs::read_to_string(p)?,
+            None => String::new(),
+        };
+        let key = args.tab.map_or_else(|| DocKey::History(url.clone()), DocKey::Tab);
+        index.insert(embedder, Document { key: &key, url, title: &args.title, text: &text, visited: now() })?;
+    }
+    if let Some(url) = &args.remove { index.remove(&DocKey::History(url.clone()))?; }
+    if args.clear_history { eprintln!("search: removed {} history entries", index.clear_history(None)?); }
+    if let Some(q) = &args.query {
+        let since = args.days.map(|d| now().saturating_sub(d * 86_400));
+        for hit in index.search(embedder, q, args.top_k, since)? {
+            println!("{:.3}\t{}\t{}", hit.score, hit.entry.url, hit.entry.title);
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    if let Err(e) = run(Args::parse()) {
+        eprintln!("search: {e}");
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,61 @@
+use ai_runtime::model::LocalModel;
+use ai_runtime::translate::{self, DictionaryTranslator, ModelTranslator, TranslateRequest, Translator};
+use ai_runtime::{AiError, MODEL_ENV};
+use clap::Parser;
+use std::path::PathBuf;
+
+#[derive(Parser, Debug)]
+#[command(name = "translate")]
+struct Args {
+    /// Text segment to translate (repeatable)
+    #[arg(long)]
+    text: Vec<String>,
+
+    /// Translate an HTML file instead, keeping its markup (- for stdin)
+    #[arg(long, value_name = "FILE")]
+    html: Option<PathBuf>,
+
+    /// Source language code; detected when omitted
+    #[arg(long)]
+    from: Option<String>,
+
+    /// Target language code
+    #[arg(long, default_value = "en")]
+    to: String,
+
+    /// Dictionary backend: tab-separated `source target word translation` lines
+    #[arg(long, value_name = "TSV")]
+    dict: Option<PathBuf>,
+
+    /// GGUF model backend (also read from AI_RUNTIME_MODEL)
+    #[arg(long)]
+    model: Option<PathBuf>,
+}
+
+fn run(args: Args) -> Result<(), AiError> {
+    let (mut dict, mut model, mut by_model) = (None, None, None);
+    let backend: &mut dyn Translator = match (&args.dict, args.model.clone().or_else(|| std::env::var_os(MODEL_ENV).map(PathBuf::from))) {
+        (Some(path), _) => dict.insert(DictionaryTranslator::from_tsv(&std::fs::read_to_string(path)?)?),
+        (None, Some(path)) => by_model.insert(ModelTranslator(model.insert(LocalModel::load(path)?))),
+        (None, None) => return Err(AiError::Translation("no backend; pass --dict or --model".into())),
+    };
+    if let Some(path) = &args.html {
+        let html = if path.as_os_str() == "-" { std::io::read_to_string(std::io::stdin())? } else { std::fs::read_to_string(path)? };
+        let page = translate::translate_html(&html, backend, args.from.as_deref(), &args.to)?;
+        eprintln!("translate: {} -> {} ({} segments)", page.source, args.to, page.segments);
+        println!("{}", page.html);
+        return Ok(());
+    }
+    if args.text.is_empty() { return Err(AiError::Empty); }
+    let out = translate::translate(backend, &TranslateRequest { segments: args.text, source: args.from, target: args.to })?;
+    eprintln!("translate: source={}", out.source);
+    for s in out.segments { println!("{s}"); }
+    Ok(())
+}
+
+fn main() {
+    if let Err(e) = run(Args::parse()) {
+        eprintln!("translate: {e}");
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,115 @@
+//! Text embeddings for semantic search. Vectors are unit length, so cosine
+//! similarity is a dot product. [`HashEmbedder`] needs no model and gives the
+//! same vector for the same text on every run; [`ModelEmbedder`] pools the
+//! local model's token embeddings.
+
+use crate::model::LocalModel;
+use crate::summarize::STOPWORDS;
+use crate::AiError;
+
+pub trait Embedder {
+    /// Length of every vector this embedder returns.
+    fn dim(&self) -> usize;
+    fn embed(&mut self, text: &str) -> Result<Vec<f32>, AiError>;
+}
+
+/// Scale `v` to unit length; the zero vector is returned as is.
+pub fn normalized(mut v: Vec<f32>) -> Vec<f32> {
+    let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 { v.iter_mut().for_each(|x| *x /= norm); }
+    v
+}
+
+/// Cosine similarity; 0 when either vector is zero or the lengths differ.
+pub fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() { return 0.0; }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let na = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let nb = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if na == 0.0 || nb == 0.0 { 0.0 } else { dot / (na * nb) }
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |h, &b| (h ^ b as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+/// Feature hashing of lowercased words (minus stopwords) and their character
+/// trigrams, so "async" and "asynchronous" still land close together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HashEmbedder {
+    dim: usize,
+}
+
+impl Default for HashEmbedder {
+    fn default() -> Self { Self { dim: 256 } }
+}
+
+impl HashEmbedder {
+    pub fn new(dim: usize) -> Result<Self, AiError> {
+        if dim == 0 { return Err(AiError::Index("embedding dimension must be positive".into())); }
+        Ok(Self { dim })
+    }
+
+    fn add(&self, v: &mut [f32], feature: &str, weight: f32) {
+        let h = fnv1a(feature.as_bytes());
+        let sign = if h >> 63 == 0 { 1.0 } else { -1.0 };
+        v[(h % self.dim as u64) as usize] += sign * weight;
+    }
+}
+
+impl Embedder for HashEmbedder {
+    fn dim(&self) -> usize { self.dim }
+
+    fn embed(&mut self, text: &str) -> Result<Vec<f32>, AiError> {
+        let mut v = vec![0.0; self.dim];
+        for word in text.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()).map(str::to_lowercase) {
+            if STOPWORDS.contains(&word.as_str()) { continue; }
+            self.add(&mut v, &word, 1.0);
+            let chars: Vec<char> = format!("^{word}$").chars().collect();
+            for tri in chars.windows(3) { self.add(&mut v, &tri.iter().collect::<String>(), 0.3); }
+        }
+        if v.iter().all(|&x| x == 0.0) { return Err(AiError::Empty); }
+        Ok(normalized(v))
+    }
+}
+
+/// [`LocalModel::embed`] as an [`Embedder`].
+pub struct ModelEmbedder<'a>(pub &'a mut LocalModel);
+
+impl Embedder for ModelEmbedder<'_> {
+    fn dim(&self) -> usize { self.0.embedding_dim() }
+    fn embed(&mut self, text: &str) -> Result<Vec<f32>, AiError> { self.0.embed(text) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::tests::{load, tiny_model};
+
+    #[test]
+    fn hash_embeddings_are_deterministic_and_topical() {
+        let mut e = HashEmbedder::default();
+        let a = e.embed("Understanding async Rust: futures and executors").unwrap();
+        assert_eq!(a, HashEmbedder::default().embed("understanding ASYNC rust, futures and executors!").unwrap());
+        assert!((cosine(&a, &a) - 1.0).abs() < 1e-5);
+        let related = e.embed("rust async article").unwrap();
+        let unrelated = e.embed("Banana bread recipe with walnuts").unwrap();
+        assert!(cosine(&a, &related) > cosine(&a, &unrelated) + 0.2);
+        assert!(matches!(e.embed(" -- the "), Err(AiError::Empty)));
+        assert!(HashEmbedder::new(0).is_err());
+        assert_eq!(HashEmbedder::new(8).unwrap().embed("rust").unwrap().len(), 8);
+    }
+
+    #[test]
+    fn model_embeddings_pool_token_vectors() {
+        let bytes = tiny_model(5);
+        let mut model = load(&bytes);
+        let mut e = ModelEmbedder(&mut model);
+        let v = e.embed("the cat sat").unwrap();
+        assert_eq!(v.len(), e.dim());
+        assert!((v.iter().map(|x| x * x).sum::<f32>() - 1.0).abs() < 1e-4);
+        assert_eq!(v, ModelEmbedder(&mut load(&bytes)).embed("the cat sat").unwrap());
+        // Mean pooling: repeating the text leaves the vector unchanged
+        assert!(cosine(&v, &e.embed("the cat sat the cat sat").unwrap()) > 0.999);
+    }
+}
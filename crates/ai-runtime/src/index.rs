@@ -0,0 +1,289 @@
+//! Local vector index over open tabs and browsing history for semantic search
+//! ("that article about rust async I read last week"). The index lives in
+//! memory and is persisted as an append-only log of inserts and removals, so
+//! each change is one small write; the log is rewritten once it is mostly
+//! stale. A torn write at the end of the log is dropped on open.
+
+use crate::embed::{cosine, Embedder};
+use crate::service::{read_frame, write_frame};
+use crate::AiError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Seek, Write};
+use std::path::{Path, PathBuf};
+use tab_manager::TabId;
+
+/// What an entry indexes. A tab is replaced when it navigates; history has one
+/// entry per URL.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DocKey {
+    Tab(TabId),
+    History(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub key: DocKey,
+    pub url: String,
+    pub title: String,
+    /// Unix seconds of the visit.
+    pub visited: u64,
+    pub vector: Vec<f32>,
+}
+
+/// A page to index; `text` is its readable content.
+#[derive(Debug, Clone, Copy)]
+pub struct Document<'a> {
+    pub key: &'a DocKey,
+    pub url: &'a str,
+    pub title: &'a str,
+    pub text: &'a str,
+    pub visited: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hit<'a> {
+    pub entry: &'a Entry,
+    pub score: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+enum Op {
+    /// First record: vector width, which must match the embedder.
+    Header { dim: usize },
+    Insert(Entry),
+    Remove(DocKey),
+}
+
+/// Characters of page text embedded per document.
+const MAX_TEXT: usize = 16 * 1024;
+
+fn index_err(e: impl std::fmt::Display) -> AiError { AiError::Index(e.to_string()) }
+
+pub struct VectorIndex {
+    dim: usize,
+    entries: HashMap<DocKey, Entry>,
+    log: Option<(PathBuf, BufWriter<File>)>,
+    /// Records in the log, to decide when to compact it.
+    records: usize,
+}
+
+impl VectorIndex {
+    /// Index that is never written to disk.
+    pub fn in_memory(dim: usize) -> Self { Self { dim, entries: HashMap::new(), log: None, records: 0 } }
+
+    /// Open (or create) the index at `path` for vectors of width `dim`.
+    pub fn open(path: impl AsRef<Path>, dim: usize) -> Result<Self, AiError> {
+        let path = path.as_ref().to_path_buf();
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) { fs::create_dir_all(dir)?; }
+        let mut file = OpenOptions::new().read(true).append(true).create(true).open(&path)?;
+        let mut index = Self::in_memory(dim);
+        let mut reader = BufReader::new(&mut file);
+        let end = reader.get_ref().metadata()?.len();
+        let mut good = 0;
+        loop {
+            match read_frame::<Op>(&mut reader) {
+                Ok(Op::Header { dim: d }) if d != dim => return Err(index_err(format!("{} holds {d}-dimensional vectors, expected {dim}", path.display()))),
+                Ok(op) => {
+                    index.apply(op);
+                    index.records += 1;
+                }
+                // A torn final write: the log simply ends mid-frame
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                // A bad frame is only a torn write if nothing follows it
+                Err(e) if e.kind() == io::ErrorKind::InvalidData && reader.stream_position()? == end => break,
+                Err(e) if e.kind() == io::ErrorKind::InvalidData => return Err(index_err(format!("{} is corrupt at byte {good}: {e}", path.display()))),
+                Err(e) => return Err(e.into()),
+            }
+            good = reader.stream_position()?;
+        }
+        file.set_len(good)?;
+        index.log = Some((path, BufWriter::new(file)));
+        if index.records == 0 { index.append(&Op::Header { dim })?; }
+        Ok(index)
+    }
+
+    pub fn dim(&self) -> usize { self.dim }
+    pub fn len(&self) -> usize { self.entries.len() }
+    pub fn is_empty(&self) -> bool { self.entries.is_empty() }
+    pub fn get(&self, key: &DocKey) -> Option<&Entry> { self.entries.get(key) }
+
+    fn apply(&mut self, op: Op) {
+        match op {
+            Op::Header { .. } => {}
+            Op::Insert(e) => { self.entries.insert(e.key.clone(), e); }
+            Op::Remove(k) => { self.entries.remove(&k); }
+        }
+    }
+
+    fn append(&mut self, op: &Op) -> Result<(), AiError> {
+        if let Some((_, w)) = &mut self.log {
+            write_frame(&mut *w, op)?;
+            self.records += 1;
+        }
+        Ok(())
+    }
+
+    /// Embed and add `doc`, replacing any entry with the same key.
+    pub fn insert(&mut self, embedder: &mut dyn Embedder, doc: Document) -> Result<(), AiError> {
+        if embedder.dim() != self.dim { return Err(index_err(format!("embedder width {} does not match index width {}", embedder.dim(), self.dim))); }
+        let text: String = format!("{}\n{}", doc.title, doc.text).chars().take(MAX_TEXT).collect();
+        let entry = Entry { key: doc.key.clone(), url: doc.url.to_string(), title: doc.title.to_string(), visited: doc.visited, vector: embedder.embed(&text)? };
+        let op = Op::Insert(entry);
+        self.append(&op)?;
+        self.apply(op);
+        self.maybe_compact()
+    }
+
+    pub fn remove(&mut self, key: &DocKey) -> Result<bool, AiError> {
+        if !self.entries.contains_key(key) { return Ok(false); }
+        let op = Op::Remove(key.clone());
+        self.append(&op)?;
+        self.apply(op);
+        self.maybe_compact()?;
+        Ok(true)
+    }
+
+    /// Drop history entries visited at or after `since` (all of them for
+    /// `None`), as when the user clears history. Tabs stay. Returns how many.
+    pub fn clear_history(&mut self, since: Option<u64>) -> Result<usize, AiError> {
+        let keys: Vec<DocKey> = self.entries.values()
+            .filter(|e| matches!(e.key, DocKey::History(_)) && since.is_none_or(|t| e.visited >= t))
+            .map(|e| e.key.clone())
+            .collect();
+        for k in &keys { self.remove(k)?; }
+        Ok(keys.len())
+    }
+
+    /// The `k` entries most similar to `query`, best first, optionally only
+    /// those visited at or after `since`.
+    pub fn search(&self, embedder: &mut dyn Embedder, query: &str, k: usize, since: Option<u64>) -> Result<Vec<Hit<'_>>, AiError> {
+        let q = embedder.embed(query)?;
+        let mut hits: Vec<Hit> = self.entries.values()
+            .filter(|e| since.is_none_or(|t| e.visited >= t))
+            .map(|entry| Hit { entry, score: cosine(&q, &entry.vector) })
+            .collect();
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score).then(b.entry.visited.cmp(&a.entry.visited)).then_with(|| a.entry.url.cmp(&b.entry.url)));
+        hits.truncate(k);
+        Ok(hits)
+    }
+
+    fn maybe_compact(&mut self) -> Result<(), AiError> {
+        if self.records > 2 * self.entries.len() + 64 { self.compact()?; }
+        Ok(())
+    }
+
+    /// Rewrite the log with only the live entries.
+    pub fn compact(&mut self) -> Result<(), AiError> {
+        let Some((path, w)) = &mut self.log else { return Ok(()) };
+        w.flush()?;
+        let tmp = path.with_extension("tmp");
+        {
+            let mut out = BufWriter::new(File::create(&tmp)?);
+            write_frame(&mut out, &Op::Header { dim: self.dim })?;
+            for e in self.entries.values() { write_frame(&mut out, &Op::Insert(e.clone()))?; }
+            out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        }
+        fs::rename(&tmp, &*path)?;
+        *w = BufWriter::new(OpenOptions::new().append(true).open(&*path)?);
+        self.records = self.entries.len() + 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embed::{Embedder, HashEmbedder};
+
+    const DAY: u64 = 86_400;
+
+    fn doc<'a>(key: &'a DocKey, url: &'a str, title: &'a str, text: &'a str, visited: u64) -> Document<'a> {
+        Document { key, url, title, text, visited }
+    }
+
+    fn fill(index: &mut VectorIndex, e: &mut HashEmbedder) {
+        let pages = [
+            (DocKey::History("https://blog.test/async".into()), "Understanding async Rust", "Futures, executors and the async await syntax in Rust.", 10 * DAY),
+            (DocKey::History("https://cook.test/bread".into()), "Banana bread", "Mash bananas, add flour and bake for an hour.", 11 * DAY),
+            (DocKey::History("https://news.test/rust".into()), "Rust 2.0 released", "The Rust release brings new language features.", 2 * DAY),
+            (DocKey::Tab(7), "Tokio tutorial", "Tokio is an async runtime for Rust with tasks and futures.", 12 * DAY),
+        ];
+        for (key, title, text, visited) in &pages {
+            let url = match key { DocKey::History(u) => u.as_str(), DocKey::Tab(_) => "https://tokio.test/" };
+            index.insert(e, doc(key, url, title, text, *visited)).unwrap();
+        }
+    }
+
+    #[test]
+    fn top_k_cosine_search_with_time_filter() {
+        let mut e = HashEmbedder::default();
+        let mut index = VectorIndex::in_memory(e.dim());
+        fill(&mut index, &mut e);
+        let hits = index.search(&mut e, "that article about rust async", 3, None).unwrap();
+        let urls: Vec<&str> = hits.iter().map(|h| h.entry.url.as_str()).collect();
+        assert_eq!(urls[0], "https://blog.test/async");
+        assert!(!urls.contains(&"https://cook.test/bread"), "{urls:?}");
+        assert!(hits.windows(2).all(|w| w[0].score >= w[1].score));
+        // "Last week": the old Rust news drops out, bread never ranks above async pages
+        let recent = index.search(&mut e, "rust", 10, Some(9 * DAY)).unwrap();
+        assert_eq!(recent.len(), 3);
+        assert!(recent.iter().all(|h| h.entry.url != "https://news.test/rust"));
+        assert_eq!(recent.last().unwrap().entry.url, "https://cook.test/bread");
+        assert!(index.insert(&mut HashEmbedder::new(8).unwrap(), doc(&DocKey::Tab(1), "x", "x", "x", 0)).is_err());
+    }
+
+    #[test]
+    fn persists_incrementally_and_forgets_cleared_history() {
+        let dir = std::env::temp_dir().join(format!("ai-runtime-index-{}", std::process::id()));
+        let path = dir.join("pages.idx");
+        let _ = fs::remove_dir_all(&dir);
+        let mut e = HashEmbedder::default();
+        {
+            let mut index = VectorIndex::open(&path, e.dim()).unwrap();
+            fill(&mut index, &mut e);
+            // A tab navigating replaces its entry
+            index.insert(&mut e, doc(&DocKey::Tab(7), "https://bake.test/", "Sourdough", "Starter, flour, water.", 13 * DAY)).unwrap();
+            assert_eq!(index.len(), 4);
+        }
+        let mut index = VectorIndex::open(&path, e.dim()).unwrap();
+        assert_eq!(index.len(), 4);
+        assert_eq!(index.get(&DocKey::Tab(7)).unwrap().title, "Sourdough");
+        assert_eq!(index.clear_history(Some(10 * DAY)).unwrap(), 2);
+        assert!(index.remove(&DocKey::History("https://news.test/rust".into())).unwrap());
+        assert!(!index.remove(&DocKey::History("https://news.test/rust".into())).unwrap());
+        drop(index);
+
+        // A torn trailing record is dropped; a width mismatch is refused
+        let len = fs::metadata(&path).unwrap().len();
+        OpenOptions::new().append(true).open(&path).unwrap().write_all(&[200, 0, 0, 0, 1, 2]).unwrap();
+        let index = VectorIndex::open(&path, e.dim()).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), len);
+        let keys: Vec<&DocKey> = index.entries.keys().collect();
+        assert_eq!(keys, [&DocKey::Tab(7)]);
+        assert!(VectorIndex::open(&path, 8).is_err());
+        drop(index);
+
+        // A bad frame followed by more records is corruption, not a torn write
+        let mut bytes = fs::read(&path).unwrap();
+        let good = bytes.clone();
+        let header = 4 + u32::from_le_bytes(bytes[..4].try_into().unwrap()) as usize;
+        let body = u32::from_le_bytes(bytes[header..header + 4].try_into().unwrap()) as usize;
+        bytes[header + 4..header + 4 + body].fill(0xff);
+        fs::write(&path, &bytes).unwrap();
+        assert!(matches!(VectorIndex::open(&path, e.dim()), Err(AiError::Index(_))));
+        assert_eq!(fs::read(&path).unwrap(), bytes, "a corrupt log is left for inspection");
+        fs::write(&path, &good).unwrap();
+        let mut index = VectorIndex::open(&path, e.dim()).unwrap();
+
+        // Churn triggers compaction without losing live entries
+        for i in 0..100 { index.insert(&mut e, doc(&DocKey::Tab(1), "https://a.test/", "A", &format!("page {i}"), i)).unwrap(); }
+        assert!(index.records < 70, "{}", index.records);
+        drop(index);
+        let index = VectorIndex::open(&path, e.dim()).unwrap();
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.get(&DocKey::Tab(1)).unwrap().visited, 99);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
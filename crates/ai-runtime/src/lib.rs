@@ -1,30 +1,85 @@
-//! M6 ai-runtime: Phase-1 mock CPU path (no external model).
+//! M6 ai-runtime: local CPU inference on a quantized GGUF model, with the
+//! Phase-1 mock summarizer as fallback when no model is configured.
+
+pub mod embed;
+pub mod index;
+pub mod model;
+pub mod service;
+pub mod stream;
+pub mod summarize;
+pub mod tokenizer;
+pub mod translate;
 
 use message_defs::{AiRequest, AiResponse};
+use model::{LocalModel, SamplingParams};
+use stream::{AskStream, TokenStream};
+use summarize::SummaryConfig;
+use std::sync::{Mutex, OnceLock};
 use thiserror::Error;
 
+/// Path of the GGUF model used by [`ask`].
+pub const MODEL_ENV: &str = "AI_RUNTIME_MODEL";
+
 #[derive(Debug, Error)]
 pub enum AiError {
     #[error("empty prompt")] Empty,
+    #[error("model: {0}")] Model(String),
+    #[error("tokenizer: {0}")] Tokenizer(String),
+    #[error("service: {0}")] Service(String),
+    #[error("translation: {0}")] Translation(String),
+    #[error("index: {0}")] Index(String),
+    #[error(transparent)] Io(#[from] std::io::Error),
 }
 
+/// Answer with the model from `AI_RUNTIME_MODEL` if set, otherwise the mock summarizer.
 pub fn ask(req: AiRequest) -> Result<AiResponse, AiError> {
     let p = req.prompt.trim();
     if p.is_empty() { return Err(AiError::Empty); }
-    Ok(AiResponse { text: summarize_text(p, req.max_tokens as usize) })
+    match shared_model() {
+        Some(Ok(m)) => m.lock().unwrap_or_else(|e| e.into_inner()).ask(&req, &SamplingParams::default()),
+        Some(Err(e)) => Err(AiError::Model(e.clone())),
+        None => Ok(AiResponse { text: summarize_text(p, req.max_tokens as usize) }),
+    }
+}
+
+/// Streaming form of [`ask`]. The shared model is locked for one token at a
+/// time, so concurrent streams interleave and an idle stream blocks no one.
+pub fn ask_stream(req: AiRequest) -> Result<AskStream, AiError> {
+    let p = req.prompt.trim();
+    if p.is_empty() { return Err(AiError::Empty); }
+    match shared_model() {
+        Some(Ok(m)) => Ok(AskStream::model(TokenStream::new(m, p, req.max_tokens as usize, &SamplingParams::default()))),
+        Some(Err(e)) => Err(AiError::Model(e.clone())),
+        None => Ok(AskStream::mock(p, &summarize_text(p, req.max_tokens as usize))),
+    }
 }
 
-/// Very small summarizer: if the input contains "Example Domain", return it;
-/// otherwise return the first up to `max_tokens` words.
+/// Model loaded once from [`MODEL_ENV`]; `None` when the variable is unset.
+pub fn shared_model() -> Option<&'static Result<Mutex<LocalModel>, String>> {
+    static MODEL: OnceLock<Option<Result<Mutex<LocalModel>, String>>> = OnceLock::new();
+    MODEL.get_or_init(|| {
+        let path = std::env::var_os(MODEL_ENV)?;
+        Some(LocalModel::load(path).map(Mutex::new).map_err(|e| e.to_string()))
+    }).as_ref()
+}
+
+/// Model-free summarizer: if the input contains "Example Domain", return it;
+/// otherwise the TextRank extract of at most `max_tokens` words.
 pub fn summarize_text(input: &str, max_tokens: usize) -> String {
     let lower = input.to_lowercase();
     if lower.contains("example domain") { return "Example Domain".to_string(); }
-    let mut out = String::new();
-    for (i, w) in input.split_whitespace().take(max_tokens.max(1)).enumerate() {
-        if i > 0 { out.push(' '); }
-        out.push_str(w);
+    summarize::extractive(input, max_tokens.max(1), summarize::words)
+}
+
+/// Summarize text of any length: map-reduce with the shared model when one is
+/// configured, otherwise [`summarize_text`].
+pub fn summarize(input: &str, cfg: &SummaryConfig) -> Result<String, AiError> {
+    if input.trim().is_empty() { return Err(AiError::Empty); }
+    match shared_model() {
+        Some(Ok(m)) => summarize::with_model(&mut m.lock().unwrap_or_else(|e| e.into_inner()), input, cfg),
+        Some(Err(e)) => Err(AiError::Model(e.clone())),
+        None => Ok(summarize_text(input, cfg.max_tokens)),
     }
-    out
 }
 
 #[cfg(test)]
@@ -36,5 +91,13 @@ mod tests {
         let s = summarize_text("<h1>Example Domain</h1>", 10);
         assert_eq!(s, "Example Domain");
     }
+
+    #[test]
+    fn long_input_is_summarized_beyond_the_first_paragraph() {
+        let intro = "Cookies help us deliver our services.";
+        let body = "Solar panels convert sunlight into electricity. Cheap solar panels changed electricity markets. Electricity from solar panels now undercuts coal.";
+        let s = summarize_text(&format!("{intro}\n{body}"), 16);
+        assert!(s.contains("solar panels") && !s.contains("Cookies"), "{s}");
+    }
 }
 
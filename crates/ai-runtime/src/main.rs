@@ -1,6 +1,12 @@
-use ai_runtime::{ask, summarize_text};
+use ai_runtime::model::{LocalModel, SamplingParams};
+use ai_runtime::service::{self, Service};
+use ai_runtime::stream::{StreamEvent, Usage};
+use ai_runtime::summarize::SummaryConfig;
+use ai_runtime::{ask, ask_stream, summarize_text, AiError};
 use clap::Parser;
 use message_defs::{AiRequest};
+use std::io::Write;
+use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
 #[command(name="ai-runtime")]
@@ -16,6 +22,72 @@ struct Args {
     /// Max tokens for summary (default 16)
     #[arg(long, default_value_t = 16)]
     max_tokens: u32,
+
+    /// GGUF model to run instead of the mock (also read from AI_RUNTIME_MODEL)
+    #[arg(long)]
+    model: Option<PathBuf>,
+
+    /// Sampling temperature; 0 selects greedy decoding
+    #[arg(long, default_value_t = 0.7)]
+    temperature: f64,
+
+    /// Sample only from the k most likely tokens (0 disables)
+    #[arg(long, default_value_t = 40)]
+    top_k: usize,
+
+    /// Nucleus sampling probability mass (1.0 disables)
+    #[arg(long, default_value_t = 0.95)]
+    top_p: f64,
+
+    /// RNG seed; the same seed reproduces the same output
+    #[arg(long, default_value_t = 42)]
+    seed: u64,
+
+    /// Print tokens as they are generated, then a usage line on stderr
+    #[arg(long)]
+    stream: bool,
+
+    /// Summarize this file (or - for stdin) with map-reduce over chunks
+    #[arg(long, value_name = "FILE")]
+    summarize: Option<PathBuf>,
+
+    /// Summarize: source tokens per chunk
+    #[arg(long, default_value_t = 512)]
+    context_tokens: usize,
+
+    /// Summarize: tokens shared between neighbouring chunks
+    #[arg(long, default_value_t = 64)]
+    overlap_tokens: usize,
+
+    /// Run as a service on this Unix socket (default path if given without a value)
+    #[arg(long, value_name = "PATH", num_args = 0..=1, default_missing_value = "")]
+    serve: Option<PathBuf>,
+
+    /// Service: requests generated concurrently
+    #[arg(long, default_value_t = 1)]
+    workers: usize,
+
+    /// Service: concurrent requests allowed per origin
+    #[arg(long, default_value_t = 1)]
+    per_origin: usize,
+}
+
+fn print_stream(events: impl Iterator<Item = Result<StreamEvent, AiError>>) -> Result<(), AiError> {
+    let mut out = std::io::stdout().lock();
+    for ev in events {
+        match ev? {
+            StreamEvent::Token(t) => {
+                out.write_all(t.as_bytes())?;
+                out.flush()?;
+            }
+            StreamEvent::Done(Usage { prompt_tokens, generated_tokens, time_to_first_token, elapsed, finish }) => {
+                writeln!(out)?;
+                let ttft = time_to_first_token.map_or("-".into(), |t| format!("{}ms", t.as_millis()));
+                eprintln!("usage: prompt={prompt_tokens} generated={generated_tokens} ttft={ttft} total={}ms finish={finish:?}", elapsed.as_millis());
+            }
+        }
+    }
+    Ok(())
 }
 
 fn main() {
@@ -31,12 +103,61 @@ fn main() {
         return;
     }
 
+    if let Some(path) = args.serve {
+        let path = if path.as_os_str().is_empty() { service::default_socket_path() } else { path };
+        let config = service::Config { workers: args.workers.max(1), per_origin: args.per_origin.max(1), ..Default::default() };
+        if let Some(model) = &args.model { std::env::set_var(ai_runtime::MODEL_ENV, model); }
+        // Load the model before accepting requests so the first one doesn't pay for it
+        if let Some(Err(e)) = ai_runtime::shared_model() {
+            eprintln!("ai-runtime: {e}");
+            std::process::exit(1);
+        }
+        let res = Service::bind(&path, config).and_then(|s| {
+            println!("ai-runtime: serving on {}", path.display());
+            s.serve()
+        });
+        if let Err(e) = res {
+            eprintln!("ai-runtime: {e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(path) = &args.summarize {
+        if let Some(model) = &args.model { std::env::set_var(ai_runtime::MODEL_ENV, model); }
+        let cfg = SummaryConfig { context_tokens: args.context_tokens, overlap_tokens: args.overlap_tokens, max_tokens: args.max_tokens as usize };
+        let input = if path.as_os_str() == "-" { std::io::read_to_string(std::io::stdin()) } else { std::fs::read_to_string(path) };
+        match input.map_err(AiError::from).and_then(|text| ai_runtime::summarize(&text, &cfg)) {
+            Ok(summary) => println!("{summary}"),
+            Err(e) => {
+                eprintln!("ai-runtime: {e}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
     if let Some(p) = args.ask {
-        let resp = ask(AiRequest { prompt: p, max_tokens: args.max_tokens }).unwrap();
-        println!("{}", resp.text);
+        let req = AiRequest { prompt: p, max_tokens: args.max_tokens };
+        let params = SamplingParams {
+            temperature: args.temperature,
+            top_k: (args.top_k > 0).then_some(args.top_k),
+            top_p: (args.top_p < 1.0).then_some(args.top_p),
+            seed: args.seed,
+        };
+        let res = match (&args.model, args.stream) {
+            (Some(path), true) => LocalModel::load(path).and_then(|mut m| print_stream(m.stream(req.prompt.trim(), req.max_tokens as usize, &params))),
+            (Some(path), false) => LocalModel::load(path).and_then(|mut m| m.ask(&req, &params)).map(|r| println!("{}", r.text)),
+            (None, true) => ask_stream(req).and_then(print_stream),
+            (None, false) => ask(req).map(|r| println!("{}", r.text)),
+        };
+        if let Err(e) = res {
+            eprintln!("ai-runtime: {e}");
+            std::process::exit(1);
+        }
         return;
     }
 
-    eprintln!("ai-runtime: nothing to do. Use --bench or --ask");
+    eprintln!("ai-runtime: nothing to do. Use --bench, --ask, --summarize or --serve");
 }
 
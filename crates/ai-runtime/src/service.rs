@@ -0,0 +1,429 @@
+//! Service mode: ai-runtime as a long-lived process on a Unix socket. Tabs send
+//! [`ClientMsg::Ask`] frames; the model stays loaded, requests are scheduled by
+//! priority with a per-origin concurrency cap, and output streams back as
+//! [`ServerMsg`] frames. Frames use event-packet's length-prefixed bincode framing.
+//! The socket server and client are Unix-only; the messages, framing and
+//! scheduler build everywhere.
+
+use crate::stream::Usage;
+use message_defs::AiRequest;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+#[cfg(unix)]
+use crate::{stream::{CancelHandle, StreamEvent}, AiError};
+#[cfg(unix)]
+use message_defs::AiResponse;
+#[cfg(unix)]
+use event_packet::ConnectionLimit;
+#[cfg(unix)]
+use std::{io::BufReader, os::unix::net::{UnixListener, UnixStream}, path::Path, sync::atomic::{AtomicU64, Ordering}, sync::{Arc, Condvar, Mutex}, thread};
+
+const MAX_FRAME: usize = 16 << 20;
+
+/// Higher priorities run first; equal priorities run in arrival order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Priority { Background, Normal, UserVisible }
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ClientMsg {
+    /// `id` is chosen by the client and echoed in every reply for this request.
+    Ask { id: u64, origin: String, priority: Priority, request: AiRequest },
+    Cancel { id: u64 },
+    Status,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ServerMsg {
+    /// Accepted with `ahead` requests queued before it.
+    Queued { id: u64, ahead: usize },
+    Token { id: u64, text: String },
+    Done { id: u64, usage: Usage },
+    Error { id: u64, message: String },
+    Status { queued: usize, running: usize },
+}
+
+pub fn write_frame<T: Serialize>(w: impl Write, msg: &T) -> io::Result<()> { event_packet::write_frame(w, msg, MAX_FRAME) }
+
+pub fn read_frame<T: DeserializeOwned>(r: impl Read) -> io::Result<T> { event_packet::read_frame(r, MAX_FRAME) }
+
+/// Socket path used when none is given: `$XDG_RUNTIME_DIR/ai-runtime.sock`, else the temp dir.
+pub fn default_socket_path() -> PathBuf {
+    std::env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from).unwrap_or_else(std::env::temp_dir).join("ai-runtime.sock")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    /// Requests generated at once. Model-backed requests still share one model.
+    pub workers: usize,
+    /// Running requests allowed per origin; the rest wait even if workers are idle.
+    pub per_origin: usize,
+    /// Queued requests beyond this are rejected.
+    pub max_queue: usize,
+    /// Clients served at once; further connections wait to be accepted.
+    pub max_connections: usize,
+    /// A client that takes longer than this to accept a reply is disconnected
+    /// and its requests cancelled, so it cannot hold up a worker.
+    pub write_timeout: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self { Self { workers: 1, per_origin: 1, max_queue: 256, max_connections: 64, write_timeout: Duration::from_secs(10) } }
+}
+
+struct Entry<T> { priority: Priority, seq: u64, origin: String, item: T }
+
+impl<T> Entry<T> {
+    fn key(&self) -> (Priority, Reverse<u64>) { (self.priority, Reverse(self.seq)) }
+}
+impl<T> PartialEq for Entry<T> { fn eq(&self, o: &Self) -> bool { self.key() == o.key() } }
+impl<T> Eq for Entry<T> {}
+impl<T> PartialOrd for Entry<T> { fn partial_cmp(&self, o: &Self) -> Option<std::cmp::Ordering> { Some(self.cmp(o)) } }
+impl<T> Ord for Entry<T> { fn cmp(&self, o: &Self) -> std::cmp::Ordering { self.key().cmp(&o.key()) } }
+
+/// Priority queue with a per-origin cap on running items.
+pub struct Scheduler<T> {
+    queue: BinaryHeap<Entry<T>>,
+    running: HashMap<String, usize>,
+    seq: u64,
+    config: Config,
+}
+
+impl<T> Scheduler<T> {
+    pub fn new(config: Config) -> Self { Self { queue: BinaryHeap::new(), running: HashMap::new(), seq: 0, config } }
+    pub fn queued(&self) -> usize { self.queue.len() }
+    pub fn running(&self) -> usize { self.running.values().sum() }
+
+    /// Queue `item`, returning how many queued items are ahead of it, or the item back if full.
+    pub fn push(&mut self, origin: &str, priority: Priority, item: T) -> Result<usize, T> {
+        if self.queue.len() >= self.config.max_queue { return Err(item); }
+        let ahead = self.queue.iter().filter(|e| e.priority >= priority).count();
+        self.seq += 1;
+        self.queue.push(Entry { priority, seq: self.seq, origin: origin.to_string(), item });
+        Ok(ahead)
+    }
+
+    /// Highest-priority item whose origin is under its cap, if a worker slot is free.
+    /// The caller reports completion with [`Self::finish`].
+    pub fn pop(&mut self) -> Option<(String, T)> {
+        if self.running() >= self.config.workers { return None; }
+        let mut skipped = Vec::new();
+        let mut found = None;
+        while let Some(e) = self.queue.pop() {
+            if self.running.get(&e.origin).copied().unwrap_or(0) < self.config.per_origin {
+                found = Some(e);
+                break;
+            }
+            skipped.push(e);
+        }
+        self.queue.extend(skipped);
+        let e = found?;
+        *self.running.entry(e.origin.clone()).or_default() += 1;
+        Some((e.origin, e.item))
+    }
+
+    pub fn finish(&mut self, origin: &str) {
+        if let Some(n) = self.running.get_mut(origin) {
+            *n -= 1;
+            if *n == 0 { self.running.remove(origin); }
+        }
+    }
+
+    /// Take queued items matching `pred` out of the queue.
+    pub fn remove(&mut self, mut pred: impl FnMut(&T) -> bool) -> Vec<T> {
+        let (gone, keep): (Vec<_>, Vec<_>) = std::mem::take(&mut self.queue).into_vec().into_iter().partition(|e| pred(&e.item));
+        self.queue = keep.into();
+        gone.into_iter().map(|e| e.item).collect()
+    }
+}
+
+#[cfg(unix)]
+type Conn = Arc<Mutex<UnixStream>>;
+
+#[cfg(unix)]
+struct Job { conn: u64, id: u64, request: AiRequest, out: Conn }
+
+#[cfg(unix)]
+struct Shared {
+    sched: Mutex<Scheduler<Job>>,
+    ready: Condvar,
+    /// Cancel handles of running requests by (connection, request id).
+    active: Mutex<HashMap<(u64, u64), CancelHandle>>,
+}
+
+#[cfg(unix)]
+impl Shared {
+    fn reply(out: &Conn, msg: &ServerMsg) -> io::Result<()> { write_frame(&*out.lock().unwrap_or_else(|e| e.into_inner()), msg) }
+
+    fn status(&self) -> ServerMsg {
+        let s = self.sched.lock().unwrap_or_else(|e| e.into_inner());
+        ServerMsg::Status { queued: s.queued(), running: s.running() }
+    }
+
+    fn cancel(&self, conn: u64, id: Option<u64>) {
+        let hit = |c: u64, i: u64| c == conn && id.is_none_or(|id| id == i);
+        let removed = self.sched.lock().unwrap_or_else(|e| e.into_inner()).remove(|j| hit(j.conn, j.id));
+        for j in removed {
+            let _ = Self::reply(&j.out, &ServerMsg::Error { id: j.id, message: "cancelled".into() });
+        }
+        for (&(c, i), h) in self.active.lock().unwrap_or_else(|e| e.into_inner()).iter() {
+            if hit(c, i) { h.cancel(); }
+        }
+    }
+
+    fn worker(&self) {
+        loop {
+            let (origin, job) = {
+                let mut s = self.sched.lock().unwrap_or_else(|e| e.into_inner());
+                loop {
+                    if let Some(next) = s.pop() { break next; }
+                    s = self.ready.wait(s).unwrap_or_else(|e| e.into_inner());
+                }
+            };
+            self.run(&job);
+            self.sched.lock().unwrap_or_else(|e| e.into_inner()).finish(&origin);
+            self.ready.notify_all();
+        }
+    }
+
+    fn run(&self, job: &Job) {
+        let id = job.id;
+        let stream = match crate::ask_stream(job.request.clone()) {
+            Ok(s) => s,
+            Err(e) => {
+                let _ = Self::reply(&job.out, &ServerMsg::Error { id, message: e.to_string() });
+                return;
+            }
+        };
+        let handle = stream.cancel_handle();
+        self.active.lock().unwrap_or_else(|e| e.into_inner()).insert((job.conn, id), handle.clone());
+        for ev in stream {
+            let msg = match ev {
+                Ok(StreamEvent::Token(text)) => ServerMsg::Token { id, text },
+                Ok(StreamEvent::Done(usage)) => ServerMsg::Done { id, usage },
+                Err(e) => ServerMsg::Error { id, message: e.to_string() },
+            };
+            // A client that went away or stopped reading loses its connection;
+            // its reader then cancels everything else it had queued
+            if Self::reply(&job.out, &msg).is_err() {
+                handle.cancel();
+                let _ = job.out.lock().unwrap_or_else(|e| e.into_inner()).shutdown(std::net::Shutdown::Both);
+                break;
+            }
+        }
+        self.active.lock().unwrap_or_else(|e| e.into_inner()).remove(&(job.conn, id));
+    }
+
+    /// Serve one client, then cancel whatever it still has queued or running.
+    fn connection(&self, conn: u64, stream: UnixStream, write_timeout: Duration) -> io::Result<()> {
+        let result = stream.set_write_timeout(Some(write_timeout).filter(|t| !t.is_zero())).and_then(|_| self.requests(conn, stream));
+        self.cancel(conn, None);
+        result
+    }
+
+    fn requests(&self, conn: u64, stream: UnixStream) -> io::Result<()> {
+        let out: Conn = Arc::new(Mutex::new(stream.try_clone()?));
+        let mut reader = BufReader::new(stream);
+        while let Ok(msg) = read_frame::<ClientMsg>(&mut reader) {
+            match msg {
+                ClientMsg::Ask { id, origin, priority, request } => {
+                    let job = Job { conn, id, request, out: out.clone() };
+                    let pushed = self.sched.lock().unwrap_or_else(|e| e.into_inner()).push(&origin, priority, job);
+                    match pushed {
+                        Ok(ahead) => {
+                            Self::reply(&out, &ServerMsg::Queued { id, ahead })?;
+                            self.ready.notify_all();
+                        }
+                        Err(_) => Self::reply(&out, &ServerMsg::Error { id, message: "queue full".into() })?,
+                    }
+                }
+                ClientMsg::Cancel { id } => self.cancel(conn, Some(id)),
+                ClientMsg::Status => Self::reply(&out, &self.status())?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Listening service; [`Service::serve`] runs until the listener fails.
+#[cfg(unix)]
+pub struct Service {
+    listener: UnixListener,
+    shared: Arc<Shared>,
+    config: Config,
+}
+
+#[cfg(unix)]
+impl Service {
+    /// Bind `path`, replacing a stale socket file left by an earlier run.
+    pub fn bind(path: impl AsRef<Path>, config: Config) -> io::Result<Self> {
+        let path = path.as_ref();
+        if UnixStream::connect(path).is_err() { let _ = std::fs::remove_file(path); }
+        let listener = UnixListener::bind(path)?;
+        let shared = Arc::new(Shared { sched: Mutex::new(Scheduler::new(config)), ready: Condvar::new(), active: Mutex::new(HashMap::new()) });
+        Ok(Self { listener, shared, config })
+    }
+
+    pub fn serve(self) -> io::Result<()> {
+        for _ in 0..self.config.workers.max(1) {
+            let shared = self.shared.clone();
+            thread::spawn(move || shared.worker());
+        }
+        let next_conn = AtomicU64::new(0);
+        let write_timeout = self.config.write_timeout;
+        let limit = ConnectionLimit::new(self.config.max_connections);
+        loop {
+            let slot = limit.acquire();
+            let (stream, _) = self.listener.accept()?;
+            let shared = self.shared.clone();
+            let conn = next_conn.fetch_add(1, Ordering::Relaxed);
+            thread::spawn(move || {
+                let _slot = slot;
+                shared.connection(conn, stream, write_timeout)
+            });
+        }
+    }
+}
+
+/// Blocking client for one connection; requests on it are answered one at a time.
+#[cfg(unix)]
+pub struct Client {
+    stream: UnixStream,
+    next_id: u64,
+}
+
+#[cfg(unix)]
+impl Client {
+    pub fn connect(path: impl AsRef<Path>) -> io::Result<Self> { Ok(Self { stream: UnixStream::connect(path)?, next_id: 0 }) }
+
+    /// Send `req` and call `on_token` for each chunk as it arrives.
+    pub fn ask_with(&mut self, origin: &str, priority: Priority, request: AiRequest, mut on_token: impl FnMut(&str)) -> Result<Usage, AiError> {
+        self.next_id += 1;
+        let id = self.next_id;
+        write_frame(&self.stream, &ClientMsg::Ask { id, origin: origin.to_string(), priority, request })?;
+        loop {
+            match read_frame::<ServerMsg>(&self.stream)? {
+                ServerMsg::Token { id: i, text } if i == id => on_token(&text),
+                ServerMsg::Done { id: i, usage } if i == id => return Ok(usage),
+                ServerMsg::Error { id: i, message } if i == id => return Err(AiError::Service(message)),
+                _ => {}
+            }
+        }
+    }
+
+    pub fn ask(&mut self, origin: &str, priority: Priority, request: AiRequest) -> Result<AiResponse, AiError> {
+        let mut text = String::new();
+        self.ask_with(origin, priority, request, |t| text.push_str(t))?;
+        Ok(AiResponse { text: text.trim().to_string() })
+    }
+
+    /// `(queued, running)` request counts.
+    pub fn status(&mut self) -> Result<(usize, usize), AiError> {
+        write_frame(&self.stream, &ClientMsg::Status)?;
+        loop {
+            if let ServerMsg::Status { queued, running } = read_frame(&self.stream)? { return Ok((queued, running)); }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scheduler_orders_by_priority_and_caps_origins() {
+        let mut s = Scheduler::new(Config { workers: 2, per_origin: 1, max_queue: 4, ..Config::default() });
+        assert_eq!(s.push("a", Priority::Background, 1), Ok(0));
+        assert_eq!(s.push("a", Priority::UserVisible, 2), Ok(0));
+        assert_eq!(s.push("a", Priority::Normal, 3), Ok(1));
+        assert_eq!(s.push("b", Priority::Normal, 4), Ok(2));
+        assert_eq!(s.push("b", Priority::Normal, 5), Err(5));
+        assert_eq!(s.pop(), Some(("a".into(), 2)));
+        // "a" is at its cap, so b's request jumps ahead of a's higher-priority one
+        assert_eq!(s.pop(), Some(("b".into(), 4)));
+        assert_eq!(s.pop(), None, "both workers busy");
+        s.finish("b");
+        assert_eq!(s.pop(), None, "only a's requests are left");
+        s.finish("a");
+        assert_eq!(s.pop(), Some(("a".into(), 3)));
+        assert_eq!(s.remove(|&i| i == 1), vec![1]);
+        assert_eq!((s.queued(), s.running()), (0, 1));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn serves_requests_over_a_socket() {
+        let dir = std::env::temp_dir().join(format!("ai-runtime-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("ai.sock");
+        let service = Service::bind(&path, Config::default()).unwrap();
+        thread::spawn(move || service.serve());
+
+        let ask = |prompt: &str| AiRequest { prompt: prompt.into(), max_tokens: 3 };
+        let tabs: Vec<_> = (0..4).map(|i| {
+            let path = path.clone();
+            thread::spawn(move || {
+                let mut c = Client::connect(&path).unwrap();
+                let mut chunks = 0;
+                let usage = c.ask_with(&format!("https://tab{i}.test"), Priority::Normal, ask("one two three four"), |_| chunks += 1).unwrap();
+                (chunks, usage.generated_tokens)
+            })
+        }).collect();
+        for t in tabs { assert_eq!(t.join().unwrap(), (3, 3)); }
+
+        let mut c = Client::connect(&path).unwrap();
+        assert_eq!(c.ask("https://a.test", Priority::UserVisible, ask("Example Domain page")).unwrap().text, "Example Domain");
+        assert!(matches!(c.ask("https://a.test", Priority::Normal, ask("  ")), Err(AiError::Service(_))));
+        // The worker releases its slot just after sending Done
+        let idle = (0..100).any(|_| c.status().unwrap() == (0, 0) || { thread::sleep(std::time::Duration::from_millis(10)); false });
+        assert!(idle);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn connections_beyond_the_cap_wait() {
+        let dir = std::env::temp_dir().join(format!("ai-runtime-cap-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("ai.sock");
+        let service = Service::bind(&path, Config { max_connections: 1, ..Config::default() }).unwrap();
+        thread::spawn(move || service.serve());
+
+        let mut first = Client::connect(&path).unwrap();
+        assert_eq!(first.status().unwrap(), (0, 0));
+        let mut second = Client::connect(&path).unwrap();
+        second.stream.set_read_timeout(Some(std::time::Duration::from_millis(200))).unwrap();
+        assert!(second.status().is_err(), "not served while the first client is connected");
+        drop(first);
+        second.stream.set_read_timeout(None).unwrap();
+        assert_eq!(second.status().unwrap(), (0, 0));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_client_that_stops_reading_does_not_hold_the_worker() {
+        let dir = std::env::temp_dir().join(format!("ai-runtime-stall-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("ai.sock");
+        let service = Service::bind(&path, Config { write_timeout: Duration::from_millis(200), ..Config::default() }).unwrap();
+        thread::spawn(move || service.serve());
+
+        // Enough output to fill the socket buffer of a client that never reads
+        let stalled = UnixStream::connect(&path).unwrap();
+        let prompt = "word ".repeat(200_000);
+        write_frame(&stalled, &ClientMsg::Ask { id: 1, origin: "https://slow.test".into(), priority: Priority::Normal, request: AiRequest { prompt, max_tokens: 200_000 } }).unwrap();
+        thread::sleep(Duration::from_millis(100));
+
+        let mut c = Client::connect(&path).unwrap();
+        let started = std::time::Instant::now();
+        assert_eq!(c.ask("https://a.test", Priority::Normal, AiRequest { prompt: "Example Domain page".into(), max_tokens: 3 }).unwrap().text, "Example Domain");
+        assert!(started.elapsed() < Duration::from_secs(5), "{:?}", started.elapsed());
+        drop(stalled);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
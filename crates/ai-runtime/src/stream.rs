@@ -0,0 +1,289 @@
+//! Incremental generation: text arrives as [`StreamEvent::Token`] chunks and the
+//! stream ends with a single [`StreamEvent::Done`] carrying a [`Usage`] report.
+//! Streams are pull-based, so they work without threads (wasm) and stop doing
+//! work as soon as the consumer stops pulling or cancels. A stream over the
+//! shared model locks it for one step at a time; when another caller used the
+//! model in between, the next step re-reads the stream's context.
+
+use crate::model::{LocalModel, SamplingParams};
+use crate::AiError;
+use candle_transformers::generation::LogitsProcessor;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use web_time::Instant;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FinishReason {
+    /// The model emitted end-of-sequence.
+    Stop,
+    /// `max_tokens` or the context window was reached.
+    Length,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Usage {
+    pub prompt_tokens: usize,
+    pub generated_tokens: usize,
+    /// `None` if nothing was generated.
+    pub time_to_first_token: Option<Duration>,
+    pub elapsed: Duration,
+    pub finish: FinishReason,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StreamEvent {
+    /// Newly decoded text; concatenating all chunks gives the full output.
+    Token(String),
+    Done(Usage),
+}
+
+/// Shared flag to stop a stream from another thread or callback.
+#[derive(Debug, Clone, Default)]
+pub struct CancelHandle(Arc<AtomicBool>);
+
+impl CancelHandle {
+    pub fn cancel(&self) { self.0.store(true, Ordering::Relaxed) }
+    pub fn is_cancelled(&self) -> bool { self.0.load(Ordering::Relaxed) }
+}
+
+/// How a stream reaches its model: borrowed for the stream's lifetime, or a
+/// shared model locked for each step.
+pub trait ModelAccess {
+    fn with_model<R>(&mut self, f: impl FnOnce(&mut LocalModel) -> R) -> R;
+}
+
+impl ModelAccess for &mut LocalModel {
+    fn with_model<R>(&mut self, f: impl FnOnce(&mut LocalModel) -> R) -> R { f(self) }
+}
+
+impl ModelAccess for &Mutex<LocalModel> {
+    fn with_model<R>(&mut self, f: impl FnOnce(&mut LocalModel) -> R) -> R { f(&mut self.lock().unwrap_or_else(|e| e.into_inner())) }
+}
+
+/// Token stream over a model reached through [`ModelAccess`].
+pub struct TokenStream<M> {
+    model: M,
+    /// Identifies this stream's context in the model's KV cache.
+    id: u64,
+    sampler: LogitsProcessor,
+    prompt: Vec<u32>,
+    tokens: Vec<u32>,
+    max_tokens: usize,
+    /// Bytes of decoded text already emitted.
+    emitted: usize,
+    cancel: CancelHandle,
+    started: Instant,
+    first_token: Option<Duration>,
+    finish: Option<FinishReason>,
+    done: bool,
+}
+
+impl<M: ModelAccess> TokenStream<M> {
+    pub(crate) fn new(mut model: M, prompt: &str, max_tokens: usize, params: &SamplingParams) -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+        let (prompt, max_tokens) = model.with_model(|m| m.budget(prompt, max_tokens));
+        let sampler = LogitsProcessor::from_sampling(params.seed, params.sampling());
+        let finish = (max_tokens == 0 || prompt.is_empty()).then_some(FinishReason::Length);
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        Self { model, id, sampler, prompt, tokens: Vec::new(), max_tokens, emitted: 0, cancel: CancelHandle::default(), started: Instant::now(), first_token: None, finish, done: false }
+    }
+
+    pub fn cancel_handle(&self) -> CancelHandle { self.cancel.clone() }
+    pub fn cancel(&self) { self.cancel.cancel() }
+    pub fn prompt_tokens(&self) -> usize { self.prompt.len() }
+    /// Token ids generated so far, excluding any end-of-sequence token.
+    pub fn tokens(&self) -> &[u32] { &self.tokens }
+
+    pub fn usage(&self) -> Usage {
+        Usage {
+            prompt_tokens: self.prompt.len(),
+            generated_tokens: self.tokens.len(),
+            time_to_first_token: self.first_token,
+            elapsed: self.started.elapsed(),
+            finish: self.finish.unwrap_or(FinishReason::Cancelled),
+        }
+    }
+
+    fn step(&mut self) -> Result<(), AiError> {
+        if self.cancel.is_cancelled() {
+            self.finish = Some(FinishReason::Cancelled);
+            return Ok(());
+        }
+        let Self { model, id, sampler, prompt, tokens, .. } = self;
+        let (next, eos) = model.with_model(|m| {
+            // The whole context at position 0 (which also resets the KV cache), then one token at a
+            // time for as long as the cache still holds this stream's context
+            let logits = match tokens.last() {
+                Some(&last) if m.kv_owner == *id => m.forward(&[last], prompt.len() + tokens.len() - 1)?,
+                _ => {
+                    m.kv_owner = 0;
+                    m.forward(&[prompt.as_slice(), tokens.as_slice()].concat(), 0)?
+                }
+            };
+            m.kv_owner = *id;
+            let next = sampler.sample(&logits).map_err(|e| AiError::Model(e.to_string()))?;
+            Ok::<_, AiError>((next, m.tokenizer().eos))
+        })?;
+        if Some(next) == eos {
+            self.finish = Some(FinishReason::Stop);
+            return Ok(());
+        }
+        self.first_token.get_or_insert_with(|| self.started.elapsed());
+        self.tokens.push(next);
+        if self.tokens.len() == self.max_tokens { self.finish = Some(FinishReason::Length); }
+        Ok(())
+    }
+
+    /// Text decoded since the last chunk. A trailing incomplete UTF-8 sequence is
+    /// held back until its remaining byte tokens arrive, or the stream ends.
+    fn pending_text(&mut self) -> Option<String> {
+        let tokens = &self.tokens;
+        let text = self.model.with_model(|m| m.tokenizer().decode(tokens));
+        let new = text.get(self.emitted..).unwrap_or_default();
+        if new.is_empty() || (self.finish.is_none() && new.ends_with('\u{FFFD}')) { return None; }
+        self.emitted = text.len();
+        Some(new.to_string())
+    }
+}
+
+impl<M: ModelAccess> Iterator for TokenStream<M> {
+    type Item = Result<StreamEvent, AiError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done { return None; }
+        while self.finish.is_none() {
+            if let Err(e) = self.step() {
+                self.done = true;
+                return Some(Err(e));
+            }
+            if let Some(text) = self.pending_text() { return Some(Ok(StreamEvent::Token(text))); }
+        }
+        if let Some(text) = self.pending_text() { return Some(Ok(StreamEvent::Token(text))); }
+        self.done = true;
+        Some(Ok(StreamEvent::Done(self.usage())))
+    }
+}
+
+/// Stream returned by [`crate::ask_stream`]: the shared model when configured,
+/// otherwise the mock summary one word at a time.
+pub struct AskStream(Inner);
+
+enum Inner {
+    Model(Box<TokenStream<&'static Mutex<LocalModel>>>),
+    Mock { words: VecDeque<String>, prompt_tokens: usize, cancel: CancelHandle, started: Instant, first: Option<Duration>, sent: usize, done: bool },
+}
+
+impl AskStream {
+    pub(crate) fn model(stream: TokenStream<&'static Mutex<LocalModel>>) -> Self { Self(Inner::Model(Box::new(stream))) }
+
+    pub(crate) fn mock(prompt: &str, summary: &str) -> Self {
+        let words = summary.split(' ').enumerate().map(|(i, w)| if i == 0 { w.to_string() } else { format!(" {w}") }).collect();
+        let prompt_tokens = prompt.split_whitespace().count();
+        Self(Inner::Mock { words, prompt_tokens, cancel: CancelHandle::default(), started: Instant::now(), first: None, sent: 0, done: false })
+    }
+
+    pub fn cancel_handle(&self) -> CancelHandle {
+        match &self.0 {
+            Inner::Model(s) => s.cancel_handle(),
+            Inner::Mock { cancel, .. } => cancel.clone(),
+        }
+    }
+
+    pub fn cancel(&self) { self.cancel_handle().cancel() }
+}
+
+impl Iterator for AskStream {
+    type Item = Result<StreamEvent, AiError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.0 {
+            Inner::Model(s) => s.next(),
+            Inner::Mock { words, prompt_tokens, cancel, started, first, sent, done } => {
+                if *done { return None; }
+                if !cancel.is_cancelled() {
+                    if let Some(w) = words.pop_front() {
+                        first.get_or_insert_with(|| started.elapsed());
+                        *sent += 1;
+                        return Some(Ok(StreamEvent::Token(w)));
+                    }
+                }
+                *done = true;
+                let finish = if cancel.is_cancelled() { FinishReason::Cancelled } else { FinishReason::Stop };
+                Some(Ok(StreamEvent::Done(Usage { prompt_tokens: *prompt_tokens, generated_tokens: *sent, time_to_first_token: *first, elapsed: started.elapsed(), finish })))
+            }
+        }
+    }
+}
+
+/// Concatenated text and usage of a finished stream.
+pub fn collect<I: Iterator<Item = Result<StreamEvent, AiError>>>(stream: I) -> Result<(String, Option<Usage>), AiError> {
+    let mut text = String::new();
+    let mut usage = None;
+    for ev in stream {
+        match ev? {
+            StreamEvent::Token(t) => text.push_str(&t),
+            StreamEvent::Done(u) => usage = Some(u),
+        }
+    }
+    Ok((text, usage))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::tests::{load, tiny_model};
+
+    #[test]
+    fn chunks_match_generate_and_report_usage() {
+        let mut model = load(&tiny_model(5));
+        let params = SamplingParams { seed: 9, ..SamplingParams::default() };
+        let full = model.generate("the cat sat", 10, &params).unwrap();
+        let events: Vec<StreamEvent> = model.stream("the cat sat", 10, &params).map(Result::unwrap).collect();
+        let Some(StreamEvent::Done(usage)) = events.last() else { panic!("stream must end with Done") };
+        assert_eq!((usage.prompt_tokens, usage.generated_tokens, usage.finish), (4, 10, FinishReason::Length));
+        assert!(usage.time_to_first_token.is_some_and(|t| t <= usage.elapsed));
+        let text: String = events.iter().filter_map(|e| if let StreamEvent::Token(t) = e { Some(t.as_str()) } else { None }).collect();
+        assert_eq!(text, full.text);
+    }
+
+    #[test]
+    fn cancel_stops_generation() {
+        let mut model = load(&tiny_model(5));
+        let mut s = model.stream("the cat", 30, &SamplingParams::greedy());
+        let handle = s.cancel_handle();
+        assert!(matches!(s.next(), Some(Ok(StreamEvent::Token(_)))));
+        handle.cancel();
+        let rest: Vec<_> = s.by_ref().map(Result::unwrap).collect();
+        let Some(StreamEvent::Done(usage)) = rest.last() else { panic!("expected Done") };
+        assert_eq!(usage.finish, FinishReason::Cancelled);
+        assert!(usage.generated_tokens < 30);
+        assert!(s.next().is_none());
+
+        let mut mock = AskStream::mock("some prompt words", "Example Domain");
+        assert_eq!(mock.next().unwrap().unwrap(), StreamEvent::Token("Example".into()));
+        mock.cancel();
+        assert!(matches!(mock.next(), Some(Ok(StreamEvent::Done(Usage { generated_tokens: 1, finish: FinishReason::Cancelled, .. })))));
+    }
+
+    #[test]
+    fn shared_model_streams_interleave() {
+        let model = Mutex::new(load(&tiny_model(5)));
+        let params = SamplingParams::greedy();
+        let text = |s: &mut TokenStream<&Mutex<LocalModel>>| collect(s).unwrap().0;
+        let alone = [text(&mut TokenStream::new(&model, "the cat sat", 8, &params)), text(&mut TokenStream::new(&model, "the cat", 8, &params))];
+        let mut streams = [TokenStream::new(&model, "the cat sat", 8, &params), TokenStream::new(&model, "the cat", 8, &params)];
+        let mut out = [String::new(), String::new()];
+        // Alternate steps: the model is free between them and each stream re-reads its context
+        for _ in 0..=8 {
+            for (s, o) in streams.iter_mut().zip(&mut out) {
+                if let Some(Ok(StreamEvent::Token(t))) = s.next() { o.push_str(&t); }
+                assert!(model.try_lock().is_ok(), "no lock held between steps");
+            }
+        }
+        assert_eq!(out, alone);
+    }
+}
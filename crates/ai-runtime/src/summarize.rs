@@ -0,0 +1,261 @@
+//! Summaries of arbitrarily long text. Input is split into overlapping chunks on
+//! sentence and paragraph boundaries, each chunk is summarized (map), and the
+//! partial summaries are joined and summarized again until they fit one context
+//! (reduce). Without a model, TextRank picks the most central sentences instead.
+
+use crate::model::{LocalModel, SamplingParams};
+use crate::AiError;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SummaryConfig {
+    /// Tokens of source text given to the model at once.
+    pub context_tokens: usize,
+    /// Tokens repeated from the end of one chunk at the start of the next.
+    pub overlap_tokens: usize,
+    /// Length of each partial summary and of the final one.
+    pub max_tokens: usize,
+}
+
+impl Default for SummaryConfig {
+    fn default() -> Self { Self { context_tokens: 512, overlap_tokens: 64, max_tokens: 64 } }
+}
+
+/// Sentence text and the index of the paragraph (line) it came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sentence<'a> {
+    pub text: &'a str,
+    pub section: usize,
+}
+
+/// Split on `.`, `!` or `?` followed by whitespace, and on line breaks.
+pub fn sentences(text: &str) -> Vec<Sentence<'_>> {
+    let mut out = Vec::new();
+    for (section, line) in text.lines().filter(|l| !l.trim().is_empty()).enumerate() {
+        let mut start = 0;
+        let mut chars = line.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            let at_end = matches!(c, '.' | '!' | '?') && chars.peek().is_none_or(|&(_, n)| n.is_whitespace());
+            if at_end || chars.peek().is_none() {
+                let end = i + c.len_utf8();
+                let s = line[start..end].trim();
+                if !s.is_empty() { out.push(Sentence { text: s, section }); }
+                start = end;
+            }
+        }
+    }
+    out
+}
+
+/// Group sentences into chunks of at most `context_tokens` (a longer sentence
+/// gets a chunk of its own). A chunk ends early at a paragraph break if that
+/// keeps it at least half full, and the next one starts up to `overlap_tokens` back.
+pub fn chunks(sentences: &[Sentence], cfg: &SummaryConfig, count: impl Fn(&str) -> usize) -> Vec<Range<usize>> {
+    let mut prefix = vec![0];
+    for s in sentences { prefix.push(prefix.last().unwrap() + count(s.text)); }
+    let tokens = |r: Range<usize>| prefix[r.end] - prefix[r.start];
+    let (n, budget) = (sentences.len(), cfg.context_tokens.max(1));
+    let mut out = Vec::new();
+    let mut start = 0;
+    while start < n {
+        let mut end = start + 1;
+        while end < n && tokens(start..end + 1) <= budget { end += 1; }
+        if end < n {
+            let brk = (start + 1..end).rev().find(|&b| sentences[b].section != sentences[b - 1].section);
+            if let Some(b) = brk.filter(|&b| tokens(start..b) * 2 >= budget) { end = b; }
+        }
+        out.push(start..end);
+        if end == n { break; }
+        let mut next = end;
+        while next > start + 1 && tokens(next - 1..end) <= cfg.overlap_tokens { next -= 1; }
+        start = next;
+    }
+    out
+}
+
+pub(crate) const STOPWORDS: &[&str] = &["the", "and", "for", "are", "but", "not", "you", "all", "any", "can", "her", "was", "one", "our", "out", "has", "his", "how", "its", "who", "did", "this", "that", "with", "from", "they", "have", "were", "been", "their", "there", "which", "will", "would", "what", "when", "into", "than", "then", "them", "these", "those", "also"];
+
+fn content_words(s: &str) -> HashSet<String> {
+    s.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.chars().count() > 2)
+        .map(str::to_lowercase)
+        .filter(|w| !STOPWORDS.contains(&w.as_str()))
+        .collect()
+}
+
+/// Sentences ranked as one graph. Longer inputs are ranked in consecutive
+/// windows of this many, keeping the cost linear in the number of sentences.
+pub const RANK_WINDOW: usize = 256;
+
+/// TextRank centrality of each sentence: PageRank over a graph weighted by
+/// shared content words, normalized by sentence length. Scores average 1 within
+/// each [`RANK_WINDOW`], so they compare across windows.
+pub fn textrank(sentences: &[&str]) -> Vec<f64> { sentences.chunks(RANK_WINDOW).flat_map(rank_window).collect() }
+
+fn rank_window(sentences: &[&str]) -> Vec<f64> {
+    const DAMPING: f64 = 0.85;
+    // Each sentence's words as sorted ids, so pairs intersect by merging
+    let mut ids = HashMap::new();
+    let words: Vec<Vec<usize>> = sentences.iter().map(|s| {
+        let mut w: Vec<usize> = content_words(s).into_iter().map(|w| { let next = ids.len(); *ids.entry(w).or_insert(next) }).collect();
+        w.sort_unstable();
+        w
+    }).collect();
+    let n = words.len();
+    // Sparse: most sentence pairs share no content word
+    let mut edges: Vec<Vec<(usize, f64)>> = vec![Vec::new(); n];
+    for i in 0..n {
+        for j in i + 1..n {
+            let shared = shared_count(&words[i], &words[j]);
+            if shared == 0 { continue; }
+            let w = shared as f64 / ((words[i].len() as f64 + 1.0).ln() + (words[j].len() as f64 + 1.0).ln());
+            edges[i].push((j, w));
+            edges[j].push((i, w));
+        }
+    }
+    let out_sum: Vec<f64> = edges.iter().map(|e| e.iter().map(|&(_, w)| w).sum()).collect();
+    let mut score = vec![1.0; n];
+    for _ in 0..100 {
+        let next: Vec<f64> = edges.iter().map(|e| (1.0 - DAMPING) + DAMPING * e.iter().map(|&(j, w)| w / out_sum[j] * score[j]).sum::<f64>()).collect();
+        let delta: f64 = next.iter().zip(&score).map(|(a, b)| (a - b).abs()).sum();
+        score = next;
+        if delta < 1e-6 * n as f64 { break; }
+    }
+    score
+}
+
+fn shared_count(a: &[usize], b: &[usize]) -> usize {
+    let (mut i, mut j, mut n) = (0, 0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => { n += 1; i += 1; j += 1; }
+        }
+    }
+    n
+}
+
+/// Highest-ranked sentences that fit in `max_tokens`, in document order. If
+/// even the best sentence is too long, its first `max_tokens` words.
+pub fn extractive(text: &str, max_tokens: usize, count: impl Fn(&str) -> usize) -> String {
+    let sents: Vec<&str> = sentences(text).iter().map(|s| s.text).collect();
+    let score = textrank(&sents);
+    let mut order: Vec<usize> = (0..sents.len()).collect();
+    order.sort_by(|&a, &b| score[b].total_cmp(&score[a]).then(a.cmp(&b)));
+    let mut picked = Vec::new();
+    let mut used = 0;
+    for &i in &order {
+        let c = count(sents[i]);
+        if used + c <= max_tokens {
+            used += c;
+            picked.push(i);
+        }
+    }
+    if picked.is_empty() {
+        return order.first().map(|&i| sents[i].split_whitespace().take(max_tokens.max(1)).collect::<Vec<_>>().join(" ")).unwrap_or_default();
+    }
+    picked.sort_unstable();
+    picked.iter().map(|&i| sents[i]).collect::<Vec<_>>().join(" ")
+}
+
+/// Map-reduce over chunks with `summarize_chunk(text, max_tokens)`. Rounds stop
+/// when the text fits one context; a round that fails to shrink the text falls
+/// back to [`extractive`] so a weak model cannot loop forever.
+pub fn map_reduce(text: &str, cfg: &SummaryConfig, count: impl Fn(&str) -> usize, mut summarize_chunk: impl FnMut(&str, usize) -> Result<String, AiError>) -> Result<String, AiError> {
+    let mut text = text.trim().to_string();
+    loop {
+        let total = count(&text);
+        if total <= cfg.context_tokens { return summarize_chunk(&text, cfg.max_tokens); }
+        let sents = sentences(&text);
+        let mut partials = Vec::new();
+        for r in chunks(&sents, cfg, &count) {
+            let chunk: Vec<&str> = sents[r].iter().map(|s| s.text).collect();
+            let summary = summarize_chunk(&chunk.join(" "), cfg.max_tokens)?;
+            if !summary.trim().is_empty() { partials.push(summary.trim().to_string()); }
+        }
+        let merged = partials.join("\n");
+        if count(&merged) >= total { return Ok(extractive(&text, cfg.max_tokens, &count)); }
+        text = merged;
+    }
+}
+
+/// Abstractive summary of `text` with `model`, counting tokens with its tokenizer.
+pub fn with_model(model: &mut LocalModel, text: &str, cfg: &SummaryConfig) -> Result<String, AiError> {
+    let tokenizer = model.tokenizer().clone();
+    map_reduce(text, cfg, |s| tokenizer.encode(s, false).len(), |chunk, max_tokens| {
+        let prompt = format!("Summarize the following text.\n\n{chunk}\n\nSummary:");
+        Ok(model.generate(&prompt, max_tokens, &SamplingParams::greedy())?.text.trim().to_string())
+    })
+}
+
+/// Word count, the token estimate used without a model.
+pub fn words(s: &str) -> usize { s.split_whitespace().count() }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunks_follow_sentence_and_paragraph_boundaries() {
+        let text = "One two three. Four five six! Seven eight?\nNine ten eleven twelve. Thirteen.\nFourteen fifteen.";
+        let s = sentences(text);
+        let texts: Vec<&str> = s.iter().map(|s| s.text).collect();
+        assert_eq!(texts, ["One two three.", "Four five six!", "Seven eight?", "Nine ten eleven twelve.", "Thirteen.", "Fourteen fifteen."]);
+        assert_eq!(s.iter().map(|s| s.section).collect::<Vec<_>>(), [0, 0, 0, 1, 1, 2]);
+
+        let cfg = SummaryConfig { context_tokens: 12, overlap_tokens: 2, max_tokens: 4 };
+        // The first chunk has room for the 4-word sentence but stops at the paragraph
+        // break; the second repeats the first's last short sentence
+        assert_eq!(chunks(&s, &cfg, words), [0..3, 2..6]);
+        assert_eq!(chunks(&s, &SummaryConfig { overlap_tokens: 0, ..cfg }, words), [0..3, 3..6]);
+        // A sentence longer than the context is a chunk of its own
+        assert_eq!(chunks(&s, &SummaryConfig { context_tokens: 2, overlap_tokens: 0, ..cfg }, words), [0..1, 1..2, 2..3, 3..4, 4..5, 5..6]);
+    }
+
+    #[test]
+    fn textrank_prefers_central_sentences() {
+        let text = "Rust compilers check memory safety at compile time.\n\
+                    The weather was pleasant on Tuesday.\n\
+                    Memory safety in Rust comes from ownership checked by the compiler.\n\
+                    Ownership rules let the Rust compiler prove memory safety.";
+        let summary = extractive(text, 20, words);
+        assert!(!summary.contains("weather"), "{summary}");
+        assert!(words(&summary) <= 20);
+        // Picked sentences keep document order
+        let first = summary.split(". ").next().unwrap();
+        assert!(text.find(first).unwrap() < text.find("Ownership rules").unwrap());
+        assert_eq!(extractive("a very long single sentence here", 3, words), "a very long");
+        assert_eq!(extractive("", 3, words), "");
+    }
+
+    #[test]
+    fn long_inputs_are_ranked_in_windows() {
+        let sents: Vec<String> = (0..RANK_WINDOW * 10).map(|i| format!("Sentence {i} mentions topic{} and detail{}.", i % 7, i % 13)).collect();
+        let refs: Vec<&str> = sents.iter().map(String::as_str).collect();
+        let score = textrank(&refs);
+        assert_eq!(score.len(), refs.len());
+        assert_eq!(score[RANK_WINDOW..2 * RANK_WINDOW], textrank(&refs[RANK_WINDOW..2 * RANK_WINDOW])[..]);
+        let mean = score.iter().sum::<f64>() / score.len() as f64;
+        assert!((mean - 1.0).abs() < 1e-3, "{mean}");
+        assert!(words(&extractive(&sents.join(" "), 30, words)) <= 30);
+    }
+
+    #[test]
+    fn map_reduce_covers_the_whole_document() {
+        let paragraphs: Vec<String> = (0..40).map(|i| format!("Topic{i} opens here. It has detail {i} that matters less.")).collect();
+        let doc = paragraphs.join("\n");
+        let cfg = SummaryConfig { context_tokens: 40, overlap_tokens: 5, max_tokens: 12 };
+        let mut calls = 0;
+        // Stand-in model: keep the first sentence of each chunk
+        let out = map_reduce(&doc, &cfg, words, |chunk, max| {
+            calls += 1;
+            Ok(chunk.split(". ").next().unwrap().split_whitespace().take(max).collect::<Vec<_>>().join(" "))
+        }).unwrap();
+        assert!(calls > 5 && words(&out) <= 12, "{calls} {out}");
+        // A summarizer that never shrinks its input ends in the extractive fallback
+        let out = map_reduce(&doc, &cfg, words, |chunk, _| Ok(chunk.to_string())).unwrap();
+        assert!(words(&out) <= 12 && !out.is_empty());
+    }
+}
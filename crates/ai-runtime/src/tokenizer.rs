@@ -0,0 +1,255 @@
+//! Tokenizer rebuilt from the vocabulary embedded in GGUF metadata
+//! (`tokenizer.ggml.*`). Supports the two families used by llama-style
+//! models: SentencePiece (`llama`, score-driven merges with byte fallback)
+//! and byte-level BPE (`gpt2`, rank-driven merges).
+
+use crate::AiError;
+use candle_core::quantized::gguf_file::Value;
+use std::collections::HashMap;
+
+/// `tokenizer.ggml.token_type` values we treat specially.
+const TYPE_CONTROL: i32 = 3;
+const TYPE_BYTE: i32 = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    SentencePiece,
+    BytePair,
+}
+
+#[derive(Debug, Clone)]
+pub struct Tokenizer {
+    kind: Kind,
+    tokens: Vec<String>,
+    types: Vec<i32>,
+    scores: Vec<f32>,
+    ids: HashMap<String, u32>,
+    /// BPE merge ranks, lower merges first.
+    merges: HashMap<(String, String), usize>,
+    /// SentencePiece `<0xNN>` byte-fallback tokens.
+    byte_tokens: Vec<Option<u32>>,
+    unk: Option<u32>,
+    pub bos: Option<u32>,
+    pub eos: Option<u32>,
+}
+
+impl Tokenizer {
+    pub fn from_gguf(md: &HashMap<String, Value>) -> Result<Self, AiError> {
+        let err = |m: &str| AiError::Tokenizer(m.to_string());
+        let get = |k: &str| md.get(k);
+        let kind = match get("tokenizer.ggml.model").and_then(|v| v.to_string().ok()).map(String::as_str) {
+            Some("llama") | None => Kind::SentencePiece,
+            Some("gpt2") => Kind::BytePair,
+            Some(other) => return Err(err(&format!("unsupported tokenizer model {other}"))),
+        };
+        let tokens: Vec<String> = get("tokenizer.ggml.tokens")
+            .ok_or_else(|| err("missing tokenizer.ggml.tokens"))?
+            .to_vec()
+            .map_err(|e| err(&e.to_string()))?
+            .iter()
+            .map(|v| v.to_string().cloned())
+            .collect::<Result<_, _>>()
+            .map_err(|e| err(&e.to_string()))?;
+        let array = |k: &str| get(k).and_then(|v| v.to_vec().ok());
+        let scores: Vec<f32> = array("tokenizer.ggml.scores").map(|a| a.iter().map(|v| v.to_f32().unwrap_or(0.0)).collect()).unwrap_or_default();
+        let types: Vec<i32> = array("tokenizer.ggml.token_type").map(|a| a.iter().map(|v| v.to_i32().unwrap_or(1)).collect()).unwrap_or_default();
+        let merges = array("tokenizer.ggml.merges")
+            .map(|a| {
+                a.iter().filter_map(|v| v.to_string().ok()?.split_once(' ').map(|(l, r)| (l.to_string(), r.to_string()))).enumerate().map(|(rank, pair)| (pair, rank)).collect()
+            })
+            .unwrap_or_default();
+        let id = |k: &str| get(k).and_then(|v| v.to_u32().ok()).filter(|&i| (i as usize) < tokens.len());
+        let ids: HashMap<String, u32> = tokens.iter().enumerate().map(|(i, t)| (t.clone(), i as u32)).collect();
+        let byte_tokens = (0..=255u8).map(|b| ids.get(&format!("<0x{b:02X}>")).copied()).collect();
+        Ok(Self {
+            kind,
+            unk: id("tokenizer.ggml.unknown_token_id").or_else(|| ids.get("<unk>").copied()),
+            bos: id("tokenizer.ggml.bos_token_id"),
+            eos: id("tokenizer.ggml.eos_token_id"),
+            tokens,
+            types,
+            scores,
+            ids,
+            merges,
+            byte_tokens,
+        })
+    }
+
+    pub fn vocab_size(&self) -> usize { self.tokens.len() }
+
+    pub fn encode(&self, text: &str, add_bos: bool) -> Vec<u32> {
+        let mut out: Vec<u32> = self.bos.filter(|_| add_bos).into_iter().collect();
+        match self.kind {
+            Kind::SentencePiece => self.encode_spm(text, &mut out),
+            Kind::BytePair => self.encode_bpe(text, &mut out),
+        }
+        out
+    }
+
+    /// Text of `ids`, skipping control tokens such as BOS/EOS.
+    pub fn decode(&self, ids: &[u32]) -> String {
+        let mut bytes = Vec::new();
+        for &id in ids {
+            let Some(tok) = self.tokens.get(id as usize) else { continue };
+            match self.types.get(id as usize).copied().unwrap_or(1) {
+                TYPE_CONTROL => {}
+                TYPE_BYTE => bytes.extend(u8::from_str_radix(tok.trim_start_matches("<0x").trim_end_matches('>'), 16).ok()),
+                _ => match self.kind {
+                    Kind::SentencePiece => bytes.extend_from_slice(tok.replace('\u{2581}', " ").as_bytes()),
+                    Kind::BytePair => bytes.extend(tok.chars().filter_map(unicode_to_byte)),
+                },
+            }
+        }
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+
+    fn encode_spm(&self, text: &str, out: &mut Vec<u32>) {
+        if text.is_empty() { return; }
+        let text = format!("\u{2581}{}", text.replace(' ', "\u{2581}"));
+        // Merge within words (a run of ▁ plus the following non-▁ text) to keep this near-linear
+        let mut start = 0;
+        let chars: Vec<(usize, char)> = text.char_indices().collect();
+        for w in chars.windows(2) {
+            if w[1].1 == '\u{2581}' && w[0].1 != '\u{2581}' {
+                self.spm_word(&text[start..w[1].0], out);
+                start = w[1].0;
+            }
+        }
+        self.spm_word(&text[start..], out);
+    }
+
+    fn spm_word(&self, word: &str, out: &mut Vec<u32>) {
+        let mut symbols: Vec<String> = word.chars().map(String::from).collect();
+        loop {
+            let best = symbols.windows(2).enumerate()
+                .filter_map(|(i, p)| self.ids.get(&format!("{}{}", p[0], p[1])).map(|&id| (i, self.scores.get(id as usize).copied().unwrap_or(0.0))))
+                .max_by(|a, b| a.1.total_cmp(&b.1).then(b.0.cmp(&a.0)));
+            let Some((i, _)) = best else { break };
+            let right = symbols.remove(i + 1);
+            symbols[i].push_str(&right);
+        }
+        for s in symbols {
+            match self.ids.get(&s) {
+                Some(&id) => out.push(id),
+                None => {
+                    for b in s.bytes() {
+                        out.extend(self.byte_tokens[b as usize].or(self.unk));
+                    }
+                }
+            }
+        }
+    }
+
+    fn encode_bpe(&self, text: &str, out: &mut Vec<u32>) {
+        for word in pretokenize(text) {
+            let mut symbols: Vec<String> = word.bytes().map(|b| byte_to_unicode(b).to_string()).collect();
+            loop {
+                let best = symbols.windows(2).enumerate()
+                    .filter_map(|(i, p)| self.merges.get(&(p[0].clone(), p[1].clone())).map(|&rank| (rank, i)))
+                    .min();
+                let Some((_, i)) = best else { break };
+                let right = symbols.remove(i + 1);
+                symbols[i].push_str(&right);
+            }
+            for s in symbols {
+                match self.ids.get(&s) {
+                    Some(&id) => out.push(id),
+                    None => out.extend(s.chars().filter_map(|c| self.ids.get(&c.to_string()).copied().or(self.unk))),
+                }
+            }
+        }
+    }
+}
+
+/// GPT-2 style pre-tokenization, simplified: runs of letters, digits,
+/// whitespace or punctuation, with a single space before a run attached to it.
+fn pretokenize(text: &str) -> Vec<&str> {
+    let class = |c: char| if c.is_alphabetic() { 0 } else if c.is_numeric() { 1 } else if c.is_whitespace() { 2 } else { 3 };
+    let mut runs: Vec<(usize, usize, u8)> = Vec::new();
+    for (i, c) in text.char_indices() {
+        let end = i + c.len_utf8();
+        match runs.last_mut() {
+            Some(run) if run.2 == class(c) => run.1 = end,
+            _ => runs.push((i, end, class(c))),
+        }
+    }
+    let mut out = Vec::new();
+    let mut carry = None;
+    for (k, &(s, e, class)) in runs.iter().enumerate() {
+        let start = carry.take().unwrap_or(s);
+        if class == 2 && text[..e].ends_with(' ') && k + 1 < runs.len() {
+            if e - 1 > start { out.push(&text[start..e - 1]); }
+            carry = Some(e - 1);
+        } else {
+            out.push(&text[start..e]);
+        }
+    }
+    out
+}
+
+/// GPT-2's reversible byte to printable-char mapping.
+fn byte_to_unicode(b: u8) -> char {
+    let printable = |b: u8| (b'!'..=b'~').contains(&b) || (0xA1..=0xAC).contains(&b) || (0xAE..=0xFF).contains(&b);
+    if printable(b) { return b as char; }
+    let offset = (0..b).filter(|&x| !printable(x)).count() as u32;
+    char::from_u32(256 + offset).expect("valid char")
+}
+
+fn unicode_to_byte(c: char) -> Option<u8> { (0..=255u8).find(|&b| byte_to_unicode(b) == c) }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Small SentencePiece vocabulary: control tokens, byte fallback and a few words.
+    pub(crate) fn spm_vocab() -> Vec<(String, Value)> {
+        let mut tokens = vec!["<unk>".to_string(), "<s>".into(), "</s>".into()];
+        let mut types = vec![2, TYPE_CONTROL, TYPE_CONTROL];
+        for b in 0..=255u8 {
+            tokens.push(format!("<0x{b:02X}>"));
+            types.push(TYPE_BYTE);
+        }
+        for w in ["\u{2581}", "\u{2581}t", "\u{2581}th", "\u{2581}the", "\u{2581}c", "\u{2581}ca", "\u{2581}cat", "\u{2581}s", "\u{2581}sat", "a", "t", "h", "e", "c", "s", "at"] {
+            tokens.push(w.to_string());
+            types.push(1);
+        }
+        let scores: Vec<Value> = tokens.iter().map(|t| Value::F32(t.chars().count() as f32)).collect();
+        vec![
+            ("tokenizer.ggml.model".into(), Value::String("llama".into())),
+            ("tokenizer.ggml.tokens".into(), Value::Array(tokens.into_iter().map(Value::String).collect())),
+            ("tokenizer.ggml.scores".into(), Value::Array(scores)),
+            ("tokenizer.ggml.token_type".into(), Value::Array(types.into_iter().map(Value::I32).collect())),
+            ("tokenizer.ggml.bos_token_id".into(), Value::U32(1)),
+        ]
+    }
+
+    #[test]
+    fn sentencepiece_round_trip_with_byte_fallback() {
+        let tok = Tokenizer::from_gguf(&spm_vocab().into_iter().collect()).unwrap();
+        let ids = tok.encode("the cat sat", true);
+        let pieces: Vec<&str> = ids.iter().map(|&i| tok.tokens[i as usize].as_str()).collect();
+        assert_eq!(pieces, vec!["<s>", "\u{2581}the", "\u{2581}cat", "\u{2581}sat"]);
+        let ids = tok.encode("café", false);
+        assert!(ids.iter().any(|&i| tok.types[i as usize] == TYPE_BYTE), "é falls back to bytes");
+        assert_eq!(tok.decode(&ids), " café");
+    }
+
+    #[test]
+    fn byte_pair_round_trip() {
+        let g = |s: &str| s.chars().map(|c| if c == ' ' { byte_to_unicode(b' ') } else { c }).collect::<String>();
+        let mut tokens: Vec<String> = (0..=255u8).map(|b| byte_to_unicode(b).to_string()).collect();
+        tokens.extend([g(" t"), g(" th"), g(" the"), "he".into()]);
+        let merges = [g(" ") + " t", g(" t") + " h", "h e".into(), g(" th") + " e"];
+        let md: HashMap<String, Value> = [
+            ("tokenizer.ggml.model".to_string(), Value::String("gpt2".into())),
+            ("tokenizer.ggml.tokens".into(), Value::Array(tokens.into_iter().map(Value::String).collect())),
+            ("tokenizer.ggml.merges".into(), Value::Array(merges.into_iter().map(Value::String).collect())),
+        ].into_iter().collect();
+        let tok = Tokenizer::from_gguf(&md).unwrap();
+        assert_eq!(pretokenize("hi the, 42!"), vec!["hi", " the", ",", " 42", "!"]);
+        let ids = tok.encode("a the", false);
+        assert_eq!(ids.len(), 2, "' the' merges into one token");
+        assert_eq!(tok.decode(&ids), "a the");
+        assert_eq!(tok.decode(&tok.encode("naïve\n", false)), "naïve\n");
+    }
+}
@@ -0,0 +1,295 @@
+//! Local translation: language detection, a batch [`Translator`] API with a
+//! model backend and a deterministic dictionary backend, and page translation
+//! that rewrites servo-lite text nodes in place so markup, comments and the
+//! doctype survive (with the `html` feature). Languages are ISO 639-1 codes
+//! ("en", "de", ...).
+
+use crate::model::{LocalModel, SamplingParams};
+use crate::AiError;
+#[cfg(feature = "html")]
+use servo_lite::dom::{Document, NodeData, NodeId};
+use std::collections::HashMap;
+
+/// Function words per Latin-script language, used to tell them apart.
+const PROFILES: &[(&str, &[&str])] = &[
+    ("en", &["the", "and", "is", "of", "to", "in", "that", "it", "with", "for", "you", "this", "are", "was"]),
+    ("es", &["el", "la", "de", "que", "y", "en", "los", "es", "por", "con", "una", "para", "las", "del"]),
+    ("fr", &["le", "la", "les", "de", "et", "est", "un", "une", "des", "que", "pour", "dans", "pas", "du"]),
+    ("de", &["der", "die", "das", "und", "ist", "nicht", "ein", "eine", "mit", "zu", "ich", "den", "auf", "sie"]),
+    ("it", &["il", "di", "che", "e", "la", "per", "non", "una", "sono", "con", "gli", "del", "della", "un"]),
+    ("pt", &["o", "de", "que", "e", "do", "da", "em", "um", "para", "com", "não", "uma", "os", "no"]),
+    ("nl", &["de", "het", "een", "en", "van", "is", "niet", "dat", "op", "te", "zijn", "met", "voor", "ik"]),
+];
+
+/// Elements whose text is code or user input rather than prose.
+#[cfg(feature = "html")]
+const SKIP: &[&str] = &["script", "style", "noscript", "textarea", "code", "pre", "template", "svg", "math"];
+#[cfg(feature = "html")]
+const TEXT_ATTRS: &[&str] = &["alt", "title", "placeholder"];
+
+fn script_of(c: char) -> Option<&'static str> {
+    Some(match c as u32 {
+        0x3040..=0x30ff => "ja",
+        0xac00..=0xd7af | 0x1100..=0x11ff => "ko",
+        0x4e00..=0x9fff | 0x3400..=0x4dbf => "zh",
+        0x0400..=0x04ff => "ru",
+        0x0600..=0x06ff => "ar",
+        0x0370..=0x03ff => "el",
+        0x0590..=0x05ff => "he",
+        _ => return None,
+    })
+}
+
+/// Best guess at the language of `text`: by script for non-Latin text, else by
+/// function-word frequency. `None` if there is too little to go on.
+pub fn detect_language(text: &str) -> Option<&'static str> {
+    let letters = text.chars().filter(|c| c.is_alphabetic()).count();
+    if letters == 0 { return None; }
+    let mut scripts: HashMap<&str, usize> = HashMap::new();
+    for c in text.chars().filter_map(script_of) { *scripts.entry(c).or_default() += 1; }
+    // Kana marks Japanese even when most characters are kanji
+    if scripts.get("ja").is_some_and(|&n| n * 10 >= letters) { return Some("ja"); }
+    if let Some((&lang, &n)) = scripts.iter().max_by_key(|(_, &n)| n) {
+        if n * 3 >= letters { return Some(lang); }
+    }
+    let words: Vec<String> = text.split(|c: char| !c.is_alphabetic()).filter(|w| !w.is_empty()).map(str::to_lowercase).collect();
+    PROFILES.iter()
+        .map(|(lang, common)| (*lang, words.iter().filter(|w| common.contains(&w.as_str())).count()))
+        .filter(|&(_, hits)| hits > 0)
+        .max_by_key(|&(_, hits)| hits)
+        .map(|(lang, _)| lang)
+}
+
+pub fn language_name(code: &str) -> &str {
+    match code {
+        "en" => "English", "es" => "Spanish", "fr" => "French", "de" => "German", "it" => "Italian",
+        "pt" => "Portuguese", "nl" => "Dutch", "ja" => "Japanese", "ko" => "Korean", "zh" => "Chinese",
+        "ru" => "Russian", "ar" => "Arabic", "el" => "Greek", "he" => "Hebrew",
+        other => other,
+    }
+}
+
+pub trait Translator {
+    /// Translate each segment from `source` to `target`, one output per input.
+    fn translate(&mut self, segments: &[&str], source: &str, target: &str) -> Result<Vec<String>, AiError>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TranslateRequest {
+    pub segments: Vec<String>,
+    /// Detected from the segments when `None`.
+    pub source: Option<String>,
+    pub target: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Translation {
+    pub source: String,
+    pub segments: Vec<String>,
+}
+
+pub fn translate(t: &mut dyn Translator, req: &TranslateRequest) -> Result<Translation, AiError> {
+    let source = match &req.source {
+        Some(s) => s.clone(),
+        None => detect_language(&req.segments.join("\n")).ok_or_else(|| AiError::Translation("could not detect source language".into()))?.to_string(),
+    };
+    if source == req.target || req.segments.is_empty() { return Ok(Translation { source, segments: req.segments.clone() }); }
+    let segs: Vec<&str> = req.segments.iter().map(String::as_str).collect();
+    let segments = t.translate(&segs, &source, &req.target)?;
+    if segments.len() != segs.len() { return Err(AiError::Translation(format!("expected {} segments, got {}", segs.len(), segments.len()))); }
+    Ok(Translation { source, segments })
+}
+
+/// Word-for-word translation from fixed tables; unknown words pass through.
+/// Deterministic, so tests can check exact output.
+#[derive(Debug, Clone, Default)]
+pub struct DictionaryTranslator {
+    tables: HashMap<(String, String), HashMap<String, String>>,
+}
+
+impl DictionaryTranslator {
+    pub fn new() -> Self { Self::default() }
+
+    pub fn insert(&mut self, source: &str, target: &str, word: &str, translation: &str) {
+        self.tables.entry((source.into(), target.into())).or_default().insert(word.to_lowercase(), translation.to_string());
+    }
+
+    pub fn with_words(mut self, source: &str, target: &str, words: &[(&str, &str)]) -> Self {
+        for (w, t) in words { self.insert(source, target, w, t); }
+        self
+    }
+
+    /// Tab-separated `source target word translation` lines; `#` starts a comment.
+    pub fn from_tsv(text: &str) -> Result<Self, AiError> {
+        let mut d = Self::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') { continue; }
+            let f: Vec<&str> = line.split('\t').collect();
+            let [source, target, word, translation] = f[..] else { return Err(AiError::Translation(format!("line {}: expected 4 tab-separated fields", i + 1))) };
+            d.insert(source, target, word, translation);
+        }
+        Ok(d)
+    }
+
+    fn word(table: &HashMap<String, String>, w: &str) -> String {
+        let Some(t) = table.get(&w.to_lowercase()) else { return w.to_string() };
+        let mut chars = w.chars();
+        match chars.next() {
+            Some(_) if w.chars().count() > 1 && w.chars().all(|c| !c.is_lowercase()) => t.to_uppercase(),
+            Some(first) if first.is_uppercase() => {
+                let mut t = t.chars();
+                t.next().map(|c| c.to_uppercase().chain(t).collect()).unwrap_or_default()
+            }
+            _ => t.clone(),
+        }
+    }
+}
+
+impl Translator for DictionaryTranslator {
+    fn translate(&mut self, segments: &[&str], source: &str, target: &str) -> Result<Vec<String>, AiError> {
+        let table = self.tables.get(&(source.to_string(), target.to_string()))
+            .ok_or_else(|| AiError::Translation(format!("no dictionary for {source}->{target}")))?;
+        Ok(segments.iter().map(|seg| {
+            let mut out = String::new();
+            let mut word = String::new();
+            for c in seg.chars() {
+                if c.is_alphanumeric() || c == '\'' {
+                    word.push(c);
+                    continue;
+                }
+                if !word.is_empty() { out.push_str(&Self::word(table, &std::mem::take(&mut word))); }
+                out.push(c);
+            }
+            if !word.is_empty() { out.push_str(&Self::word(table, &word)); }
+            out
+        }).collect())
+    }
+}
+
+/// Prompted translation with a local model, one segment at a time.
+pub struct ModelTranslator<'a>(pub &'a mut LocalModel);
+
+impl Translator for ModelTranslator<'_> {
+    fn translate(&mut self, segments: &[&str], source: &str, target: &str) -> Result<Vec<String>, AiError> {
+        let (from, to) = (language_name(source), language_name(target));
+        segments.iter().map(|seg| {
+            let prompt = format!("Translate the following text from {from} to {to}. Reply with the translation only.\n\n{seg}\n\n{to}:");
+            let budget = self.0.tokenizer().encode(seg, false).len() * 2 + 16;
+            let g = self.0.generate(&prompt, budget, &SamplingParams::greedy())?;
+            Ok(g.text.trim().lines().next().unwrap_or_default().trim().to_string())
+        }).collect()
+    }
+}
+
+#[cfg(feature = "html")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageTranslation {
+    pub html: String,
+    pub source: String,
+    /// Text nodes and attributes rewritten.
+    pub segments: usize,
+}
+
+/// Where a segment came from, so its translation can be put back.
+#[cfg(feature = "html")]
+enum Slot { Text(NodeId), Attr(NodeId, &'static str) }
+
+/// Translate the prose of an HTML document: text nodes plus `alt`, `title` and
+/// `placeholder`, skipping code, scripts and anything under `translate="no"` or
+/// `class="notranslate"`. Whitespace around each text node is kept as is.
+#[cfg(feature = "html")]
+pub fn translate_html(html: &str, t: &mut dyn Translator, source: Option<&str>, target: &str) -> Result<PageTranslation, AiError> {
+    let mut doc = Document::parse_with_comments(html);
+    let mut slots = Vec::new();
+    let mut segments = Vec::new();
+    let mut stack = vec![doc.root()];
+    while let Some(n) = stack.pop() {
+        match &doc.node(n).data {
+            NodeData::Text(text) if !text.trim().is_empty() => {
+                slots.push(Slot::Text(n));
+                segments.push(text.trim().to_string());
+            }
+            NodeData::Element { name, .. } => {
+                let opted_out = doc.attr(n, "translate").is_some_and(|v| v.eq_ignore_ascii_case("no"))
+                    || doc.attr(n, "class").is_some_and(|c| c.split_whitespace().any(|c| c == "notranslate"));
+                if opted_out || SKIP.contains(&name.as_str()) { continue; }
+                for &a in TEXT_ATTRS {
+                    if let Some(v) = doc.attr(n, a).filter(|v| !v.trim().is_empty()) {
+                        segments.push(v.trim().to_string());
+                        slots.push(Slot::Attr(n, a));
+                    }
+                }
+                stack.extend(doc.children(n).iter().rev());
+            }
+            _ => stack.extend(doc.children(n).iter().rev()),
+        }
+    }
+    let out = translate(t, &TranslateRequest { segments, source: source.map(str::to_string), target: target.to_string() })?;
+    for (slot, translated) in slots.iter().zip(&out.segments) {
+        match *slot {
+            Slot::Text(n) => {
+                let old = doc.text(n).unwrap_or_default();
+                let lead = &old[..old.len() - old.trim_start().len()];
+                let trail = &old[old.trim_end().len()..];
+                let new = format!("{lead}{translated}{trail}");
+                doc.set_text(n, &new);
+            }
+            Slot::Attr(n, a) => doc.set_attr(n, a, translated),
+        }
+    }
+    if out.source != target {
+        let root = doc.elements_by_tag("html").next();
+        if let Some(root) = root { doc.set_attr(root, "lang", target); }
+    }
+    Ok(PageTranslation { html: doc.to_html(doc.root()), source: out.source, segments: slots.len() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn en_de() -> DictionaryTranslator {
+        DictionaryTranslator::new().with_words("en", "de", &[("the", "der"), ("cat", "Katze"), ("is", "ist"), ("black", "schwarz"), ("photo", "Foto"), ("of", "von"), ("a", "eine"), ("hello", "hallo")])
+    }
+
+    #[test]
+    fn detects_languages() {
+        assert_eq!(detect_language("The cat is on the mat and it is black."), Some("en"));
+        assert_eq!(detect_language("El gato es negro y la casa es grande."), Some("es"));
+        assert_eq!(detect_language("Die Katze ist schwarz und das Haus ist nicht groß."), Some("de"));
+        assert_eq!(detect_language("猫は黒いです"), Some("ja"));
+        assert_eq!(detect_language("Кошка черная"), Some("ru"));
+        assert_eq!(detect_language("12345 !!"), None);
+    }
+
+    #[test]
+    fn dictionary_batch_keeps_case_and_punctuation() {
+        let req = TranslateRequest { segments: vec!["The cat is black.".into(), "HELLO, world!".into()], source: None, target: "de".into() };
+        let out = translate(&mut en_de(), &req).unwrap();
+        assert_eq!(out.source, "en");
+        assert_eq!(out.segments, ["Der Katze ist schwarz.", "HALLO, world!"]);
+        let same = translate(&mut en_de(), &TranslateRequest { target: "en".into(), ..req.clone() }).unwrap();
+        assert_eq!(same.segments, req.segments);
+        assert!(matches!(translate(&mut en_de(), &TranslateRequest { target: "fr".into(), ..req }), Err(AiError::Translation(_))));
+        let d = DictionaryTranslator::from_tsv("# en->de\nen\tde\tcat\tKatze\n").unwrap();
+        assert_eq!(d.clone().translate(&["cat"], "en", "de").unwrap(), ["Katze"]);
+        assert!(DictionaryTranslator::from_tsv("en de cat").is_err());
+    }
+
+    #[cfg(feature = "html")]
+    #[test]
+    fn page_translation_preserves_markup() {
+        let html = "<html><body><p class=\"intro\">The <b>cat</b> is black.</p>\n  <img alt=\"a photo of the cat\" src=\"cat.png\">\
+                    <code>the cat</code><p translate=\"no\">the cat</p><script>var the = 'cat';</script></body></html>";
+        let out = translate_html(html, &mut en_de(), None, "de").unwrap();
+        assert_eq!(out.source, "en");
+        assert_eq!(out.segments, 4);
+        assert_eq!(out.html, "<html lang=\"de\"><body><p class=\"intro\">Der <b>Katze</b> ist schwarz.</p>\n  <img alt=\"eine Foto von der Katze\" src=\"cat.png\">\
+                              <code>the cat</code><p translate=\"no\">the cat</p><script>var the = 'cat';</script></body></html>");
+
+        let html = "<!DOCTYPE html>\n<html><!-- the cat --><body>the cat</body></html>";
+        let out = translate_html(html, &mut en_de(), None, "de").unwrap();
+        assert_eq!(out.html, "<!DOCTYPE html>\n<html lang=\"de\"><!-- the cat --><body>der Katze</body></html>");
+    }
+}
@@ -1,11 +1,11 @@
-use anyhow::{anyhow, Result};
-use clap::Parser;
+mod screenshot;
+
+use anyhow::{anyhow, Context, Result};
+use clap::{CommandFactory, Parser};
 use clap::ValueEnum;
 
 use sha2::{Digest, Sha256};
-use message_defs::{HttpRequest, DisplayList, DrawCmd};
-use std::fs::File;
-use std::io::{BufWriter, Write};
+use message_defs::HttpRequest;
 use std::path::PathBuf;
 use std::process::Command;
 use std::sync::Arc;
@@ -29,9 +29,24 @@ use extension_api::{InMemoryBus, HostMessenger, Event};
 struct Args {
     /// URL to open
     url: String,
-    /// Save a CPU-rasterized DisplayList preview to this PPM file (Phase-1 demo)
+    /// Save a CPU-rasterized preview of the page; PNG unless the extension or --screenshot-format says otherwise
     #[arg(long)]
     screenshot: Option<PathBuf>,
+    /// Screenshot encoding: png | jpeg | webp (lossless)
+    #[arg(long, value_enum)]
+    screenshot_format: Option<screenshot::Format>,
+    /// JPEG quality, 1-100 (default 90). Refused for WebP, which is only encoded lossless
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=100))]
+    screenshot_quality: Option<u8>,
+    /// Capture the whole document instead of the viewport
+    #[arg(long)]
+    full_page: bool,
+    /// Capture only the first element matching this CSS selector
+    #[arg(long, value_name = "SELECTOR")]
+    screenshot_selector: Option<String>,
+    /// Device pixels per CSS pixel for the screenshot
+    #[arg(long, default_value_t = 1.0)]
+    device_pixel_ratio: f32,
     /// Save a real WebKit render to this PNG (spawns content-srv)
     #[arg(long)]
     real_screenshot: Option<PathBuf>,
@@ -59,6 +74,14 @@ struct Args {
     /// P2 S6: load extension from directory (repeatable; requires manifest.json; optional background.wat)
     #[arg(long, value_name = "DIR")]
     ext_load: Vec<PathBuf>,
+
+    /// ai-runtime service socket; the service is spawned there if nothing is listening
+    #[arg(long, value_name = "PATH")]
+    ai_socket: Option<PathBuf>,
+
+    /// Print which backend gpu-srv renders with (GPU_SRV_BACKEND overrides) and exit
+    #[arg(long)]
+    gpu_info: bool,
 }
 
 #[tokio::main(flavor = "multi_thread")]
@@ -67,6 +90,19 @@ async fn main() -> Result<()> {
     let mut ext_bus_events: Vec<Event> = Vec::new();
 
     let args = Args::parse();
+    if let Some(path) = &args.screenshot {
+        let format = args.screenshot_format.unwrap_or_else(|| screenshot::Format::from_path(path));
+        if let Err(e) = format.check_quality(args.screenshot_quality) {
+            Args::command().error(clap::error::ErrorKind::ArgumentConflict, e).exit();
+        }
+    }
+
+    if args.gpu_info {
+        let info = gpu_srv::backend::probe()?;
+        println!("GPU_BACKEND {}", info);
+        for why in &info.skipped { println!("GPU_SKIPPED {}", why); }
+        return Ok(());
+    }
 
     // P2 S11: stub print to PDF and exit early when requested
     if let Some(dir) = &args.print_stub {
@@ -135,15 +171,39 @@ async fn main() -> Result<()> {
     let body = String::from_utf8_lossy(&resp.body);
     println!("HTTP {} ({} bytes)", resp.status, resp.body.len());
 
-    // AI summarize (mock)
-    let summary = ai_runtime::summarize_text(&body, 16);
+    // AI summarize (mock) over the reader-mode article text rather than raw markup;
+    // pages without an identifiable article fall back to the whole body
+    let summary_input = match reader_mode::extract(&body, Some(&args.url)) {
+        Ok(article) => {
+            println!("READER: title={:?} words={}", article.title, article.word_count);
+            format!("{}\n{}", article.title, article.text)
+        }
+        Err(e) => {
+            println!("READER: {}", e);
+            body.to_string()
+        }
+    };
+    let socket = args.ai_socket.clone().unwrap_or_else(ai_runtime::service::default_socket_path);
+    let ai_origin = origin.clone();
+    let fallback_input = summary_input.clone();
+    let summary = match tokio::task::spawn_blocking(move || summarize_via_ai_runtime(&socket, &ai_origin, summary_input)).await? {
+        Ok(summary) => summary,
+        Err(e) => {
+            // Without the service the mock summarizer runs in-process
+            eprintln!("SUMMARY_FALLBACK: {}", e);
+            ai_runtime::summarize_text(&fallback_input, SUMMARY_TOKENS)
+        }
+    };
     println!("SUMMARY: {}", summary);
 
-    // Servo-lite DL (Phase-1 minimal display list)
+    // Servo-lite page; images load off-thread through network-srv, so give them a
+    // short grace period and re-render once decoded (broken ones get placeholders)
     let viewport = (800, 600);
-    let dl = servo_lite::html_to_display_list(&body, viewport).unwrap_or_else(|_| {
-        DisplayList { items: vec![] }
-    });
+    let fetcher = servo_lite::image::NetworkFetcher::new(net.clone(), tokio::runtime::Handle::current());
+    let images = Arc::new(servo_lite::image::ImagePipeline::new(Arc::new(fetcher), 64 * 1024 * 1024, 2));
+    let mut page = servo_lite::Page::with_images(&body, viewport, Some(&args.url), images.clone()).ok();
+    tokio::task::block_in_place(|| images.wait_idle(std::time::Duration::from_secs(2)));
+    if let Some(page) = &mut page { page.update(); }
 
     // Optional: show a real WebKit window via content-srv (non-blocking)
     if args.show {
@@ -172,11 +232,18 @@ async fn main() -> Result<()> {
         return Ok(());
     }
 
-    // Optional: CPU rasterize DL to PPM for preview
+    // Optional: CPU rasterized preview of the page, images already loaded above
     if let Some(path) = &args.screenshot {
-        let img = rasterize_dl_rgba8(viewport.0, viewport.1, &dl);
-        write_ppm(path, viewport.0, viewport.1, &img)?;
-        println!("Saved screenshot to {} (PPM)", path.display());
+        let opts = screenshot::Options {
+            format: args.screenshot_format.unwrap_or_else(|| screenshot::Format::from_path(path)),
+            quality: args.screenshot_quality.unwrap_or(90),
+            full_page: args.full_page,
+            selector: args.screenshot_selector.clone(),
+            device_pixel_ratio: args.device_pixel_ratio,
+        };
+        let page = page.as_mut().context("page has nothing to capture")?;
+        let (w, h) = screenshot::save(page, path, &opts)?;
+        println!("Saved screenshot to {} ({:?}, {}x{})", path.display(), opts.format, w, h);
     }
 
     // GPU solid screenshot & hash (placeholder for Phase-1)
@@ -189,37 +256,6 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
-fn rasterize_dl_rgba8(width: u32, height: u32, dl: &DisplayList) -> Vec<u8> {
-    let mut buf = vec![0u8; (width * height * 4) as usize];
-    for cmd in &dl.items {
-        let DrawCmd::Rect { x, y, w, h, rgba } = *cmd;
-        let (a, r, g, b) = unpack_argb_u32(rgba);
-        for yy in y..y.saturating_add(h).min(height) {
-            for xx in x..x.saturating_add(w).min(width) {
-                let idx = ((yy * width + xx) * 4) as usize;
-                let sa = a as u32;
-                let da = 255u32 - sa;
-                buf[idx + 0] = ((r as u32 * sa + buf[idx + 0] as u32 * da) / 255) as u8;
-                buf[idx + 1] = ((g as u32 * sa + buf[idx + 1] as u32 * da) / 255) as u8;
-                buf[idx + 2] = ((b as u32 * sa + buf[idx + 2] as u32 * da) / 255) as u8;
-                buf[idx + 3] = 255u8;
-            }
-        }
-    }
-    buf
-}
-
-#[inline]
-fn unpack_argb_u32(v: u32) -> (u8, u8, u8, u8) {
-    // Phase-1 contract: 0xAARRGGBB
-    let a = ((v >> 24) & 0xFF) as u8;
-    let r = ((v >> 16) & 0xFF) as u8;
-    let g = ((v >> 8) & 0xFF) as u8;
-    let b = (v & 0xFF) as u8;
-    (a, r, g, b)
-}
-
-
 fn real_render_via_content_srv(url: &str, out: &PathBuf) -> Result<()> {
     let out_str = out.to_string_lossy().to_string();
     // Prefer local target builds first
@@ -242,17 +278,61 @@ fn real_render_via_content_srv(url: &str, out: &PathBuf) -> Result<()> {
 }
 
 
-fn write_ppm(path: &PathBuf, width: u32, height: u32, rgba: &[u8]) -> Result<()> {
-    let mut f = BufWriter::new(File::create(path)?);
-    // PPM (P6): header + raw RGB bytes; ignore alpha
-    writeln!(f, "P6\n{} {}\n255", width, height)?;
-    for y in 0..height {
-        for x in 0..width {
-            let idx = ((y * width + x) * 4) as usize;
-            f.write_all(&[rgba[idx], rgba[idx + 1], rgba[idx + 2]])?;
+const SUMMARY_TOKENS: usize = 16;
+
+/// An ai-runtime service this process started; stopped again when dropped.
+struct SpawnedService(std::process::Child);
+
+impl Drop for SpawnedService {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// Start an ai-runtime that supports `--serve` on `socket`.
+fn spawn_ai_runtime(socket: &std::path::Path) -> Result<SpawnedService> {
+    use std::path::Path;
+    // Prefer local target builds first
+    let candidates = ["target/debug/ai-runtime", "target/release/ai-runtime", "ai-runtime"];
+    for bin in &candidates {
+        if *bin != "ai-runtime" && !Path::new(bin).exists() {
+            continue;
+        }
+        // Probe for --serve support to avoid spawning an old binary on PATH
+        let supports_serve = match Command::new(bin).arg("--help").output() {
+            Ok(out) => String::from_utf8_lossy(&out.stdout).contains("--serve") || String::from_utf8_lossy(&out.stderr).contains("--serve"),
+            Err(_) => false,
+        };
+        if !supports_serve {
+            continue;
         }
+        let child = Command::new(bin).arg("--serve").arg(socket).stdout(std::process::Stdio::null()).spawn()?;
+        return Ok(SpawnedService(child));
     }
-    Ok(())
+    Err(anyhow!("no ai-runtime with --serve found; tried {:?}", candidates))
+}
+
+/// Ask the ai-runtime service for a summary. When the socket is not live a
+/// service is started for this request and stopped once it has answered.
+fn summarize_via_ai_runtime(socket: &std::path::Path, origin: &str, text: String) -> Result<String> {
+    use ai_runtime::service::{Client, Priority};
+    let mut spawned = None;
+    let mut client = match Client::connect(socket) {
+        Ok(c) => c,
+        Err(_) => {
+            let service = spawned.insert(spawn_ai_runtime(socket)?);
+            (0..50)
+                .find_map(|_| {
+                    // A service that exited (e.g. it could not bind) will never answer
+                    if service.0.try_wait().ok().flatten().is_some() { return Some(Err(anyhow!("ai-runtime exited before serving {}", socket.display()))); }
+                    Client::connect(socket).ok().map(Ok).or_else(|| { std::thread::sleep(std::time::Duration::from_millis(100)); None })
+                })
+                .unwrap_or_else(|| Err(anyhow!("ai-runtime did not come up on {}", socket.display())))?
+        }
+    };
+    let resp = client.ask(origin, Priority::UserVisible, message_defs::AiRequest { prompt: text, max_tokens: SUMMARY_TOKENS as u32 })?;
+    Ok(resp.text)
 }
 
 fn show_render_window_via_content_srv(url: &str) -> Result<()> {
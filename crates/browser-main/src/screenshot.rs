@@ -0,0 +1,189 @@
+//! `--screenshot` capture: the servo-lite page rasterized on the CPU, either
+//! the viewport, the whole document or one element, at a device pixel
+//! ratio, and encoded as PNG, JPEG or WebP. WebP is always lossless: the
+//! image crate has no lossy WebP encoder, so a quality is refused for it
+//! rather than silently ignored.
+
+use anyhow::{anyhow, bail, Result};
+use clap::ValueEnum;
+use image::codecs::{jpeg::JpegEncoder, png::PngEncoder, webp::WebPEncoder};
+use image::{ExtendedColorType, ImageEncoder};
+use servo_lite::Page;
+use std::path::Path;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    Png,
+    Jpeg,
+    /// Lossless; quality does not apply
+    Webp,
+}
+
+impl Format {
+    /// From the file extension, PNG when it is not recognised.
+    pub fn from_path(path: &Path) -> Format {
+        match path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase).as_deref() {
+            Some("jpg" | "jpeg") => Format::Jpeg,
+            Some("webp") => Format::Webp,
+            _ => Format::Png,
+        }
+    }
+
+    /// Whether `quality` can be honoured when encoding in this format.
+    pub fn check_quality(self, quality: Option<u8>) -> Result<()> {
+        match (self, quality) {
+            (Format::Webp, Some(_)) => bail!("--screenshot-quality does not apply to WebP screenshots: only lossless WebP encoding is available"),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub format: Format,
+    /// JPEG quality, 1-100.
+    pub quality: u8,
+    /// Capture the whole document rather than the viewport.
+    pub full_page: bool,
+    /// Capture only the first element matching this selector.
+    pub selector: Option<String>,
+    /// Device pixels per CSS pixel.
+    pub device_pixel_ratio: f32,
+}
+
+/// Tightly packed RGBA8 pixels.
+pub struct Shot {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+
+fn device(v: i32, dpr: f32) -> u32 { (v.max(0) as f32 * dpr).round() as u32 }
+
+/// Rasterize `page`. Full-page and element captures grow the viewport to the
+/// document height so nothing below the fold is clipped, and put it back
+/// afterwards. Captures larger than [`gpu_srv::cpu::MAX_DIMENSION`] device
+/// pixels on either side are refused.
+pub fn capture(page: &mut Page, opts: &Options) -> Result<Shot> {
+    let dpr = opts.device_pixel_ratio;
+    if !(dpr.is_finite() && dpr > 0.0 && dpr <= 8.0) { bail!("device pixel ratio must be in (0, 8], got {dpr}"); }
+    let viewport = page.viewport();
+    if !(opts.full_page || opts.selector.is_some()) { return capture_viewport(page, opts); }
+    page.set_viewport((viewport.0, (page.layout_root().rect.h.max(0) as u32).max(viewport.1)));
+    page.update();
+    let shot = capture_viewport(page, opts);
+    page.set_viewport(viewport);
+    page.update();
+    shot
+}
+
+fn capture_viewport(page: &Page, opts: &Options) -> Result<Shot> {
+    let dpr = opts.device_pixel_ratio;
+    let (w, h) = page.viewport();
+    let (x0, y0, x1, y1) = match &opts.selector {
+        Some(selector) => {
+            let r = element_rect(page, selector)?;
+            (r.x.max(0), r.y.max(0), r.right().min(w as i32), r.bottom().min(h as i32))
+        }
+        None => (0, 0, w as i32, h as i32),
+    };
+    if x1 <= x0 || y1 <= y0 { bail!("nothing to capture: the area is empty"); }
+
+    let (dw, dh) = (device(w.min(i32::MAX as u32) as i32, dpr), device(h.min(i32::MAX as u32) as i32, dpr));
+    let max = gpu_srv::cpu::MAX_DIMENSION;
+    if dw > max || dh > max { bail!("capture would be {dw}x{dh} device pixels; at most {max} per side is supported"); }
+    let frame = gpu_srv::cpu::rasterize_rgba8(dw, dh, &gpu_srv::cpu::scale_display_list(page.display_list(), dpr));
+    let (cx0, cy0, cx1, cy1) = (device(x0, dpr), device(y0, dpr), device(x1, dpr).min(dw), device(y1, dpr).min(dh));
+    let row_len = (cx1 - cx0) as usize * 4;
+    let mut rgba = Vec::with_capacity(row_len * (cy1 - cy0) as usize);
+    for y in cy0..cy1 {
+        let row = (y as usize * dw as usize + cx0 as usize) * 4;
+        rgba.extend_from_slice(&frame[row..row + row_len]);
+    }
+    Ok(Shot { width: cx1 - cx0, height: cy1 - cy0, rgba })
+}
+
+/// Border box of the first element matching `selector`, in document coordinates.
+fn element_rect(page: &Page, selector: &str) -> Result<servo_lite::layout::Rect> {
+    let doc = page.document();
+    let node = *doc.select(doc.root(), selector).first().ok_or_else(|| anyhow!("no element matches {selector:?}"))?;
+    let mut rect = None;
+    page.layout_root().walk(&mut |b| if b.node == node && rect.is_none() { rect = Some(b.rect) });
+    rect.ok_or_else(|| anyhow!("{selector:?} matches an element that is not rendered"))
+}
+
+pub fn encode(shot: &Shot, format: Format, quality: u8) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    match format {
+        Format::Png => PngEncoder::new(&mut out).write_image(&shot.rgba, shot.width, shot.height, ExtendedColorType::Rgba8)?,
+        Format::Jpeg => {
+            // JPEG has no alpha; captures are opaque anyway
+            let rgb: Vec<u8> = shot.rgba.chunks_exact(4).flat_map(|p| [p[0], p[1], p[2]]).collect();
+            JpegEncoder::new_with_quality(&mut out, quality.clamp(1, 100)).write_image(&rgb, shot.width, shot.height, ExtendedColorType::Rgb8)?
+        }
+        Format::Webp => WebPEncoder::new_lossless(&mut out).write_image(&shot.rgba, shot.width, shot.height, ExtendedColorType::Rgba8)?,
+    }
+    Ok(out)
+}
+
+/// Capture `page` into `path`; returns the image size in device pixels.
+pub fn save(page: &mut Page, path: &Path, opts: &Options) -> Result<(u32, u32)> {
+    let shot = capture(page, opts)?;
+    std::fs::write(path, encode(&shot, opts.format, opts.quality)?)?;
+    Ok((shot.width, shot.height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options() -> Options { Options { format: Format::Png, quality: 90, full_page: false, selector: None, device_pixel_ratio: 1.0 } }
+
+    #[test]
+    fn captures_viewport_full_page_element_and_scale() {
+        let html = format!("<p>top</p>{}<div id=\"last\" style=\"height: 30px\"></div>", "<p>filler</p>".repeat(40));
+        let mut page = Page::new(&html, (200, 150)).unwrap();
+        let shot = capture(&mut page, &options()).unwrap();
+        assert_eq!((shot.width, shot.height), (200, 150));
+
+        let full = capture(&mut Page::new(&html, (200, 150)).unwrap(), &Options { full_page: true, ..options() }).unwrap();
+        assert!(full.height > 150 && full.width == 200, "{}", full.height);
+
+        let el = capture(&mut page, &Options { selector: Some("#last".into()), device_pixel_ratio: 2.0, ..options() }).unwrap();
+        assert_eq!(el.height, 60);
+        assert_eq!(page.viewport(), (200, 150), "the caller's viewport is restored");
+        assert!(capture(&mut page, &Options { selector: Some("#nope".into()), ..options() }).is_err());
+        assert!(capture(&mut page, &Options { device_pixel_ratio: 0.0, ..options() }).is_err());
+
+        let hidpi = capture(&mut Page::new(&html, (200, 150)).unwrap(), &Options { device_pixel_ratio: 1.5, ..options() }).unwrap();
+        assert_eq!((hidpi.width, hidpi.height), (300, 225));
+
+        // A tall page at a high ratio is refused rather than overflowing
+        let mut tall = Page::new("<div style=\"height: 5000px\"></div>", (200, 150)).unwrap();
+        let Err(err) = capture(&mut tall, &Options { full_page: true, device_pixel_ratio: 2.0, ..options() }) else { panic!("capture should be refused") };
+        assert!(err.to_string().contains("at most"), "{err}");
+        assert_eq!(tall.viewport(), (200, 150));
+        assert!(capture(&mut tall, &Options { full_page: true, ..options() }).is_ok());
+    }
+
+    #[test]
+    fn encodes_each_format() {
+        let shot = Shot { width: 4, height: 3, rgba: [10, 200, 30, 255].repeat(12) };
+        let png = encode(&shot, Format::Png, 90).unwrap();
+        assert_eq!(image::load_from_memory(&png).unwrap().to_rgba8().into_raw(), shot.rgba);
+        let webp = encode(&shot, Format::Webp, 90).unwrap();
+        assert_eq!(image::load_from_memory(&webp).unwrap().to_rgba8().into_raw(), shot.rgba);
+        let jpeg = encode(&shot, Format::Jpeg, 95).unwrap();
+        assert_eq!(image::guess_format(&jpeg).unwrap(), image::ImageFormat::Jpeg);
+        assert_eq!(Format::from_path(Path::new("a/shot.JPG")), Format::Jpeg);
+        assert_eq!(Format::from_path(Path::new("shot")), Format::Png);
+    }
+
+    #[test]
+    fn quality_is_refused_for_lossless_webp() {
+        let err = Format::Webp.check_quality(Some(80)).unwrap_err();
+        assert!(err.to_string().contains("lossless"), "{err}");
+        assert!(Format::Webp.check_quality(None).is_ok());
+        assert!(Format::Jpeg.check_quality(Some(80)).is_ok());
+    }
+}
@@ -0,0 +1,296 @@
+//! Shadow DOM embedding: sanitized HTML plus scoped CSS mounted in a closed
+//! shadow root, for extension and AI UI shown inside third-party pages.
+//!
+//! Isolation works both ways. The page cannot select into the closed root,
+//! and the host resets every inherited property (`all: initial`) so page
+//! fonts and colours do not bleed in. Embedded styles live in the shadow root
+//! and cannot match page elements. The injected script talks to the page over
+//! a `MessageChannel`: its port is handed out in a [`READY_EVENT`] on the host,
+//! and messages follow [`BridgeMessage`].
+
+use crate::sanitize::{sanitize, Policy, Removed};
+use crate::DomError;
+use serde::{Deserialize, Serialize};
+
+/// Tag of the host element; custom so page type selectors do not match it.
+pub const HOST_TAG: &str = "monatize-embed";
+/// Event dispatched on the host once mounted; `detail` is `{ id, port }`.
+pub const READY_EVENT: &str = "monatize-embed-ready";
+/// Bridge messages larger than this are rejected.
+pub const MAX_MESSAGE_BYTES: usize = 64 * 1024;
+
+/// Inline host style: inline declarations beat page rules targeting the host.
+const HOST_RESET: &str = "all: initial; display: block; contain: content;";
+
+const BOOTSTRAP: &str = r#"(() => {
+  const id = $ID;
+  if (document.getElementById(id)) return;
+  const host = document.createElement($TAG);
+  host.id = id;
+  host.setAttribute('style', $HOST_STYLE);
+  const root = host.attachShadow({ mode: 'closed' });
+  root.innerHTML = '<style>' + $CSS + '<\/style>' + $HTML;
+  const channel = new MessageChannel();
+  const port = channel.port1;
+  root.addEventListener('click', (e) => {
+    const el = e.target instanceof Element ? e.target.closest('[data-action]') : null;
+    if (el) port.postMessage({ type: 'action', action: el.getAttribute('data-action') });
+  });
+  port.onmessage = (e) => {
+    const m = e.data;
+    if (!m || m.type !== 'text' || typeof m.slot !== 'string') return;
+    for (const el of root.querySelectorAll('[data-slot]')) {
+      if (el.getAttribute('data-slot') === m.slot) el.textContent = String(m.text);
+    }
+  };
+  (document.querySelector($MOUNT) || document.body || document.documentElement).appendChild(host);
+  host.dispatchEvent(new CustomEvent($READY, { bubbles: true, composed: true, detail: { id, port: channel.port2 } }));
+})();
+"#;
+
+/// What to embed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EmbedSpec {
+    /// Host element id: ASCII letters, digits, `-` and `_`, at most 64 chars.
+    pub id: String,
+    pub html: String,
+    /// Styles scoped to the shadow root; `:host` styles the host box.
+    pub css: String,
+    /// Extra inline declarations for the host (position, size), applied after the reset.
+    pub host_style: String,
+    /// Selector of the element to append the host to; defaults to `<body>`.
+    pub mount: Option<String>,
+}
+
+/// A sanitized, ready-to-inject embed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Embed {
+    pub id: String,
+    pub html: String,
+    pub css: String,
+    pub host_style: String,
+    pub mount: Option<String>,
+    /// Everything sanitization removed from the HTML and CSS.
+    pub removed: Vec<Removed>,
+}
+
+/// Default policy plus `<button>` and the `data-action`/`data-slot`
+/// attributes the message bridge understands.
+pub fn embed_policy() -> Policy {
+    Policy::default().allow_element("button").allow_attribute("*", "data-action").allow_attribute("*", "data-slot")
+}
+
+impl Embed {
+    pub fn new(spec: EmbedSpec, policy: &Policy) -> Result<Self, DomError> {
+        let valid = !spec.id.is_empty() && spec.id.len() <= 64 && spec.id.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+        if !valid { return Err(DomError::InvalidId(spec.id)); }
+        let clean = sanitize(&spec.html, policy);
+        let mut removed = clean.removed;
+        let css = scrub_css(&spec.css, policy, &mut removed);
+        let host_style = format!("{HOST_RESET} {}", scrub_css(&spec.host_style, policy, &mut removed)).trim_end().to_string();
+        Ok(Self { id: spec.id, html: clean.html, css, host_style, mount: spec.mount, removed })
+    }
+
+    /// Script that mounts the embed and opens the message bridge. Safe to run
+    /// more than once: an existing host with the same id is left alone.
+    pub fn injection_script(&self) -> String {
+        let value = |key: &str| match key {
+            "ID" => self.id.as_str(),
+            "TAG" => HOST_TAG,
+            "HOST_STYLE" => &self.host_style,
+            "CSS" => &self.css,
+            "HTML" => &self.html,
+            "MOUNT" => self.mount.as_deref().unwrap_or("body"),
+            "READY" => READY_EVENT,
+            _ => unreachable!("unknown bootstrap placeholder {key}"),
+        };
+        // Single pass, so substituted text is never scanned for placeholders again
+        let mut js = String::with_capacity(BOOTSTRAP.len() + self.html.len() + self.css.len());
+        let mut rest = BOOTSTRAP;
+        while let Some(i) = rest.find('$') {
+            js.push_str(&rest[..i]);
+            let key_len = rest[i + 1..].find(|c: char| !(c.is_ascii_uppercase() || c == '_')).unwrap_or(rest.len() - i - 1);
+            js.push_str(&js_string(value(&rest[i + 1..i + 1 + key_len])));
+            rest = &rest[i + 1 + key_len..];
+        }
+        js.push_str(rest);
+        js
+    }
+
+    /// Declarative shadow DOM markup for documents being generated rather
+    /// than patched. Static only: there is no script, so no message bridge.
+    pub fn declarative_html(&self) -> String {
+        format!(
+            "<{HOST_TAG} id=\"{}\" style=\"{}\"><template shadowrootmode=\"closed\"><style>{}</style>{}</template></{HOST_TAG}>",
+            self.id,
+            self.host_style.replace('&', "&amp;").replace('"', "&quot;"),
+            self.css,
+            self.html,
+        )
+    }
+}
+
+/// Messages on the bridge port, as JSON objects tagged by `type`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum BridgeMessage {
+    /// Embed to page: an element with `data-action` was clicked.
+    Action { action: String },
+    /// Page to embed: replace the text of every `data-slot` element named `slot`.
+    Text { slot: String, text: String },
+}
+
+impl BridgeMessage {
+    pub fn to_json(&self) -> String { serde_json::to_string(self).expect("bridge message serializes") }
+
+    pub fn from_json(s: &str) -> Result<Self, DomError> {
+        if s.len() > MAX_MESSAGE_BYTES { return Err(DomError::Message(format!("{} bytes exceeds limit", s.len()))); }
+        serde_json::from_str(s).map_err(|e| DomError::Message(e.to_string()))
+    }
+}
+
+/// JSON string literal, which is also a valid JS string literal; `<` is
+/// escaped so the text never closes a surrounding `<script>`.
+fn js_string(s: &str) -> String { serde_json::to_string(s).expect("string serializes").replace('<', "\\u003c") }
+
+/// Make embedded CSS safe to place in a `<style>`: drop comments and
+/// `@import`/`@charset`/`@namespace`, neutralize `url()`s with schemes the
+/// policy rejects and escape `<` so the text cannot close the element.
+fn scrub_css(css: &str, policy: &Policy, removed: &mut Vec<Removed>) -> String {
+    let mut out = String::with_capacity(css.len());
+    let mut rest = css;
+    while let Some(c) = rest.chars().next() {
+        if let Some(after) = rest.strip_prefix("/*") {
+            rest = after.find("*/").map(|i| &after[i + 2..]).unwrap_or("");
+        } else if c == '"' || c == '\'' {
+            let end = string_end(rest);
+            push_escaped(&mut out, &rest[..end]);
+            rest = &rest[end..];
+        } else if c == '@' {
+            let name: String = rest[1..].chars().take_while(|c| c.is_ascii_alphanumeric() || *c == '-').collect();
+            if ["import", "charset", "namespace"].contains(&name.to_ascii_lowercase().as_str()) {
+                removed.push(Removed::CssRule(format!("@{name}")));
+                rest = skip_statement(rest);
+            } else {
+                out.push('@');
+                rest = &rest[1..];
+            }
+        } else if rest.get(..4).is_some_and(|p| p.eq_ignore_ascii_case("url(")) {
+            let end = url_end(rest);
+            let inner = rest[4..end].trim_end_matches(')').trim();
+            let url = inner.trim_matches(|q| q == '"' || q == '\'');
+            if policy.allows_url(url) {
+                push_escaped(&mut out, &rest[..end]);
+            } else {
+                removed.push(Removed::Url { element: "style".into(), attribute: "url".into(), value: url.to_string() });
+                out.push_str("none");
+            }
+            rest = &rest[end..];
+        } else {
+            push_escaped(&mut out, &rest[..c.len_utf8()]);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    out
+}
+
+fn push_escaped(out: &mut String, s: &str) {
+    for c in s.chars() {
+        if c == '<' { out.push_str("\\3c "); } else { out.push(c); }
+    }
+}
+
+/// Byte length of the quoted string at the start of `s`, including quotes.
+fn string_end(s: &str) -> usize {
+    let quote = s.as_bytes()[0];
+    let mut i = 1;
+    while i < s.len() {
+        match s.as_bytes()[i] {
+            b'\\' => i += 2,
+            b if b == quote => return i + 1,
+            b'\n' => return i,
+            _ => i += 1,
+        }
+    }
+    s.len()
+}
+
+/// Byte length of a `url(...)` token, quoted or not.
+fn url_end(s: &str) -> usize {
+    let mut i = 4;
+    while i < s.len() {
+        match s.as_bytes()[i] {
+            b'"' | b'\'' => i += string_end(&s[i..]),
+            b')' => return i + 1,
+            _ => i += 1,
+        }
+    }
+    s.len()
+}
+
+/// Rest of the input after an at-rule statement ending in `;` (or its block).
+fn skip_statement(s: &str) -> &str {
+    let mut i = 0;
+    while i < s.len() {
+        match s.as_bytes()[i] {
+            b'"' | b'\'' => i += string_end(&s[i..]),
+            b';' => return &s[i + 1..],
+            b'{' => {
+                let close = s[i..].find('}').map(|j| i + j + 1).unwrap_or(s.len());
+                return &s[close..];
+            }
+            _ => i += 1,
+        }
+    }
+    ""
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn embed(html: &str, css: &str) -> Embed {
+        let spec = EmbedSpec { id: "ai-panel".into(), html: html.into(), css: css.into(), host_style: "position: fixed; right: 0".into(), mount: None };
+        Embed::new(spec, &embed_policy()).unwrap()
+    }
+
+    #[test]
+    fn sanitizes_content_and_css() {
+        let e = embed(
+            "<button data-action=summarize onclick=x()>Go</button><p data-slot=out>…</p><script>1</script>",
+            "@import url(https://evil/x.css); /* c */ p { color: red; background: url(javascript:alert(1)) } .x::after { content: '</style><script>' } i { background: url('https://cdn/i.png') }",
+        );
+        assert_eq!(e.html, "<button data-action=\"summarize\">Go</button><p data-slot=\"out\">…</p>");
+        assert!(!e.css.contains('<') && !e.css.contains("@import") && !e.css.contains("javascript"), "{}", e.css);
+        assert!(e.css.contains("url('https://cdn/i.png')") && e.css.contains("background: none"));
+        assert_eq!(e.host_style, "all: initial; display: block; contain: content; position: fixed; right: 0");
+        assert!(e.removed.contains(&Removed::CssRule("@import".into())));
+        assert!(e.removed.contains(&Removed::Attribute { element: "button".into(), name: "onclick".into() }));
+        let bad = EmbedSpec { id: "x\" onload=\"y".into(), ..Default::default() };
+        assert!(matches!(Embed::new(bad, &embed_policy()), Err(DomError::InvalidId(_))));
+    }
+
+    #[test]
+    fn injection_script_is_valid_js_with_closed_root() {
+        use boa_engine::{Context, Script, Source};
+        let e = embed("<p title=\"a'b\">$MOUNT x</p>", "p { content: \"\\\"$HTML\" }");
+        let js = e.injection_script();
+        assert!(js.contains("mode: 'closed'") && js.contains("\"ai-panel\"") && js.contains(READY_EVENT));
+        assert!(!js.contains("</"), "no raw closing tags in the script text");
+        assert!(js.contains("$MOUNT x") && js.contains("$HTML"), "placeholders in content are not substituted");
+        Script::parse(Source::from_bytes(&js), None, &mut Context::default()).expect("bootstrap parses");
+        let html = e.declarative_html();
+        assert!(html.starts_with("<monatize-embed id=\"ai-panel\" style=\"all: initial;"));
+        assert!(html.contains("<template shadowrootmode=\"closed\"><style>"));
+    }
+
+    #[test]
+    fn bridge_messages_round_trip() {
+        let m = BridgeMessage::Text { slot: "out".into(), text: "Done".into() };
+        assert_eq!(m.to_json(), r#"{"type":"text","slot":"out","text":"Done"}"#);
+        assert_eq!(BridgeMessage::from_json(&m.to_json()).unwrap(), m);
+        assert_eq!(BridgeMessage::from_json(r#"{"type":"action","action":"go"}"#).unwrap(), BridgeMessage::Action { action: "go".into() });
+        assert!(BridgeMessage::from_json(r#"{"type":"eval","code":"x"}"#).is_err());
+        assert!(BridgeMessage::from_json(&" ".repeat(MAX_MESSAGE_BYTES + 1)).is_err());
+    }
+}
@@ -102,6 +102,73 @@ pub fn read_len_prefixed<R: std::io::Read>(mut r: R) -> std::io::Result<Message>
         .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
 }
 
+/// `bincode::serialize`'s encoding, refusing to produce or accept more than `limit` bytes.
+#[cfg(all(feature = "serde", feature = "bincode"))]
+fn codec(limit: usize) -> impl bincode::Options {
+    use bincode::Options;
+    bincode::options().with_fixint_encoding().allow_trailing_bytes().with_limit(limit as u64)
+}
+
+/// Write any message as one length-prefixed (u32 LE) bincode frame, the same
+/// encoding as [`write_len_prefixed`]. Bodies over `max` bytes are refused.
+#[cfg(all(feature = "serde", feature = "bincode"))]
+pub fn write_frame<T: serde::Serialize>(mut w: impl std::io::Write, msg: &T, max: usize) -> std::io::Result<()> {
+    use bincode::Options;
+    let body = codec(max).serialize(msg).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    w.write_all(&(body.len() as u32).to_le_bytes())?;
+    w.write_all(&body)?;
+    w.flush()
+}
+
+/// Read one frame written by [`write_frame`]. A length over `max` is rejected
+/// before anything is allocated, and the body cannot decode past its length.
+#[cfg(all(feature = "serde", feature = "bincode"))]
+pub fn read_frame<T: serde::de::DeserializeOwned>(mut r: impl std::io::Read, max: usize) -> std::io::Result<T> {
+    use bincode::Options;
+    let mut len = [0u8; 4];
+    r.read_exact(&mut len)?;
+    let len = u32::from_le_bytes(len) as usize;
+    if len > max { return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "frame too large")); }
+    let mut body = vec![0u8; len];
+    r.read_exact(&mut body)?;
+    codec(len).deserialize(&body).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+/// Cap on connections a socket server handles at once, shared between its
+/// accept loop and the threads serving each connection.
+#[derive(Debug)]
+pub struct ConnectionLimit {
+    max: usize,
+    open: std::sync::Mutex<usize>,
+    closed: std::sync::Condvar,
+}
+
+/// A connection's claim on a [`ConnectionLimit`]; the slot frees when dropped.
+#[derive(Debug)]
+pub struct ConnectionSlot(std::sync::Arc<ConnectionLimit>);
+
+impl ConnectionLimit {
+    /// Allow `max` connections at once (at least one).
+    pub fn new(max: usize) -> std::sync::Arc<Self> {
+        std::sync::Arc::new(Self { max: max.max(1), open: std::sync::Mutex::new(0), closed: std::sync::Condvar::new() })
+    }
+
+    /// Wait until fewer than the maximum are open, then claim a slot.
+    pub fn acquire(self: &std::sync::Arc<Self>) -> ConnectionSlot {
+        let mut n = self.open.lock().unwrap_or_else(|e| e.into_inner());
+        while *n >= self.max { n = self.closed.wait(n).unwrap_or_else(|e| e.into_inner()); }
+        *n += 1;
+        ConnectionSlot(self.clone())
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        *self.0.open.lock().unwrap_or_else(|e| e.into_inner()) -= 1;
+        self.0.closed.notify_one();
+    }
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -157,6 +224,33 @@ mod tests {
         assert_eq!(m1, d1);
         assert_eq!(m2, d2);
     }
-}
 
+    #[cfg(all(feature = "serde", feature = "bincode"))]
+    #[test]
+    fn frames_share_the_message_encoding_and_are_bounded() {
+        let m = Message::Event(InputEvent::Resize { w: 3, h: 4 });
+        let mut buf = Vec::new();
+        super::write_frame(&mut buf, &m, 64).unwrap();
+        assert_eq!(super::read_len_prefixed(&buf[..]).unwrap(), m);
+        assert_eq!(super::read_frame::<Message>(&buf[..], 64).unwrap(), m);
+        assert_eq!(super::read_frame::<Message>(&buf[..], 4).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(super::write_frame(std::io::sink(), &m, 4).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        // A short frame cannot claim a longer body inside it
+        let mut lying = buf.clone();
+        lying[..4].copy_from_slice(&2u32.to_le_bytes());
+        assert!(super::read_frame::<Message>(&lying[..], 64).is_err());
+    }
 
+    #[test]
+    fn connection_slots_wait_for_a_free_one() {
+        use std::sync::mpsc;
+        let limit = ConnectionLimit::new(1);
+        let first = limit.acquire();
+        let (tx, rx) = mpsc::channel();
+        let waiter = limit.clone();
+        std::thread::spawn(move || tx.send(waiter.acquire()).unwrap());
+        assert!(rx.recv_timeout(std::time::Duration::from_millis(100)).is_err());
+        drop(first);
+        assert!(rx.recv_timeout(std::time::Duration::from_secs(5)).is_ok());
+    }
+}
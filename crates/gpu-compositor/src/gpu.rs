@@ -0,0 +1,202 @@
+//! wgpu path: one texture per surface, uploaded when its contents change, and
+//! an offscreen output that keeps the previous frame so only the damaged
+//! rect is cleared and redrawn.
+
+use crate::layer::{Placed, Rect};
+use crate::TabId;
+use gpu_srv::backend::{BackendInfo, GpuError};
+use gpu_srv::raster::upload;
+use std::collections::HashMap;
+use wgpu::util::DeviceExt;
+
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+/// Screen position (2), layer origin (2) and opacity (1) floats.
+const VERTEX_BYTES: usize = 5 * 4;
+
+const SHADER: &str = r#"
+struct VsIn {
+    @location(0) pos: vec2<f32>,
+    @location(1) origin: vec2<f32>,
+    @location(2) opacity: f32,
+};
+
+struct VsOut {
+    @builtin(position) pos: vec4<f32>,
+    @location(0) @interpolate(flat) origin: vec2<f32>,
+    @location(1) @interpolate(flat) opacity: f32,
+};
+
+@group(0) @binding(0) var image: texture_2d<f32>;
+
+@vertex
+fn vs_main(v: VsIn) -> VsOut {
+    return VsOut(vec4<f32>(v.pos, 0.0, 1.0), v.origin, v.opacity);
+}
+
+@fragment
+fn fs_main(v: VsOut) -> @location(0) vec4<f32> {
+    let size = vec2<i32>(textureDimensions(image));
+    let t = clamp(vec2<i32>(floor(v.pos.xy)) - vec2<i32>(v.origin), vec2<i32>(0), size - vec2<i32>(1));
+    let c = textureLoad(image, t, 0);
+    return vec4<f32>(c.rgb, c.a * v.opacity);
+}
+"#;
+
+pub(crate) struct GpuTarget {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pub info: BackendInfo,
+    pipeline: wgpu::RenderPipeline,
+    layout: wgpu::BindGroupLayout,
+    /// Opaque black, drawn over the damaged rect before the layers.
+    black: wgpu::BindGroup,
+    output: wgpu::Texture,
+    /// Uploaded surface contents and the version they hold.
+    textures: HashMap<TabId, (Option<u64>, wgpu::BindGroup)>,
+}
+
+impl GpuTarget {
+    pub fn new(device: wgpu::Device, queue: wgpu::Queue, info: BackendInfo, size: (u32, u32)) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("surface"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture { sample_type: wgpu::TextureSampleType::Float { filterable: false }, view_dimension: wgpu::TextureViewDimension::D2, multisampled: false },
+                count: None,
+            }],
+        });
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor { label: Some("compositor"), source: wgpu::ShaderSource::Wgsl(SHADER.into()) });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor { label: Some("compositor"), bind_group_layouts: &[&layout], push_constant_ranges: &[] });
+        let attributes = wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Float32];
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("compositor"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[wgpu::VertexBufferLayout { array_stride: VERTEX_BYTES as u64, step_mode: wgpu::VertexStepMode::Vertex, attributes: &attributes }],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: Some("fs_main"),
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: FORMAT,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+        let black = upload(&device, &queue, &layout, (1, 1), &[0, 0, 0, 255]);
+        let output = Self::output_texture(&device, size);
+        Self { device, queue, info, pipeline, layout, black, output, textures: HashMap::new() }
+    }
+
+    fn output_texture(device: &wgpu::Device, (width, height): (u32, u32)) -> wgpu::Texture {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("composite"),
+            size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        })
+    }
+
+    pub fn max_size(&self) -> u32 { self.device.limits().max_texture_dimension_2d }
+
+    pub fn resize(&mut self, size: (u32, u32)) { self.output = Self::output_texture(&self.device, size); }
+
+    pub fn forget(&mut self, tab: TabId) { self.textures.remove(&tab); }
+
+    /// Redraw `damage` from `layers` (bottom first), each with its pixels and size.
+    pub fn draw(&mut self, damage: Rect, layers: &[(Placed, &[u8], (u32, u32))]) {
+        for (p, pixels, size) in layers {
+            if self.textures.get(&p.tab).is_none_or(|(v, _)| *v != p.version) {
+                let bg = upload(&self.device, &self.queue, &self.layout, *size, pixels);
+                self.textures.insert(p.tab, (p.version, bg));
+            }
+        }
+        let (w, h) = (self.output.width() as f32, self.output.height() as f32);
+        let mut data = Vec::new();
+        let mut quad = |r: Rect, origin: (i32, i32), opacity: f32| {
+            let (x0, y0, x1, y1) = (r.x as f32, r.y as f32, r.right() as f32, r.bottom() as f32);
+            for (x, y) in [(x0, y0), (x1, y0), (x0, y1), (x0, y1), (x1, y0), (x1, y1)] {
+                for v in [x / w * 2.0 - 1.0, 1.0 - y / h * 2.0, origin.0 as f32, origin.1 as f32, opacity] { data.extend_from_slice(&v.to_le_bytes()); }
+            }
+        };
+        quad(damage, (damage.x, damage.y), 1.0);
+        let mut draws = Vec::new();
+        for (p, _, _) in layers {
+            if let Some(r) = p.bounds.intersect(&damage) {
+                quad(r, p.origin, p.opacity);
+                draws.push(p.tab);
+            }
+        }
+        let vertices = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor { label: Some("compositor"), contents: &data, usage: wgpu::BufferUsages::VERTEX });
+        let view = self.output.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("compositor") });
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("composite"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    depth_slice: None,
+                    ops: wgpu::Operations { load: wgpu::LoadOp::Load, store: wgpu::StoreOp::Store },
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_vertex_buffer(0, vertices.slice(..));
+            pass.set_bind_group(0, &self.black, &[]);
+            pass.draw(0..6, 0..1);
+            for (i, tab) in draws.iter().enumerate() {
+                pass.set_bind_group(0, &self.textures[tab].1, &[]);
+                pass.draw((i as u32 + 1) * 6..(i as u32 + 2) * 6, 0..1);
+            }
+        }
+        self.queue.submit(std::iter::once(encoder.finish()));
+    }
+
+    /// Tightly packed RGBA8 copy of the output.
+    pub fn read(&self) -> Result<Vec<u8>, GpuError> {
+        let (width, height) = (self.output.width(), self.output.height());
+        let row = width * 4;
+        let padded = row.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT) * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let buf = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("readback"),
+            size: (padded * height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("readback") });
+        encoder.copy_texture_to_buffer(
+            self.output.as_image_copy(),
+            wgpu::TexelCopyBufferInfo { buffer: &buf, layout: wgpu::TexelCopyBufferLayout { offset: 0, bytes_per_row: Some(padded), rows_per_image: Some(height) } },
+            wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+        );
+        self.queue.submit(std::iter::once(encoder.finish()));
+        let slice = buf.slice(..);
+        let (tx, rx) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |r| { let _ = tx.send(r); });
+        self.device.poll(wgpu::PollType::Wait { submission_index: None, timeout: None }).map_err(|e| GpuError::Readback(e.to_string()))?;
+        rx.recv().map_err(|e| GpuError::Readback(e.to_string()))?.map_err(|e| GpuError::Readback(e.to_string()))?;
+        let data = slice.get_mapped_range();
+        let mut out = Vec::with_capacity((row * height) as usize);
+        for y in 0..height as usize { out.extend_from_slice(&data[y * padded as usize..][..row as usize]); }
+        Ok(out)
+    }
+}
@@ -0,0 +1,226 @@
+//! Layer tree resolution and damage. Every surface has one layer; layers nest
+//! through `parent`, children paint above their parent, and siblings paint in
+//! `z` order (ties by tab id). Position and clip are relative to the parent,
+//! opacity multiplies down the tree.
+
+use crate::TabId;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use tab_manager::Visibility;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub w: u32,
+    pub h: u32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, w: u32, h: u32) -> Self { Self { x, y, w, h } }
+    pub fn right(&self) -> i32 { self.x + self.w as i32 }
+    pub fn bottom(&self) -> i32 { self.y + self.h as i32 }
+    pub fn is_empty(&self) -> bool { self.w == 0 || self.h == 0 }
+    pub fn offset(&self, dx: i32, dy: i32) -> Self { Self { x: self.x + dx, y: self.y + dy, ..*self } }
+
+    pub fn intersect(&self, o: &Rect) -> Option<Rect> {
+        let (x0, y0) = (self.x.max(o.x), self.y.max(o.y));
+        let (x1, y1) = (self.right().min(o.right()), self.bottom().min(o.bottom()));
+        (x0 < x1 && y0 < y1).then(|| Rect::new(x0, y0, (x1 - x0) as u32, (y1 - y0) as u32))
+    }
+
+    /// Parts of `self` not covered by `o`, as up to four disjoint rects.
+    pub fn subtract(&self, o: &Rect) -> Vec<Rect> {
+        let Some(i) = self.intersect(o) else { return vec![*self] };
+        let pieces = [
+            Rect::new(self.x, self.y, self.w, (i.y - self.y) as u32),
+            Rect::new(self.x, i.bottom(), self.w, (self.bottom() - i.bottom()) as u32),
+            Rect::new(self.x, i.y, (i.x - self.x) as u32, i.h),
+            Rect::new(i.right(), i.y, (self.right() - i.right()) as u32, i.h),
+        ];
+        pieces.into_iter().filter(|r| !r.is_empty()).collect()
+    }
+
+    /// Bounding box of both.
+    pub fn union(&self, o: &Rect) -> Rect {
+        if self.is_empty() { return *o; }
+        if o.is_empty() { return *self; }
+        let (x0, y0) = (self.x.min(o.x), self.y.min(o.y));
+        Rect::new(x0, y0, (self.right().max(o.right()) - x0) as u32, (self.bottom().max(o.bottom()) - y0) as u32)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Layer {
+    /// Nest under another tab's layer; a missing parent makes this a root.
+    pub parent: Option<TabId>,
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+    pub opacity: f32,
+    /// Clip for this layer and its children, in this layer's coordinates.
+    pub clip: Option<Rect>,
+    pub visible: bool,
+}
+
+impl Default for Layer {
+    fn default() -> Self { Self { parent: None, x: 0, y: 0, z: 0, opacity: 1.0, clip: None, visible: true } }
+}
+
+/// Input to [`resolve`]: a layer, its surface size and content version.
+pub(crate) struct Node {
+    pub layer: Layer,
+    pub size: (u32, u32),
+    /// `None` while the surface has no contents; such layers are not drawn.
+    pub version: Option<u64>,
+    /// Contents have no transparent pixels.
+    pub opaque: bool,
+}
+
+/// A layer as drawn: screen origin, visible screen rect and effective opacity.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Placed {
+    pub tab: TabId,
+    pub origin: (i32, i32),
+    pub bounds: Rect,
+    pub opacity: f32,
+    pub version: Option<u64>,
+}
+
+/// On-screen layers, bottom first, including those without contents yet.
+/// Layers in a parent cycle are never reached from a root and are not drawn.
+pub(crate) fn resolve(nodes: &HashMap<TabId, Node>, screen: Rect) -> Vec<Placed> {
+    let mut kids: HashMap<Option<TabId>, Vec<TabId>> = HashMap::new();
+    for (&tab, n) in nodes {
+        let parent = n.layer.parent.filter(|p| *p != tab && nodes.contains_key(p));
+        kids.entry(parent).or_default().push(tab);
+    }
+    for list in kids.values_mut() { list.sort_by_key(|t| (nodes[t].layer.z, *t)); }
+
+    let mut out = Vec::new();
+    let mut seen = HashSet::new();
+    // (tab, parent origin, parent clip, parent opacity); reversed so the lowest pops first
+    let mut stack: Vec<(TabId, (i32, i32), Rect, f32)> = kids.get(&None).into_iter().flatten().rev().map(|&t| (t, (0, 0), screen, 1.0)).collect();
+    while let Some((tab, (px, py), clip, opacity)) = stack.pop() {
+        if !seen.insert(tab) { continue; }
+        let n = &nodes[&tab];
+        if !n.layer.visible { continue; }
+        let origin = (px + n.layer.x, py + n.layer.y);
+        let clip = match n.layer.clip {
+            Some(c) => c.offset(origin.0, origin.1).intersect(&clip).unwrap_or_default(),
+            None => clip,
+        };
+        let opacity = opacity * n.layer.opacity.clamp(0.0, 1.0);
+        if let Some(bounds) = Rect::new(origin.0, origin.1, n.size.0, n.size.1).intersect(&clip) {
+            if opacity > 0.0 { out.push(Placed { tab, origin, bounds, opacity, version: n.version }); }
+        }
+        for &k in kids.get(&Some(tab)).into_iter().flatten().rev() { stack.push((k, origin, clip, opacity)); }
+    }
+    out
+}
+
+/// Screen area that differs between two resolved frames: layers that were
+/// added, removed or changed, and overlaps whose paint order flipped.
+pub(crate) fn damage(old: &[Placed], new: &[Placed]) -> Option<Rect> {
+    let index = |list: &[Placed]| list.iter().enumerate().map(|(i, p)| (p.tab, (i, *p))).collect::<HashMap<_, _>>();
+    let (before, after) = (index(old), index(new));
+    let mut area: Option<Rect> = None;
+    let mut add = |r: Rect| area = Some(area.map_or(r, |a| a.union(&r)));
+    for (tab, (_, p)) in &before {
+        match after.get(tab) {
+            Some((_, q)) if q == p => {}
+            Some((_, q)) => { add(p.bounds); add(q.bounds); }
+            None => add(p.bounds),
+        }
+    }
+    for (tab, (_, q)) in &after {
+        if !before.contains_key(tab) { add(q.bounds); }
+    }
+    for (a, (ia, pa)) in &after {
+        for (b, (ib, pb)) in &after {
+            let (Some((ja, _)), Some((jb, _))) = (before.get(a), before.get(b)) else { continue };
+            if ia < ib && ja > jb {
+                if let Some(r) = pa.bounds.intersect(&pb.bounds) { add(r); }
+            }
+        }
+    }
+    area
+}
+
+/// Visibility of every layer in `nodes`: hidden unless placed on screen, and
+/// occluded when opaque layers above cover all of it.
+pub(crate) fn occlusion(nodes: &HashMap<TabId, Node>, placed: &[Placed]) -> BTreeMap<TabId, Visibility> {
+    let mut out: BTreeMap<TabId, Visibility> = nodes.keys().map(|&t| (t, Visibility::Hidden)).collect();
+    for (i, p) in placed.iter().enumerate() {
+        let mut uncovered = vec![p.bounds];
+        for q in &placed[i + 1..] {
+            if q.opacity < 1.0 || q.version.is_none() || !nodes[&q.tab].opaque { continue; }
+            uncovered = uncovered.iter().flat_map(|r| r.subtract(&q.bounds)).collect();
+            if uncovered.is_empty() { break; }
+        }
+        out.insert(p.tab, if uncovered.is_empty() { Visibility::Occluded } else { Visibility::Visible });
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(layer: Layer, size: (u32, u32)) -> Node { Node { layer, size, version: Some(1), opaque: true } }
+
+    #[test]
+    fn children_inherit_position_clip_and_opacity() {
+        let screen = Rect::new(0, 0, 100, 100);
+        let mut nodes = HashMap::new();
+        nodes.insert(1, node(Layer { x: 10, y: 10, opacity: 0.5, clip: Some(Rect::new(0, 0, 30, 30)), ..Default::default() }, (50, 50)));
+        nodes.insert(2, node(Layer { parent: Some(1), x: 20, y: 20, z: 1, ..Default::default() }, (40, 40)));
+        nodes.insert(3, node(Layer { z: -1, ..Default::default() }, (5, 5)));
+        nodes.insert(4, node(Layer { parent: Some(1), z: -5, visible: false, ..Default::default() }, (5, 5)));
+        let placed = resolve(&nodes, screen);
+        assert_eq!(placed.iter().map(|p| p.tab).collect::<Vec<_>>(), [3, 1, 2]);
+        assert_eq!(placed[1].bounds, Rect::new(10, 10, 30, 30));
+        assert_eq!((placed[2].origin, placed[2].bounds, placed[2].opacity), ((30, 30), Rect::new(30, 30, 10, 10), 0.5));
+
+        // A parent cycle is unreachable and not drawn
+        nodes.insert(5, node(Layer { parent: Some(6), ..Default::default() }, (5, 5)));
+        nodes.insert(6, node(Layer { parent: Some(5), ..Default::default() }, (5, 5)));
+        assert_eq!(resolve(&nodes, screen).len(), 3);
+    }
+
+    #[test]
+    fn damage_covers_changes_and_reordered_overlaps() {
+        let p = |tab, x, version| Placed { tab, origin: (x, 0), bounds: Rect::new(x, 0, 10, 10), opacity: 1.0, version: Some(version) };
+        let old = [p(1, 0, 1), p(2, 5, 1), p(3, 50, 1)];
+        assert_eq!(damage(&old, &old), None);
+        assert_eq!(damage(&old, &[p(1, 0, 1), p(2, 5, 1), p(3, 50, 2)]), Some(Rect::new(50, 0, 10, 10)));
+        assert_eq!(damage(&old, &[p(1, 0, 1), p(2, 5, 1), p(3, 60, 1)]), Some(Rect::new(50, 0, 20, 10)));
+        assert_eq!(damage(&old, &[p(1, 0, 1), p(3, 50, 1)]), Some(Rect::new(5, 0, 10, 10)));
+        // Swapping 1 and 2 only changes where they overlap
+        assert_eq!(damage(&old, &[p(2, 5, 1), p(1, 0, 1), p(3, 50, 1)]), Some(Rect::new(5, 0, 5, 10)));
+    }
+
+    #[test]
+    fn occlusion_needs_full_opaque_cover() {
+        assert_eq!(Rect::new(0, 0, 10, 10).subtract(&Rect::new(2, 2, 3, 3)).iter().map(|r| r.w * r.h).sum::<u32>(), 91);
+        assert_eq!(Rect::new(0, 0, 10, 10).subtract(&Rect::new(-5, -5, 20, 20)), vec![]);
+
+        let screen = Rect::new(0, 0, 100, 100);
+        let mut nodes = HashMap::new();
+        nodes.insert(1, node(Layer::default(), (50, 50)));
+        // Two halves together cover tab 1
+        nodes.insert(2, node(Layer { z: 1, ..Default::default() }, (25, 60)));
+        nodes.insert(3, node(Layer { x: 25, z: 1, ..Default::default() }, (30, 50)));
+        nodes.insert(4, node(Layer { x: 200, ..Default::default() }, (5, 5)));
+        nodes.insert(5, node(Layer { visible: false, ..Default::default() }, (5, 5)));
+        let vis = |nodes: &HashMap<TabId, Node>| occlusion(nodes, &resolve(nodes, screen));
+        assert_eq!(vis(&nodes).into_iter().collect::<Vec<_>>(), [
+            (1, Visibility::Occluded), (2, Visibility::Visible), (3, Visibility::Visible), (4, Visibility::Hidden), (5, Visibility::Hidden),
+        ]);
+        // Translucent or transparent-content covers don't occlude
+        nodes.get_mut(&3).unwrap().layer.opacity = 0.9;
+        assert_eq!(vis(&nodes)[&1], Visibility::Visible);
+        nodes.get_mut(&3).unwrap().layer.opacity = 1.0;
+        nodes.get_mut(&2).unwrap().opaque = false;
+        assert_eq!(vis(&nodes)[&1], Visibility::Visible);
+    }
+}
@@ -1,3 +1,26 @@
+//! Composites per-tab surfaces into one offscreen frame. Each surface carries a
+//! [`Layer`] (position, z-order, opacity, clip, parent); a frame redraws only
+//! the rect damaged since the previous one and only the layers touching it.
+//! Rendering uses wgpu through gpu-srv's backend selection and falls back to
+//! compositing on the CPU when no adapter is usable.
+//!
+//! Each frame also works out which surfaces can be seen. Occluded layers are
+//! skipped, and visibility changes are passed on to tab-manager so that
+//! background tabs are throttled and eventually frozen.
+
+mod gpu;
+pub mod layer;
+pub mod schedule;
+
+use gpu::GpuTarget;
+use gpu_srv::backend::{open_device, BackendInfo, GpuError, Preference};
+pub use layer::{Layer, Rect};
+use layer::{Node, Placed};
+use schedule::BeginFrame;
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+use tab_manager::{TabManager, Visibility};
+
 pub type TabId = u64;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -13,30 +36,261 @@ impl SurfaceHandle {
     }
 }
 
-#[derive(Default)]
+/// What the last [`GpuCompositor::render_frame`] did.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameStats {
+    /// Rect that was redrawn; `None` when nothing changed.
+    pub damage: Option<Rect>,
+    /// Layers drawn into the damaged rect.
+    pub drawn: usize,
+    /// Layers visible in the frame.
+    pub layers: usize,
+    /// Frames handed to [`GpuCompositor::present`] that did not match their
+    /// surface's size and were skipped.
+    pub rejected: usize,
+}
+
+struct Surface {
+    handle: SurfaceHandle,
+    layer: Layer,
+    /// RGBA8, `width * height * 4` bytes.
+    pixels: Option<Vec<u8>>,
+    /// Every pixel of `pixels` has alpha 255.
+    opaque: bool,
+    version: u64,
+}
+
+enum Target {
+    Gpu(Box<GpuTarget>),
+    Cpu(BackendInfo, Vec<u8>),
+}
+
+pub const DEFAULT_OUTPUT_SIZE: (u32, u32) = (800, 600);
+
 pub struct GpuCompositor {
-    surfaces: std::collections::HashMap<TabId, SurfaceHandle>,
+    surfaces: HashMap<TabId, Surface>,
     frame_seq: u64,
+    size: (u32, u32),
+    /// `None` means [`Preference::from_env`].
+    pref: Option<Preference>,
+    /// Opened on the first frame.
+    target: Option<Target>,
+    placed: Vec<Placed>,
+    /// Redraw everything next frame (new or resized output).
+    full: bool,
+    stats: FrameStats,
+    next_version: u64,
+    visibility: BTreeMap<TabId, Visibility>,
+    /// Visibility changes not yet taken by [`GpuCompositor::take_visibility_changes`].
+    changes: Vec<(TabId, Visibility)>,
+}
+
+impl Default for GpuCompositor {
+    fn default() -> Self {
+        Self { surfaces: HashMap::new(), frame_seq: 0, size: DEFAULT_OUTPUT_SIZE, pref: None, target: None, placed: Vec::new(), full: true, stats: FrameStats::default(), next_version: 0, visibility: BTreeMap::new(), changes: Vec::new() }
+    }
 }
 
 impl GpuCompositor {
     pub fn new() -> Self { Self::default() }
 
+    /// Composite with `pref` instead of the backend from the environment.
+    pub fn with_preference(mut self, pref: Preference) -> Self {
+        self.pref = Some(pref);
+        self.target = None;
+        self.full = true;
+        self
+    }
+
+    pub fn output_size(&self) -> (u32, u32) { self.size }
+
+    /// Largest output width or height the current target can hold; before
+    /// the first frame, the CPU limit.
+    fn max_size(&self) -> u32 {
+        match &self.target {
+            Some(Target::Gpu(g)) => g.max_size(),
+            _ => gpu_srv::cpu::MAX_DIMENSION,
+        }
+    }
+
+    /// Resize the output; the next frame is redrawn in full. Sizes beyond the
+    /// texture limit are refused and leave the output as it was.
+    pub fn resize(&mut self, width: u32, height: u32) -> anyhow::Result<()> {
+        let size = (width.max(1), height.max(1));
+        if size == self.size { return Ok(()); }
+        let max = self.max_size();
+        anyhow::ensure!(size.0.max(size.1) <= max, "output {}x{} exceeds the {max}px texture limit", size.0, size.1);
+        self.size = size;
+        match &mut self.target {
+            Some(Target::Gpu(g)) => g.resize(size),
+            Some(Target::Cpu(_, out)) => *out = vec![0; rgba_len(size)],
+            None => {}
+        }
+        self.full = true;
+        Ok(())
+    }
+
+    fn bump(&mut self) -> u64 {
+        self.next_version += 1;
+        self.next_version
+    }
+
+    /// Add or replace `tab`'s surface. Its layer is kept; contents are dropped
+    /// if the size changed.
     pub fn add_surface(&mut self, tab: TabId, handle: SurfaceHandle) {
-        self.surfaces.insert(tab, handle);
+        let version = self.bump();
+        let s = self.surfaces.entry(tab).or_insert(Surface { handle, layer: Layer::default(), pixels: None, opaque: false, version });
+        if (s.handle.width, s.handle.height) != (handle.width, handle.height) { s.pixels = None; }
+        s.handle = handle;
+        s.version = version;
     }
 
     pub fn remove_surface(&mut self, tab: TabId) -> Option<SurfaceHandle> {
-        self.surfaces.remove(&tab)
+        if let Some(Target::Gpu(g)) = &mut self.target { g.forget(tab); }
+        self.visibility.remove(&tab);
+        self.surfaces.remove(&tab).map(|s| s.handle)
     }
 
-    /// Render one frame (stub). Returns number of active surfaces.
+    /// Replace `tab`'s contents with RGBA8 `pixels` of the surface's size.
+    pub fn update_surface(&mut self, tab: TabId, pixels: Vec<u8>) -> anyhow::Result<()> {
+        let version = self.bump();
+        let s = self.surfaces.get_mut(&tab).ok_or_else(|| anyhow::anyhow!("no surface for tab {tab}"))?;
+        let want = rgba_len((s.handle.width, s.handle.height));
+        anyhow::ensure!(pixels.len() == want, "surface {} is {}x{}: expected {want} bytes, got {}", s.handle.id, s.handle.width, s.handle.height, pixels.len());
+        s.opaque = pixels.chunks_exact(4).all(|p| p[3] == 255);
+        s.pixels = Some(pixels);
+        s.version = version;
+        Ok(())
+    }
+
+    pub fn layer(&self, tab: TabId) -> Option<&Layer> { self.surfaces.get(&tab).map(|s| &s.layer) }
+
+    /// Set `tab`'s layer properties; `false` if it has no surface.
+    pub fn set_layer(&mut self, tab: TabId, layer: Layer) -> bool {
+        match self.surfaces.get_mut(&tab) {
+            Some(s) => { s.layer = layer; true }
+            None => false,
+        }
+    }
+
+    fn open_target(&self) -> anyhow::Result<Target> {
+        let pref = match self.pref { Some(p) => p, None => Preference::from_env()? };
+        Ok(match open_device(pref) {
+            Ok((device, queue, info)) => {
+                let max = device.limits().max_texture_dimension_2d;
+                anyhow::ensure!(self.size.0.max(self.size.1) <= max, "output {}x{} exceeds the {max}px texture limit", self.size.0, self.size.1);
+                Target::Gpu(Box::new(GpuTarget::new(device, queue, info, self.size)))
+            }
+            Err(GpuError::NoAdapter(why)) => Target::Cpu(BackendInfo::cpu(vec![why]), vec![0; rgba_len(self.size)]),
+            Err(e) => return Err(e.into()),
+        })
+    }
+
+    /// Render one frame. Returns number of active surfaces.
     pub fn render_frame(&mut self) -> anyhow::Result<usize> {
+        if self.target.is_none() { self.target = Some(self.open_target()?); }
+        let nodes = self.surfaces.iter().map(|(&t, s)| (t, Node { layer: s.layer, size: (s.handle.width, s.handle.height), version: s.pixels.as_ref().map(|_| s.version), opaque: s.opaque })).collect();
+        let screen = Rect::new(0, 0, self.size.0, self.size.1);
+        let placed = layer::resolve(&nodes, screen);
+        let visibility = layer::occlusion(&nodes, &placed);
+        for (&tab, &v) in &visibility {
+            if self.visibility.get(&tab).copied().unwrap_or_default() != v { self.changes.push((tab, v)); }
+        }
+        self.visibility = visibility;
+        let damage = if std::mem::take(&mut self.full) { Some(screen) } else { layer::damage(&self.placed, &placed).and_then(|d| d.intersect(&screen)) };
+        let mut drawn = 0;
+        if let Some(damage) = damage {
+            let layers: Vec<(Placed, &[u8], (u32, u32))> = placed.iter().filter(|p| self.visibility[&p.tab] == Visibility::Visible).filter_map(|p| {
+                let s = &self.surfaces[&p.tab];
+                Some((*p, s.pixels.as_deref()?, (s.handle.width, s.handle.height)))
+            }).collect();
+            drawn = layers.iter().filter(|(p, _, _)| p.bounds.intersect(&damage).is_some()).count();
+            match self.target.as_mut() {
+                Some(Target::Gpu(g)) => g.draw(damage, &layers),
+                Some(Target::Cpu(_, out)) => composite_cpu(out, self.size.0, damage, &layers),
+                None => unreachable!(),
+            }
+        }
+        let layers = placed.iter().filter(|p| p.version.is_some() && self.visibility[&p.tab] == Visibility::Visible).count();
+        self.stats = FrameStats { damage, drawn, layers, rejected: 0 };
+        self.placed = placed;
         self.frame_seq = self.frame_seq.wrapping_add(1);
         Ok(self.surfaces.len())
     }
 
+    /// Apply the surface contents delivered with `frame` and render. A frame
+    /// that no longer fits its surface (e.g. the tab resized after submitting
+    /// it) is skipped and counted in [`FrameStats::rejected`]; the tab keeps
+    /// its previous contents.
+    pub fn present(&mut self, frame: BeginFrame<Vec<u8>>) -> anyhow::Result<usize> {
+        let mut rejected = 0;
+        for (tab, pixels) in frame.frames {
+            // The tab may have closed since it submitted the frame
+            if self.surfaces.contains_key(&tab) && self.update_surface(tab, pixels).is_err() { rejected += 1; }
+        }
+        let n = self.render_frame()?;
+        self.stats.rejected = rejected;
+        Ok(n)
+    }
+
     pub fn frames_rendered(&self) -> u64 { self.frame_seq }
+
+    /// `tab`'s visibility as of the last frame.
+    pub fn visibility(&self, tab: TabId) -> Option<Visibility> { self.visibility.get(&tab).copied() }
+
+    /// Visibility changes since the last call, oldest first. Surfaces start
+    /// out visible, so a new surface is only reported once it isn't.
+    pub fn take_visibility_changes(&mut self) -> Vec<(TabId, Visibility)> { std::mem::take(&mut self.changes) }
+
+    /// Pass visibility changes to `tabs` and freeze tabs that have been out of
+    /// sight too long; returns the newly frozen tabs. Surfaces without a tab
+    /// in `tabs` are ignored.
+    pub fn sync_tabs(&mut self, tabs: &mut TabManager, now: Duration) -> Vec<TabId> {
+        for (tab, v) in self.take_visibility_changes() { let _ = tabs.set_visibility(tab, v, now); }
+        tabs.tick(now)
+    }
+
+    pub fn last_frame(&self) -> FrameStats { self.stats }
+
+    /// Backend in use; `None` before the first frame.
+    pub fn backend(&self) -> Option<&BackendInfo> {
+        match self.target.as_ref()? {
+            Target::Gpu(g) => Some(&g.info),
+            Target::Cpu(info, _) => Some(info),
+        }
+    }
+
+    /// Tightly packed RGBA8 copy of the output as of the last frame.
+    pub fn read_output(&self) -> anyhow::Result<Vec<u8>> {
+        match &self.target {
+            Some(Target::Gpu(g)) => Ok(g.read()?),
+            Some(Target::Cpu(_, out)) => Ok(out.clone()),
+            None => anyhow::bail!("no frame rendered yet"),
+        }
+    }
+}
+
+/// Bytes in a tightly packed RGBA8 buffer of `size`.
+fn rgba_len((width, height): (u32, u32)) -> usize { width as usize * height as usize * 4 }
+
+/// Clear `damage` to opaque black and blend `layers` over it, bottom first.
+fn composite_cpu(out: &mut [u8], width: u32, damage: Rect, layers: &[(Placed, &[u8], (u32, u32))]) {
+    let at = |x: i32, y: i32| (y as usize * width as usize + x as usize) * 4;
+    for y in damage.y..damage.bottom() {
+        for x in damage.x..damage.right() { out[at(x, y)..][..4].copy_from_slice(&[0, 0, 0, 255]); }
+    }
+    for (p, pixels, (w, _)) in layers {
+        let Some(r) = p.bounds.intersect(&damage) else { continue };
+        for y in r.y..r.bottom() {
+            for x in r.x..r.right() {
+                let s = &pixels[((y - p.origin.1) as usize * *w as usize + (x - p.origin.0) as usize) * 4..][..4];
+                let a = s[3] as f32 / 255.0 * p.opacity;
+                let d = &mut out[at(x, y)..][..4];
+                for c in 0..3 { d[c] = (s[c] as f32 * a + d[c] as f32 * (1.0 - a)).round() as u8; }
+                d[3] = ((a + d[3] as f32 / 255.0 * (1.0 - a)) * 255.0).round() as u8;
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -45,7 +299,7 @@ mod tests {
 
     #[test]
     fn add_and_remove_surface() {
-        let mut comp = GpuCompositor::new();
+        let mut comp = GpuCompositor::new().with_preference(Preference::Cpu);
         comp.add_surface(1, SurfaceHandle::new(42, 800, 600));
         assert_eq!(comp.render_frame().unwrap(), 1);
         assert_eq!(comp.frames_rendered(), 1);
@@ -54,11 +308,133 @@ mod tests {
         assert_eq!(comp.render_frame().unwrap(), 0);
     }
 
+    #[test]
+    fn oversized_outputs_are_refused() {
+        for pref in [Preference::Cpu, Preference::Auto] {
+            let mut comp = GpuCompositor::new().with_preference(pref);
+            assert!(comp.resize(u32::MAX, 10).is_err());
+            comp.render_frame().unwrap();
+            let max = comp.max_size();
+            assert!(comp.resize(max + 1, 10).is_err());
+            assert_eq!(comp.output_size(), DEFAULT_OUTPUT_SIZE);
+            comp.resize(64, max).unwrap();
+            assert_eq!(comp.output_size(), (64, max));
+        }
+    }
+
     #[test]
     fn remove_missing_is_none() {
-        let mut comp = GpuCompositor::new();
+        let mut comp = GpuCompositor::new().with_preference(Preference::Cpu);
         assert!(comp.remove_surface(999).is_none());
         assert_eq!(comp.render_frame().unwrap(), 0);
     }
-}
 
+    fn solid(w: u32, h: u32, px: [u8; 4]) -> Vec<u8> { px.repeat((w * h) as usize) }
+
+    /// Two overlapping tabs, the top one half transparent and clipped.
+    fn scene(pref: Preference) -> GpuCompositor {
+        let mut comp = GpuCompositor::new().with_preference(pref);
+        comp.resize(64, 48).unwrap();
+        comp.add_surface(1, SurfaceHandle::new(1, 40, 30));
+        comp.add_surface(2, SurfaceHandle::new(2, 30, 30));
+        comp.update_surface(1, solid(40, 30, [200, 0, 0, 255])).unwrap();
+        comp.update_surface(2, (0..30 * 30).flat_map(|i| [0, (i % 30 * 8) as u8, 255, 255]).collect()).unwrap();
+        comp.set_layer(1, Layer { x: 4, y: 4, ..Default::default() });
+        comp.set_layer(2, Layer { x: 20, y: 10, z: 1, opacity: 0.5, clip: Some(Rect::new(0, 0, 30, 20)), ..Default::default() });
+        comp
+    }
+
+    #[test]
+    fn composites_layers_and_redraws_only_damage() {
+        let mut comp = scene(Preference::Cpu);
+        assert!(comp.update_surface(1, vec![0; 3]).is_err());
+        comp.render_frame().unwrap();
+        assert_eq!(comp.last_frame(), FrameStats { damage: Some(Rect::new(0, 0, 64, 48)), drawn: 2, layers: 2, rejected: 0 });
+        let out = comp.read_output().unwrap();
+        let px = |out: &[u8], x: usize, y: usize| out[(y * 64 + x) * 4..][..4].to_vec();
+        assert_eq!(px(&out, 0, 0), [0, 0, 0, 255]);
+        assert_eq!(px(&out, 5, 5), [200, 0, 0, 255]);
+        assert_eq!(px(&out, 21, 12), [100, 4, 128, 255]);
+        assert_eq!(px(&out, 45, 12), [0, 100, 128, 255]);
+        assert_eq!(px(&out, 45, 32), [0, 0, 0, 255], "clipped");
+
+        comp.render_frame().unwrap();
+        assert_eq!(comp.last_frame(), FrameStats { damage: None, drawn: 0, layers: 2, rejected: 0 });
+        // Moving the bottom layer off the top one's area doesn't redraw the top one
+        comp.set_layer(1, Layer { x: 0, y: 30, ..Default::default() });
+        comp.set_layer(2, Layer { x: 40, y: 0, z: 1, ..Default::default() });
+        comp.render_frame().unwrap();
+        comp.update_surface(1, solid(40, 30, [0, 200, 0, 255])).unwrap();
+        comp.render_frame().unwrap();
+        assert_eq!(comp.last_frame(), FrameStats { damage: Some(Rect::new(0, 30, 40, 18)), drawn: 1, layers: 2, rejected: 0 });
+        assert_eq!(px(&comp.read_output().unwrap(), 5, 40), [0, 200, 0, 255]);
+    }
+
+    #[test]
+    fn presents_scheduled_frames() {
+        let clock = schedule::VirtualClock::default();
+        let mut sched = schedule::FrameScheduler::with_clock(60, clock.clone());
+        let mut comp = scene(Preference::Cpu);
+        comp.present(sched.poll().unwrap()).unwrap();
+        sched.submit(1, solid(40, 30, [0, 0, 90, 255]));
+        sched.submit(1, solid(40, 30, [0, 0, 200, 255]));
+        sched.submit(7, solid(1, 1, [0; 4]));
+        clock.advance(sched.interval());
+        assert_eq!(comp.present(sched.poll().unwrap()).unwrap(), 2);
+        assert_eq!(comp.last_frame().damage, Some(Rect::new(4, 4, 40, 30)));
+        assert_eq!(comp.read_output().unwrap()[(5 * 64 + 5) * 4..][..4], [0, 0, 200, 255]);
+        assert_eq!(sched.stats().coalesced, 1);
+
+        // A frame of the wrong size is skipped; the other tab still composites
+        sched.submit(1, solid(20, 30, [0, 90, 0, 255]));
+        sched.submit(2, solid(30, 30, [0, 0, 0, 255]));
+        clock.advance(sched.interval());
+        comp.present(sched.poll().unwrap()).unwrap();
+        assert_eq!(comp.last_frame().rejected, 1);
+        let out = comp.read_output().unwrap();
+        assert_eq!(out[(5 * 64 + 5) * 4..][..4], [0, 0, 200, 255]);
+        assert_eq!(out[(12 * 64 + 45) * 4..][..4], [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn background_tabs_are_throttled_and_frozen() {
+        let secs = Duration::from_secs;
+        let mut tabs = TabManager::new().with_policy(tab_manager::ThrottlePolicy { freeze_after: secs(30), ..Default::default() });
+        let (front, behind, hidden) = (tabs.new_tab("https://a.test"), tabs.new_tab("https://b.test"), tabs.new_tab("https://c.test"));
+        let mut comp = GpuCompositor::new().with_preference(Preference::Cpu);
+        comp.resize(64, 48).unwrap();
+        for tab in [front, behind, hidden] {
+            comp.add_surface(tab, SurfaceHandle::new(tab, 64, 48));
+            comp.update_surface(tab, solid(64, 48, [tab as u8 * 50, 0, 0, 255])).unwrap();
+        }
+        comp.set_layer(front, Layer { z: 1, ..Default::default() });
+        comp.set_layer(hidden, Layer { visible: false, ..Default::default() });
+        comp.render_frame().unwrap();
+        assert_eq!((comp.last_frame().drawn, comp.last_frame().layers), (1, 1));
+        assert_eq!((comp.visibility(behind), comp.visibility(hidden)), (Some(Visibility::Occluded), Some(Visibility::Hidden)));
+        assert!(comp.sync_tabs(&mut tabs, secs(0)).is_empty());
+        assert_eq!([front, behind, hidden].map(|t| tabs.frame_rate(t)), [60, 1, 1]);
+
+        // Uncovering a tab thaws its timer; the hidden one freezes
+        comp.update_surface(front, solid(64, 48, [0, 0, 0, 128])).unwrap();
+        comp.render_frame().unwrap();
+        assert_eq!(comp.take_visibility_changes(), vec![(behind, Visibility::Visible)]);
+        tabs.set_visibility(behind, Visibility::Visible, secs(10)).unwrap();
+        assert_eq!(comp.sync_tabs(&mut tabs, secs(30)), vec![hidden]);
+        assert_eq!([front, behind, hidden].map(|t| tabs.frame_rate(t)), [60, 60, 0]);
+    }
+
+    #[test]
+    fn gpu_output_matches_cpu() {
+        let (mut gpu, mut cpu) = (scene(Preference::Auto), scene(Preference::Cpu));
+        for comp in [&mut gpu, &mut cpu] {
+            comp.render_frame().unwrap();
+            comp.set_layer(2, Layer { x: 30, y: 5, z: -1, opacity: 0.75, ..Default::default() });
+            comp.render_frame().unwrap();
+            assert_eq!(comp.last_frame().drawn, 2);
+        }
+        let (g, c) = (gpu.read_output().unwrap(), cpu.read_output().unwrap());
+        let worst = g.iter().zip(&c).map(|(a, b)| a.abs_diff(*b)).max().unwrap();
+        assert!(worst <= 1, "{:?} differs from CPU by up to {worst}", gpu.backend());
+    }
+}
@@ -0,0 +1,277 @@
+//! Frame pacing. [`FrameScheduler`] emits a [`BeginFrame`] on every vsync at
+//! the target rate. Producers submit frames whenever they like: a newer frame
+//! from the same producer replaces the pending one, and frames that waited
+//! longer than the maximum age are dropped and counted as stale. Producers can
+//! be throttled to a lower rate, e.g. background tabs; their frames wait for
+//! the producer's next slot. Time comes from a [`Clock`], so tests can drive a
+//! [`VirtualClock`] by hand.
+
+use crate::TabId;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+pub trait Clock {
+    /// Time since an arbitrary fixed origin.
+    fn now(&self) -> Duration;
+}
+
+pub struct SystemClock(Instant);
+
+impl Default for SystemClock {
+    fn default() -> Self { Self(Instant::now()) }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration { self.0.elapsed() }
+}
+
+/// Clock that only moves when told to. Clones share the same time.
+#[derive(Debug, Clone, Default)]
+pub struct VirtualClock(Arc<AtomicU64>);
+
+impl VirtualClock {
+    pub fn advance(&self, by: Duration) { self.0.fetch_add(by.as_nanos() as u64, Ordering::Relaxed); }
+    pub fn set(&self, to: Duration) { self.0.store(to.as_nanos() as u64, Ordering::Relaxed); }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> Duration { Duration::from_nanos(self.0.load(Ordering::Relaxed)) }
+}
+
+/// One vsync: the latest frame from every producer that submitted since the last one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BeginFrame<T> {
+    pub seq: u64,
+    /// Vsync time this frame belongs to.
+    pub time: Duration,
+    /// When the next vsync is due; drawing should finish by then.
+    pub deadline: Duration,
+    /// By producer id, ascending.
+    pub frames: Vec<(TabId, T)>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TimingStats {
+    pub begin_frames: u64,
+    /// Begin-frames that carried at least one frame.
+    pub presented: u64,
+    /// Frames replaced by a newer frame from the same producer.
+    pub coalesced: u64,
+    /// Frames older than the maximum age when their vsync came.
+    pub stale: u64,
+    /// Vsyncs that passed without a begin-frame because polling was late.
+    pub missed_vsyncs: u64,
+    /// Begin-frames that came after one or more missed vsyncs.
+    pub janky: u64,
+    pub max_interval: Duration,
+    /// Longest time a delivered frame waited for its vsync.
+    pub max_latency: Duration,
+    total_interval: Duration,
+}
+
+impl TimingStats {
+    /// Frames that never reached a begin-frame.
+    pub fn dropped(&self) -> u64 { self.coalesced + self.stale }
+
+    /// Mean time between consecutive begin-frames.
+    pub fn mean_interval(&self) -> Duration {
+        match self.begin_frames {
+            0 | 1 => Duration::ZERO,
+            n => self.total_interval / (n - 1) as u32,
+        }
+    }
+}
+
+struct Pending<T> {
+    frame: T,
+    submitted: Duration,
+}
+
+pub struct FrameScheduler<T, C: Clock = SystemClock> {
+    clock: C,
+    interval: Duration,
+    max_age: Duration,
+    /// Time of the next vsync.
+    next: Duration,
+    last: Option<Duration>,
+    seq: u64,
+    pending: BTreeMap<TabId, Pending<T>>,
+    /// Minimum time between delivered frames of throttled producers; `None` means never.
+    throttled: BTreeMap<TabId, Option<Duration>>,
+    delivered: BTreeMap<TabId, Duration>,
+    stats: TimingStats,
+}
+
+impl<T> FrameScheduler<T> {
+    /// Scheduler at `hz` frames per second on the system clock.
+    pub fn new(hz: u32) -> Self { Self::with_clock(hz, SystemClock::default()) }
+}
+
+impl<T, C: Clock> FrameScheduler<T, C> {
+    /// The first vsync is due immediately. Frames may wait two intervals
+    /// before they count as stale.
+    pub fn with_clock(hz: u32, clock: C) -> Self {
+        let interval = Duration::from_secs(1) / hz.max(1);
+        let next = clock.now();
+        Self { clock, interval, max_age: interval * 2, next, last: None, seq: 0, pending: BTreeMap::new(), throttled: BTreeMap::new(), delivered: BTreeMap::new(), stats: TimingStats::default() }
+    }
+
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    pub fn interval(&self) -> Duration { self.interval }
+
+    pub fn clock(&self) -> &C { &self.clock }
+
+    /// Queue `frame` for the next vsync; `true` if it replaced a pending frame from `producer`.
+    pub fn submit(&mut self, producer: TabId, frame: T) -> bool {
+        let replaced = self.pending.insert(producer, Pending { frame, submitted: self.clock.now() }).is_some();
+        if replaced { self.stats.coalesced += 1; }
+        replaced
+    }
+
+    /// Forget `producer`'s pending frame and rate, e.g. when its tab closes.
+    pub fn cancel(&mut self, producer: TabId) -> Option<T> {
+        self.throttled.remove(&producer);
+        self.delivered.remove(&producer);
+        self.pending.remove(&producer).map(|p| p.frame)
+    }
+
+    /// Deliver `producer`'s frames at most `hz` times a second; 0 holds them
+    /// until the rate is raised again. Rates at or above the scheduler's own
+    /// remove the limit.
+    pub fn set_rate(&mut self, producer: TabId, hz: u32) {
+        let min = (hz > 0).then(|| Duration::from_secs(1) / hz);
+        if min.is_some_and(|m| m <= self.interval) { self.throttled.remove(&producer); } else { self.throttled.insert(producer, min); }
+    }
+
+    /// When `producer` may next deliver a frame; `None` while it is held.
+    fn slot(&self, producer: TabId) -> Option<Duration> {
+        match (self.throttled.get(&producer), self.delivered.get(&producer)) {
+            (Some(None), _) => None,
+            (Some(Some(min)), Some(last)) => Some(*last + *min),
+            _ => Some(Duration::ZERO),
+        }
+    }
+
+    pub fn has_pending(&self) -> bool { !self.pending.is_empty() }
+
+    /// Time of the next vsync.
+    pub fn next_begin_frame(&self) -> Duration { self.next }
+
+    /// How long until the next vsync; zero if it is already due.
+    pub fn time_until_next(&self) -> Duration { self.next.saturating_sub(self.clock.now()) }
+
+    /// The due [`BeginFrame`], if any. Vsyncs that passed while nobody polled
+    /// are skipped and counted as missed.
+    pub fn poll(&mut self) -> Option<BeginFrame<T>> {
+        let now = self.clock.now();
+        if now < self.next { return None; }
+        let missed = ((now - self.next).as_nanos() / self.interval.as_nanos()) as u32;
+        let time = self.next + self.interval * missed;
+        self.next = time + self.interval;
+        self.seq += 1;
+
+        let s = &mut self.stats;
+        s.begin_frames += 1;
+        s.missed_vsyncs += missed as u64;
+        if missed > 0 { s.janky += 1; }
+        if let Some(last) = self.last.replace(time) {
+            s.total_interval += time - last;
+            s.max_interval = s.max_interval.max(time - last);
+        }
+        let mut frames = Vec::new();
+        for (producer, p) in std::mem::take(&mut self.pending) {
+            // A throttled frame's age counts from its producer's slot, not its submission
+            let slot = match self.slot(producer) {
+                Some(slot) if slot <= time => slot,
+                _ => {
+                    self.pending.insert(producer, p);
+                    continue;
+                }
+            };
+            let s = &mut self.stats;
+            let age = time.saturating_sub(p.submitted.max(slot));
+            if age > self.max_age {
+                s.stale += 1;
+            } else {
+                s.max_latency = s.max_latency.max(age);
+                self.delivered.insert(producer, time);
+                frames.push((producer, p.frame));
+            }
+        }
+        if !frames.is_empty() { self.stats.presented += 1; }
+        Some(BeginFrame { seq: self.seq, time, deadline: self.next, frames })
+    }
+
+    pub fn stats(&self) -> TimingStats { self.stats }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn paces_coalesces_and_drops_stale_frames() {
+        let clock = VirtualClock::default();
+        let mut s = FrameScheduler::with_clock(100, clock.clone());
+        assert_eq!(s.interval(), 10 * MS);
+        let first = s.poll().unwrap();
+        assert_eq!((first.seq, first.time, first.deadline, first.frames.len()), (1, Duration::ZERO, 10 * MS, 0));
+        assert!(s.poll().is_none());
+
+        assert!(!s.submit(2, "a1"));
+        s.submit(1, "b1");
+        clock.advance(4 * MS);
+        assert!(s.submit(2, "a2"));
+        assert_eq!(s.time_until_next(), 6 * MS);
+        assert!(s.poll().is_none());
+        clock.advance(6 * MS);
+        let bf = s.poll().unwrap();
+        assert_eq!((bf.time, bf.frames), (10 * MS, vec![(1, "b1"), (2, "a2")]));
+
+        // Polling 35ms late skips three vsyncs; a frame older than 20ms is stale
+        s.submit(1, "old");
+        clock.advance(35 * MS);
+        s.submit(2, "new");
+        let bf = s.poll().unwrap();
+        assert_eq!((bf.time, bf.deadline, bf.frames), (40 * MS, 50 * MS, vec![(2, "new")]));
+
+        let st = s.stats();
+        assert_eq!((st.begin_frames, st.presented, st.coalesced, st.stale, st.dropped()), (3, 2, 1, 1, 2));
+        assert_eq!((st.missed_vsyncs, st.janky, st.max_interval, st.mean_interval()), (2, 1, 30 * MS, 20 * MS));
+        assert_eq!(st.max_latency, 10 * MS);
+    }
+
+    #[test]
+    fn throttled_producers_wait_for_their_slot() {
+        let clock = VirtualClock::default();
+        let mut s = FrameScheduler::with_clock(100, clock.clone());
+        s.set_rate(1, 20);
+        s.set_rate(2, 0);
+        s.set_rate(3, 200);
+        let mut delivered = Vec::new();
+        for tick in 0..12u32 {
+            for producer in 1..=3 { s.submit(producer, tick); }
+            delivered.extend(s.poll().unwrap().frames);
+            clock.advance(s.interval());
+        }
+        // Producer 1 gets every fifth vsync, 2 is held, 3 is unthrottled
+        let of = |p| delivered.iter().filter(|d| d.0 == p).map(|d| d.1).collect::<Vec<_>>();
+        assert_eq!(of(1), [0, 5, 10]);
+        assert!(of(2).is_empty());
+        assert_eq!(of(3), (0..12).collect::<Vec<_>>());
+        assert_eq!(s.stats().stale, 0);
+
+        // Raising the rate releases the held frame; 1's next slot is still 30ms away
+        s.set_rate(2, 60);
+        assert_eq!(s.poll().unwrap().frames, vec![(2, 11)]);
+        assert_eq!(s.cancel(1), Some(11));
+    }
+}
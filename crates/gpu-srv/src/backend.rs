@@ -0,0 +1,224 @@
+//! What gpu-srv renders with. Hardware adapters are tried first, best device
+//! type first; then wgpu's software fallback adapter; and when no device can be
+//! created at all, the CPU rasterizer in [`crate::cpu`]. Every adapter that was
+//! skipped is reported along with the reason.
+
+use crate::raster::{Frame, GpuRasterizer, TileStats};
+use message_defs::DisplayList;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Overrides the backend choice: `auto`, `software` or `cpu`.
+pub const BACKEND_ENV: &str = "GPU_SRV_BACKEND";
+
+#[derive(Debug, Error)]
+pub enum GpuError {
+    #[error("no usable GPU adapter ({0})")] NoAdapter(String),
+    #[error("empty frame {width}x{height}")] EmptyFrame { width: u32, height: u32 },
+    #[error("frame {width}x{height} exceeds the {max}px texture limit")] TooLarge { width: u32, height: u32, max: u32 },
+    #[error("readback failed: {0}")] Readback(String),
+    #[error("unknown backend {0:?}; expected auto, software or cpu")] UnknownBackend(String),
+    #[error("service: {0}")] Service(String),
+    #[error(transparent)] Io(#[from] std::io::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BackendKind {
+    /// A GPU adapter.
+    Hardware,
+    /// A software adapter such as llvmpipe or WARP, driven through wgpu.
+    Software,
+    /// gpu-srv's own CPU rasterizer; no wgpu device at all.
+    Cpu,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackendInfo {
+    pub kind: BackendKind,
+    /// Adapter name, graphics API and driver; `None` for [`BackendKind::Cpu`].
+    pub adapter: Option<String>,
+    pub api: Option<String>,
+    pub driver: Option<String>,
+    /// Adapters tried before this one and why they could not be used.
+    pub skipped: Vec<String>,
+}
+
+impl BackendInfo {
+    /// The CPU rasterizer, used after `skipped` adapters failed.
+    pub fn cpu(skipped: Vec<String>) -> Self { Self { kind: BackendKind::Cpu, adapter: None, api: None, driver: None, skipped } }
+
+    fn from_adapter(info: &wgpu::AdapterInfo, skipped: Vec<String>) -> Self {
+        let kind = if info.device_type == wgpu::DeviceType::Cpu { BackendKind::Software } else { BackendKind::Hardware };
+        Self { kind, adapter: Some(info.name.clone()), api: Some(format!("{:?}", info.backend)), driver: Some(info.driver.clone()).filter(|d| !d.is_empty()), skipped }
+    }
+}
+
+impl fmt::Display for BackendInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "kind={:?}", self.kind)?;
+        if let Some(a) = &self.adapter { write!(f, " adapter={a:?}")?; }
+        if let Some(a) = &self.api { write!(f, " api={a}")?; }
+        if let Some(d) = &self.driver { write!(f, " driver={d:?}")?; }
+        if !self.skipped.is_empty() { write!(f, " skipped={}", self.skipped.len())?; }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Preference {
+    /// Best adapter available, down to the CPU rasterizer.
+    #[default]
+    Auto,
+    /// Skip hardware adapters.
+    Software,
+    /// CPU rasterizer only.
+    Cpu,
+}
+
+impl FromStr for Preference {
+    type Err = GpuError;
+    fn from_str(s: &str) -> Result<Self, GpuError> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "" | "auto" => Ok(Preference::Auto),
+            "software" | "fallback" => Ok(Preference::Software),
+            "cpu" => Ok(Preference::Cpu),
+            other => Err(GpuError::UnknownBackend(other.to_string())),
+        }
+    }
+}
+
+impl Preference {
+    /// From [`BACKEND_ENV`]; unset means [`Preference::Auto`].
+    pub fn from_env() -> Result<Self, GpuError> { std::env::var(BACKEND_ENV).unwrap_or_default().parse() }
+}
+
+fn rank(t: wgpu::DeviceType) -> u8 {
+    match t {
+        wgpu::DeviceType::DiscreteGpu => 0,
+        wgpu::DeviceType::IntegratedGpu => 1,
+        wgpu::DeviceType::VirtualGpu => 2,
+        wgpu::DeviceType::Other => 3,
+        wgpu::DeviceType::Cpu => 4,
+    }
+}
+
+async fn device_for(adapter: &wgpu::Adapter) -> Result<(wgpu::Device, wgpu::Queue), wgpu::RequestDeviceError> {
+    let desc = wgpu::DeviceDescriptor { label: Some("gpu-srv"), required_limits: wgpu::Limits::downlevel_defaults().using_resolution(adapter.limits()), ..Default::default() };
+    adapter.request_device(&desc).await
+}
+
+/// Open a wgpu device per `pref`. [`Preference::Cpu`] always fails with
+/// [`GpuError::NoAdapter`]; callers fall back to the CPU rasterizer.
+pub fn open_device(pref: Preference) -> Result<(wgpu::Device, wgpu::Queue, BackendInfo), GpuError> {
+    if pref == Preference::Cpu { return Err(GpuError::NoAdapter("CPU rasterizer requested".into())); }
+    pollster::block_on(async {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+        let mut skipped = Vec::new();
+        if pref == Preference::Auto {
+            let mut adapters = instance.enumerate_adapters(wgpu::Backends::all());
+            adapters.sort_by_key(|a| rank(a.get_info().device_type));
+            for adapter in adapters {
+                let info = adapter.get_info();
+                match device_for(&adapter).await {
+                    Ok((device, queue)) => return Ok((device, queue, BackendInfo::from_adapter(&info, skipped))),
+                    Err(e) => skipped.push(format!("{} ({:?}): {e}", info.name, info.backend)),
+                }
+            }
+        }
+        let opts = wgpu::RequestAdapterOptions { power_preference: wgpu::PowerPreference::LowPower, compatible_surface: None, force_fallback_adapter: true };
+        match instance.request_adapter(&opts).await {
+            Ok(adapter) => {
+                let info = adapter.get_info();
+                match device_for(&adapter).await {
+                    Ok((device, queue)) => return Ok((device, queue, BackendInfo::from_adapter(&info, skipped))),
+                    Err(e) => skipped.push(format!("{} ({:?}): {e}", info.name, info.backend)),
+                }
+            }
+            Err(e) => skipped.push(format!("fallback adapter: {e}")),
+        }
+        Err(GpuError::NoAdapter(skipped.join("; ")))
+    })
+}
+
+/// Whatever rasterizer could be set up, GPU or CPU.
+pub enum Renderer {
+    Gpu(Box<GpuRasterizer>),
+    Cpu(BackendInfo),
+}
+
+impl Renderer {
+    /// Never fails: without a usable adapter the CPU rasterizer is used, and
+    /// the reasons end up in [`BackendInfo::skipped`].
+    pub fn new(pref: Preference) -> Self {
+        match open_device(pref) {
+            Ok((device, queue, info)) => Renderer::Gpu(Box::new(GpuRasterizer::with_device(device, queue, info))),
+            Err(GpuError::NoAdapter(why)) => Renderer::Cpu(BackendInfo::cpu(vec![why])),
+            Err(e) => Renderer::Cpu(BackendInfo::cpu(vec![e.to_string()])),
+        }
+    }
+
+    pub fn info(&self) -> &BackendInfo {
+        match self {
+            Renderer::Gpu(g) => g.backend(),
+            Renderer::Cpu(info) => info,
+        }
+    }
+
+    pub fn render(&mut self, width: u32, height: u32, dl: &DisplayList) -> Result<Frame, GpuError> { self.render_surface(0, width, height, dl) }
+
+    /// Render into `surface`'s tile cache; the CPU path has no cache and ignores it.
+    pub fn render_surface(&mut self, surface: u64, width: u32, height: u32, dl: &DisplayList) -> Result<Frame, GpuError> {
+        match self {
+            Renderer::Gpu(g) => g.render_surface(surface, width, height, dl),
+            Renderer::Cpu(_) => {
+                if width == 0 || height == 0 { return Err(GpuError::EmptyFrame { width, height }); }
+                let max = crate::cpu::MAX_DIMENSION;
+                if width > max || height > max { return Err(GpuError::TooLarge { width, height, max }); }
+                Ok(Frame { width, height, rgba: crate::cpu::rasterize_rgba8(width, height, dl), stats: TileStats { tiles: 1, repainted: 1 } })
+            }
+        }
+    }
+
+    pub fn release_surface(&mut self, surface: u64) {
+        if let Renderer::Gpu(g) = self { g.release_surface(surface); }
+    }
+}
+
+/// The backend gpu-srv would use under the current [`BACKEND_ENV`].
+pub fn probe() -> Result<BackendInfo, GpuError> { Ok(Renderer::new(Preference::from_env()?).info().clone()) }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use message_defs::DrawCmd;
+
+    #[test]
+    fn preference_parses_and_cpu_renderer_is_always_available() {
+        assert_eq!("Software".parse::<Preference>().unwrap(), Preference::Software);
+        assert_eq!("".parse::<Preference>().unwrap(), Preference::Auto);
+        assert!(matches!("metal".parse::<Preference>(), Err(GpuError::UnknownBackend(_))));
+
+        let mut r = Renderer::new(Preference::Cpu);
+        assert_eq!(r.info().kind, BackendKind::Cpu);
+        assert_eq!(r.info().skipped.len(), 1);
+        let dl = DisplayList { items: vec![DrawCmd::Rect { x: 1, y: 1, w: 2, h: 2, rgba: 0xFFFF0000 }] };
+        assert_eq!(r.render(4, 4, &dl).unwrap().rgba, crate::cpu::rasterize_rgba8(4, 4, &dl));
+        assert!(matches!(r.render(0, 4, &dl), Err(GpuError::EmptyFrame { .. })));
+        assert!(matches!(r.render(u32::MAX, u32::MAX, &dl), Err(GpuError::TooLarge { max: crate::cpu::MAX_DIMENSION, .. })));
+        assert!(r.info().to_string().starts_with("kind=Cpu"));
+    }
+
+    #[test]
+    fn auto_reports_the_adapter_it_picked() {
+        let r = Renderer::new(Preference::Auto);
+        let info = r.info();
+        match info.kind {
+            BackendKind::Cpu => assert!(matches!(r, Renderer::Cpu(_)) && !info.skipped.is_empty()),
+            _ => assert!(matches!(r, Renderer::Gpu(_)) && info.adapter.is_some() && info.api.is_some()),
+        }
+        // The software path never picks a hardware adapter
+        assert_ne!(Renderer::new(Preference::Software).info().kind, BackendKind::Hardware);
+    }
+}
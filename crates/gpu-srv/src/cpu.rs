@@ -0,0 +1,201 @@
+//! CPU rasterizer for DisplayList (reference path for previews and tests).
+
+use message_defs::{DisplayList, DrawCmd};
+
+/// Largest frame width or height the CPU path accepts; wgpu's default
+/// `max_texture_dimension_2d`, so both paths refuse the same frames.
+pub const MAX_DIMENSION: u32 = 8192;
+
+/// Bytes in a tightly packed `width`x`height` RGBA8 frame, `None` if that
+/// does not fit in memory addresses.
+pub fn frame_len(width: u32, height: u32) -> Option<usize> { (width as usize).checked_mul(height as usize)?.checked_mul(4) }
+
+/// Rasterize `dl` into a tightly packed RGBA8 buffer (opaque black background).
+/// Panics if the frame size overflows `usize`; check [`MAX_DIMENSION`] first.
+pub fn rasterize_rgba8(width: u32, height: u32, dl: &DisplayList) -> Vec<u8> {
+    let mut buf = vec![0u8; frame_len(width, height).expect("frame size overflows usize")];
+    draw(&mut buf, width, dl, (0, 0, width, height));
+    buf
+}
+
+/// Repaint only `region` (x, y, w, h) of an existing `width`x`height` frame from `dl`,
+/// e.g. for the damage rects produced by DisplayList diffing.
+pub fn repaint_region_rgba8(buf: &mut [u8], width: u32, height: u32, dl: &DisplayList, region: (u32, u32, u32, u32)) {
+    let (x0, y0) = (region.0.min(width), region.1.min(height));
+    let (x1, y1) = (region.0.saturating_add(region.2).min(width), region.1.saturating_add(region.3).min(height));
+    for y in y0..y1 {
+        let row = (y as usize * width as usize + x0 as usize) * 4;
+        buf[row..row + (x1 - x0) as usize * 4].fill(0);
+    }
+    draw(buf, width, dl, (x0, y0, x1, y1));
+}
+
+/// `dl` in device pixels for a device pixel ratio of `scale`. Edges are
+/// scaled and rounded rather than sizes, so abutting rects stay abutting.
+pub fn scale_display_list(dl: &DisplayList, scale: f32) -> DisplayList {
+    let at = |v: u32| (v as f32 * scale).round() as u32;
+    let span = |pos: u32, len: u32| at(pos.saturating_add(len)) - at(pos);
+    let items = dl.items.iter().map(|cmd| match cmd {
+        DrawCmd::Rect { x, y, w, h, rgba } => DrawCmd::Rect { x: at(*x), y: at(*y), w: span(*x, *w), h: span(*y, *h), rgba: *rgba },
+        DrawCmd::Image { x, y, w, h, src_w, src_h, pixels } => {
+            DrawCmd::Image { x: at(*x), y: at(*y), w: span(*x, *w), h: span(*y, *h), src_w: *src_w, src_h: *src_h, pixels: pixels.clone() }
+        }
+        DrawCmd::PushLayer { clip_x, clip_y, clip_w, clip_h, scroll_x, scroll_y } => DrawCmd::PushLayer {
+            clip_x: at(*clip_x),
+            clip_y: at(*clip_y),
+            clip_w: span(*clip_x, *clip_w),
+            clip_h: span(*clip_y, *clip_h),
+            scroll_x: (*scroll_x as f32 * scale).round() as i32,
+            scroll_y: (*scroll_y as f32 * scale).round() as i32,
+        },
+        DrawCmd::PopLayer => DrawCmd::PopLayer,
+    });
+    DisplayList { items: items.collect() }
+}
+
+/// Paint every command clipped to the half-open box (x0, y0)..(x1, y1).
+fn draw(buf: &mut [u8], width: u32, dl: &DisplayList, (x0, y0, x1, y1): (u32, u32, u32, u32)) {
+    // Active layer: screen offset of layer coordinates and screen clip box
+    let root = Layer { ox: 0, oy: 0, clip: (x0 as i64, y0 as i64, x1 as i64, y1 as i64) };
+    let mut stack: Vec<Layer> = Vec::new();
+    let mut cur = root;
+    for cmd in &dl.items {
+        match cmd {
+            DrawCmd::Rect { x, y, w, h, rgba } => {
+                let (a, r, g, b) = unpack_argb_u32(*rgba);
+                let (sx, sy) = (*x as i64 - cur.ox, *y as i64 - cur.oy);
+                let (cx0, cy0, cx1, cy1) = cur.clip;
+                for yy in sy.max(cy0)..(sy + *h as i64).min(cy1) {
+                    for xx in sx.max(cx0)..(sx + *w as i64).min(cx1) {
+                        blend(buf, ((yy as u64 * width as u64 + xx as u64) * 4) as usize, [r, g, b, a]);
+                    }
+                }
+            }
+            DrawCmd::Image { x, y, w, h, src_w, src_h, pixels } => {
+                if *src_w == 0 || *src_h == 0 || frame_len(*src_w, *src_h).is_none_or(|n| pixels.len() < n) { continue; }
+                let (sx, sy) = (*x as i64 - cur.ox, *y as i64 - cur.oy);
+                let (cx0, cy0, cx1, cy1) = cur.clip;
+                // Nearest-neighbour scale from source into the destination rect
+                for yy in sy.max(cy0)..(sy + *h as i64).min(cy1) {
+                    let py = ((yy - sy) as u64 * *src_h as u64 / (*h).max(1) as u64) as usize;
+                    for xx in sx.max(cx0)..(sx + *w as i64).min(cx1) {
+                        let px = ((xx - sx) as u64 * *src_w as u64 / (*w).max(1) as u64) as usize;
+                        let s = (py * *src_w as usize + px) * 4;
+                        let rgba = [pixels[s], pixels[s + 1], pixels[s + 2], pixels[s + 3]];
+                        blend(buf, ((yy as u64 * width as u64 + xx as u64) * 4) as usize, rgba);
+                    }
+                }
+            }
+            DrawCmd::PushLayer { clip_x, clip_y, clip_w, clip_h, scroll_x, scroll_y } => {
+                let (lx, ly) = (*clip_x as i64 - cur.ox, *clip_y as i64 - cur.oy);
+                let (cx0, cy0, cx1, cy1) = cur.clip;
+                let clip = (lx.max(cx0), ly.max(cy0), (lx + *clip_w as i64).min(cx1), (ly + *clip_h as i64).min(cy1));
+                stack.push(cur);
+                cur = Layer { ox: cur.ox + *scroll_x as i64, oy: cur.oy + *scroll_y as i64, clip };
+            }
+            DrawCmd::PopLayer => cur = stack.pop().unwrap_or(root),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Layer {
+    ox: i64,
+    oy: i64,
+    clip: (i64, i64, i64, i64),
+}
+
+#[inline]
+fn blend(buf: &mut [u8], idx: usize, [r, g, b, a]: [u8; 4]) {
+    let sa = a as u32;
+    let da = 255u32 - sa;
+    buf[idx] = ((r as u32 * sa + buf[idx] as u32 * da) / 255) as u8;
+    buf[idx + 1] = ((g as u32 * sa + buf[idx + 1] as u32 * da) / 255) as u8;
+    buf[idx + 2] = ((b as u32 * sa + buf[idx + 2] as u32 * da) / 255) as u8;
+    buf[idx + 3] = 255u8;
+}
+
+#[inline]
+pub fn unpack_argb_u32(v: u32) -> (u8, u8, u8, u8) {
+    // Phase-1 contract: 0xAARRGGBB
+    let a = ((v >> 24) & 0xFF) as u8;
+    let r = ((v >> 16) & 0xFF) as u8;
+    let g = ((v >> 8) & 0xFF) as u8;
+    let b = (v & 0xFF) as u8;
+    (a, r, g, b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rect_and_scaled_image() {
+        let pixels = bytes::Bytes::from(vec![255, 0, 0, 255, 0, 255, 0, 255]);
+        let dl = DisplayList {
+            items: vec![
+                DrawCmd::Rect { x: 0, y: 0, w: 4, h: 2, rgba: 0xFF0000FF },
+                DrawCmd::Image { x: 0, y: 0, w: 4, h: 1, src_w: 2, src_h: 1, pixels },
+            ],
+        };
+        let img = rasterize_rgba8(4, 2, &dl);
+        assert_eq!(&img[0..4], &[255, 0, 0, 255]);
+        assert_eq!(&img[4..8], &[255, 0, 0, 255]);
+        assert_eq!(&img[8..12], &[0, 255, 0, 255]);
+        assert_eq!(&img[16..20], &[0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn repaint_region_only_touches_region() {
+        let dl = DisplayList { items: vec![DrawCmd::Rect { x: 0, y: 0, w: 4, h: 4, rgba: 0xFF00FF00 }] };
+        let mut buf = vec![7u8; 4 * 4 * 4];
+        repaint_region_rgba8(&mut buf, 4, 4, &dl, (1, 1, 2, 2));
+        assert_eq!(&buf[0..4], &[7, 7, 7, 7]);
+        assert_eq!(&buf[(4 + 1) * 4..(4 + 1) * 4 + 4], &[0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn scaled_list_rasterizes_at_device_pixels() {
+        let pixels = bytes::Bytes::from(vec![255, 0, 0, 255, 0, 255, 0, 255]);
+        let dl = DisplayList {
+            items: vec![
+                DrawCmd::Rect { x: 0, y: 0, w: 3, h: 2, rgba: 0xFF0000FF },
+                DrawCmd::PushLayer { clip_x: 0, clip_y: 1, clip_w: 3, clip_h: 1, scroll_x: 0, scroll_y: 1 },
+                DrawCmd::Image { x: 1, y: 2, w: 2, h: 1, src_w: 2, src_h: 1, pixels },
+                DrawCmd::PopLayer,
+            ],
+        };
+        let css = rasterize_rgba8(3, 2, &dl);
+        let device = rasterize_rgba8(6, 4, &scale_display_list(&dl, 2.0));
+        for (i, px) in device.chunks(4).enumerate() {
+            let (x, y) = (i % 6 / 2, i / 6 / 2);
+            assert_eq!(px, &css[(y * 3 + x) * 4..][..4], "device pixel {i}");
+        }
+        // 1.5x: rects at 1..3 and 3..4 become 2..5 and 5..6 with no gap
+        let dl = DisplayList { items: vec![DrawCmd::Rect { x: 1, y: 0, w: 2, h: 1, rgba: 0xFFFF0000 }, DrawCmd::Rect { x: 3, y: 0, w: 1, h: 1, rgba: 0xFF00FF00 }] };
+        assert_eq!(scale_display_list(&dl, 1.5).items, vec![
+            DrawCmd::Rect { x: 2, y: 0, w: 3, h: 2, rgba: 0xFFFF0000 },
+            DrawCmd::Rect { x: 5, y: 0, w: 1, h: 2, rgba: 0xFF00FF00 },
+        ]);
+    }
+
+    #[test]
+    fn layer_clips_and_scrolls_content() {
+        let red = 0xFFFF0000;
+        let dl = DisplayList {
+            items: vec![
+                DrawCmd::PushLayer { clip_x: 1, clip_y: 1, clip_w: 2, clip_h: 2, scroll_x: 0, scroll_y: 2 },
+                DrawCmd::Rect { x: 1, y: 2, w: 1, h: 1, rgba: red },
+                DrawCmd::Rect { x: 0, y: 0, w: 4, h: 1, rgba: red },
+                DrawCmd::PopLayer,
+                DrawCmd::Rect { x: 3, y: 3, w: 1, h: 1, rgba: red },
+            ],
+        };
+        let img = rasterize_rgba8(4, 4, &dl);
+        let red_at = |x: usize, y: usize| img[(y * 4 + x) * 4] == 255;
+        // (1, 2) scrolled up by 2 lands at (1, 0), outside the layer clip
+        assert!(!red_at(1, 0) && !red_at(1, 2));
+        assert!(red_at(3, 3), "commands after PopLayer are unclipped");
+        assert_eq!(img.chunks(4).filter(|p| p[0] == 255).count(), 1);
+    }
+}
@@ -1,25 +1,47 @@
-use anyhow::Result;
+pub mod backend;
+pub mod cpu;
+pub mod raster;
+#[cfg(unix)]
+pub mod service;
 
-pub fn render_solid_rgba8(width: u32, height: u32, rgba: [f32; 4]) -> Result<Vec<u8>> {
-    pollster::block_on(render_solid_rgba8_async(width, height, rgba))
+use backend::{GpuError, Preference};
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+type Device = (wgpu::Device, wgpu::Queue);
+
+/// Device per backend preference, opened on first use and kept for the
+/// process; `None` when no adapter could be opened.
+fn shared_device(pref: Preference) -> Option<Device> {
+    static DEVICES: OnceLock<Mutex<HashMap<Preference, Option<Device>>>> = OnceLock::new();
+    let mut devices = DEVICES.get_or_init(Default::default).lock().unwrap_or_else(|e| e.into_inner());
+    devices.entry(pref).or_insert_with(|| backend::open_device(pref).ok().map(|(d, q, _)| (d, q))).clone()
 }
 
-async fn render_solid_rgba8_async(width: u32, height: u32, rgba: [f32; 4]) -> Result<Vec<u8>> {
-    let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
-    let adapter = instance
-        .request_adapter(&wgpu::RequestAdapterOptions {
-            power_preference: wgpu::PowerPreference::LowPower,
-            compatible_surface: None,
-            force_fallback_adapter: false,
-        })
-        .await
-        .expect("request adapter");
-
-    let (device, queue) = adapter
-        .request_device(&wgpu::DeviceDescriptor::default())
-        .await
-        .expect("request device");
+/// Clear a `width`x`height` sRGB target to the linear color `rgba` and read it
+/// back. Without a usable adapter the frame is filled on the CPU instead.
+pub fn render_solid_rgba8(width: u32, height: u32, rgba: [f32; 4]) -> Result<Vec<u8>, GpuError> {
+    if width == 0 || height == 0 { return Err(GpuError::EmptyFrame { width, height }); }
+    let device = shared_device(Preference::from_env()?);
+    let max = device.as_ref().map_or(cpu::MAX_DIMENSION, |(d, _)| d.limits().max_texture_dimension_2d);
+    if width > max || height > max { return Err(GpuError::TooLarge { width, height, max }); }
+    match device {
+        Some((device, queue)) => pollster::block_on(render_solid_rgba8_async(&device, &queue, width, height, rgba)),
+        None => Ok(solid_cpu(width, height, rgba)),
+    }
+}
 
+fn solid_cpu(width: u32, height: u32, rgba: [f32; 4]) -> Vec<u8> {
+    let srgb = |c: f32| {
+        let c = c.clamp(0.0, 1.0);
+        let v = if c <= 0.003_130_8 { c * 12.92 } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 };
+        (v * 255.0).round() as u8
+    };
+    let px = [srgb(rgba[0]), srgb(rgba[1]), srgb(rgba[2]), (rgba[3].clamp(0.0, 1.0) * 255.0).round() as u8];
+    px.repeat(width as usize * height as usize)
+}
+
+async fn render_solid_rgba8_async(device: &wgpu::Device, queue: &wgpu::Queue, width: u32, height: u32, rgba: [f32; 4]) -> Result<Vec<u8>, GpuError> {
     let tex = device.create_texture(&wgpu::TextureDescriptor {
         label: Some("offscreen"),
         size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
@@ -53,7 +75,7 @@ async fn render_solid_rgba8_async(width: u32, height: u32, rgba: [f32; 4]) -> Re
 
     // Copy texture to buffer and map for readback
     let bytes_per_pixel = 4u32;
-    let padded_bytes_per_row = ((width * bytes_per_pixel + 255) / 256) * 256;
+    let padded_bytes_per_row = (width * bytes_per_pixel).div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT) * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
     let size = (padded_bytes_per_row * height) as usize;
     let buf = device.create_buffer(&wgpu::BufferDescriptor {
         label: Some("readback"),
@@ -71,8 +93,10 @@ async fn render_solid_rgba8_async(width: u32, height: u32, rgba: [f32; 4]) -> Re
 
     // Map and read back
     let slice = buf.slice(..);
-    slice.map_async(wgpu::MapMode::Read, |_| {});
-    device.poll(wgpu::PollType::Wait { submission_index: None, timeout: None }).ok();
+    let (tx, rx) = std::sync::mpsc::channel();
+    slice.map_async(wgpu::MapMode::Read, move |r| { let _ = tx.send(r); });
+    device.poll(wgpu::PollType::Wait { submission_index: None, timeout: None }).map_err(|e| GpuError::Readback(e.to_string()))?;
+    rx.recv().map_err(|e| GpuError::Readback(e.to_string()))?.map_err(|e| GpuError::Readback(e.to_string()))?;
     let data = slice.get_mapped_range();
 
     // Unpad rows
@@ -101,5 +125,13 @@ mod tests {
         // top-left pixel should be red (sRGB approx 255,0,0,255)
         assert!(img[0] > 200 && img[1] < 30 && img[2] < 30 && img[3] > 200);
     }
+
+    #[test]
+    fn cpu_fill_matches_srgb_clear() {
+        assert_eq!(solid_cpu(2, 1, [1.0, 0.0, 0.2, 1.0]), [255, 0, 124, 255, 255, 0, 124, 255]);
+        let gpu = render_solid_rgba8(2, 1, [1.0, 0.0, 0.2, 1.0]).unwrap();
+        assert!(gpu.iter().zip(solid_cpu(2, 1, [1.0, 0.0, 0.2, 1.0])).all(|(a, b)| a.abs_diff(b) <= 1), "{gpu:?}");
+        assert!(matches!(render_solid_rgba8(1, 1 << 20, [0.0; 4]), Err(GpuError::TooLarge { .. })));
+    }
 }
 
@@ -1,4 +1,6 @@
 use clap::Parser;
+use gpu_srv::backend::{Preference, Renderer, BACKEND_ENV};
+use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
 #[command(name = "gpu-srv", version, author, about = "Headless GPU service (Phase-1)")]
@@ -6,11 +8,70 @@ struct Cli {
     /// Render a simple triangle (stub). For now, clears to solid color.
     #[arg(long)]
     triangle: bool,
+
+    /// Rasterize a bincode-encoded DisplayList instead
+    #[arg(long, value_name = "FILE")]
+    display_list: Option<PathBuf>,
+
+    #[arg(long, default_value_t = 64)]
+    width: u32,
+
+    #[arg(long, default_value_t = 64)]
+    height: u32,
+
+    /// Tile edge in pixels
+    #[arg(long, default_value_t = gpu_srv::raster::DEFAULT_TILE_SIZE)]
+    tile_size: u32,
+
+    /// Backend: auto, software or cpu (default from GPU_SRV_BACKEND)
+    #[arg(long)]
+    backend: Option<String>,
+
+    /// Print the backend in use and exit
+    #[arg(long)]
+    info: bool,
+
+    /// Serve render jobs on a Unix socket (default $XDG_RUNTIME_DIR/gpu-srv.sock)
+    #[arg(long, value_name = "SOCKET", num_args = 0..=1, default_missing_value = "")]
+    serve: Option<PathBuf>,
+}
+
+#[cfg(unix)]
+fn serve(path: PathBuf, tile_size: u32) -> anyhow::Result<()> {
+    let path = if path.as_os_str().is_empty() { gpu_srv::service::default_socket_path() } else { path };
+    let renderer = match Renderer::new(Preference::from_env()?) {
+        Renderer::Gpu(g) => Renderer::Gpu(Box::new(g.with_tile_size(tile_size))),
+        cpu => cpu,
+    };
+    let service = gpu_srv::service::Service::with_renderer(&path, renderer)?;
+    eprintln!("gpu-srv: serving on {} ({})", path.display(), service.info());
+    Ok(service.serve()?)
 }
 
+#[cfg(not(unix))]
+fn serve(_path: PathBuf, _tile_size: u32) -> anyhow::Result<()> { anyhow::bail!("--serve needs Unix domain sockets") }
+
 fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
-    let _img = gpu_srv::render_solid_rgba8(64, 64, if cli.triangle { [0.0, 1.0, 0.0, 1.0] } else { [1.0, 0.0, 0.0, 1.0] })?;
+    if let Some(b) = &cli.backend { std::env::set_var(BACKEND_ENV, b); }
+    if cli.info {
+        let info = gpu_srv::backend::probe()?;
+        println!("GPU_BACKEND {info}");
+        for why in &info.skipped { println!("GPU_SKIPPED {why}"); }
+        return Ok(());
+    }
+    if let Some(path) = cli.serve { return serve(path, cli.tile_size); }
+    if let Some(path) = &cli.display_list {
+        let dl: message_defs::DisplayList = bincode::deserialize(&std::fs::read(path)?)?;
+        let mut renderer = match Renderer::new(Preference::from_env()?) {
+            Renderer::Gpu(g) => Renderer::Gpu(Box::new(g.with_tile_size(cli.tile_size))),
+            cpu => cpu,
+        };
+        let frame = renderer.render(cli.width, cli.height, &dl)?;
+        println!("raster ok {}x{} tiles={} {}", frame.width, frame.height, frame.stats.tiles, renderer.info());
+        return Ok(());
+    }
+    let _img = gpu_srv::render_solid_rgba8(cli.width, cli.height, if cli.triangle { [0.0, 1.0, 0.0, 1.0] } else { [1.0, 0.0, 0.0, 1.0] })?;
     if cli.triangle {
         println!("triangle ok");
     } else {
@@ -18,4 +79,3 @@ fn main() -> anyhow::Result<()> {
     }
     Ok(())
 }
-
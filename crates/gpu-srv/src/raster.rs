@@ -0,0 +1,565 @@
+//! Tiled GPU rasterizer for DisplayList. The list is flattened into clipped
+//! screen-space primitives, the frame is split into square tiles, and a tile is
+//! only re-rendered when the primitives covering it changed since the last
+//! frame; cached tiles are copied into the output as they are. Blending and
+//! image sampling use the same integer math as [`crate::cpu`], so the two paths
+//! agree to within rounding.
+
+use crate::backend::{open_device, BackendInfo, GpuError, Preference};
+use message_defs::{DisplayList, DrawCmd};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+use std::hash::{Hash, Hasher};
+use wgpu::util::DeviceExt;
+
+pub const DEFAULT_TILE_SIZE: u32 = 256;
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+const SHADER: &str = r#"
+struct VsIn {
+    @location(0) pos: vec2<f32>,
+    @location(1) color: vec4<f32>,
+    @location(2) dest: vec4<f32>,
+    @location(3) src: vec2<f32>,
+};
+
+struct VsOut {
+    @builtin(position) pos: vec4<f32>,
+    @location(0) color: vec4<f32>,
+    @location(1) @interpolate(flat) dest: vec4<f32>,
+    @location(2) @interpolate(flat) src: vec2<f32>,
+};
+
+@group(0) @binding(0) var image: texture_2d<f32>;
+
+@vertex
+fn vs_main(v: VsIn) -> VsOut {
+    return VsOut(vec4<f32>(v.pos, 0.0, 1.0), v.color, v.dest, v.src);
+}
+
+@fragment
+fn fs_main(v: VsOut) -> @location(0) vec4<f32> {
+    if (v.src.x == 0.0) { return v.color; }
+    // Nearest neighbour from the pixel's offset in the destination rect
+    let d = vec2<i32>(floor(v.pos.xy)) - vec2<i32>(v.dest.xy);
+    let t = vec2<u32>(u32(d.x) * u32(v.src.x) / u32(v.dest.z), u32(d.y) * u32(v.src.y) / u32(v.dest.w));
+    return textureLoad(image, t, 0);
+}
+"#;
+
+/// Half-open screen box `(x0, y0, x1, y1)`.
+type Box2 = (i64, i64, i64, i64);
+
+fn intersect(a: Box2, b: Box2) -> Box2 { (a.0.max(b.0), a.1.max(b.1), a.2.min(b.2), a.3.min(b.3)) }
+fn is_empty(b: Box2) -> bool { b.0 >= b.2 || b.1 >= b.3 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Paint {
+    Solid([u8; 4]),
+    /// Index into the frame's images; `dest` is the unclipped (x, y, w, h).
+    Image { image: usize, dest: (i64, i64, u32, u32) },
+}
+
+/// One visible, already clipped draw.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Prim {
+    rect: Box2,
+    paint: Paint,
+}
+
+/// Image source of a frame: content hash, size and straight-alpha pixels.
+struct Source<'a> {
+    hash: u64,
+    size: (u32, u32),
+    pixels: &'a [u8],
+}
+
+/// Resolve layers, clips and scroll offsets the way [`crate::cpu`] does.
+fn flatten<'a>(dl: &'a DisplayList, bounds: Box2) -> (Vec<Prim>, Vec<Source<'a>>) {
+    #[derive(Clone, Copy)]
+    struct Layer { ox: i64, oy: i64, clip: Box2 }
+    let root = Layer { ox: 0, oy: 0, clip: bounds };
+    let (mut cur, mut stack) = (root, Vec::new());
+    let (mut prims, mut sources) = (Vec::new(), Vec::<Source>::new());
+    for cmd in &dl.items {
+        match cmd {
+            DrawCmd::Rect { x, y, w, h, rgba } => {
+                let (sx, sy) = (*x as i64 - cur.ox, *y as i64 - cur.oy);
+                let rect = intersect((sx, sy, sx + *w as i64, sy + *h as i64), cur.clip);
+                let (a, r, g, b) = crate::cpu::unpack_argb_u32(*rgba);
+                if !is_empty(rect) && a > 0 { prims.push(Prim { rect, paint: Paint::Solid([r, g, b, a]) }); }
+            }
+            DrawCmd::Image { x, y, w, h, src_w, src_h, pixels } => {
+                if *src_w == 0 || *src_h == 0 || pixels.len() < (*src_w * *src_h * 4) as usize { continue; }
+                let (sx, sy) = (*x as i64 - cur.ox, *y as i64 - cur.oy);
+                let rect = intersect((sx, sy, sx + *w as i64, sy + *h as i64), cur.clip);
+                if is_empty(rect) { continue; }
+                let pixels = &pixels[..(*src_w * *src_h * 4) as usize];
+                let mut hasher = DefaultHasher::new();
+                (src_w, src_h, pixels).hash(&mut hasher);
+                let hash = hasher.finish();
+                let image = match sources.iter().position(|s| s.hash == hash) {
+                    Some(i) => i,
+                    None => {
+                        sources.push(Source { hash, size: (*src_w, *src_h), pixels });
+                        sources.len() - 1
+                    }
+                };
+                prims.push(Prim { rect, paint: Paint::Image { image, dest: (sx, sy, *w, *h) } });
+            }
+            DrawCmd::PushLayer { clip_x, clip_y, clip_w, clip_h, scroll_x, scroll_y } => {
+                let (lx, ly) = (*clip_x as i64 - cur.ox, *clip_y as i64 - cur.oy);
+                let clip = intersect((lx, ly, lx + *clip_w as i64, ly + *clip_h as i64), cur.clip);
+                stack.push(cur);
+                cur = Layer { ox: cur.ox + *scroll_x as i64, oy: cur.oy + *scroll_y as i64, clip };
+            }
+            DrawCmd::PopLayer => cur = stack.pop().unwrap_or(root),
+        }
+    }
+    (prims, sources)
+}
+
+/// Tiles in a frame and how many had to be rendered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TileStats {
+    pub tiles: usize,
+    pub repainted: usize,
+}
+
+/// Tightly packed RGBA8 readback of a rendered frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+    pub stats: TileStats,
+}
+
+struct CachedTile {
+    hash: u64,
+    texture: wgpu::Texture,
+}
+
+/// Tile cache and output texture of one render target, e.g. one tab.
+#[derive(Default)]
+struct Surface {
+    tiles: HashMap<(u32, u32), CachedTile>,
+    output: Option<wgpu::Texture>,
+}
+
+/// GPU resources created so far. Under a steady workload these stop growing
+/// because textures and buffers are taken from the pools.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Allocations {
+    pub textures: usize,
+    pub buffers: usize,
+}
+
+/// Spare textures kept for reuse per kind.
+const MAX_SPARE_TILES: usize = 256;
+const MAX_SPARE_OUTPUTS: usize = 4;
+
+pub struct GpuRasterizer {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    info: BackendInfo,
+    pipeline: wgpu::RenderPipeline,
+    layout: wgpu::BindGroupLayout,
+    /// Bound for solid draws, which never sample.
+    blank: wgpu::BindGroup,
+    tile_size: u32,
+    surfaces: HashMap<u64, Surface>,
+    /// Uploaded images by content hash, kept while frames still use them.
+    images: HashMap<u64, wgpu::BindGroup>,
+    spare_tiles: Vec<wgpu::Texture>,
+    spare_outputs: Vec<wgpu::Texture>,
+    /// Grown as needed and reused by every frame.
+    vertices: Option<wgpu::Buffer>,
+    readback: Option<wgpu::Buffer>,
+    allocations: Allocations,
+}
+
+impl GpuRasterizer {
+    /// Rasterizer on the best adapter [`open_device`] finds.
+    pub fn new() -> Result<Self, GpuError> {
+        let (device, queue, info) = open_device(Preference::Auto)?;
+        Ok(Self::with_device(device, queue, info))
+    }
+
+    pub fn with_device(device: wgpu::Device, queue: wgpu::Queue, info: BackendInfo) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("image"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture { sample_type: wgpu::TextureSampleType::Float { filterable: false }, view_dimension: wgpu::TextureViewDimension::D2, multisampled: false },
+                count: None,
+            }],
+        });
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor { label: Some("raster"), source: wgpu::ShaderSource::Wgsl(SHADER.into()) });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor { label: Some("raster"), bind_group_layouts: &[&layout], push_constant_ranges: &[] });
+        let attributes = wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x4, 2 => Float32x4, 3 => Float32x2];
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("raster"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[wgpu::VertexBufferLayout { array_stride: VERTEX_BYTES as u64, step_mode: wgpu::VertexStepMode::Vertex, attributes: &attributes }],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: Some("fs_main"),
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: FORMAT,
+                    // Straight alpha over an opaque destination, which stays opaque
+                    blend: Some(wgpu::BlendState {
+                        color: wgpu::BlendComponent { src_factor: wgpu::BlendFactor::SrcAlpha, dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha, operation: wgpu::BlendOperation::Add },
+                        alpha: wgpu::BlendComponent { src_factor: wgpu::BlendFactor::Zero, dst_factor: wgpu::BlendFactor::One, operation: wgpu::BlendOperation::Add },
+                    }),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+        let blank = upload(&device, &queue, &layout, (1, 1), &[0; 4]);
+        Self {
+            device, queue, info, pipeline, layout, blank,
+            tile_size: DEFAULT_TILE_SIZE,
+            surfaces: HashMap::new(),
+            images: HashMap::new(),
+            spare_tiles: Vec::new(),
+            spare_outputs: Vec::new(),
+            vertices: None,
+            readback: None,
+            allocations: Allocations { textures: 1, buffers: 0 },
+        }
+    }
+
+    /// Use `size`-pixel tiles (default [`DEFAULT_TILE_SIZE`]); drops all caches.
+    pub fn with_tile_size(mut self, size: u32) -> Self {
+        self.tile_size = size.clamp(1, self.device.limits().max_texture_dimension_2d);
+        self.surfaces.clear();
+        self.spare_tiles.clear();
+        self
+    }
+
+    pub fn tile_size(&self) -> u32 { self.tile_size }
+
+    /// Adapter the rasterizer runs on.
+    pub fn backend(&self) -> &BackendInfo { &self.info }
+
+    pub fn allocations(&self) -> Allocations { self.allocations }
+
+    fn texture(&mut self, label: &str, width: u32, height: u32, usage: wgpu::TextureUsages) -> wgpu::Texture {
+        self.allocations.textures += 1;
+        self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: FORMAT,
+            usage,
+            view_formats: &[],
+        })
+    }
+
+    /// `slot` (vertices or readback) with room for `size` bytes; grows by doubling.
+    fn buffer(&mut self, readback: bool, size: u64) -> wgpu::Buffer {
+        let slot = if readback { &self.readback } else { &self.vertices };
+        if let Some(b) = slot.as_ref().filter(|b| b.size() >= size) { return b.clone(); }
+        let usage = if readback { wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ } else { wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST };
+        let b = self.device.create_buffer(&wgpu::BufferDescriptor { label: Some(if readback { "readback" } else { "vertices" }), size: size.next_power_of_two().max(4096), usage, mapped_at_creation: false });
+        self.allocations.buffers += 1;
+        *(if readback { &mut self.readback } else { &mut self.vertices }) = Some(b.clone());
+        b
+    }
+
+    /// Rasterize `dl` onto an opaque black `width`x`height` frame.
+    pub fn render(&mut self, width: u32, height: u32, dl: &DisplayList) -> Result<Frame, GpuError> { self.render_surface(0, width, height, dl) }
+
+    /// [`Self::render`] with a separate tile cache per `surface`, so that
+    /// interleaved targets do not evict each other's tiles.
+    pub fn render_surface(&mut self, surface: u64, width: u32, height: u32, dl: &DisplayList) -> Result<Frame, GpuError> {
+        if width == 0 || height == 0 { return Err(GpuError::EmptyFrame { width, height }); }
+        let max = self.device.limits().max_texture_dimension_2d;
+        if width > max || height > max { return Err(GpuError::TooLarge { width, height, max }); }
+        let (prims, sources) = flatten(dl, (0, 0, width as i64, height as i64));
+        let ts = self.tile_size;
+        let (cols, rows) = (width.div_ceil(ts), height.div_ceil(ts));
+
+        // Primitives per tile, in paint order
+        let mut per_tile: Vec<Vec<&Prim>> = vec![Vec::new(); (cols * rows) as usize];
+        for p in &prims {
+            let (c0, r0) = ((p.rect.0 / ts as i64) as u32, (p.rect.1 / ts as i64) as u32);
+            let (c1, r1) = (((p.rect.2 - 1) / ts as i64) as u32, ((p.rect.3 - 1) / ts as i64) as u32);
+            for r in r0..=r1 {
+                for c in c0..=c1 { per_tile[(r * cols + c) as usize].push(p); }
+            }
+        }
+
+        let mut state = self.surfaces.remove(&surface).unwrap_or_default();
+        let stale: Vec<(u32, u32)> = state.tiles.keys().copied().filter(|&(c, r)| c >= cols || r >= rows).collect();
+        for k in stale { self.spare_tiles.extend(state.tiles.remove(&k).map(|t| t.texture)); }
+        if let Some(out) = state.output.take() {
+            if out.width() == width && out.height() == height { state.output = Some(out); } else { self.spare_outputs.push(out); }
+        }
+        let output = match state.output.take() {
+            Some(t) => t,
+            None => match self.spare_outputs.iter().position(|t| t.width() == width && t.height() == height) {
+                Some(i) => self.spare_outputs.swap_remove(i),
+                None => self.texture("frame", width, height, wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::COPY_SRC),
+            },
+        };
+        self.spare_outputs.truncate(MAX_SPARE_OUTPUTS);
+
+        // Find the tiles to repaint and build all their vertices into one buffer
+        let mut dirty = Vec::new();
+        let mut data = Vec::new();
+        for r in 0..rows {
+            for c in 0..cols {
+                let origin = ((c * ts) as i64, (r * ts) as i64);
+                let bounds = (origin.0, origin.1, (origin.0 + ts as i64).min(width as i64), (origin.1 + ts as i64).min(height as i64));
+                let list = &per_tile[(r * cols + c) as usize];
+                let mut hasher = DefaultHasher::new();
+                bounds.hash(&mut hasher);
+                for p in list {
+                    (intersect(p.rect, bounds), p.paint).hash(&mut hasher);
+                    if let Paint::Image { image, .. } = p.paint { sources[image].hash.hash(&mut hasher); }
+                }
+                let hash = hasher.finish();
+                if state.tiles.get(&(c, r)).is_some_and(|t| t.hash == hash) { continue; }
+                let first = (data.len() / VERTEX_BYTES) as u32;
+                self.tile_vertices(&mut data, origin, list, &sources);
+                dirty.push(((c, r), hash, first));
+            }
+        }
+        let used: HashSet<u64> = sources.iter().map(|s| s.hash).collect();
+        self.images.retain(|h, _| used.contains(h));
+        for s in &sources {
+            if !self.images.contains_key(&s.hash) {
+                let bg = upload(&self.device, &self.queue, &self.layout, s.size, s.pixels);
+                self.allocations.textures += 1;
+                self.images.insert(s.hash, bg);
+            }
+        }
+        let vertices = (!data.is_empty()).then(|| {
+            let b = self.buffer(false, data.len() as u64);
+            self.queue.write_buffer(&b, 0, &data);
+            b
+        });
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("raster") });
+        for &((c, r), hash, first) in &dirty {
+            let texture = match state.tiles.remove(&(c, r)) {
+                Some(t) => t.texture,
+                None => match self.spare_tiles.pop() {
+                    Some(t) => t,
+                    None => self.texture("tile", ts, ts, wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC),
+                },
+            };
+            self.draw_tile(&mut encoder, &texture, vertices.as_ref(), first, &per_tile[(r * cols + c) as usize], &sources);
+            state.tiles.insert((c, r), CachedTile { hash, texture });
+        }
+        for ((c, r), tile) in &state.tiles {
+            let w = (width - c * ts).min(ts);
+            let h = (height - r * ts).min(ts);
+            encoder.copy_texture_to_texture(
+                tile.texture.as_image_copy(),
+                wgpu::TexelCopyTextureInfo { texture: &output, mip_level: 0, origin: wgpu::Origin3d { x: c * ts, y: r * ts, z: 0 }, aspect: wgpu::TextureAspect::All },
+                wgpu::Extent3d { width: w, height: h, depth_or_array_layers: 1 },
+            );
+        }
+        let rgba = self.read_back(encoder, &output, width, height);
+        state.output = Some(output);
+        self.surfaces.insert(surface, state);
+        Ok(Frame { width, height, rgba: rgba?, stats: TileStats { tiles: per_tile.len(), repainted: dirty.len() } })
+    }
+
+    /// Drop `surface`'s caches, returning its textures to the pools.
+    pub fn release_surface(&mut self, surface: u64) {
+        let Some(state) = self.surfaces.remove(&surface) else { return };
+        self.spare_tiles.extend(state.tiles.into_values().map(|t| t.texture));
+        self.spare_tiles.truncate(MAX_SPARE_TILES);
+        self.spare_outputs.extend(state.output);
+        self.spare_outputs.truncate(MAX_SPARE_OUTPUTS);
+    }
+
+    fn tile_vertices(&self, data: &mut Vec<u8>, origin: (i64, i64), list: &[&Prim], sources: &[Source]) {
+        let ts = self.tile_size as f32;
+        for p in list {
+            let (x0, y0, x1, y1) = ((p.rect.0 - origin.0) as f32, (p.rect.1 - origin.1) as f32, (p.rect.2 - origin.0) as f32, (p.rect.3 - origin.1) as f32);
+            let (color, dest, src) = match p.paint {
+                Paint::Solid(c) => (c.map(|v| v as f32 / 255.0), [0.0; 4], [0.0; 2]),
+                Paint::Image { image, dest } => {
+                    let size = sources[image].size;
+                    ([0.0; 4], [(dest.0 - origin.0) as f32, (dest.1 - origin.1) as f32, dest.2 as f32, dest.3 as f32], [size.0 as f32, size.1 as f32])
+                }
+            };
+            for (x, y) in [(x0, y0), (x1, y0), (x0, y1), (x0, y1), (x1, y0), (x1, y1)] {
+                let pos = [x / ts * 2.0 - 1.0, 1.0 - y / ts * 2.0];
+                for v in pos.iter().chain(&color).chain(&dest).chain(&src) { data.extend_from_slice(&v.to_le_bytes()); }
+            }
+        }
+    }
+
+    /// Clear `texture` and draw `list`, whose vertices start at `first` in `vertices`.
+    fn draw_tile(&self, encoder: &mut wgpu::CommandEncoder, texture: &wgpu::Texture, vertices: Option<&wgpu::Buffer>, first: u32, list: &[&Prim], sources: &[Source]) {
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("tile"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &view,
+                resolve_target: None,
+                depth_slice: None,
+                ops: wgpu::Operations { load: wgpu::LoadOp::Clear(wgpu::Color::BLACK), store: wgpu::StoreOp::Store },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        let Some(vertices) = vertices.filter(|_| !list.is_empty()) else { return };
+        pass.set_pipeline(&self.pipeline);
+        pass.set_vertex_buffer(0, vertices.slice(..));
+        // One draw per run of primitives sharing a texture
+        let mut start = 0;
+        while start < list.len() {
+            let group = |p: &Prim| match p.paint { Paint::Solid(_) => None, Paint::Image { image, .. } => Some(sources[image].hash) };
+            let key = group(list[start]);
+            let end = (start..list.len()).find(|&i| group(list[i]) != key).unwrap_or(list.len());
+            pass.set_bind_group(0, key.map_or(&self.blank, |h| &self.images[&h]), &[]);
+            pass.draw(first + (start * 6) as u32..first + (end * 6) as u32, 0..1);
+            start = end;
+        }
+    }
+
+    fn read_back(&mut self, mut encoder: wgpu::CommandEncoder, output: &wgpu::Texture, width: u32, height: u32) -> Result<Vec<u8>, GpuError> {
+        let row = width * 4;
+        let padded = row.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT) * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let buf = self.buffer(true, (padded * height) as u64);
+        encoder.copy_texture_to_buffer(
+            output.as_image_copy(),
+            wgpu::TexelCopyBufferInfo { buffer: &buf, layout: wgpu::TexelCopyBufferLayout { offset: 0, bytes_per_row: Some(padded), rows_per_image: Some(height) } },
+            wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+        );
+        self.queue.submit(std::iter::once(encoder.finish()));
+        let slice = buf.slice(..(padded * height) as u64);
+        let (tx, rx) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |r| { let _ = tx.send(r); });
+        self.device.poll(wgpu::PollType::Wait { submission_index: None, timeout: None }).map_err(|e| GpuError::Readback(e.to_string()))?;
+        rx.recv().map_err(|e| GpuError::Readback(e.to_string()))?.map_err(|e| GpuError::Readback(e.to_string()))?;
+        let data = slice.get_mapped_range();
+        let mut out = Vec::with_capacity((row * height) as usize);
+        for y in 0..height as usize { out.extend_from_slice(&data[y * padded as usize..][..row as usize]); }
+        drop(data);
+        buf.unmap();
+        Ok(out)
+    }
+}
+
+/// Upload tightly packed RGBA8 `pixels` as a texture bound at binding 0 of
+/// `layout`, which must declare a non-filterable float 2D texture there.
+pub fn upload(device: &wgpu::Device, queue: &wgpu::Queue, layout: &wgpu::BindGroupLayout, size: (u32, u32), pixels: &[u8]) -> wgpu::BindGroup {
+    let texture = device.create_texture_with_data(
+        queue,
+        &wgpu::TextureDescriptor {
+            label: Some("image"),
+            size: wgpu::Extent3d { width: size.0, height: size.1, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        },
+        wgpu::util::TextureDataOrder::LayerMajor,
+        pixels,
+    );
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    device.create_bind_group(&wgpu::BindGroupDescriptor { label: Some("image"), layout, entries: &[wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(&view) }] })
+}
+
+/// pos (2) + color (4) + dest (4) + src (2) floats.
+const VERTEX_BYTES: usize = 12 * 4;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::rasterize_rgba8;
+
+    fn assert_close(gpu: &[u8], cpu: &[u8]) {
+        assert_eq!(gpu.len(), cpu.len());
+        let worst = gpu.iter().zip(cpu).map(|(a, b)| a.abs_diff(*b)).max().unwrap_or(0);
+        assert!(worst <= 1, "GPU and CPU differ by up to {worst}");
+    }
+
+    fn scene(offset: u32) -> DisplayList {
+        let pixels = bytes::Bytes::from((0..6u8).flat_map(|i| [i * 40, 255 - i * 40, 128, 255]).collect::<Vec<u8>>());
+        DisplayList {
+            items: vec![
+                DrawCmd::Rect { x: 0, y: 0, w: 100, h: 70, rgba: 0xFF203040 },
+                DrawCmd::Rect { x: 5 + offset, y: 5, w: 30, h: 20, rgba: 0xFFFF0000 },
+                DrawCmd::Rect { x: 20, y: 10, w: 60, h: 40, rgba: 0x8000FF00 },
+                DrawCmd::PushLayer { clip_x: 40, clip_y: 30, clip_w: 35, clip_h: 25, scroll_x: 0, scroll_y: 10 },
+                DrawCmd::Image { x: 30, y: 30, w: 50, h: 30, src_w: 3, src_h: 2, pixels },
+                DrawCmd::Rect { x: 0, y: 0, w: 200, h: 45, rgba: 0x400000FF },
+                DrawCmd::PopLayer,
+                DrawCmd::Rect { x: 90, y: 60, w: 40, h: 40, rgba: 0xFFFFFFFF },
+            ],
+        }
+    }
+
+    #[test]
+    fn matches_cpu_rasterizer_across_tiles() {
+        let mut gpu = GpuRasterizer::new().expect("adapter").with_tile_size(16);
+        let frame = gpu.render(100, 70, &scene(0)).unwrap();
+        assert_eq!((frame.width, frame.height), (100, 70));
+        assert_eq!(frame.stats, TileStats { tiles: 7 * 5, repainted: 7 * 5 });
+        assert_close(&frame.rgba, &rasterize_rgba8(100, 70, &scene(0)));
+        assert!(matches!(gpu.render(0, 10, &scene(0)), Err(GpuError::EmptyFrame { .. })));
+        assert!(matches!(gpu.render(1 << 20, 10, &scene(0)), Err(GpuError::TooLarge { .. })));
+    }
+
+    #[test]
+    fn unchanged_tiles_come_from_the_cache() {
+        let mut gpu = GpuRasterizer::new().expect("adapter").with_tile_size(32);
+        let first = gpu.render(100, 70, &scene(0)).unwrap();
+        let again = gpu.render(100, 70, &scene(0)).unwrap();
+        assert_eq!(again.stats.repainted, 0);
+        assert_eq!(again.rgba, first.rgba);
+        // Moving the red rect by 4px touches only the tiles under its old and new position
+        let moved = gpu.render(100, 70, &scene(4)).unwrap();
+        assert_eq!(moved.stats, TileStats { tiles: 4 * 3, repainted: 2 });
+        assert_close(&moved.rgba, &rasterize_rgba8(100, 70, &scene(4)));
+        // A resize re-renders the edge tiles whose bounds changed
+        let resized = gpu.render(90, 70, &scene(4)).unwrap();
+        assert_eq!(resized.stats.repainted, 3);
+        assert_close(&resized.rgba, &rasterize_rgba8(90, 70, &scene(4)));
+    }
+
+    #[test]
+    fn surfaces_keep_their_own_tiles_and_steady_state_reuses_resources() {
+        let mut gpu = GpuRasterizer::new().expect("adapter").with_tile_size(32);
+        gpu.render_surface(1, 100, 70, &scene(0)).unwrap();
+        gpu.render_surface(2, 100, 70, &scene(4)).unwrap();
+        assert_eq!(gpu.render_surface(1, 100, 70, &scene(0)).unwrap().stats.repainted, 0);
+        assert_eq!(gpu.render_surface(2, 100, 70, &scene(4)).unwrap().stats.repainted, 0);
+
+        // Released textures are picked up again by the next surface
+        gpu.release_surface(2);
+        let before = gpu.allocations();
+        let frame = gpu.render_surface(3, 100, 70, &scene(0)).unwrap();
+        assert_eq!(frame.stats.repainted, 12);
+        assert_close(&frame.rgba, &rasterize_rgba8(100, 70, &scene(0)));
+        for offset in 0..8 { gpu.render_surface(3, 100, 70, &scene(offset)).unwrap(); }
+        assert_eq!(gpu.allocations(), before);
+    }
+}
@@ -0,0 +1,344 @@
+//! Service mode: gpu-srv as a long-lived process on a Unix socket. The device is
+//! opened once and shared by all connections; each connection renders into its
+//! own surfaces, whose tile caches and textures persist between jobs and go back
+//! to the rasterizer's pools when released. Frames come back inline or through a
+//! per-connection shared-memory file. Messages use event-packet's length-prefixed
+//! bincode framing and are at most [`MAX_FRAME`] bytes either way; larger frames
+//! must come back shared. At most [`MAX_CONNECTIONS`] clients are served at once
+//! unless [`Service::max_connections`] says otherwise.
+
+use crate::backend::{BackendInfo, GpuError, Preference, Renderer};
+use crate::raster::{Frame, TileStats};
+use message_defs::DisplayList;
+use event_packet::ConnectionLimit;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::File;
+use std::os::unix::fs::OpenOptionsExt;
+use std::io::{self, BufReader, Read, Write};
+use std::os::unix::fs::FileExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Large enough for a 4096x4096 frame plus framing.
+pub const MAX_FRAME: usize = 80 << 20;
+
+/// Inline pixels must leave room for the rest of the reply in one frame.
+const MAX_INLINE: usize = MAX_FRAME - 4096;
+
+/// Clients served at once by default; further connections wait to be accepted.
+pub const MAX_CONNECTIONS: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RenderJob {
+    /// Chosen by the client and echoed in the reply.
+    pub id: u64,
+    /// Render target on this connection; jobs for the same surface reuse its tiles.
+    pub surface: u32,
+    pub width: u32,
+    pub height: u32,
+    pub display_list: DisplayList,
+    /// Return pixels through the connection's shared-memory file instead of inline.
+    pub shared: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Request {
+    Render(RenderJob),
+    Info,
+    /// Drop a surface's caches; its textures are reused by later jobs.
+    ReleaseSurface(u32),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Pixels {
+    Inline(Vec<u8>),
+    /// The first `len` bytes of the file at `path`, valid until the next job on
+    /// this connection. The file is removed when the connection closes.
+    Shared { path: PathBuf, len: usize },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Response {
+    Frame { id: u64, width: u32, height: u32, stats: TileStats, pixels: Pixels },
+    Info(BackendInfo),
+    Released,
+    Error { id: Option<u64>, message: String },
+}
+
+pub fn write_frame<T: Serialize>(w: impl Write, msg: &T) -> io::Result<()> { event_packet::write_frame(w, msg, MAX_FRAME) }
+
+pub fn read_frame<T: DeserializeOwned>(r: impl Read) -> io::Result<T> { event_packet::read_frame(r, MAX_FRAME) }
+
+/// Socket path used when none is given: `$XDG_RUNTIME_DIR/gpu-srv.sock`, else the temp dir.
+pub fn default_socket_path() -> PathBuf {
+    std::env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from).unwrap_or_else(std::env::temp_dir).join("gpu-srv.sock")
+}
+
+/// Directory for shared frames: `/dev/shm` where it exists, else the temp dir.
+fn shm_dir() -> PathBuf {
+    let shm = Path::new("/dev/shm");
+    if shm.is_dir() { shm.to_path_buf() } else { std::env::temp_dir() }
+}
+
+/// Connection-local surface ids mapped into the rasterizer's id space.
+fn surface_key(conn: u64, surface: u32) -> u64 { conn << 32 | surface as u64 }
+
+/// Per-connection state: its surfaces and its shared-memory file.
+struct Conn {
+    id: u64,
+    surfaces: HashSet<u32>,
+    shm: Option<(PathBuf, File)>,
+}
+
+impl Conn {
+    fn share(&mut self, rgba: &[u8]) -> io::Result<Pixels> {
+        if self.shm.is_none() {
+            let path = shm_dir().join(format!("gpu-srv-{}-{}.rgba", std::process::id(), self.id));
+            let file = File::options().read(true).write(true).create(true).truncate(true).mode(0o600).open(&path)?;
+            self.shm = Some((path, file));
+        }
+        let Some((path, file)) = &self.shm else { unreachable!() };
+        // Only grow, so a reader of a smaller earlier frame never sees the file shrink
+        if file.metadata()?.len() < rgba.len() as u64 { file.set_len(rgba.len() as u64)?; }
+        file.write_all_at(rgba, 0)?;
+        Ok(Pixels::Shared { path: path.clone(), len: rgba.len() })
+    }
+}
+
+impl Drop for Conn {
+    fn drop(&mut self) {
+        if let Some((path, _)) = self.shm.take() { let _ = std::fs::remove_file(path); }
+    }
+}
+
+struct Shared {
+    renderer: Mutex<Renderer>,
+}
+
+impl Shared {
+    fn renderer(&self) -> std::sync::MutexGuard<'_, Renderer> { self.renderer.lock().unwrap_or_else(|e| e.into_inner()) }
+
+    fn render(&self, conn: &mut Conn, job: RenderJob) -> Response {
+        let frame = self.renderer().render_surface(surface_key(conn.id, job.surface), job.width, job.height, &job.display_list);
+        conn.surfaces.insert(job.surface);
+        let Frame { width, height, rgba, stats } = match frame {
+            Ok(f) => f,
+            Err(e) => return Response::Error { id: Some(job.id), message: e.to_string() },
+        };
+        if !job.shared && rgba.len() > MAX_INLINE {
+            return Response::Error { id: Some(job.id), message: format!("{width}x{height} frame is too large to return inline; request shared pixels") };
+        }
+        let pixels = if job.shared {
+            match conn.share(&rgba) {
+                Ok(p) => p,
+                Err(e) => return Response::Error { id: Some(job.id), message: e.to_string() },
+            }
+        } else {
+            Pixels::Inline(rgba)
+        };
+        Response::Frame { id: job.id, width, height, stats, pixels }
+    }
+
+    fn connection(&self, id: u64, stream: UnixStream) -> io::Result<()> {
+        let mut conn = Conn { id, surfaces: HashSet::new(), shm: None };
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let result = (|| {
+            while let Ok(req) = read_frame::<Request>(&mut reader) {
+                let reply = match req {
+                    Request::Render(job) => self.render(&mut conn, job),
+                    Request::Info => Response::Info(self.renderer().info().clone()),
+                    Request::ReleaseSurface(s) => {
+                        conn.surfaces.remove(&s);
+                        self.renderer().release_surface(surface_key(id, s));
+                        Response::Released
+                    }
+                };
+                write_frame(&stream, &reply)?;
+            }
+            Ok(())
+        })();
+        let mut renderer = self.renderer();
+        for s in conn.surfaces.drain() { renderer.release_surface(surface_key(id, s)); }
+        result
+    }
+}
+
+/// Listening service; [`Service::serve`] runs until the listener fails.
+pub struct Service {
+    listener: UnixListener,
+    shared: Arc<Shared>,
+    max_connections: usize,
+}
+
+impl Service {
+    /// Open the device per `pref` and bind `path`, replacing a stale socket
+    /// file left by an earlier run.
+    pub fn bind(path: impl AsRef<Path>, pref: Preference) -> io::Result<Self> { Self::with_renderer(path, Renderer::new(pref)) }
+
+    pub fn with_renderer(path: impl AsRef<Path>, renderer: Renderer) -> io::Result<Self> {
+        let path = path.as_ref();
+        if UnixStream::connect(path).is_err() { let _ = std::fs::remove_file(path); }
+        let listener = UnixListener::bind(path)?;
+        let shared = Arc::new(Shared { renderer: Mutex::new(renderer) });
+        Ok(Self { listener, shared, max_connections: MAX_CONNECTIONS })
+    }
+
+    pub fn max_connections(mut self, n: usize) -> Self {
+        self.max_connections = n.max(1);
+        self
+    }
+
+    pub fn info(&self) -> BackendInfo { self.shared.renderer().info().clone() }
+
+    pub fn serve(self) -> io::Result<()> {
+        let next_conn = AtomicU64::new(1);
+        let limit = ConnectionLimit::new(self.max_connections);
+        loop {
+            let slot = limit.acquire();
+            let (stream, _) = self.listener.accept()?;
+            let shared = self.shared.clone();
+            let conn = next_conn.fetch_add(1, Ordering::Relaxed);
+            thread::spawn(move || {
+                let _slot = slot;
+                shared.connection(conn, stream)
+            });
+        }
+    }
+}
+
+/// Blocking client for one connection; jobs on it are answered in order.
+pub struct Client {
+    stream: UnixStream,
+    next_id: u64,
+}
+
+impl Client {
+    pub fn connect(path: impl AsRef<Path>) -> io::Result<Self> { Ok(Self { stream: UnixStream::connect(path)?, next_id: 0 }) }
+
+    fn call(&mut self, req: &Request) -> Result<Response, GpuError> {
+        write_frame(&self.stream, req)?;
+        match read_frame(&self.stream)? {
+            Response::Error { message, .. } => Err(GpuError::Service(message)),
+            r => Ok(r),
+        }
+    }
+
+    /// Render `dl` into `surface`, reading shared pixels back into the frame.
+    pub fn render(&mut self, surface: u32, width: u32, height: u32, dl: &DisplayList, shared: bool) -> Result<Frame, GpuError> {
+        self.next_id += 1;
+        let job = RenderJob { id: self.next_id, surface, width, height, display_list: dl.clone(), shared };
+        match self.call(&Request::Render(job))? {
+            Response::Frame { id, width, height, stats, pixels } if id == self.next_id => {
+                let rgba = match pixels {
+                    Pixels::Inline(rgba) => rgba,
+                    Pixels::Shared { path, len } => {
+                        let mut rgba = vec![0; len];
+                        File::open(path)?.read_exact_at(&mut rgba, 0)?;
+                        rgba
+                    }
+                };
+                Ok(Frame { width, height, rgba, stats })
+            }
+            other => Err(GpuError::Service(format!("unexpected reply {other:?}"))),
+        }
+    }
+
+    pub fn info(&mut self) -> Result<BackendInfo, GpuError> {
+        match self.call(&Request::Info)? {
+            Response::Info(info) => Ok(info),
+            other => Err(GpuError::Service(format!("unexpected reply {other:?}"))),
+        }
+    }
+
+    pub fn release_surface(&mut self, surface: u32) -> Result<(), GpuError> {
+        match self.call(&Request::ReleaseSurface(surface))? {
+            Response::Released => Ok(()),
+            other => Err(GpuError::Service(format!("unexpected reply {other:?}"))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use message_defs::DrawCmd;
+
+    #[test]
+    fn renders_inline_and_shared_frames_over_a_socket() {
+        let dir = std::env::temp_dir().join(format!("gpu-srv-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("gpu.sock");
+        let service = Service::bind(&path, Preference::Auto).unwrap();
+        let info = service.info();
+        thread::spawn(move || service.serve());
+
+        let dl = |x: u32| DisplayList {
+            items: vec![DrawCmd::Rect { x: 0, y: 0, w: 40, h: 30, rgba: 0xFF102030 }, DrawCmd::Rect { x, y: 2, w: 5, h: 5, rgba: 0xFF00FF00 }],
+        };
+        let mut c = Client::connect(&path).unwrap();
+        assert_eq!(c.info().unwrap(), info);
+        let inline = c.render(1, 40, 30, &dl(3), false).unwrap();
+        assert_eq!(inline.rgba, crate::cpu::rasterize_rgba8(40, 30, &dl(3)));
+        let shared = c.render(1, 40, 30, &dl(3), true).unwrap();
+        assert_eq!(shared.rgba, inline.rgba);
+        if info.kind != crate::backend::BackendKind::Cpu { assert_eq!(shared.stats.repainted, 0, "surface kept its tiles"); }
+
+        // Another connection's surface 1 is a different surface
+        let mut other = Client::connect(&path).unwrap();
+        assert_eq!(other.render(1, 40, 30, &dl(20), true).unwrap().rgba, crate::cpu::rasterize_rgba8(40, 30, &dl(20)));
+        assert!(matches!(other.render(2, 0, 30, &dl(0), false), Err(GpuError::Service(_))));
+        other.release_surface(1).unwrap();
+        drop(other);
+        assert_eq!(c.render(1, 40, 30, &dl(4), false).unwrap().rgba, crate::cpu::rasterize_rgba8(40, 30, &dl(4)));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn shared_frames_are_private_to_the_owner() {
+        use std::os::unix::fs::PermissionsExt;
+        let mut conn = Conn { id: u64::from(u32::MAX) + 7, surfaces: HashSet::new(), shm: None };
+        let Pixels::Shared { path, .. } = conn.share(&[1, 2, 3, 4]).unwrap() else { panic!("expected shared pixels") };
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        drop(conn);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn frames_are_bounded_both_ways() {
+        let mut huge = Vec::new();
+        huge.extend_from_slice(&(MAX_FRAME as u32 + 1).to_le_bytes());
+        assert_eq!(read_frame::<Request>(&huge[..]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        // A small frame cannot claim a larger payload inside it
+        let mut lying = Vec::new();
+        write_frame(&mut lying, &Response::Frame { id: 1, width: 1, height: 1, stats: TileStats::default(), pixels: Pixels::Inline(vec![0; 4]) }).unwrap();
+        let n = lying.len();
+        lying[n - 12..n - 4].copy_from_slice(&(u64::MAX / 2).to_le_bytes());
+        assert!(read_frame::<Response>(&lying[..]).is_err());
+        let big = Response::Frame { id: 1, width: 1, height: 1, stats: TileStats::default(), pixels: Pixels::Inline(vec![0; MAX_FRAME]) };
+        assert_eq!(write_frame(io::sink(), &big).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn connections_beyond_the_cap_wait() {
+        let dir = std::env::temp_dir().join(format!("gpu-srv-cap-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("gpu.sock");
+        let service = Service::with_renderer(&path, Renderer::new(Preference::Cpu)).unwrap().max_connections(1);
+        thread::spawn(move || service.serve());
+
+        let mut first = Client::connect(&path).unwrap();
+        first.info().unwrap();
+        let mut second = Client::connect(&path).unwrap();
+        second.stream.set_read_timeout(Some(std::time::Duration::from_millis(200))).unwrap();
+        assert!(second.info().is_err(), "not served while the first client is connected");
+        drop(first);
+        second.stream.set_read_timeout(None).unwrap();
+        assert_eq!(second.info().unwrap().kind, crate::backend::BackendKind::Cpu);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,16 +1,19 @@
 use std::env;
 use std::os::unix::net::UnixListener;
-use std::sync::mpsc;
 use std::thread;
 
 
 
 use event_packet::{read_len_prefixed, Message};
+use gpu_compositor::schedule::FrameScheduler;
+use gpu_compositor::{GpuCompositor, Layer, SurfaceHandle};
+use gpu_srv::backend::Preference;
+use tab_manager::{TabId, TabManager};
 use texture_verify::is_roughly_color_rgba8;
 
 use winit::application::ApplicationHandler;
 use winit::event::WindowEvent;
-use winit::event_loop::{ActiveEventLoop, ControlFlow, EventLoop};
+use winit::event_loop::{ActiveEventLoop, ControlFlow, EventLoop, EventLoopProxy};
 use winit::window::{Window, WindowAttributes};
 
 const SHADER: &str = r#"
@@ -87,17 +90,42 @@ struct GpuState {
 struct App {
     window: Option<Window>,
     gpu: Option<GpuState>,
-    rx: mpsc::Receiver<Msg>,
     clear_ok: bool,
     pending: Option<PendingFrame>,
+    /// Paces redraws; frames arriving between vsyncs are coalesced.
+    scheduler: FrameScheduler<PendingFrame>,
+    /// The window's one tab, which is also its producer id in `scheduler`.
+    tab: TabId,
+    tabs: TabManager,
+    /// Tracks the tab's visibility only; frames are drawn by [`App::render`].
+    compositor: GpuCompositor,
+    started: std::time::Instant,
     screenshot_pending: bool,
     screenshot_done: bool,
     pending_quit: bool,
 }
 
 impl App {
-    fn new(rx: mpsc::Receiver<Msg>) -> Self {
-        Self { window: None, gpu: None, rx, clear_ok: true, pending: None, screenshot_pending: true, screenshot_done: false, pending_quit: false }
+    fn new() -> Self {
+        let mut tabs = TabManager::new();
+        let tab = tabs.new_tab("uds:");
+        let mut compositor = GpuCompositor::new().with_preference(Preference::Cpu);
+        let (w, h) = compositor.output_size();
+        compositor.add_surface(tab, SurfaceHandle::new(tab, w, h));
+        Self { window: None, gpu: None, clear_ok: true, pending: None, scheduler: FrameScheduler::new(60), tab, tabs, compositor, started: std::time::Instant::now(), screenshot_pending: true, screenshot_done: false, pending_quit: false }
+    }
+
+    /// Show or hide the tab's layer and let tab-manager throttle or freeze it.
+    fn set_occluded(&mut self, occluded: bool) {
+        self.compositor.set_layer(self.tab, Layer { visible: !occluded, ..Default::default() });
+        if let Err(e) = self.compositor.render_frame() { eprintln!("visibility: {e}"); }
+        self.sync_tabs();
+    }
+
+    /// Pass visibility changes and freezes on to the scheduler's rate for the tab.
+    fn sync_tabs(&mut self) {
+        self.compositor.sync_tabs(&mut self.tabs, self.started.elapsed());
+        self.scheduler.set_rate(self.tab, self.tabs.frame_rate(self.tab));
     }
 
     fn init_gpu(&mut self) {
@@ -426,7 +454,7 @@ impl App {
     }
 }
 
-impl ApplicationHandler for App {
+impl ApplicationHandler<Msg> for App {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
         let attrs = WindowAttributes::default().with_title("Monazite Phase-0");
         self.window = Some(event_loop.create_window(attrs).expect("create window"));
@@ -438,6 +466,7 @@ impl ApplicationHandler for App {
     fn window_event(&mut self, event_loop: &ActiveEventLoop, _id: winit::window::WindowId, event: WindowEvent) {
         match event {
             WindowEvent::CloseRequested => event_loop.exit(),
+            WindowEvent::Occluded(occluded) => self.set_occluded(occluded),
             WindowEvent::Resized(_) | WindowEvent::ScaleFactorChanged { .. } => {
                 self.reconfigure();
                 if let Some(w) = self.window.as_ref() { w.request_redraw(); }
@@ -454,23 +483,49 @@ impl ApplicationHandler for App {
         }
     }
 
+    /// Messages from the UDS thread; each one wakes the event loop.
+    fn user_event(&mut self, _event_loop: &ActiveEventLoop, msg: Msg) {
+        match msg {
+            Msg::FrameOk(ok) => self.clear_ok = ok,
+            Msg::Frame { pixels, w, h, stride, ok } => {
+                self.scheduler.submit(self.tab, PendingFrame { pixels, w, h, stride, ok });
+            }
+            Msg::Quit => {
+                // Defer quit until after screenshot is taken/presented
+                self.pending_quit = true;
+            }
+        }
+    }
+
     fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
-        // Drain incoming messages from UDS thread.
-        while let Ok(msg) = self.rx.try_recv() {
-            match msg {
-                Msg::FrameOk(ok) => self.clear_ok = ok,
-                Msg::Frame { pixels, w, h, stride, ok } => {
-                    self.clear_ok = ok;
-                    self.pending = Some(PendingFrame { pixels, w, h, stride, ok });
-                }
-                Msg::Quit => {
-                    // Defer quit until after screenshot is taken/presented
-                    self.pending_quit = true;
-                }
+        // Out-of-sight tabs freeze after a while, even with no new visibility change
+        self.sync_tabs();
+        let mut redraw = self.pending_quit;
+        if self.pending_quit {
+            // Nothing follows a quit: show the last frame now, whatever its
+            // vsync or age, so the final redraw and screenshot reflect it
+            if let Some(frame) = self.scheduler.cancel(self.tab) {
+                self.clear_ok = frame.ok;
+                self.pending = Some(frame);
             }
         }
-        if let Some(w) = self.window.as_ref() {
-            w.request_redraw();
+        if let Some(begin) = self.scheduler.poll() {
+            if let Some((_, frame)) = begin.frames.into_iter().next() {
+                self.clear_ok = frame.ok;
+                self.pending = Some(frame);
+            }
+            redraw = true;
+        }
+        if redraw {
+            if let Some(w) = self.window.as_ref() { w.request_redraw(); }
+        }
+        // Wake for the next vsync only while a frame or a quit is waiting;
+        // otherwise sleep until the socket thread sends something
+        if self.scheduler.has_pending() || self.pending_quit {
+            let wake = std::time::Instant::now() + self.scheduler.time_until_next();
+            event_loop.set_control_flow(ControlFlow::WaitUntil(wake));
+        } else {
+            event_loop.set_control_flow(ControlFlow::Wait);
         }
     }
 }
@@ -485,7 +540,8 @@ fn main() {
         }
     };
 
-    let (tx, rx) = mpsc::channel();
+    let event_loop = EventLoop::<Msg>::with_user_event().build().expect("event loop");
+    let tx: EventLoopProxy<Msg> = event_loop.create_proxy();
 
     // Spawn background thread for UDS accept + read loop, keep stdout protocol stable.
     thread::spawn(move || {
@@ -498,7 +554,7 @@ fn main() {
                     Ok(Message::Frame { pixels, size, stride }) => {
                         let ok = is_roughly_color_rgba8(&pixels, (255, 0, 0), 16);
                         println!("FRAME {}x{} {}", size.0, size.1, if ok { "OK" } else { "FAIL" });
-                        let _ = tx.send(Msg::Frame { pixels, w: size.0, h: size.1, stride, ok });
+                        let _ = tx.send_event(Msg::Frame { pixels, w: size.0, h: size.1, stride, ok });
                         if !ok {
                             // Keep process alive but mark FAIL in title.
                         }
@@ -507,7 +563,7 @@ fn main() {
                         println!("EVENT");
                     }
                     Ok(Message::Quit) => {
-                        let _ = tx.send(Msg::Quit);
+                        let _ = tx.send_event(Msg::Quit);
                         break;
                     }
                     Err(e) => {
@@ -520,8 +576,7 @@ fn main() {
         }
     });
 
-    let event_loop = EventLoop::new().expect("event loop");
-    let mut app = App::new(rx);
+    let mut app = App::new();
     let _ = event_loop.run_app(&mut app);
 }
 
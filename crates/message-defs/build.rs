@@ -44,6 +44,12 @@ pub struct DisplayList {{ pub items: Vec<DrawCmd> }}
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum DrawCmd {{
     Rect {{ x: u32, y: u32, w: u32, h: u32, rgba: u32 }},
+    /// Straight-alpha RGBA8 bitmap of `src_w`x`src_h`, scaled into the `w`x`h` destination.
+    Image {{ x: u32, y: u32, w: u32, h: u32, src_w: u32, src_h: u32, pixels: Bytes }},
+    /// Open a scroll/clip layer: later commands are clipped to the clip rect (given in the
+    /// enclosing layer's coordinates) and shifted by (-scroll_x, -scroll_y) until `PopLayer`.
+    PushLayer {{ clip_x: u32, clip_y: u32, clip_w: u32, clip_h: u32, scroll_x: i32, scroll_y: i32 }},
+    PopLayer,
 }}
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -8,6 +8,8 @@ pub enum PermissionKind {
     Microphone,
     ClipboardRead,
     ClipboardWrite,
+    /// window.ai access to the local model
+    Ai,
 }
 
 impl PermissionKind {
@@ -19,6 +21,7 @@ impl PermissionKind {
             PermissionKind::Microphone => "Microphone",
             PermissionKind::ClipboardRead => "ClipboardRead",
             PermissionKind::ClipboardWrite => "ClipboardWrite",
+            PermissionKind::Ai => "Ai",
         }
     }
 }
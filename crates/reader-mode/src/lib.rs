@@ -0,0 +1,361 @@
+//! Reader mode: finds the main article of a page, drops navigation, ads and
+//! other boilerplate, and returns clean sanitized HTML plus plain text and
+//! metadata (title, byline, publish date, lead image).
+//!
+//! Candidates are scored Readability-style. Every paragraph adds to the
+//! scores of its ancestors, weighted by text length and commas, and is
+//! penalised by link density. Semantic tags and class/id hints adjust the
+//! result.
+
+use dom_embed::sanitize::{sanitize_nodes, Policy};
+use servo_lite::dom::{Document, NodeId};
+use std::collections::HashMap;
+use thiserror::Error;
+use url::Url;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ReaderError {
+    #[error("no readable content")] NoContent,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Article {
+    pub title: String,
+    pub byline: Option<String>,
+    /// Publish date as written in the page (usually ISO 8601).
+    pub published: Option<String>,
+    /// Absolute URL of the lead image.
+    pub lead_image: Option<String>,
+    pub site_name: Option<String>,
+    /// Sanitized article markup with absolute links.
+    pub html: String,
+    /// Plain text, one paragraph per line; what summarizers should consume.
+    pub text: String,
+    pub word_count: usize,
+}
+
+/// Articles with less text than this are not worth a reader view.
+pub const MIN_TEXT_CHARS: usize = 100;
+/// Paragraph-like elements shorter than this are not scored.
+const MIN_PARAGRAPH_CHARS: usize = 25;
+
+/// Removed with their content before scoring.
+const BOILERPLATE_TAGS: &[&str] = &[
+    "script", "style", "noscript", "template", "iframe", "object", "embed", "svg", "canvas", "form", "button", "input", "select",
+    "textarea", "nav", "aside", "footer", "dialog", "head",
+];
+const BOILERPLATE_ROLES: &[&str] = &["navigation", "complementary", "banner", "contentinfo", "search", "menu", "menubar", "dialog", "alert"];
+/// class/id tokens of elements that are almost never content.
+const UNLIKELY: &[&str] = &[
+    "ad", "ads", "advert", "advertisement", "banner", "breadcrumb", "breadcrumbs", "comment", "comments", "cookie", "disqus",
+    "footer", "masthead", "menu", "modal", "nav", "navbar", "newsletter", "outbrain", "pager", "pagination", "popup", "promo",
+    "related", "share", "sharing", "sidebar", "social", "sponsor", "sponsored", "subscribe", "taboola", "toolbar", "widget",
+];
+/// class/id tokens of elements that are likely content.
+const POSITIVE: &[&str] = &["article", "blog", "body", "content", "entry", "main", "page", "post", "story", "text"];
+const BLOCKS: &[&str] = &[
+    "address", "article", "blockquote", "dd", "div", "dl", "dt", "figcaption", "figure", "h1", "h2", "h3", "h4", "h5", "h6",
+    "hr", "li", "main", "ol", "p", "pre", "section", "table", "tr", "td", "th", "ul",
+];
+
+/// Extract the article from `html`. `base_url` resolves relative links and images.
+pub fn extract(html: &str, base_url: Option<&str>) -> Result<Article, ReaderError> {
+    let mut doc = Document::parse(html);
+    let base = base_url.and_then(|b| Url::parse(b).ok());
+    let mut article = metadata(&doc);
+
+    strip_boilerplate(&mut doc);
+    let content = pick_content(&doc).ok_or(ReaderError::NoContent)?;
+    clean_content(&mut doc, &content, &article.title);
+    if let Some(base) = &base { absolutize(&mut doc, &content, base); }
+
+    article.text = content.iter().map(|&n| block_text(&doc, n)).collect::<Vec<_>>().join("\n").trim().to_string();
+    if article.text.chars().count() < MIN_TEXT_CHARS { return Err(ReaderError::NoContent); }
+    article.word_count = article.text.split_whitespace().count();
+    article.html = sanitize_nodes(&doc, &content, &Policy::default()).html;
+    article.lead_image = article.lead_image.map(|src| resolve(base.as_ref(), &src)).or_else(|| {
+        content.iter().flat_map(|&n| doc.descendants(n)).find_map(|n| (doc.tag_name(n) == Some("img")).then(|| doc.attr(n, "src")).flatten().map(str::to_string))
+    });
+    if article.title.is_empty() {
+        article.title = doc.descendants(doc.root()).find(|&n| doc.tag_name(n) == Some("h1")).map(|h| text(&doc, h)).unwrap_or_default();
+    }
+    Ok(article)
+}
+
+/// Title, byline, date, image and site name from `<meta>` tags and common markup.
+fn metadata(doc: &Document) -> Article {
+    let meta = |keys: &[&str]| {
+        doc.elements_by_tag("meta").find_map(|m| {
+            let key = doc.attr(m, "property").or_else(|| doc.attr(m, "name")).or_else(|| doc.attr(m, "itemprop"))?;
+            keys.iter().any(|k| k.eq_ignore_ascii_case(key)).then(|| doc.attr(m, "content")).flatten().map(collapse).filter(|v| !v.is_empty())
+        })
+    };
+    let site_name = meta(&["og:site_name", "application-name"]);
+    let title = meta(&["og:title", "twitter:title"])
+        .or_else(|| doc.elements_by_tag("title").next().map(|t| clean_title(&text(doc, t), site_name.as_deref())))
+        .unwrap_or_default();
+    let byline = meta(&["author", "article:author", "byl", "dc.creator"]).or_else(|| {
+        doc.descendants(doc.root()).find_map(|n| {
+            let is_byline = doc.attr(n, "rel") == Some("author")
+                || doc.attr(n, "itemprop") == Some("author")
+                || tokens(doc, n).any(|t| t == "byline" || t == "author");
+            let t = if is_byline { text(doc, n) } else { return None };
+            (!t.is_empty() && t.len() < 100).then_some(t)
+        })
+    });
+    let published = meta(&["article:published_time", "datePublished", "date", "pubdate", "dc.date", "publish-date"]).or_else(|| {
+        doc.elements_by_tag("time").find_map(|t| doc.attr(t, "datetime").map(str::to_string).or_else(|| Some(text(doc, t)).filter(|s| !s.is_empty())))
+    });
+    Article { title, byline, published, lead_image: meta(&["og:image", "twitter:image"]), site_name, ..Default::default() }
+}
+
+/// Drop a trailing or leading " | Site" / " - Site" part when what remains is still a real title.
+fn clean_title(title: &str, site: Option<&str>) -> String {
+    for sep in [" | ", " - ", " – ", " — ", " :: "] {
+        if let Some((head, tail)) = title.rsplit_once(sep) {
+            let (main, other) = if site.is_some_and(|s| head.eq_ignore_ascii_case(s)) { (tail, head) } else { (head, tail) };
+            if main.split_whitespace().count() >= 3 || site.is_some_and(|s| other.eq_ignore_ascii_case(s)) { return main.trim().to_string(); }
+        }
+    }
+    title.to_string()
+}
+
+fn strip_boilerplate(doc: &mut Document) {
+    let doomed: Vec<NodeId> = doc.descendants(doc.root()).filter(|&n| is_boilerplate(doc, n)).collect();
+    for n in doomed { doc.detach(n); }
+}
+
+fn is_boilerplate(doc: &Document, n: NodeId) -> bool {
+    let Some(tag) = doc.tag_name(n) else { return false };
+    if matches!(tag, "html" | "body" | "article" | "main") { return false; }
+    if BOILERPLATE_TAGS.contains(&tag) { return true; }
+    if doc.attr(n, "role").is_some_and(|r| BOILERPLATE_ROLES.contains(&r.trim().to_ascii_lowercase().as_str())) { return true; }
+    if doc.attr(n, "hidden").is_some() || doc.attr(n, "aria-hidden") == Some("true") { return true; }
+    if doc.attr(n, "style").is_some_and(|s| s.replace(' ', "").to_ascii_lowercase().contains("display:none")) { return true; }
+    // Page headers go; an article's own header (title, byline) stays
+    if tag == "header" && !ancestors(doc, n).any(|a| matches!(doc.tag_name(a), Some("article" | "main"))) { return true; }
+    let (unlikely, positive) = class_hints(doc, n);
+    unlikely && !positive
+}
+
+/// Whether the class/id carries unlikely and positive tokens.
+fn class_hints(doc: &Document, n: NodeId) -> (bool, bool) {
+    let (mut unlikely, mut positive) = (false, false);
+    for t in tokens(doc, n) {
+        unlikely |= UNLIKELY.contains(&t.as_str());
+        positive |= POSITIVE.contains(&t.as_str());
+    }
+    (unlikely, positive)
+}
+
+fn tokens<'a>(doc: &'a Document, n: NodeId) -> impl Iterator<Item = String> + 'a {
+    [doc.attr(n, "class"), doc.attr(n, "id")].into_iter().flatten()
+        .flat_map(|v| v.split(|c: char| !c.is_ascii_alphanumeric()))
+        .filter(|t| !t.is_empty())
+        .map(str::to_ascii_lowercase)
+}
+
+fn ancestors(doc: &Document, n: NodeId) -> impl Iterator<Item = NodeId> + '_ { std::iter::successors(doc.parent(n), |&p| doc.parent(p)) }
+
+/// Score candidates and return the top one together with related siblings.
+fn pick_content(doc: &Document) -> Option<Vec<NodeId>> {
+    let mut scores: HashMap<NodeId, f32> = HashMap::new();
+    for n in doc.descendants(doc.root()) {
+        if !is_paragraph(doc, n) { continue; }
+        let t = text(doc, n);
+        let len = t.chars().count();
+        if len < MIN_PARAGRAPH_CHARS { continue; }
+        let score = 1.0 + t.matches([',', '，']).count() as f32 + (len as f32 / 100.0).min(3.0);
+        for (level, a) in ancestors(doc, n).take(3).enumerate() {
+            if doc.tag_name(a).is_none() { break; }
+            *scores.entry(a).or_insert_with(|| initial_score(doc, a)) += score / (level + 1) as f32;
+        }
+    }
+    for (&n, s) in scores.iter_mut() { *s *= 1.0 - link_density(doc, n); }
+    let (&top, &top_score) = scores.iter().max_by(|a, b| a.1.total_cmp(b.1).then(b.0.cmp(a.0)))?;
+
+    let Some(parent) = doc.parent(top).filter(|&p| doc.tag_name(p).is_some_and(|t| t != "html")) else { return Some(vec![top]) };
+    let threshold = (top_score * 0.2).max(10.0);
+    let top_class = doc.attr(top, "class");
+    let content = doc.children(parent).iter().copied().filter(|&s| {
+        if s == top { return true; }
+        if doc.tag_name(s).is_none() { return false; }
+        let bonus = if top_class.is_some() && doc.attr(s, "class") == top_class { top_score * 0.2 } else { 0.0 };
+        if scores.get(&s).is_some_and(|&sc| sc + bonus >= threshold) { return true; }
+        if doc.tag_name(s) != Some("p") { return false; }
+        let (t, ld) = (text(doc, s), link_density(doc, s));
+        let len = t.chars().count();
+        (len > 80 && ld < 0.25) || (len > 0 && ld == 0.0 && (t.contains(". ") || t.ends_with('.')))
+    });
+    Some(content.collect())
+}
+
+/// Elements scored as paragraphs: `<p>`-likes and `<div>`s without block children.
+fn is_paragraph(doc: &Document, n: NodeId) -> bool {
+    match doc.tag_name(n) {
+        Some("p" | "pre" | "td" | "blockquote") => true,
+        Some("div") => !doc.children(n).iter().any(|&c| doc.tag_name(c).is_some_and(|t| BLOCKS.contains(&t))),
+        _ => false,
+    }
+}
+
+fn initial_score(doc: &Document, n: NodeId) -> f32 {
+    let tag_score = match doc.tag_name(n) {
+        Some("article") => 10.0,
+        Some("main") => 8.0,
+        Some("div" | "section") => 5.0,
+        Some("pre" | "td" | "blockquote") => 3.0,
+        Some("address" | "ol" | "ul" | "dl" | "dd" | "dt" | "li" | "form") => -3.0,
+        Some("h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "th") => -5.0,
+        _ => 0.0,
+    };
+    let (unlikely, positive) = class_hints(doc, n);
+    tag_score + if positive { 25.0 } else { 0.0 } - if unlikely { 25.0 } else { 0.0 }
+}
+
+/// Share of the element's text that sits inside links.
+fn link_density(doc: &Document, n: NodeId) -> f32 {
+    let total = text(doc, n).chars().count();
+    if total == 0 { return 0.0; }
+    let linked: usize = doc.descendants(n).filter(|&d| doc.tag_name(d) == Some("a")).map(|a| text(doc, a).chars().count()).sum();
+    linked as f32 / total as f32
+}
+
+/// Remove link farms and the heading that repeats the title from the chosen content.
+fn clean_content(doc: &mut Document, content: &[NodeId], title: &str) {
+    let mut doomed = Vec::new();
+    let mut title_seen = false;
+    for n in content.iter().flat_map(|&c| doc.descendants(c)) {
+        match doc.tag_name(n) {
+            Some("ul" | "ol" | "div" | "section" | "table" | "p") => {
+                let t = text(doc, n);
+                if t.chars().count() < 200 && link_density(doc, n) > 0.5 { doomed.push(n); }
+            }
+            Some("h1" | "h2") if !title_seen && !title.is_empty() && text(doc, n).eq_ignore_ascii_case(title) => {
+                title_seen = true;
+                doomed.push(n);
+            }
+            _ => {}
+        }
+    }
+    for n in doomed { doc.detach(n); }
+}
+
+/// Resolve links and images against `base`, promoting lazy-load `data-src` images.
+fn absolutize(doc: &mut Document, content: &[NodeId], base: &Url) {
+    let nodes: Vec<NodeId> = content.iter().flat_map(|&c| doc.descendants(c)).collect();
+    for n in nodes {
+        let attr = match doc.tag_name(n) {
+            Some("a") => "href",
+            Some("img") => {
+                let lazy = doc.attr(n, "data-src").map(str::to_string);
+                let placeholder = doc.attr(n, "src").is_none_or(|s| s.is_empty() || s.starts_with("data:"));
+                if let Some(lazy) = lazy.filter(|_| placeholder) { doc.set_attr(n, "src", &lazy); }
+                "src"
+            }
+            _ => continue,
+        };
+        let Some(v) = doc.attr(n, attr).map(str::to_string) else { continue };
+        if !v.starts_with('#') { doc.set_attr(n, attr, &resolve(Some(base), &v)); }
+    }
+}
+
+fn resolve(base: Option<&Url>, href: &str) -> String {
+    base.and_then(|b| b.join(href.trim()).ok()).map(String::from).unwrap_or_else(|| href.to_string())
+}
+
+/// Text with blocks on their own lines and whitespace collapsed within lines.
+fn block_text(doc: &Document, n: NodeId) -> String {
+    fn walk(doc: &Document, n: NodeId, line: &mut String, out: &mut Vec<String>) {
+        if let Some(t) = doc.text(n) {
+            line.push_str(t);
+            return;
+        }
+        let tag = doc.tag_name(n).unwrap_or("");
+        let block = BLOCKS.contains(&tag) || tag == "br";
+        if block { flush(line, out); }
+        for &c in doc.children(n) { walk(doc, c, line, out); }
+        if block { flush(line, out); }
+    }
+    fn flush(line: &mut String, out: &mut Vec<String>) {
+        let l = collapse(line);
+        if !l.is_empty() { out.push(l); }
+        line.clear();
+    }
+    let (mut line, mut out) = (String::new(), Vec::new());
+    walk(doc, n, &mut line, &mut out);
+    flush(&mut line, &mut out);
+    out.join("\n")
+}
+
+fn text(doc: &Document, n: NodeId) -> String { collapse(&doc.text_content(n)) }
+
+fn collapse(s: &str) -> String { s.split_whitespace().collect::<Vec<_>>().join(" ") }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = r#"<html><head>
+        <title>Rust ships a new compiler release | Example News</title>
+        <meta property="og:site_name" content="Example News">
+        <meta name="author" content="Jane Doe">
+        <meta property="article:published_time" content="2024-05-02T10:00:00Z">
+        <script>track()</script>
+        </head><body>
+        <header class="site-header"><a href="/">Home</a> <a href="/world">World</a></header>
+        <nav><ul><li><a href="/a">A</a></li><li><a href="/b">B</a></li></ul></nav>
+        <div class="ad-slot">Buy now, limited offer, click here, act fast!</div>
+        <article class="post">
+          <h1>Rust ships a new compiler release</h1>
+          <p>The Rust team released a new version of the compiler today, bringing faster builds, better diagnostics and several long-awaited language features.</p>
+          <img data-src="/img/lead.jpg" src="data:image/gif;base64,R0lGOD" alt="Ferris">
+          <p>Incremental compilation was reworked, which cuts rebuild times for large workspaces, according to the release notes published on the project blog.</p>
+          <ul class="share"><li><a href="https://x.com/share">Share</a></li><li><a href="https://fb.com">Like</a></li></ul>
+          <p>Developers can update with rustup, and the full changelog <a href="/changelog">is available</a> online for anyone who wants the details.</p>
+        </article>
+        <aside class="sidebar"><p>Popular: ten tricks, five secrets, and three things you never knew about anything.</p></aside>
+        <div class="comments"><p>First! This is a comment, with commas, and opinions, lots of them, really.</p></div>
+        <footer>Copyright</footer>
+        </body></html>"#;
+
+    #[test]
+    fn extracts_article_and_metadata() {
+        let a = extract(PAGE, Some("https://news.example.com/2024/rust")).unwrap();
+        assert_eq!(a.title, "Rust ships a new compiler release");
+        assert_eq!(a.site_name.as_deref(), Some("Example News"));
+        assert_eq!(a.byline.as_deref(), Some("Jane Doe"));
+        assert_eq!(a.published.as_deref(), Some("2024-05-02T10:00:00Z"));
+        assert_eq!(a.lead_image.as_deref(), Some("https://news.example.com/img/lead.jpg"), "lazy image promoted and resolved");
+        let lines: Vec<&str> = a.text.lines().collect();
+        assert_eq!(lines.len(), 3, "{}", a.text);
+        assert!(lines[0].starts_with("The Rust team released"));
+        for junk in ["Home", "Buy now", "Share", "Popular", "First!", "Copyright", "track()"] {
+            assert!(!a.text.contains(junk) && !a.html.contains(junk), "{junk} leaked");
+        }
+        assert!(a.html.contains("<a href=\"https://news.example.com/changelog\">"));
+        assert!(!a.html.contains("<h1>"), "title heading shown separately");
+        assert_eq!(a.word_count, a.text.split_whitespace().count());
+    }
+
+    #[test]
+    fn scores_div_soup_without_semantic_tags() {
+        let para = |s: &str| format!("<p>{s}, with enough words, commas, and length to count as a real paragraph of content.</p>");
+        let html = format!(
+            "<title>Plain</title><div id=menu><a href=/1>One</a> <a href=/2>Two</a></div><div class=wrap><div class=inner>{}{}{}</div></div><div class=links>{}</div>",
+            para("First"), para("Second"), para("Third"),
+            "<p><a href=/x>Link heavy paragraph that is mostly anchors and should never win the scoring</a></p>",
+        );
+        let a = extract(&html, None).unwrap();
+        assert_eq!(a.title, "Plain");
+        assert_eq!(a.text.lines().count(), 3);
+        assert!(a.text.starts_with("First") && !a.text.contains("Link heavy") && !a.text.contains("One"));
+    }
+
+    #[test]
+    fn rejects_pages_without_content() {
+        assert_eq!(extract("<nav><a href=/>Home</a></nav><p>Short.</p>", None), Err(ReaderError::NoContent));
+        assert_eq!(clean_title("A - B", None), "A - B");
+        assert_eq!(clean_title("Site | A longer article title", Some("Site")), "A longer article title");
+    }
+}
@@ -0,0 +1,228 @@
+//! Reference-test harness for servo-lite. Each test/reference pair from a
+//! [`Manifest`] is rendered headlessly through servo-lite and the CPU
+//! rasterizer, compared with texture-verify, and reported as pass, fuzzy
+//! pass, fail or error. `<img>` sources load from disk relative to the page.
+
+pub mod manifest;
+pub mod report;
+pub mod wpt;
+
+pub use manifest::{Entry, Fuzzy, Kind, Manifest};
+
+use servo_lite::image::{ImageError, ImageFetcher, ImagePipeline};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use texture_verify::{diff, DiffOptions, Image, VerifyError};
+use thiserror::Error;
+
+/// Viewport WPT reftests are written for.
+pub const DEFAULT_VIEWPORT: (u32, u32) = (800, 600);
+
+const IMAGE_BUDGET: usize = 64 << 20;
+
+#[derive(Debug, Error)]
+pub enum ReftestError {
+    #[error("{0}: {1}")] Io(PathBuf, #[source] std::io::Error),
+    #[error("{path}:{line}: {message}")] Manifest { path: PathBuf, line: usize, message: String },
+    #[error("layout: {0}")] Layout(#[from] servo_lite::LayoutError),
+    #[error("{0}")] Verify(#[from] VerifyError),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Pass,
+    /// Matched only within the entry's fuzzy allowance.
+    Fuzzy { max_difference: u8, different: usize },
+    Fail { max_difference: u8, different: usize },
+    /// Either page could not be loaded or rendered.
+    Error(String),
+}
+
+impl Outcome {
+    pub fn is_ok(&self) -> bool { matches!(self, Outcome::Pass | Outcome::Fuzzy { .. }) }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Outcome::Pass => "PASS",
+            Outcome::Fuzzy { .. } => "FUZZY",
+            Outcome::Fail { .. } => "FAIL",
+            Outcome::Error(_) => "ERROR",
+        }
+    }
+}
+
+/// Renderings kept for the report when a test fails.
+#[derive(Debug, Clone)]
+pub struct Failure {
+    pub test: Image,
+    pub reference: Image,
+    pub diff: Image,
+}
+
+#[derive(Debug, Clone)]
+pub struct TestResult {
+    pub entry: Entry,
+    pub outcome: Outcome,
+    pub failure: Option<Failure>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub pass: usize,
+    pub fuzzy: usize,
+    pub fail: usize,
+    pub error: usize,
+}
+
+impl Summary {
+    pub fn of(results: &[TestResult]) -> Summary {
+        let mut s = Summary::default();
+        for r in results {
+            match r.outcome {
+                Outcome::Pass => s.pass += 1,
+                Outcome::Fuzzy { .. } => s.fuzzy += 1,
+                Outcome::Fail { .. } => s.fail += 1,
+                Outcome::Error(_) => s.error += 1,
+            }
+        }
+        s
+    }
+
+    pub fn total(&self) -> usize { self.pass + self.fuzzy + self.fail + self.error }
+
+    pub fn all_ok(&self) -> bool { self.fail + self.error == 0 }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} tests: {} pass, {} fuzzy, {} fail, {} error", self.total(), self.pass, self.fuzzy, self.fail, self.error)
+    }
+}
+
+/// Reads `file://` URLs.
+struct FileFetcher;
+
+impl ImageFetcher for FileFetcher {
+    fn fetch(&self, url: &str) -> Result<Vec<u8>, ImageError> {
+        let path = url::Url::parse(url).ok().and_then(|u| u.to_file_path().ok()).ok_or_else(|| ImageError::Fetch(format!("not a file URL: {url}")))?;
+        std::fs::read(&path).map_err(|e| ImageError::Fetch(format!("{}: {e}", path.display())))
+    }
+}
+
+pub struct Runner {
+    viewport: (u32, u32),
+    images: ImagePipeline,
+    image_timeout: Duration,
+}
+
+impl Default for Runner {
+    fn default() -> Self { Self::new(DEFAULT_VIEWPORT) }
+}
+
+impl Runner {
+    pub fn new(viewport: (u32, u32)) -> Self {
+        Self { viewport, images: ImagePipeline::new(Arc::new(FileFetcher), IMAGE_BUDGET, 2), image_timeout: Duration::from_secs(10) }
+    }
+
+    /// Render the page at `path`, waiting for its images to load.
+    pub fn render(&self, path: &Path) -> Result<Image, ReftestError> {
+        let html = std::fs::read_to_string(path).map_err(|e| ReftestError::Io(path.to_path_buf(), e))?;
+        let abs = std::fs::canonicalize(path).map_err(|e| ReftestError::Io(path.to_path_buf(), e))?;
+        let base = url::Url::from_file_path(&abs).map(String::from).ok();
+        let layout = || servo_lite::html_to_display_list_with_images(&html, self.viewport, base.as_deref(), &self.images);
+        let generation = self.images.generation();
+        let mut dl = layout()?;
+        if !self.images.wait_idle(self.image_timeout) || self.images.generation() != generation { dl = layout()?; }
+        let (w, h) = self.viewport;
+        Ok(Image::new(w, h, gpu_srv::cpu::rasterize_rgba8(w, h, &dl))?)
+    }
+
+    pub fn run_entry(&self, entry: &Entry) -> TestResult {
+        let (outcome, failure) = match self.render(&entry.test).and_then(|t| Ok((t, self.render(&entry.reference)?))) {
+            Ok((test, reference)) => compare(entry, test, reference),
+            Err(e) => (Outcome::Error(e.to_string()), None),
+        };
+        TestResult { entry: entry.clone(), outcome, failure }
+    }
+
+    pub fn run(&self, manifest: &Manifest) -> Vec<TestResult> { manifest.entries.iter().map(|e| self.run_entry(e)).collect() }
+}
+
+/// Exact comparison, relaxed by the entry's fuzzy allowance. For mismatches,
+/// renderings within the allowance count as the same.
+fn compare(entry: &Entry, test: Image, reference: Image) -> (Outcome, Option<Failure>) {
+    let d = match diff(&reference, &test, DiffOptions { tolerance: 0, ignore_antialiasing: false }) {
+        Ok(d) => d,
+        Err(e) => return (Outcome::Error(e.to_string()), None),
+    };
+    let (max_difference, different) = (d.max_delta, d.different);
+    let same = different == 0;
+    let fuzzy = !same && entry.fuzzy.as_ref().is_some_and(|f| f.allows(max_difference, different));
+    let outcome = match entry.kind {
+        Kind::Match if same => Outcome::Pass,
+        Kind::Match if fuzzy => Outcome::Fuzzy { max_difference, different },
+        Kind::Mismatch if !same && !fuzzy => Outcome::Pass,
+        _ => Outcome::Fail { max_difference, different },
+    };
+    let failure = (!outcome.is_ok()).then(|| Failure { diff: d.image(&reference), test, reference });
+    (outcome, failure)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(kind: Kind, test: &Path, reference: &Path, fuzzy: Option<Fuzzy>) -> Entry {
+        Entry { kind, test: test.to_path_buf(), reference: reference.to_path_buf(), fuzzy }
+    }
+
+    #[test]
+    fn renders_pairs_and_classifies_outcomes() {
+        let dir = std::env::temp_dir().join(format!("reftest-run-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let page = |name: &str, body: &str| {
+            let p = dir.join(name);
+            std::fs::write(&p, format!("<html><body>{body}</body></html>")).unwrap();
+            p
+        };
+        let mut near = Image::filled(8, 8, [0, 128, 0, 255]);
+        near.set_pixel(3, 3, [0, 130, 0, 255]);
+        Image::filled(8, 8, [0, 128, 0, 255]).write_png(dir.join("green.png")).unwrap();
+        near.write_png(dir.join("near.png")).unwrap();
+
+        let green = page("green.html", r#"<img src="green.png" width="8" height="8">"#);
+        let near_green = page("near.html", r#"<img src="near.png" width="8" height="8">"#);
+        let heading = page("heading.html", "<h1>Title</h1>");
+
+        let runner = Runner::new((64, 96));
+        assert!(runner.render(&green).unwrap().rgba.chunks_exact(4).any(|p| p == [0, 128, 0, 255]), "image loaded from disk");
+        let fuzzy = Fuzzy { max_difference: 0..=2, total_pixels: 0..=1 };
+        let results = runner.run(&Manifest { entries: vec![
+            entry(Kind::Match, &green, &green, None),
+            entry(Kind::Match, &near_green, &green, Some(fuzzy.clone())),
+            entry(Kind::Match, &near_green, &green, None),
+            entry(Kind::Mismatch, &heading, &green, None),
+            entry(Kind::Mismatch, &near_green, &green, Some(fuzzy)),
+            entry(Kind::Match, &dir.join("missing.html"), &green, None),
+        ] });
+        let outcomes: Vec<_> = results.iter().map(|r| r.outcome.clone()).collect();
+        assert_eq!(outcomes[..5], [
+            Outcome::Pass,
+            Outcome::Fuzzy { max_difference: 2, different: 1 },
+            Outcome::Fail { max_difference: 2, different: 1 },
+            Outcome::Pass,
+            Outcome::Fail { max_difference: 2, different: 1 },
+        ]);
+        assert!(matches!(outcomes[5], Outcome::Error(_)));
+        assert!(results[2].failure.is_some() && results[1].failure.is_none());
+        assert_eq!(Summary::of(&results), Summary { pass: 2, fuzzy: 1, fail: 2, error: 1 });
+
+        let index = report::write_html(&dir.join("report"), &results).unwrap();
+        let html = std::fs::read_to_string(index).unwrap();
+        assert!(html.contains("6 tests: 2 pass, 1 fuzzy, 2 fail, 1 error") && html.contains("near.html"));
+        assert!(dir.join("report/2-diff.png").is_file());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
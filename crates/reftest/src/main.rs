@@ -0,0 +1,65 @@
+use clap::{Parser, Subcommand};
+use reftest::{report, wpt, Manifest, Runner};
+use std::path::PathBuf;
+
+#[derive(Parser, Debug)]
+#[command(name = "reftest", version, about = "Reference tests for servo-lite rendering")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Render and compare every pair in a manifest
+    Run {
+        manifest: PathBuf,
+        /// Write an HTML report of failures into this directory
+        #[arg(long, value_name = "DIR")]
+        report: Option<PathBuf>,
+        #[arg(long, default_value_t = reftest::DEFAULT_VIEWPORT.0)]
+        width: u32,
+        #[arg(long, default_value_t = reftest::DEFAULT_VIEWPORT.1)]
+        height: u32,
+    },
+    /// Write a manifest for the reftests in a local web-platform-tests checkout
+    ImportWpt {
+        root: PathBuf,
+        /// Only tests whose path under ROOT starts with this, e.g. css/css-backgrounds
+        #[arg(long)]
+        filter: Option<String>,
+        #[arg(long)]
+        limit: Option<usize>,
+        /// Manifest to write (default ROOT/reftest.list)
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+}
+
+fn main() -> anyhow::Result<()> {
+    match Cli::parse().command {
+        Command::Run { manifest, report: report_dir, width, height } => {
+            let manifest = Manifest::load(&manifest)?;
+            let results = Runner::new((width, height)).run(&manifest);
+            for r in &results {
+                let detail = match &r.outcome {
+                    reftest::Outcome::Fuzzy { max_difference, different } | reftest::Outcome::Fail { max_difference, different } => format!(" ({different} px, max {max_difference})"),
+                    reftest::Outcome::Error(e) => format!(" ({e})"),
+                    reftest::Outcome::Pass => String::new(),
+                };
+                println!("{} {}{detail}", r.outcome.label(), r.entry.test.display());
+            }
+            let summary = reftest::Summary::of(&results);
+            println!("{summary}");
+            if let Some(dir) = report_dir { println!("report: {}", report::write_html(&dir, &results)?.display()); }
+            if !summary.all_ok() { std::process::exit(1); }
+        }
+        Command::ImportWpt { root, filter, limit, output } => {
+            let manifest = wpt::import(&root, filter.as_deref(), limit)?;
+            let output = output.unwrap_or_else(|| root.join("reftest.list"));
+            manifest.save(&output)?;
+            println!("{} reftests written to {}", manifest.entries.len(), output.display());
+        }
+    }
+    Ok(())
+}
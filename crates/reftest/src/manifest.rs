@@ -0,0 +1,200 @@
+//! Reftest manifests in the `reftest.list` style, one pair per line with
+//! paths relative to the manifest:
+//!
+//! ```text
+//! # comment
+//! == test.html ref.html
+//! != test.html different.html
+//! fuzzy(0-2,0-100) == test.html ref.html
+//! include sub/reftest.list
+//! ```
+//!
+//! `fuzzy(maxDifference,totalPixels)` takes ranges `a-b` or single numbers
+//! `n`, meaning `0-n`.
+
+use crate::ReftestError;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// `==`: test and reference must render the same.
+    Match,
+    /// `!=`: they must differ.
+    Mismatch,
+}
+
+/// Differences tolerated by a match: the largest channel difference and the
+/// number of differing pixels must both fall in range.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fuzzy {
+    pub max_difference: RangeInclusive<u8>,
+    pub total_pixels: RangeInclusive<usize>,
+}
+
+impl Fuzzy {
+    pub fn allows(&self, max_difference: u8, total_pixels: usize) -> bool {
+        self.max_difference.contains(&max_difference) && self.total_pixels.contains(&total_pixels)
+    }
+
+    /// `a-b,c-d` (or `;` separated); single numbers are upper bounds. WPT's
+    /// `maxDifference=`/`totalPixels=` keys and a leading `url:` are accepted.
+    pub fn parse(s: &str) -> Option<Fuzzy> {
+        let s = s.rsplit_once(':').map_or(s, |(_, v)| v);
+        let mut parts = s.split([',', ';']).map(|p| p.trim()).filter(|p| !p.is_empty());
+        let (mut diff, mut pixels) = (None, None);
+        for (i, part) in parts.by_ref().enumerate() {
+            let (key, value) = part.split_once('=').map_or((None, part), |(k, v)| (Some(k.trim()), v.trim()));
+            match (key, i) {
+                (Some("maxDifference"), _) | (None, 0) => diff = Some(range::<u8>(value)?),
+                (Some("totalPixels"), _) | (None, 1) => pixels = Some(range::<usize>(value)?),
+                _ => return None,
+            }
+        }
+        Some(Fuzzy { max_difference: diff?, total_pixels: pixels? })
+    }
+}
+
+fn range<T: std::str::FromStr + Default + Copy + PartialOrd>(s: &str) -> Option<RangeInclusive<T>> {
+    let (lo, hi) = match s.split_once('-') {
+        Some((lo, hi)) => (lo.trim().parse().ok()?, hi.trim().parse().ok()?),
+        None => (T::default(), s.parse().ok()?),
+    };
+    (lo <= hi).then_some(lo..=hi)
+}
+
+impl fmt::Display for Fuzzy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "fuzzy({}-{},{}-{})", self.max_difference.start(), self.max_difference.end(), self.total_pixels.start(), self.total_pixels.end())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub kind: Kind,
+    pub test: PathBuf,
+    pub reference: PathBuf,
+    pub fuzzy: Option<Fuzzy>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Manifest {
+    pub entries: Vec<Entry>,
+}
+
+impl Manifest {
+    /// Parse `text`, resolving paths against `dir`. `include` lines are read
+    /// from disk; an include that leads back to a manifest being read is an error.
+    pub fn parse(text: &str, dir: &Path, origin: &Path) -> Result<Manifest, ReftestError> {
+        Manifest::parse_included(text, dir, origin, &mut vec![origin.canonicalize().unwrap_or_else(|_| origin.to_path_buf())])
+    }
+
+    /// `parse` with `including` holding the canonical paths of the manifests
+    /// whose includes led here, `origin` last.
+    fn parse_included(text: &str, dir: &Path, origin: &Path, including: &mut Vec<PathBuf>) -> Result<Manifest, ReftestError> {
+        let mut entries = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() { continue; }
+            let err = |message: &str| ReftestError::Manifest { path: origin.to_path_buf(), line: i + 1, message: message.to_string() };
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if let ["include", path] = words[..] {
+                let path = dir.join(path);
+                let canonical = path.canonicalize().map_err(|e| ReftestError::Io(path.clone(), e))?;
+                if including.contains(&canonical) { return Err(err(&format!("include cycle through {}", path.display()))); }
+                including.push(canonical);
+                entries.extend(Manifest::load_included(&path, including)?.entries);
+                including.pop();
+                continue;
+            }
+            let (fuzzy, rest) = match words.split_first() {
+                Some((w, rest)) if w.starts_with("fuzzy(") && w.ends_with(')') => {
+                    (Some(Fuzzy::parse(&w[6..w.len() - 1]).ok_or_else(|| err("bad fuzzy() annotation"))?), rest)
+                }
+                _ => (None, &words[..]),
+            };
+            let (kind, test, reference) = match rest {
+                ["==", t, r] => (Kind::Match, t, r),
+                ["!=", t, r] => (Kind::Mismatch, t, r),
+                _ => return Err(err("expected `== test ref` or `!= test ref`")),
+            };
+            entries.push(Entry { kind, test: dir.join(test), reference: dir.join(reference), fuzzy });
+        }
+        Ok(Manifest { entries })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Manifest, ReftestError> {
+        let path = path.as_ref();
+        let canonical = path.canonicalize().map_err(|e| ReftestError::Io(path.to_path_buf(), e))?;
+        Manifest::load_included(path, &mut vec![canonical])
+    }
+
+    fn load_included(path: &Path, including: &mut Vec<PathBuf>) -> Result<Manifest, ReftestError> {
+        let text = std::fs::read_to_string(path).map_err(|e| ReftestError::Io(path.to_path_buf(), e))?;
+        Manifest::parse_included(&text, path.parent().unwrap_or(Path::new(".")), path, including)
+    }
+
+    /// Manifest text with paths relative to `dir` where possible.
+    pub fn to_text(&self, dir: &Path) -> String {
+        let rel = |p: &Path| p.strip_prefix(dir).unwrap_or(p).display().to_string();
+        let mut out = String::new();
+        for e in &self.entries {
+            if let Some(f) = &e.fuzzy { out += &format!("{f} "); }
+            let op = match e.kind { Kind::Match => "==", Kind::Mismatch => "!=" };
+            out += &format!("{op} {} {}\n", rel(&e.test), rel(&e.reference));
+        }
+        out
+    }
+
+    /// Write to `path`, relative to its directory.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ReftestError> {
+        let path = path.as_ref();
+        let text = self.to_text(path.parent().unwrap_or(Path::new(".")));
+        std::fs::write(path, text).map_err(|e| ReftestError::Io(path.to_path_buf(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_entries_fuzzy_and_errors() {
+        let dir = Path::new("/t");
+        let m = Manifest::parse("# pairs\n== a.html a-ref.html\nfuzzy(1-3,10) != b.html c.html # trailing\n\n", dir, Path::new("x")).unwrap();
+        assert_eq!(m.entries[0], Entry { kind: Kind::Match, test: "/t/a.html".into(), reference: "/t/a-ref.html".into(), fuzzy: None });
+        assert_eq!(m.entries[1].fuzzy, Some(Fuzzy { max_difference: 1..=3, total_pixels: 0..=10 }));
+        assert_eq!(m.to_text(dir), "== a.html a-ref.html\nfuzzy(1-3,0-10) != b.html c.html\n");
+        assert_eq!(Manifest::parse(&m.to_text(dir), dir, Path::new("x")).unwrap(), m);
+
+        match Manifest::parse("== a.html\n", dir, Path::new("x")) {
+            Err(ReftestError::Manifest { line: 1, .. }) => {}
+            other => panic!("{other:?}"),
+        }
+        assert_eq!(Fuzzy::parse("ref.html:maxDifference=0-2;totalPixels=5-300"), Some(Fuzzy { max_difference: 0..=2, total_pixels: 5..=300 }));
+        assert!(Fuzzy::parse("3-1,4").is_none());
+        assert!(Fuzzy::parse("2").is_none());
+    }
+
+    #[test]
+    fn include_cycles_are_errors() {
+        let dir = std::env::temp_dir().join(format!("reftest-include-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        let write = |p: &str, s: &str| std::fs::write(dir.join(p), s).unwrap();
+        write("shared.list", "== s.html s-ref.html\n");
+        write("sub/b.list", "include ../shared.list\ninclude ../a.list\n");
+        write("a.list", "include shared.list\ninclude sub/b.list\n");
+        match Manifest::load(dir.join("a.list")) {
+            Err(ReftestError::Manifest { path, line: 2, message }) => {
+                assert_eq!(path, dir.join("sub/b.list"));
+                assert!(message.contains("include cycle"), "{message}");
+            }
+            other => panic!("{other:?}"),
+        }
+        // Including the same manifest twice is not a cycle
+        write("sub/b.list", "include ../shared.list\n");
+        assert_eq!(Manifest::load(dir.join("a.list")).unwrap().entries.len(), 2);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
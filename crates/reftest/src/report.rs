@@ -0,0 +1,41 @@
+//! HTML report of failed and errored reftests: test, reference and diff
+//! renderings side by side, written as PNGs next to `index.html`.
+
+use crate::{ReftestError, Summary, TestResult};
+use std::path::{Path, PathBuf};
+
+fn escape(s: &str) -> String { s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;") }
+
+/// Write the report into `dir`; returns the path of its `index.html`.
+pub fn write_html(dir: &Path, results: &[TestResult]) -> Result<PathBuf, ReftestError> {
+    std::fs::create_dir_all(dir).map_err(|e| ReftestError::Io(dir.to_path_buf(), e))?;
+    let mut body = String::new();
+    for (i, r) in results.iter().enumerate().filter(|(_, r)| !r.outcome.is_ok()) {
+        let op = match r.entry.kind { crate::Kind::Match => "==", crate::Kind::Mismatch => "!=" };
+        body += &format!("<section><h2>{} {} {op} {}</h2>\n", r.outcome.label(), escape(&r.entry.test.display().to_string()), escape(&r.entry.reference.display().to_string()));
+        match &r.outcome {
+            crate::Outcome::Fail { max_difference, different } => body += &format!("<p>{different} pixels differ, max difference {max_difference}</p>\n"),
+            crate::Outcome::Error(e) => body += &format!("<p>{}</p>\n", escape(e)),
+            _ => {}
+        }
+        if let Some(f) = &r.failure {
+            body += "<div class=\"shots\">";
+            for (name, img) in [("test", &f.test), ("reference", &f.reference), ("diff", &f.diff)] {
+                img.write_png(dir.join(format!("{i}-{name}.png")))?;
+                body += &format!("<figure><img src=\"{i}-{name}.png\"><figcaption>{name}</figcaption></figure>");
+            }
+            body += "</div>\n";
+        }
+        body += "</section>\n";
+    }
+    if body.is_empty() { body = "<p>No failures.</p>\n".into(); }
+    let html = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Reftest report</title>\n\
+         <style>body{{font-family:sans-serif}}.shots{{display:flex;gap:8px}}figure{{margin:0}}img{{border:1px solid #ccc;max-width:32vw}}</style>\n\
+         </head><body>\n<h1>{}</h1>\n{body}</body></html>\n",
+        Summary::of(results)
+    );
+    let index = dir.join("index.html");
+    std::fs::write(&index, html).map_err(|e| ReftestError::Io(index.clone(), e))?;
+    Ok(index)
+}
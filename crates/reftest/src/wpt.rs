@@ -0,0 +1,98 @@
+//! Import reftests from a local web-platform-tests checkout. A WPT reftest
+//! is an HTML file with `<link rel="match" href="...">` (or `mismatch`) and
+//! optionally `<meta name="fuzzy" content="...">`. Hrefs starting with `/`
+//! are resolved against the checkout root. Tests that wait on script
+//! (`class="reftest-wait"`) or whose reference is missing are skipped.
+
+use crate::manifest::{Entry, Fuzzy, Kind, Manifest};
+use crate::ReftestError;
+use servo_lite::dom::Document;
+use std::path::{Path, PathBuf};
+
+/// Reftests under `root` whose path relative to it starts with `filter`, at most `limit` of them.
+pub fn import(root: &Path, filter: Option<&str>, limit: Option<usize>) -> Result<Manifest, ReftestError> {
+    let mut files = Vec::new();
+    walk(&root.join(filter.unwrap_or("")), &mut files)?;
+    // A filter that names a file prefix rather than a directory
+    if files.is_empty() && filter.is_some() { walk(root, &mut files)?; }
+    files.sort();
+    let mut entries = Vec::new();
+    for path in files {
+        let rel = path.strip_prefix(root).unwrap_or(&path);
+        if filter.is_some_and(|f| !rel.to_string_lossy().starts_with(f)) { continue; }
+        if limit.is_some_and(|n| entries.len() >= n) { break; }
+        let html = std::fs::read_to_string(&path).map_err(|e| ReftestError::Io(path.clone(), e))?;
+        entries.extend(references(&html, &path, root));
+    }
+    if let Some(n) = limit { entries.truncate(n); }
+    Ok(Manifest { entries })
+}
+
+fn walk(dir: &Path, out: &mut Vec<PathBuf>) -> Result<(), ReftestError> {
+    let Ok(read) = std::fs::read_dir(dir) else { return Ok(()) };
+    for entry in read {
+        let entry = entry.map_err(|e| ReftestError::Io(dir.to_path_buf(), e))?;
+        let path = entry.path();
+        // Directory symlinks are not followed, so a link back up the tree cannot loop
+        if entry.file_type().map_err(|e| ReftestError::Io(path.clone(), e))?.is_dir() {
+            walk(&path, out)?;
+        } else if path.extension().is_some_and(|e| e == "html" || e == "htm") {
+            out.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// The entries `html` (at `path`) declares, one per reference.
+fn references(html: &str, path: &Path, root: &Path) -> Vec<Entry> {
+    let doc = Document::parse(html);
+    let waits = doc.elements_by_tag("html").any(|n| doc.attr(n, "class").is_some_and(|c| c.split_whitespace().any(|c| c == "reftest-wait")));
+    if waits { return Vec::new(); }
+    let fuzzy = doc.elements_by_tag("meta")
+        .filter(|&n| doc.attr(n, "name").is_some_and(|v| v.eq_ignore_ascii_case("fuzzy")))
+        .find_map(|n| doc.attr(n, "content").and_then(Fuzzy::parse));
+    let dir = path.parent().unwrap_or(root);
+    doc.elements_by_tag("link")
+        .filter_map(|n| {
+            let kind = match doc.attr(n, "rel")?.trim().to_ascii_lowercase().as_str() {
+                "match" => Kind::Match,
+                "mismatch" => Kind::Mismatch,
+                _ => return None,
+            };
+            let href = doc.attr(n, "href")?.trim();
+            let href = href.split(['#', '?']).next().unwrap_or(href);
+            if href.contains("://") { return None; }
+            let reference = match href.strip_prefix('/') { Some(abs) => root.join(abs), None => dir.join(href) };
+            reference.is_file().then(|| Entry { kind, test: path.to_path_buf(), reference, fuzzy: fuzzy.clone() })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn imports_match_and_mismatch_links() {
+        let root = std::env::temp_dir().join(format!("reftest-wpt-{}", std::process::id()));
+        let css = root.join("css/box");
+        std::fs::create_dir_all(css.join("reference")).unwrap();
+        let write = |p: &Path, s: &str| std::fs::write(p, s).unwrap();
+        write(&css.join("reference/green-ref.html"), "<p>ref</p>");
+        write(&css.join("a.html"), r#"<link rel="match" href="reference/green-ref.html"><meta name="fuzzy" content="0-2;0-10"><p>a</p>"#);
+        write(&css.join("b.html"), r#"<link rel="mismatch" href="/css/box/a.html"><link rel="match" href="missing.html">"#);
+        write(&css.join("c.html"), r#"<html class="reftest-wait"><link rel="match" href="a.html"></html>"#);
+        write(&root.join("other.html"), r#"<link rel="match" href="css/box/a.html">"#);
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&root, css.join("loop")).unwrap();
+
+        let m = import(&root, Some("css"), None).unwrap();
+        assert_eq!(m.entries.len(), 2);
+        assert_eq!((m.entries[0].kind, &m.entries[0].reference), (Kind::Match, &css.join("reference/green-ref.html")));
+        assert_eq!(m.entries[0].fuzzy, Some(Fuzzy { max_difference: 0..=2, total_pixels: 0..=10 }));
+        assert_eq!((m.entries[1].kind, &m.entries[1].reference), (Kind::Mismatch, &root.join("css/box/a.html")));
+        assert_eq!(import(&root, None, Some(1)).unwrap().entries.len(), 1);
+        assert_eq!(import(&root, Some("css/box/b"), None).unwrap().entries.len(), 1);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,553 @@
+//! Accessibility tree: roles, names, states and bounds derived from the DOM,
+//! ARIA attributes and the current layout. Elements without semantics (plain
+//! `div`/`span` and friends) are flattened away, like the "ignored" nodes of
+//! browser accessibility trees, so the result is what a screen reader walks.
+
+use crate::dom::{Document, NodeId};
+use crate::events;
+use crate::layout::{self, LayoutBox, Rect};
+use crate::scroll::ScrollLayers;
+use serde::Serialize;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Document,
+    Generic,
+    StaticText,
+    Heading,
+    Paragraph,
+    Link,
+    Button,
+    CheckBox,
+    Radio,
+    TextBox,
+    SearchBox,
+    SpinButton,
+    Slider,
+    ComboBox,
+    ListBox,
+    Option,
+    List,
+    ListItem,
+    Img,
+    Figure,
+    Table,
+    Row,
+    Cell,
+    ColumnHeader,
+    RowHeader,
+    Navigation,
+    Main,
+    Banner,
+    ContentInfo,
+    Complementary,
+    Region,
+    Article,
+    Form,
+    Search,
+    Group,
+    Dialog,
+    Alert,
+    Status,
+    Separator,
+    ProgressBar,
+    Tab,
+    TabList,
+    TabPanel,
+    Menu,
+    MenuBar,
+    MenuItem,
+    Tooltip,
+}
+
+impl Role {
+    /// Role for an explicit ARIA `role` token; abstract or unknown roles yield `None`.
+    pub fn from_aria(token: &str) -> Option<Role> {
+        Some(match token.to_ascii_lowercase().as_str() {
+            "generic" => Role::Generic,
+            "heading" => Role::Heading,
+            "paragraph" => Role::Paragraph,
+            "link" => Role::Link,
+            "button" => Role::Button,
+            "checkbox" | "switch" => Role::CheckBox,
+            "radio" => Role::Radio,
+            "textbox" => Role::TextBox,
+            "searchbox" => Role::SearchBox,
+            "spinbutton" => Role::SpinButton,
+            "slider" => Role::Slider,
+            "combobox" => Role::ComboBox,
+            "listbox" => Role::ListBox,
+            "option" => Role::Option,
+            "list" => Role::List,
+            "listitem" => Role::ListItem,
+            "img" | "image" => Role::Img,
+            "figure" => Role::Figure,
+            "table" | "grid" => Role::Table,
+            "row" => Role::Row,
+            "cell" | "gridcell" => Role::Cell,
+            "columnheader" => Role::ColumnHeader,
+            "rowheader" => Role::RowHeader,
+            "navigation" => Role::Navigation,
+            "main" => Role::Main,
+            "banner" => Role::Banner,
+            "contentinfo" => Role::ContentInfo,
+            "complementary" => Role::Complementary,
+            "region" => Role::Region,
+            "article" => Role::Article,
+            "form" => Role::Form,
+            "search" => Role::Search,
+            "group" => Role::Group,
+            "dialog" | "alertdialog" => Role::Dialog,
+            "alert" => Role::Alert,
+            "status" | "log" => Role::Status,
+            "separator" => Role::Separator,
+            "progressbar" => Role::ProgressBar,
+            "tab" => Role::Tab,
+            "tablist" => Role::TabList,
+            "tabpanel" => Role::TabPanel,
+            "menu" => Role::Menu,
+            "menubar" => Role::MenuBar,
+            "menuitem" | "menuitemcheckbox" | "menuitemradio" => Role::MenuItem,
+            "tooltip" => Role::Tooltip,
+            _ => return None,
+        })
+    }
+
+    /// Roles whose name falls back to their text content.
+    fn name_from_content(self) -> bool {
+        matches!(
+            self,
+            Role::Heading | Role::Link | Role::Button | Role::CheckBox | Role::Radio | Role::Option | Role::Cell | Role::ColumnHeader
+                | Role::RowHeader | Role::Tab | Role::MenuItem | Role::Tooltip | Role::StaticText
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Checked {
+    False,
+    True,
+    Mixed,
+}
+
+/// Boolean states; `None` means the state does not apply to the role.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub struct States {
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub focusable: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub focused: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub disabled: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub required: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub readonly: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub selected: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub checked: Option<Checked>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expanded: Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AccessNode {
+    #[serde(rename = "id", serialize_with = "node_index")]
+    pub node: NodeId,
+    pub role: Role,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    /// Heading level (1-6).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub level: Option<u8>,
+    pub states: States,
+    /// Border box in viewport coordinates, after scroll offsets.
+    #[serde(serialize_with = "rect_array")]
+    pub bounds: Rect,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<AccessNode>,
+}
+
+fn node_index<S: serde::Serializer>(n: &NodeId, s: S) -> Result<S::Ok, S::Error> { s.serialize_u64(n.0 as u64) }
+
+fn rect_array<S: serde::Serializer>(r: &Rect, s: S) -> Result<S::Ok, S::Error> { [r.x, r.y, r.w, r.h].serialize(s) }
+
+impl AccessNode {
+    /// Pre-order walk over this node and its descendants.
+    pub fn walk<'a>(&'a self, f: &mut impl FnMut(&'a AccessNode)) {
+        f(self);
+        for c in &self.children { c.walk(f); }
+    }
+
+    /// All nodes with `role`, in tree order.
+    pub fn find_all(&self, role: Role) -> Vec<&AccessNode> {
+        let mut out = Vec::new();
+        self.walk(&mut |n| if n.role == role { out.push(n) });
+        out
+    }
+
+    /// First node with `role` and exactly `name`.
+    pub fn find(&self, role: Role, name: &str) -> Option<&AccessNode> { self.find_all(role).into_iter().find(|n| n.name == name) }
+
+    /// Pretty-printed JSON dump; false states and empty fields are omitted.
+    pub fn to_json(&self) -> String { serde_json::to_string_pretty(self).expect("accessibility tree serializes") }
+}
+
+/// Build the accessibility tree for `doc` laid out as `root`.
+pub fn build(doc: &Document, root: &LayoutBox, scroll: &ScrollLayers, focus: Option<NodeId>) -> AccessNode {
+    let mut bounds = HashMap::new();
+    collect_bounds(root, scroll, (0, 0), &mut bounds);
+    let b = Builder { doc, bounds, focus };
+    let node = doc.root();
+    AccessNode {
+        node,
+        role: Role::Document,
+        name: doc.elements_by_tag("title").next().map(|t| collapse(&doc.text_content(t))).unwrap_or_default(),
+        description: None,
+        value: None,
+        level: None,
+        states: States::default(),
+        bounds: root.rect,
+        children: b.children(node),
+    }
+}
+
+/// Viewport rect of every node with a box, shifted by the offsets of enclosing scroll layers.
+fn collect_bounds(b: &LayoutBox, scroll: &ScrollLayers, (dx, dy): (i32, i32), out: &mut HashMap<NodeId, Rect>) {
+    let r = b.rect.translate(-dx, -dy);
+    out.entry(b.node).and_modify(|e: &mut Rect| *e = e.union(&r)).or_insert(r);
+    let (sx, sy) = scroll.offset(b.node);
+    for c in &b.children { collect_bounds(c, scroll, (dx + sx, dy + sy), out); }
+}
+
+struct Builder<'a> {
+    doc: &'a Document,
+    bounds: HashMap<NodeId, Rect>,
+    focus: Option<NodeId>,
+}
+
+impl Builder<'_> {
+    /// Accessible children of `node`, hoisting the children of ignored elements.
+    fn children(&self, node: NodeId) -> Vec<AccessNode> {
+        let mut out = Vec::new();
+        for &c in self.doc.children(node) {
+            if is_excluded(self.doc, c) { continue; }
+            match self.node(c) {
+                Some(n) => out.push(n),
+                None => out.extend(self.children(c)),
+            }
+        }
+        out
+    }
+
+    /// Accessible node for `node`, or `None` if it is ignored.
+    fn node(&self, node: NodeId) -> Option<AccessNode> {
+        let doc = self.doc;
+        if let Some(text) = doc.text(node) {
+            let name = collapse(text);
+            if name.is_empty() { return None; }
+            let bounds = self.bounds.get(&node).copied().unwrap_or_default();
+            return Some(AccessNode { node, role: Role::StaticText, name, description: None, value: None, level: None, states: States::default(), bounds, children: Vec::new() });
+        }
+        let focusable = events::is_focusable(doc, node);
+        let role = match role_of(doc, node) {
+            Some(Role::Generic) | None if focusable || doc.attr(node, "aria-label").is_some() => Role::Generic,
+            Some(r) => r,
+            None => return None,
+        };
+        let (name, from_title) = accessible_name(doc, node, role);
+        let description = doc.attr(node, "aria-describedby")
+            .map(|ids| labelled_by(doc, ids))
+            .or_else(|| doc.attr(node, "title").filter(|_| !from_title).map(collapse))
+            .filter(|d| !d.is_empty());
+        let children = self.children(node);
+        let bounds = match self.bounds.get(&node) {
+            Some(r) => *r,
+            None => children.iter().fold(Rect::default(), |r, c| r.union(&c.bounds)),
+        };
+        Some(AccessNode {
+            node,
+            role,
+            name,
+            description,
+            value: value_of(doc, node, role),
+            level: level_of(doc, node, role),
+            states: self.states(node, role, focusable),
+            bounds,
+            children,
+        })
+    }
+
+    fn states(&self, node: NodeId, role: Role, focusable: bool) -> States {
+        let doc = self.doc;
+        let aria = |name| doc.attr(node, name).map(|v| v.trim().to_ascii_lowercase());
+        let flag = |attr, aria_attr| doc.attr(node, attr).is_some() || aria(aria_attr).as_deref() == Some("true");
+        let input_checked = matches!(doc.tag_name(node), Some("input")).then(|| if doc.attr(node, "checked").is_some() { Checked::True } else { Checked::False });
+        let checked = match aria("aria-checked").as_deref() {
+            Some("true") => Some(Checked::True),
+            Some("mixed") => Some(Checked::Mixed),
+            Some("false") => Some(Checked::False),
+            _ => input_checked.filter(|_| matches!(role, Role::CheckBox | Role::Radio)),
+        };
+        States {
+            focusable,
+            focused: self.focus == Some(node),
+            disabled: flag("disabled", "aria-disabled"),
+            required: flag("required", "aria-required"),
+            readonly: flag("readonly", "aria-readonly"),
+            selected: flag("selected", "aria-selected"),
+            checked,
+            expanded: aria("aria-expanded").and_then(|v| v.parse().ok()).or_else(|| (doc.tag_name(node) == Some("details")).then(|| doc.attr(node, "open").is_some())),
+        }
+    }
+}
+
+/// Subtrees that are not exposed at all: non-rendered elements, `hidden`,
+/// `aria-hidden="true"`, `display: none` and hidden inputs.
+fn is_excluded(doc: &Document, node: NodeId) -> bool {
+    if doc.tag_name(node).is_none() { return false; }
+    layout::is_hidden(doc, node)
+        || doc.attr(node, "hidden").is_some()
+        || doc.attr(node, "aria-hidden").is_some_and(|v| v.trim().eq_ignore_ascii_case("true"))
+        || layout::style_value(doc, node, "display").is_some_and(|v| v.eq_ignore_ascii_case("none"))
+        || (doc.tag_name(node) == Some("input") && input_type(doc, node) == "hidden")
+}
+
+fn input_type(doc: &Document, node: NodeId) -> String { doc.attr(node, "type").unwrap_or("text").trim().to_ascii_lowercase() }
+
+/// Explicit ARIA role (first recognised token) or the element's implicit role.
+/// `None` for elements without semantics; `presentation`/`none` also map to `None`.
+fn role_of(doc: &Document, node: NodeId) -> Option<Role> {
+    if let Some(tokens) = doc.attr(node, "role") {
+        for t in tokens.split_whitespace() {
+            if t.eq_ignore_ascii_case("presentation") || t.eq_ignore_ascii_case("none") { return None; }
+            if let Some(r) = Role::from_aria(t) { return Some(r); }
+        }
+    }
+    let tag = doc.tag_name(node)?;
+    Some(match tag {
+        "a" | "area" if doc.attr(node, "href").is_some() => Role::Link,
+        "button" | "summary" => Role::Button,
+        "input" => match input_type(doc, node).as_str() {
+            "checkbox" => Role::CheckBox,
+            "radio" => Role::Radio,
+            "button" | "submit" | "reset" | "image" => Role::Button,
+            "range" => Role::Slider,
+            "number" => Role::SpinButton,
+            "search" => Role::SearchBox,
+            _ => Role::TextBox,
+        },
+        "textarea" => Role::TextBox,
+        "select" if doc.attr(node, "multiple").is_some() || doc.attr(node, "size").and_then(|s| s.trim().parse::<u32>().ok()).is_some_and(|s| s > 1) => Role::ListBox,
+        "select" => Role::ComboBox,
+        "option" => Role::Option,
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => Role::Heading,
+        "p" => Role::Paragraph,
+        "ul" | "ol" | "menu" => Role::List,
+        "li" => Role::ListItem,
+        "img" if doc.attr(node, "alt") == Some("") => return None,
+        "img" => Role::Img,
+        "figure" => Role::Figure,
+        "table" => Role::Table,
+        "tr" => Role::Row,
+        "td" => Role::Cell,
+        "th" if doc.attr(node, "scope").is_some_and(|s| s.eq_ignore_ascii_case("row")) => Role::RowHeader,
+        "th" => Role::ColumnHeader,
+        "nav" => Role::Navigation,
+        "main" => Role::Main,
+        "header" => Role::Banner,
+        "footer" => Role::ContentInfo,
+        "aside" => Role::Complementary,
+        // A section is only a landmark when it is labelled
+        "section" if doc.attr(node, "aria-label").is_some() || doc.attr(node, "aria-labelledby").is_some() => Role::Region,
+        "article" => Role::Article,
+        "form" => Role::Form,
+        "search" => Role::Search,
+        "fieldset" | "details" | "optgroup" => Role::Group,
+        "dialog" => Role::Dialog,
+        "hr" => Role::Separator,
+        "progress" | "meter" => Role::ProgressBar,
+        _ => return None,
+    })
+}
+
+/// Simplified accessible name computation: `aria-labelledby`, `aria-label`,
+/// native labels (`<label>`, `alt`, `<legend>`, `<caption>`...), content for
+/// roles that allow it, then `title` and `placeholder`. Also reports whether
+/// the name came from `title`, which is then not repeated as the description.
+fn accessible_name(doc: &Document, node: NodeId, role: Role) -> (String, bool) {
+    let nonempty = |s: String| Some(s).filter(|s| !s.is_empty());
+    let native = || -> Option<String> {
+        match doc.tag_name(node)? {
+            "input" if role == Role::Button => doc.attr(node, "value").map(collapse).or_else(|| match input_type(doc, node).as_str() {
+                "submit" => Some("Submit".into()),
+                "reset" => Some("Reset".into()),
+                "image" => doc.attr(node, "alt").map(collapse),
+                _ => None,
+            }),
+            "input" | "select" | "textarea" | "meter" | "progress" => nonempty(label_text(doc, node)),
+            "img" | "area" => doc.attr(node, "alt").map(collapse),
+            "fieldset" => child_text(doc, node, "legend"),
+            "table" => child_text(doc, node, "caption"),
+            "figure" => child_text(doc, node, "figcaption"),
+            _ => None,
+        }
+    };
+    let name = doc.attr(node, "aria-labelledby").map(|ids| labelled_by(doc, ids)).and_then(nonempty)
+        .or_else(|| doc.attr(node, "aria-label").map(collapse).and_then(nonempty))
+        .or_else(|| native().and_then(nonempty))
+        .or_else(|| role.name_from_content().then(|| collapse(&text_alternative(doc, node))).and_then(nonempty));
+    if let Some(name) = name { return (name, false); }
+    if let Some(title) = doc.attr(node, "title").map(collapse).and_then(nonempty) { return (title, true); }
+    (doc.attr(node, "placeholder").map(collapse).unwrap_or_default(), false)
+}
+
+/// Text of the elements referenced by a space-separated id list.
+fn labelled_by(doc: &Document, ids: &str) -> String {
+    let parts: Vec<String> = ids.split_whitespace().filter_map(|id| element_by_id(doc, id)).map(|n| collapse(&text_alternative(doc, n))).collect();
+    collapse(&parts.join(" "))
+}
+
+/// `<label for=id>` text, or the text of an enclosing `<label>`.
+fn label_text(doc: &Document, node: NodeId) -> String {
+    let by_for = doc.attr(node, "id").and_then(|id| {
+        doc.elements_by_tag("label").find(|&l| doc.attr(l, "for") == Some(id))
+    });
+    let label = by_for.or_else(|| std::iter::successors(doc.parent(node), |&n| doc.parent(n)).find(|&n| doc.tag_name(n) == Some("label")));
+    label.map(|l| collapse(&text_alternative(doc, l))).unwrap_or_default()
+}
+
+fn child_text(doc: &Document, node: NodeId, tag: &str) -> Option<String> {
+    doc.children(node).iter().find(|&&c| doc.tag_name(c) == Some(tag)).map(|&c| collapse(&text_alternative(doc, c)))
+}
+
+fn element_by_id(doc: &Document, id: &str) -> Option<NodeId> { doc.descendants(doc.root()).find(|&n| doc.attr(n, "id") == Some(id)) }
+
+/// Text contributed by a subtree: text nodes, `alt` of images and `aria-label`
+/// of elements, skipping hidden content.
+fn text_alternative(doc: &Document, node: NodeId) -> String {
+    if let Some(t) = doc.text(node) { return t.to_string(); }
+    if is_excluded(doc, node) { return String::new(); }
+    if let Some(label) = doc.attr(node, "aria-label").filter(|l| !l.trim().is_empty()) { return format!(" {label} "); }
+    if doc.tag_name(node) == Some("img") { return format!(" {} ", doc.attr(node, "alt").unwrap_or_default()); }
+    let inner: String = doc.children(node).iter().map(|&c| text_alternative(doc, c)).collect();
+    if layout::is_block(doc, node) { format!(" {inner} ") } else { inner }
+}
+
+fn value_of(doc: &Document, node: NodeId, role: Role) -> Option<String> {
+    if let Some(v) = doc.attr(node, "aria-valuetext").or_else(|| doc.attr(node, "aria-valuenow")) { return Some(v.trim().to_string()); }
+    match (doc.tag_name(node)?, role) {
+        ("input", Role::TextBox | Role::SearchBox | Role::SpinButton | Role::Slider) | ("progress" | "meter", _) => doc.attr(node, "value").map(str::to_string),
+        ("textarea", _) => Some(doc.text_content(node)),
+        ("select", _) => {
+            let options: Vec<NodeId> = doc.descendants(node).filter(|&n| doc.tag_name(n) == Some("option")).collect();
+            let selected = options.iter().find(|&&o| doc.attr(o, "selected").is_some()).or(options.first());
+            selected.map(|&o| collapse(&doc.text_content(o)))
+        }
+        _ => None,
+    }
+}
+
+fn level_of(doc: &Document, node: NodeId, role: Role) -> Option<u8> {
+    if role != Role::Heading { return None; }
+    doc.attr(node, "aria-level").and_then(|l| l.trim().parse().ok())
+        .or_else(|| doc.tag_name(node)?.strip_prefix('h')?.parse().ok())
+        .or(Some(2))
+}
+
+/// Collapse runs of whitespace to single spaces and trim the ends.
+fn collapse(s: &str) -> String { s.split_whitespace().collect::<Vec<_>>().join(" ") }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scroll::ScrollBehavior;
+    use crate::Page;
+
+    fn tree(html: &str) -> AccessNode { Page::new(html, (400, 300)).unwrap().accessibility_tree() }
+
+    #[test]
+    fn roles_names_and_flattening() {
+        let t = tree(r#"<title>Demo</title><div><span>
+            <h2>Intro <em>text</em></h2>
+            <nav aria-label=Site><ul><li><a href=/a>Home</a></li><li><a href=/b><img src=x.png alt=Logo></a></li></ul></nav>
+            <img src=y.png alt=""><p aria-hidden=true>secret</p><div hidden>gone</div>
+            <label for=q>Search</label><input id=q type=search placeholder=ignored>
+            <button title="Closes it" aria-describedby=d>X</button><p id=d>Closes   the dialog</p>
+            </span></div>"#);
+        assert_eq!((t.role, t.name.as_str()), (Role::Document, "Demo"));
+        let roles: Vec<Role> = t.children.iter().map(|c| c.role).collect();
+        assert_eq!(roles, vec![Role::Heading, Role::Navigation, Role::StaticText, Role::SearchBox, Role::Button, Role::Paragraph], "div/span flattened, hidden and decorative content dropped");
+        let h = &t.children[0];
+        assert_eq!((h.name.as_str(), h.level), ("Intro text", Some(2)));
+        let nav = t.find(Role::Navigation, "Site").unwrap();
+        assert_eq!(nav.children[0].role, Role::List);
+        let links: Vec<&str> = t.find_all(Role::Link).iter().map(|l| l.name.as_str()).collect();
+        assert_eq!(links, vec!["Home", "Logo"]);
+        assert_eq!(t.children[3].name, "Search");
+        let button = t.find(Role::Button, "X").unwrap();
+        assert_eq!(button.description.as_deref(), Some("Closes the dialog"));
+        assert!(button.states.focusable);
+        assert!(t.find_all(Role::StaticText).iter().all(|s| s.name != "secret" && s.name != "gone"));
+    }
+
+    #[test]
+    fn aria_roles_and_states() {
+        let t = tree(r#"<div role="switch bogus" aria-checked=mixed tabindex=0>Wifi</div>
+            <input type=checkbox checked disabled aria-label=Agree><div role=presentation><p>kept</p></div>
+            <select><option>One</option><option selected>Two</option></select>
+            <div role=button aria-expanded=false aria-labelledby="a b">?</div><b id=a>More</b><b id=b>options</b>
+            <input type=hidden value=x><textarea required readonly>hello</textarea>"#);
+        let switch = t.find(Role::CheckBox, "Wifi").unwrap();
+        assert_eq!(switch.states.checked, Some(Checked::Mixed));
+        assert!(switch.states.focusable);
+        let agree = t.find(Role::CheckBox, "Agree").unwrap();
+        assert_eq!((agree.states.checked, agree.states.disabled, agree.states.focusable), (Some(Checked::True), true, false));
+        assert!(t.find(Role::Paragraph, "").is_some(), "presentation role only drops the element itself");
+        let combo = &t.find_all(Role::ComboBox)[0];
+        assert_eq!(combo.value.as_deref(), Some("Two"));
+        assert!(combo.find(Role::Option, "Two").unwrap().states.selected);
+        let more = t.find(Role::Button, "More options").unwrap();
+        assert_eq!(more.states.expanded, Some(false));
+        let text = &t.find_all(Role::TextBox)[0];
+        assert_eq!((text.value.as_deref(), text.states.required, text.states.readonly), (Some("hello"), true, true));
+        assert_eq!(t.find_all(Role::TextBox).len(), 1, "hidden inputs are not exposed");
+    }
+
+    #[test]
+    fn bounds_follow_layout_scroll_and_focus() {
+        let mut page = Page::new("<p style='height:500px'>top</p><button>Go</button>", (400, 300)).unwrap();
+        let go = |page: &Page| page.accessibility_tree().find(Role::Button, "Go").unwrap().clone();
+        let before = go(&page);
+        let button = before.node;
+        assert_eq!(before.bounds, page.layout_root().find(button).unwrap().rect);
+        assert!(!before.states.focused);
+        page.scroll_by(page.document().root(), 0, 100, ScrollBehavior::Instant);
+        page.set_focus(Some(button));
+        let after = go(&page);
+        assert_eq!(after.bounds, before.bounds.translate(0, -100));
+        assert!(after.states.focused);
+    }
+
+    #[test]
+    fn json_dump_omits_defaults() {
+        let t = tree("<h1>Hi</h1><input type=checkbox aria-label=Ok>");
+        let v: serde_json::Value = serde_json::from_str(&t.to_json()).unwrap();
+        assert_eq!(v["role"], "document");
+        let h = &v["children"][0];
+        assert_eq!((&h["role"], &h["name"], &h["level"]), (&"heading".into(), &"Hi".into(), &1.into()));
+        assert_eq!(h["states"], serde_json::json!({}));
+        assert_eq!(h["bounds"].as_array().unwrap().len(), 4);
+        assert!(h.get("description").is_none() && h.get("value").is_none());
+        assert_eq!(v["children"][1]["states"], serde_json::json!({ "focusable": true, "checked": "false" }));
+        assert_eq!(v["children"][0]["children"][0]["role"], "statictext");
+    }
+}
@@ -0,0 +1,431 @@
+//! AT-SPI bridge: publishes an [`AccessNode`] tree on the accessibility bus so
+//! Orca and other Linux assistive technology can read servo-lite pages.
+//!
+//! Every accessible node is served at `/org/a11y/atspi/accessible/<id>` with
+//! the `Accessible` and `Component` interfaces, below an application object
+//! at the AT-SPI root path. The tree is a snapshot: call [`AtspiBridge::update`]
+//! after layout or focus changes to republish it.
+
+use crate::a11y::{AccessNode, Checked, Role};
+use crate::layout::Rect;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, RwLock};
+use zbus::zvariant::OwnedObjectPath;
+use zbus::{connection, interface, Connection};
+
+const ROOT_PATH: &str = "/org/a11y/atspi/accessible/root";
+const NULL_PATH: &str = "/org/a11y/atspi/null";
+/// Snapshot key of the application object that parents the document.
+const APP_ID: usize = usize::MAX;
+/// `ATSPI_ROLE_APPLICATION`
+const ROLE_APPLICATION: u32 = 75;
+
+/// `(bus name, object path)` reference, D-Bus signature `(so)`.
+type ObjectRef = (String, OwnedObjectPath);
+
+/// Flattened copy of the tree shared by all served objects.
+#[derive(Default)]
+struct Snapshot {
+    nodes: HashMap<usize, Entry>,
+    app_name: String,
+    /// Top-left corner of the page viewport on screen.
+    origin: (i32, i32),
+    /// Desktop object the application was embedded into.
+    desktop: Option<ObjectRef>,
+}
+
+struct Entry {
+    role: u32,
+    name: String,
+    description: String,
+    states: [u32; 2],
+    level: Option<u8>,
+    value: Option<String>,
+    bounds: Rect,
+    parent: Option<usize>,
+    children: Vec<usize>,
+}
+
+impl Snapshot {
+    fn load(&mut self, tree: &AccessNode) {
+        self.nodes.clear();
+        let app = Entry {
+            role: ROLE_APPLICATION,
+            name: self.app_name.clone(),
+            description: String::new(),
+            states: [0, 0],
+            level: None,
+            value: None,
+            bounds: tree.bounds,
+            parent: None,
+            children: vec![tree.node.0],
+        };
+        self.nodes.insert(APP_ID, app);
+        self.insert(tree, Some(APP_ID));
+    }
+
+    fn insert(&mut self, n: &AccessNode, parent: Option<usize>) {
+        let entry = Entry {
+            role: atspi_role(n.role),
+            name: n.name.clone(),
+            description: n.description.clone().unwrap_or_default(),
+            states: state_set(n),
+            level: n.level,
+            value: n.value.clone(),
+            bounds: n.bounds,
+            parent,
+            children: n.children.iter().map(|c| c.node.0).collect(),
+        };
+        self.nodes.insert(n.node.0, entry);
+        for c in &n.children { self.insert(c, Some(n.node.0)); }
+    }
+}
+
+/// Connection to the accessibility bus serving one page.
+pub struct AtspiBridge {
+    conn: Connection,
+    snapshot: Arc<RwLock<Snapshot>>,
+    served: BTreeSet<usize>,
+}
+
+impl AtspiBridge {
+    /// Connect to the accessibility bus, publish `tree` and embed the
+    /// application under the desktop. Needs a running session bus and
+    /// an AT-SPI registry.
+    pub async fn start(app_name: &str, tree: &AccessNode) -> zbus::Result<Self> {
+        let session = Connection::session().await?;
+        let reply = session.call_method(Some("org.a11y.Bus"), "/org/a11y/bus", Some("org.a11y.Bus"), "GetAddress", &()).await?;
+        let address: String = reply.body().deserialize()?;
+        let conn = connection::Builder::address(address.as_str())?.build().await?;
+        let snapshot = Arc::new(RwLock::new(Snapshot { app_name: app_name.to_string(), ..Default::default() }));
+        conn.object_server().at(ROOT_PATH, Application { name: app_name.to_string(), id: 0 }).await?;
+        let mut bridge = Self { conn, snapshot, served: BTreeSet::new() };
+        bridge.update(tree).await?;
+        let plug = object_ref(&bridge.bus_name(), Some(APP_ID));
+        let reply = bridge.conn.call_method(Some("org.a11y.atspi.Registry"), ROOT_PATH, Some("org.a11y.atspi.Socket"), "Embed", &(plug,)).await?;
+        let desktop: ObjectRef = reply.body().deserialize()?;
+        bridge.snapshot.write().unwrap().desktop = Some(desktop);
+        Ok(bridge)
+    }
+
+    /// Republish the tree, registering objects for new nodes and dropping removed ones.
+    pub async fn update(&mut self, tree: &AccessNode) -> zbus::Result<()> {
+        let ids: BTreeSet<usize> = {
+            let mut snap = self.snapshot.write().unwrap();
+            snap.load(tree);
+            snap.nodes.keys().copied().collect()
+        };
+        let server = self.conn.object_server();
+        for &id in self.served.difference(&ids) {
+            let path = node_path(id);
+            server.remove::<Accessible, _>(path.as_str()).await?;
+            server.remove::<Component, _>(path.as_str()).await?;
+        }
+        for &id in ids.difference(&self.served) {
+            let path = node_path(id);
+            let bus = self.bus_name();
+            server.at(path.as_str(), Accessible { id, bus, snapshot: self.snapshot.clone() }).await?;
+            server.at(path.as_str(), Component { id, snapshot: self.snapshot.clone() }).await?;
+        }
+        self.served = ids;
+        Ok(())
+    }
+
+    /// Screen position of the page viewport, used for screen-relative extents.
+    pub fn set_origin(&self, x: i32, y: i32) { self.snapshot.write().unwrap().origin = (x, y); }
+
+    fn bus_name(&self) -> String { self.conn.unique_name().map(|n| n.to_string()).unwrap_or_default() }
+}
+
+fn node_path(id: usize) -> String {
+    if id == APP_ID { ROOT_PATH.to_string() } else { format!("/org/a11y/atspi/accessible/{id}") }
+}
+
+fn object_ref(bus: &str, id: Option<usize>) -> ObjectRef {
+    let path = id.map(node_path).unwrap_or_else(|| NULL_PATH.to_string());
+    (bus.to_string(), OwnedObjectPath::try_from(path).expect("valid object path"))
+}
+
+/// `org.a11y.atspi.Application` on the root path.
+struct Application {
+    name: String,
+    id: i32,
+}
+
+#[interface(name = "org.a11y.atspi.Application")]
+impl Application {
+    #[zbus(property)]
+    fn toolkit_name(&self) -> String { self.name.clone() }
+
+    #[zbus(property)]
+    fn version(&self) -> String { env!("CARGO_PKG_VERSION").to_string() }
+
+    #[zbus(property)]
+    fn atspi_version(&self) -> String { "2.1".to_string() }
+
+    #[zbus(property)]
+    fn id(&self) -> i32 { self.id }
+
+    #[zbus(property)]
+    fn set_id(&mut self, id: i32) { self.id = id; }
+
+    fn get_locale(&self, _lctype: u32) -> String { "C".to_string() }
+}
+
+struct Accessible {
+    id: usize,
+    bus: String,
+    snapshot: Arc<RwLock<Snapshot>>,
+}
+
+impl Accessible {
+    fn with<T: Default>(&self, f: impl FnOnce(&Entry) -> T) -> T {
+        self.snapshot.read().unwrap().nodes.get(&self.id).map(f).unwrap_or_default()
+    }
+}
+
+#[interface(name = "org.a11y.atspi.Accessible")]
+impl Accessible {
+    #[zbus(property)]
+    fn name(&self) -> String { self.with(|e| e.name.clone()) }
+
+    #[zbus(property)]
+    fn description(&self) -> String { self.with(|e| e.description.clone()) }
+
+    #[zbus(property)]
+    fn parent(&self) -> ObjectRef {
+        let snap = self.snapshot.read().unwrap();
+        match snap.nodes.get(&self.id).and_then(|e| e.parent) {
+            Some(p) => object_ref(&self.bus, Some(p)),
+            None => snap.desktop.clone().unwrap_or_else(|| object_ref(&self.bus, None)),
+        }
+    }
+
+    #[zbus(property)]
+    fn child_count(&self) -> i32 { self.with(|e| e.children.len() as i32) }
+
+    #[zbus(property)]
+    fn locale(&self) -> String { "C".to_string() }
+
+    #[zbus(property)]
+    fn accessible_id(&self) -> String { self.id.to_string() }
+
+    fn get_child_at_index(&self, index: i32) -> ObjectRef {
+        let child = self.with(|e| usize::try_from(index).ok().and_then(|i| e.children.get(i).copied()));
+        object_ref(&self.bus, child)
+    }
+
+    fn get_children(&self) -> Vec<ObjectRef> { self.with(|e| e.children.iter().map(|&c| object_ref(&self.bus, Some(c))).collect()) }
+
+    fn get_index_in_parent(&self) -> i32 {
+        let snap = self.snapshot.read().unwrap();
+        let parent = snap.nodes.get(&self.id).and_then(|e| e.parent).and_then(|p| snap.nodes.get(&p));
+        parent.and_then(|p| p.children.iter().position(|&c| c == self.id)).map(|i| i as i32).unwrap_or(-1)
+    }
+
+    fn get_relation_set(&self) -> Vec<(u32, Vec<ObjectRef>)> { Vec::new() }
+
+    fn get_role(&self) -> u32 { self.with(|e| e.role) }
+
+    fn get_role_name(&self) -> String { self.with(|e| role_name(e.role).to_string()) }
+
+    fn get_localized_role_name(&self) -> String { self.get_role_name() }
+
+    fn get_state(&self) -> Vec<u32> { self.with(|e| e.states.to_vec()) }
+
+    fn get_attributes(&self) -> HashMap<String, String> {
+        self.with(|e| {
+            let mut attrs = HashMap::from([("toolkit".to_string(), "servo-lite".to_string())]);
+            if let Some(level) = e.level { attrs.insert("level".into(), level.to_string()); }
+            if let Some(value) = &e.value { attrs.insert("valuetext".into(), value.clone()); }
+            attrs
+        })
+    }
+
+    fn get_application(&self) -> ObjectRef { (self.bus.clone(), OwnedObjectPath::try_from(ROOT_PATH).expect("valid object path")) }
+
+    fn get_interfaces(&self) -> Vec<String> { vec!["org.a11y.atspi.Accessible".into(), "org.a11y.atspi.Component".into()] }
+}
+
+struct Component {
+    id: usize,
+    snapshot: Arc<RwLock<Snapshot>>,
+}
+
+impl Component {
+    /// Bounds in the requested coordinate type: 0 screen, 1 window, 2 parent.
+    fn extents(&self, coord_type: u32) -> Rect {
+        let snap = self.snapshot.read().unwrap();
+        let Some(e) = snap.nodes.get(&self.id) else { return Rect::default() };
+        match coord_type {
+            0 => e.bounds.translate(snap.origin.0, snap.origin.1),
+            2 => {
+                let p = e.parent.and_then(|p| snap.nodes.get(&p)).map(|p| p.bounds).unwrap_or_default();
+                e.bounds.translate(-p.x, -p.y)
+            }
+            _ => e.bounds,
+        }
+    }
+}
+
+#[interface(name = "org.a11y.atspi.Component")]
+impl Component {
+    fn get_extents(&self, coord_type: u32) -> (i32, i32, i32, i32) {
+        let r = self.extents(coord_type);
+        (r.x, r.y, r.w, r.h)
+    }
+
+    fn get_position(&self, coord_type: u32) -> (i32, i32) {
+        let r = self.extents(coord_type);
+        (r.x, r.y)
+    }
+
+    fn get_size(&self) -> (i32, i32) {
+        let r = self.extents(1);
+        (r.w, r.h)
+    }
+
+    fn contains(&self, x: i32, y: i32, coord_type: u32) -> bool { self.extents(coord_type).contains(x, y) }
+
+    fn get_layer(&self) -> u32 {
+        // ATSPI_LAYER_WIDGET
+        3
+    }
+}
+
+/// `AtspiRole` value for a role.
+fn atspi_role(role: Role) -> u32 {
+    match role {
+        Role::Alert => 2,
+        Role::CheckBox => 7,
+        Role::ColumnHeader => 57,
+        Role::ComboBox => 11,
+        Role::Dialog => 16,
+        Role::Img => 27,
+        Role::List => 31,
+        Role::ListItem | Role::Option => 32,
+        Role::Menu => 33,
+        Role::MenuBar => 34,
+        Role::MenuItem => 35,
+        Role::Tab => 37,
+        Role::TabList => 38,
+        Role::TabPanel => 39,
+        Role::ProgressBar => 42,
+        Role::Button => 43,
+        Role::Radio => 44,
+        Role::RowHeader => 58,
+        Role::Separator => 50,
+        Role::Slider => 51,
+        Role::SpinButton => 52,
+        Role::Status => 54,
+        Role::Table => 55,
+        Role::Cell => 56,
+        Role::Tooltip => 64,
+        Role::Paragraph => 73,
+        Role::TextBox | Role::SearchBox => 79,
+        Role::Heading => 83,
+        Role::Generic | Role::Figure => 85,
+        Role::Form => 87,
+        Role::Link => 88,
+        Role::Row => 90,
+        Role::Document => 95,
+        Role::ListBox => 98,
+        Role::Group => 99,
+        Role::Article => 109,
+        Role::Navigation | Role::Main | Role::Banner | Role::ContentInfo | Role::Complementary | Role::Region | Role::Search => 110,
+        Role::StaticText => 116,
+    }
+}
+
+fn role_name(role: u32) -> &'static str {
+    match role {
+        2 => "alert",
+        7 => "check box",
+        11 => "combo box",
+        16 => "dialog",
+        27 => "image",
+        31 => "list",
+        32 => "list item",
+        33 => "menu",
+        34 => "menu bar",
+        35 => "menu item",
+        37 => "page tab",
+        38 => "page tab list",
+        39 => "panel",
+        42 => "progress bar",
+        43 => "push button",
+        44 => "radio button",
+        50 => "separator",
+        51 => "slider",
+        52 => "spin button",
+        54 => "status bar",
+        55 => "table",
+        56 => "table cell",
+        57 => "table column header",
+        58 => "table row header",
+        64 => "tool tip",
+        73 => "paragraph",
+        75 => "application",
+        79 => "entry",
+        83 => "heading",
+        87 => "form",
+        88 => "link",
+        90 => "table row",
+        95 => "document web",
+        98 => "list box",
+        99 => "grouping",
+        109 => "article",
+        110 => "landmark",
+        116 => "static",
+        _ => "section",
+    }
+}
+
+/// `AtspiStateSet` bit words for a node.
+fn state_set(n: &AccessNode) -> [u32; 2] {
+    let mut bits: u64 = 0;
+    let mut set = |bit: u32, on: bool| if on { bits |= 1 << bit };
+    let s = &n.states;
+    set(8, !s.disabled); // ENABLED
+    set(24, !s.disabled); // SENSITIVE
+    set(25, !n.bounds.is_empty()); // SHOWING
+    set(30, true); // VISIBLE
+    set(11, s.focusable);
+    set(12, s.focused);
+    set(33, s.required);
+    set(43, s.readonly);
+    set(23, s.selected);
+    set(22, n.role == Role::Option);
+    set(41, s.checked.is_some()); // CHECKABLE
+    set(4, s.checked == Some(Checked::True));
+    set(32, s.checked == Some(Checked::Mixed)); // INDETERMINATE
+    set(9, s.expanded.is_some()); // EXPANDABLE
+    set(10, s.expanded == Some(true));
+    set(5, s.expanded == Some(false)); // COLLAPSED
+    let editable = matches!(n.role, Role::TextBox | Role::SearchBox | Role::SpinButton) && !s.readonly;
+    set(7, editable);
+    set(26, editable);
+    [bits as u32, (bits >> 32) as u32]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Page;
+
+    #[test]
+    fn snapshot_roles_and_state_bits() {
+        let tree = Page::new("<input type=checkbox checked aria-label=A><h3>T</h3>", (400, 300)).unwrap().accessibility_tree();
+        let mut snap = Snapshot { app_name: "test".into(), ..Default::default() };
+        snap.load(&tree);
+        let app = &snap.nodes[&APP_ID];
+        assert_eq!((app.role, app.name.as_str(), node_path(APP_ID).as_str()), (ROLE_APPLICATION, "test", ROOT_PATH));
+        let root = &snap.nodes[&app.children[0]];
+        assert_eq!((root.role, root.parent, root.children.len()), (95, Some(APP_ID), 2));
+        let check = &snap.nodes[&root.children[0]];
+        assert_eq!(check.states[0] & (1 << 4 | 1 << 11), 1 << 4 | 1 << 11, "checked and focusable");
+        assert_eq!(check.states[1] & (1 << (41 - 32)), 1 << (41 - 32), "checkable");
+        let heading = &snap.nodes[&root.children[1]];
+        assert_eq!((role_name(heading.role), heading.level, heading.parent), ("heading", Some(3), Some(tree.node.0)));
+    }
+}
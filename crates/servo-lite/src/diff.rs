@@ -0,0 +1,149 @@
+//! DisplayList diffing: compute the screen regions that changed between two frames.
+
+use crate::layout::Rect;
+use message_defs::{DisplayList, DrawCmd};
+
+/// Above this many cell comparisons the middle section is damaged wholesale instead of aligned.
+const MAX_LCS_CELLS: usize = 1 << 20;
+
+/// Bounds covered by a draw command.
+pub fn cmd_bounds(cmd: &DrawCmd) -> Rect {
+    match cmd {
+        DrawCmd::Rect { x, y, w, h, .. } | DrawCmd::Image { x, y, w, h, .. } => Rect::new(*x as i32, *y as i32, *w as i32, *h as i32),
+        DrawCmd::PushLayer { clip_x, clip_y, clip_w, clip_h, .. } => Rect::new(*clip_x as i32, *clip_y as i32, *clip_w as i32, *clip_h as i32),
+        DrawCmd::PopLayer => Rect::default(),
+    }
+}
+
+/// Screen bounds of every command, following the offsets and clips of enclosing layers.
+fn screen_bounds(items: &[DrawCmd], clip: Rect) -> Vec<Rect> {
+    let mut stack = Vec::new();
+    let (mut offset, mut clip) = ((0, 0), clip);
+    items.iter().map(|cmd| {
+        let r = cmd_bounds(cmd).translate(-offset.0, -offset.1).intersection(&clip).unwrap_or_default();
+        match cmd {
+            DrawCmd::PushLayer { scroll_x, scroll_y, .. } => {
+                stack.push((offset, clip));
+                offset = (offset.0 + scroll_x, offset.1 + scroll_y);
+                clip = r;
+            }
+            DrawCmd::PopLayer => (offset, clip) = stack.pop().unwrap_or((offset, clip)),
+            _ => {}
+        }
+        r
+    }).collect()
+}
+
+/// Damage rectangles needed to turn a frame painted from `old` into one painted from `new`.
+///
+/// Commands are aligned by longest common subsequence so that an unchanged command
+/// keeps its painter's-order slot; every inserted or removed command damages its
+/// bounds. A layer whose scroll offset changed damages its whole clip. Overlapping
+/// or touching rects are merged and the result clipped to `clip`.
+pub fn damage(old: &DisplayList, new: &DisplayList, clip: Rect) -> Vec<Rect> {
+    let (a, b) = (&old.items, &new.items);
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..].iter().rev().zip(b[prefix..].iter().rev()).take_while(|(x, y)| x == y).count();
+    let (ra, rb) = (prefix..a.len() - suffix, prefix..b.len() - suffix);
+    let (bounds_a, bounds_b) = (screen_bounds(a, clip), screen_bounds(b, clip));
+    let (a, b, bounds_a, bounds_b) = (&a[ra.clone()], &b[rb.clone()], &bounds_a[ra], &bounds_b[rb]);
+
+    let mut rects = Vec::new();
+    if a.len().saturating_mul(b.len()) > MAX_LCS_CELLS {
+        rects.extend(bounds_a.iter().chain(bounds_b));
+    } else {
+        let (keep_a, keep_b) = lcs(a, b);
+        rects.extend(bounds_a.iter().zip(keep_a).filter(|(_, k)| !k).map(|(r, _)| *r));
+        rects.extend(bounds_b.iter().zip(keep_b).filter(|(_, k)| !k).map(|(r, _)| *r));
+    }
+    merge(rects.into_iter().filter(|r| !r.is_empty()).collect())
+}
+
+/// Flags for the items of `a` and `b` that belong to one longest common subsequence.
+fn lcs(a: &[DrawCmd], b: &[DrawCmd]) -> (Vec<bool>, Vec<bool>) {
+    let (n, m) = (a.len(), b.len());
+    let mut t = vec![0u32; (n + 1) * (m + 1)];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            t[i * (m + 1) + j] = if a[i] == b[j] {
+                t[(i + 1) * (m + 1) + j + 1] + 1
+            } else {
+                t[(i + 1) * (m + 1) + j].max(t[i * (m + 1) + j + 1])
+            };
+        }
+    }
+    let (mut keep_a, mut keep_b) = (vec![false; n], vec![false; m]);
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if a[i] == b[j] {
+            keep_a[i] = true;
+            keep_b[j] = true;
+            i += 1;
+            j += 1;
+        } else if t[(i + 1) * (m + 1) + j] >= t[i * (m + 1) + j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    (keep_a, keep_b)
+}
+
+fn touches(a: &Rect, b: &Rect) -> bool { a.x <= b.right() && b.x <= a.right() && a.y <= b.bottom() && b.y <= a.bottom() }
+
+/// Repeatedly union rects that overlap or share an edge until none do.
+fn merge(mut rects: Vec<Rect>) -> Vec<Rect> {
+    let mut out: Vec<Rect> = Vec::with_capacity(rects.len());
+    while let Some(mut r) = rects.pop() {
+        while let Some(i) = out.iter().position(|o| touches(o, &r)) {
+            r = r.union(&out.swap_remove(i));
+        }
+        out.push(r);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: u32, y: u32, w: u32, h: u32, rgba: u32) -> DrawCmd { DrawCmd::Rect { x, y, w, h, rgba } }
+
+    #[test]
+    fn identical_lists_have_no_damage() {
+        let dl = DisplayList { items: vec![rect(0, 0, 10, 10, 1), rect(2, 2, 3, 3, 2)] };
+        assert!(damage(&dl, &dl.clone(), Rect::new(0, 0, 100, 100)).is_empty());
+    }
+
+    #[test]
+    fn changed_and_moved_items_damage_both_positions() {
+        let old = DisplayList { items: vec![rect(0, 0, 100, 100, 1), rect(10, 10, 5, 5, 2), rect(50, 50, 5, 5, 3)] };
+        let new = DisplayList { items: vec![rect(0, 0, 100, 100, 1), rect(10, 10, 5, 5, 2), rect(60, 70, 5, 5, 3)] };
+        let mut d = damage(&old, &new, Rect::new(0, 0, 100, 100));
+        d.sort_by_key(|r| (r.x, r.y));
+        assert_eq!(d, vec![Rect::new(50, 50, 5, 5), Rect::new(60, 70, 5, 5)]);
+    }
+
+    #[test]
+    fn adjacent_damage_is_merged_and_clipped() {
+        let old = DisplayList { items: vec![] };
+        let new = DisplayList { items: vec![rect(0, 0, 10, 10, 1), rect(10, 0, 10, 10, 1), rect(90, 90, 50, 50, 1)] };
+        let mut d = damage(&old, &new, Rect::new(0, 0, 100, 100));
+        d.sort_by_key(|r| (r.x, r.y));
+        assert_eq!(d, vec![Rect::new(0, 0, 20, 10), Rect::new(90, 90, 10, 10)]);
+    }
+
+    #[test]
+    fn scrolled_layer_damages_its_clip_only() {
+        let layer = |scroll_y| DrawCmd::PushLayer { clip_x: 10, clip_y: 10, clip_w: 20, clip_h: 20, scroll_x: 0, scroll_y };
+        let old = DisplayList { items: vec![rect(0, 0, 100, 100, 1), layer(0), rect(10, 10, 5, 50, 2), DrawCmd::PopLayer] };
+        let mut new = DisplayList { items: vec![rect(0, 0, 100, 100, 1), layer(5), rect(10, 10, 5, 50, 2), DrawCmd::PopLayer] };
+        assert_eq!(damage(&old, &new, Rect::new(0, 0, 100, 100)), vec![Rect::new(10, 10, 20, 20)]);
+        // Inside an unchanged layer, damage is offset by the scroll and clipped to the layer
+        new.items[1] = layer(0);
+        new.items[2] = rect(20, 25, 40, 5, 3);
+        let mut d = damage(&old, &new, Rect::new(0, 0, 100, 100));
+        d.sort_by_key(|r| (r.x, r.y));
+        assert_eq!(d, vec![Rect::new(10, 10, 5, 20), Rect::new(20, 25, 10, 5)]);
+    }
+}
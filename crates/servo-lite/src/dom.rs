@@ -0,0 +1,669 @@
+//! Tiny tolerant HTML parser producing an arena-backed DOM.
+//! Handles void elements, raw-text elements, implicit `<p>`/`<li>` closing and
+//! a handful of character references; anything fancier is out of scope.
+
+use std::collections::BTreeSet;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(pub usize);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NodeData {
+    Document,
+    Element { name: String, attrs: Vec<(String, String)> },
+    Text(String),
+    /// Only from [`Document::parse_with_comments`].
+    Comment(String),
+    /// A `<!...>` declaration such as the doctype, as written between `<!` and `>`.
+    /// Only from [`Document::parse_with_comments`].
+    Declaration(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub parent: Option<NodeId>,
+    pub children: Vec<NodeId>,
+    pub data: NodeData,
+}
+
+#[derive(Debug, Clone)]
+pub struct Document {
+    nodes: Vec<Node>,
+    /// Nodes mutated since the last `take_dirty`, consumed by incremental layout.
+    dirty: BTreeSet<NodeId>,
+}
+
+const VOID: &[&str] = &["area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source", "track", "wbr"];
+const RAW_TEXT: &[&str] = &["script", "style", "textarea", "title"];
+/// Opening one of these implicitly closes an open `<p>`.
+const CLOSES_P: &[&str] = &[
+    "address", "article", "aside", "blockquote", "div", "dl", "fieldset", "footer", "form", "h1", "h2", "h3", "h4", "h5", "h6",
+    "header", "hr", "main", "nav", "ol", "p", "pre", "section", "table", "ul",
+];
+
+impl Default for Document {
+    fn default() -> Self { Self::new() }
+}
+
+impl Document {
+    /// Empty document containing only the root node.
+    pub fn new() -> Self {
+        Self { nodes: vec![Node { parent: None, children: Vec::new(), data: NodeData::Document }], dirty: BTreeSet::new() }
+    }
+
+    pub fn parse(html: &str) -> Self {
+        let mut doc = Self::new();
+        Parser { src: html, pos: 0, stack: vec![doc.root()], keep_comments: false }.run(&mut doc);
+        doc.dirty.clear();
+        doc
+    }
+
+    /// Like [`Self::parse`], but keeps comments and the doctype as nodes so that
+    /// [`Self::to_html`] reproduces them.
+    pub fn parse_with_comments(html: &str) -> Self {
+        let mut doc = Self::new();
+        Parser { src: html, pos: 0, stack: vec![doc.root()], keep_comments: true }.run(&mut doc);
+        doc.dirty.clear();
+        doc
+    }
+
+    pub fn root(&self) -> NodeId { NodeId(0) }
+
+    pub fn len(&self) -> usize { self.nodes.len() }
+
+    pub fn is_empty(&self) -> bool { self.nodes.len() <= 1 }
+
+    pub fn node(&self, id: NodeId) -> &Node { &self.nodes[id.0] }
+
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> { self.nodes[id.0].parent }
+
+    pub fn children(&self, id: NodeId) -> &[NodeId] { &self.nodes[id.0].children }
+
+    /// Lowercase tag name for elements, `None` for text and the document node.
+    pub fn tag_name(&self, id: NodeId) -> Option<&str> {
+        match &self.nodes[id.0].data {
+            NodeData::Element { name, .. } => Some(name),
+            _ => None,
+        }
+    }
+
+    pub fn attr(&self, id: NodeId, name: &str) -> Option<&str> {
+        match &self.nodes[id.0].data {
+            NodeData::Element { attrs, .. } => attrs.iter().find(|(k, _)| k.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str()),
+            _ => None,
+        }
+    }
+
+    pub fn text(&self, id: NodeId) -> Option<&str> {
+        match &self.nodes[id.0].data {
+            NodeData::Text(t) => Some(t),
+            _ => None,
+        }
+    }
+
+    /// Concatenated text of all descendant text nodes.
+    pub fn text_content(&self, id: NodeId) -> String {
+        let mut out = String::new();
+        for n in self.descendants(id) {
+            if let Some(t) = self.text(n) { out.push_str(t); }
+        }
+        out
+    }
+
+    /// Pre-order traversal of `id` and everything below it.
+    pub fn descendants(&self, id: NodeId) -> Descendants<'_> {
+        Descendants { doc: self, stack: vec![id] }
+    }
+
+    pub fn elements_by_tag<'a>(&'a self, tag: &'a str) -> impl Iterator<Item = NodeId> + 'a {
+        self.descendants(self.root()).filter(move |&n| self.tag_name(n).is_some_and(|t| t.eq_ignore_ascii_case(tag)))
+    }
+
+    /// Descendants of `scope` (excluding it) matching a selector list, in document order.
+    /// See [`Selector`] for the supported syntax; invalid selectors match nothing.
+    pub fn select(&self, scope: NodeId, selector: &str) -> Vec<NodeId> {
+        let Some(list) = Selector::parse_list(selector) else { return Vec::new() };
+        self.descendants(scope).skip(1).filter(|&n| list.iter().any(|s| s.matches(self, n))).collect()
+    }
+
+    pub fn create_element(&mut self, name: &str, attrs: Vec<(String, String)>) -> NodeId {
+        self.push(NodeData::Element { name: name.to_ascii_lowercase(), attrs })
+    }
+
+    pub fn create_text(&mut self, text: &str) -> NodeId { self.push(NodeData::Text(text.to_string())) }
+
+    /// Append `child` to `parent`, detaching it from its old parent first.
+    pub fn append_child(&mut self, parent: NodeId, child: NodeId) {
+        self.detach(child);
+        self.nodes[child.0].parent = Some(parent);
+        self.nodes[parent.0].children.push(child);
+        self.dirty.insert(parent);
+    }
+
+    /// Insert `child` before `reference` (or append when `reference` is not a child of `parent`).
+    pub fn insert_before(&mut self, parent: NodeId, child: NodeId, reference: NodeId) {
+        self.detach(child);
+        let pos = self.nodes[parent.0].children.iter().position(|&c| c == reference);
+        let pos = pos.unwrap_or(self.nodes[parent.0].children.len());
+        self.nodes[child.0].parent = Some(parent);
+        self.nodes[parent.0].children.insert(pos, child);
+        self.dirty.insert(parent);
+    }
+
+    /// Unlink `id` from its parent. The node stays in the arena and can be re-attached.
+    pub fn detach(&mut self, id: NodeId) {
+        if let Some(parent) = self.nodes[id.0].parent.take() {
+            self.nodes[parent.0].children.retain(|&c| c != id);
+            self.dirty.insert(parent);
+        }
+    }
+
+    /// Set or replace an attribute; no-op on non-elements.
+    pub fn set_attr(&mut self, id: NodeId, name: &str, value: &str) {
+        if let NodeData::Element { attrs, .. } = &mut self.nodes[id.0].data {
+            let name = name.to_ascii_lowercase();
+            match attrs.iter_mut().find(|(k, _)| *k == name) {
+                Some((_, v)) if v == value => return,
+                Some((_, v)) => *v = value.to_string(),
+                None => attrs.push((name, value.to_string())),
+            }
+            self.dirty.insert(id);
+        }
+    }
+
+    pub fn remove_attr(&mut self, id: NodeId, name: &str) {
+        if let NodeData::Element { attrs, .. } = &mut self.nodes[id.0].data {
+            let before = attrs.len();
+            attrs.retain(|(k, _)| !k.eq_ignore_ascii_case(name));
+            if attrs.len() != before { self.dirty.insert(id); }
+        }
+    }
+
+    /// Replace the contents of a text node, or all children of an element with one text node.
+    pub fn set_text(&mut self, id: NodeId, text: &str) {
+        if let NodeData::Text(t) = &mut self.nodes[id.0].data {
+            if t != text {
+                *t = text.to_string();
+                self.dirty.insert(id);
+            }
+            return;
+        }
+        for c in std::mem::take(&mut self.nodes[id.0].children) { self.nodes[c.0].parent = None; }
+        if !text.is_empty() {
+            let t = self.create_text(text);
+            self.append_child(id, t);
+        }
+        self.dirty.insert(id);
+    }
+
+    /// True when `id` is reachable from the root (not detached).
+    pub fn is_connected(&self, mut id: NodeId) -> bool {
+        loop {
+            if id == self.root() { return true; }
+            match self.nodes[id.0].parent {
+                Some(p) => id = p,
+                None => return false,
+            }
+        }
+    }
+
+    /// Serialize `id` (the children only, for the root) back to HTML. Raw-text
+    /// elements are written verbatim, mirroring how the parser reads them.
+    pub fn to_html(&self, id: NodeId) -> String {
+        enum Step<'a> { Node(NodeId), Close(&'a str) }
+        let mut out = String::new();
+        let mut stack: Vec<Step> = self.children(id).iter().rev().map(|&c| Step::Node(c)).collect();
+        if id != self.root() { stack = vec![Step::Node(id)]; }
+        while let Some(step) = stack.pop() {
+            let n = match step {
+                Step::Close(name) => {
+                    out.push_str("</");
+                    out.push_str(name);
+                    out.push('>');
+                    continue;
+                }
+                Step::Node(n) => n,
+            };
+            match &self.nodes[n.0].data {
+                NodeData::Document => stack.extend(self.children(n).iter().rev().map(|&c| Step::Node(c))),
+                NodeData::Text(t) => {
+                    let raw = self.parent(n).and_then(|p| self.tag_name(p)).is_some_and(|p| RAW_TEXT.contains(&p));
+                    if raw { out.push_str(t) } else { escape_html(&mut out, t, false) }
+                }
+                NodeData::Comment(c) => {
+                    out.push_str("<!--");
+                    out.push_str(c);
+                    out.push_str("-->");
+                }
+                NodeData::Declaration(d) => {
+                    out.push_str("<!");
+                    out.push_str(d);
+                    out.push('>');
+                }
+                NodeData::Element { name, attrs } => {
+                    out.push('<');
+                    out.push_str(name);
+                    for (k, v) in attrs {
+                        out.push(' ');
+                        out.push_str(k);
+                        out.push_str("=\"");
+                        escape_html(&mut out, v, true);
+                        out.push('"');
+                    }
+                    out.push('>');
+                    if VOID.contains(&name.as_str()) { continue; }
+                    stack.push(Step::Close(name));
+                    stack.extend(self.children(n).iter().rev().map(|&c| Step::Node(c)));
+                }
+            }
+        }
+        out
+    }
+
+    /// Mark a node as needing relayout without mutating it (e.g. its image finished loading).
+    pub fn mark_dirty(&mut self, id: NodeId) { self.dirty.insert(id); }
+
+    pub fn has_dirty(&self) -> bool { !self.dirty.is_empty() }
+
+    /// Drain the set of nodes mutated since the last call.
+    pub fn take_dirty(&mut self) -> BTreeSet<NodeId> { std::mem::take(&mut self.dirty) }
+
+    fn push(&mut self, data: NodeData) -> NodeId {
+        self.nodes.push(Node { parent: None, children: Vec::new(), data });
+        NodeId(self.nodes.len() - 1)
+    }
+}
+
+pub struct Descendants<'a> {
+    doc: &'a Document,
+    stack: Vec<NodeId>,
+}
+
+impl Iterator for Descendants<'_> {
+    type Item = NodeId;
+
+    fn next(&mut self) -> Option<NodeId> {
+        let id = self.stack.pop()?;
+        self.stack.extend(self.doc.children(id).iter().rev().copied());
+        Some(id)
+    }
+}
+
+/// One complex selector: compounds of type (`p`, `*`), `#id`, `.class`, `[attr]` and
+/// `[attr=value]`, joined by descendant (space) or child (`>`) combinators.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selector {
+    compounds: Vec<Compound>,
+    /// `child[i]`: the combinator between compounds `i` and `i + 1` is `>`.
+    child: Vec<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+struct Compound {
+    tag: Option<String>,
+    id: Option<String>,
+    classes: Vec<String>,
+    attrs: Vec<(String, Option<String>)>,
+}
+
+impl Selector {
+    /// Parse a comma-separated selector list.
+    pub fn parse_list(s: &str) -> Option<Vec<Selector>> { s.split(',').map(Selector::parse).collect() }
+
+    pub fn parse(s: &str) -> Option<Selector> {
+        let mut sel = Selector { compounds: Vec::new(), child: Vec::new() };
+        let mut pending_child = false;
+        for token in s.replace('>', " > ").split_whitespace() {
+            if token == ">" {
+                if sel.compounds.is_empty() || pending_child { return None; }
+                pending_child = true;
+                continue;
+            }
+            if !sel.compounds.is_empty() { sel.child.push(pending_child); }
+            pending_child = false;
+            sel.compounds.push(Compound::parse(token)?);
+        }
+        (!sel.compounds.is_empty() && !pending_child).then_some(sel)
+    }
+
+    pub fn matches(&self, doc: &Document, node: NodeId) -> bool { self.matches_at(doc, node, self.compounds.len() - 1) }
+
+    fn matches_at(&self, doc: &Document, node: NodeId, i: usize) -> bool {
+        if !self.compounds[i].matches(doc, node) { return false; }
+        if i == 0 { return true; }
+        let mut ancestors = std::iter::successors(doc.parent(node), |&p| doc.parent(p));
+        if self.child[i - 1] {
+            ancestors.next().is_some_and(|p| self.matches_at(doc, p, i - 1))
+        } else {
+            ancestors.any(|a| self.matches_at(doc, a, i - 1))
+        }
+    }
+}
+
+impl Compound {
+    fn parse(s: &str) -> Option<Compound> {
+        let mut c = Compound::default();
+        let name_len = |s: &str| s.find(|ch: char| !(ch.is_alphanumeric() || ch == '-' || ch == '_')).unwrap_or(s.len());
+        let mut rest = s;
+        let n = name_len(rest);
+        if n > 0 {
+            c.tag = Some(rest[..n].to_ascii_lowercase());
+            rest = &rest[n..];
+        } else if let Some(r) = rest.strip_prefix('*') {
+            rest = r;
+        }
+        while !rest.is_empty() {
+            let (kind, r) = rest.split_at(1);
+            if kind == "[" {
+                let end = r.find(']')?;
+                let (name, value) = match r[..end].split_once('=') {
+                    Some((n, v)) => (n, Some(v.trim().trim_matches(|q| q == '"' || q == '\'').to_string())),
+                    None => (&r[..end], None),
+                };
+                c.attrs.push((name.trim().to_ascii_lowercase(), value));
+                rest = &r[end + 1..];
+                continue;
+            }
+            let n = name_len(r);
+            if n == 0 { return None; }
+            match kind {
+                "#" => c.id = Some(r[..n].to_string()),
+                "." => c.classes.push(r[..n].to_string()),
+                _ => return None,
+            }
+            rest = &r[n..];
+        }
+        Some(c)
+    }
+
+    fn matches(&self, doc: &Document, node: NodeId) -> bool {
+        let Some(tag) = doc.tag_name(node) else { return false };
+        self.tag.as_ref().is_none_or(|t| t == tag)
+            && self.id.as_ref().is_none_or(|id| doc.attr(node, "id") == Some(id))
+            && self.classes.iter().all(|c| doc.attr(node, "class").is_some_and(|v| v.split_whitespace().any(|x| x == c)))
+            && self.attrs.iter().all(|(n, v)| doc.attr(node, n).is_some_and(|a| v.as_ref().is_none_or(|v| a == v)))
+    }
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+    stack: Vec<NodeId>,
+    keep_comments: bool,
+}
+
+impl Parser<'_> {
+    fn run(&mut self, doc: &mut Document) {
+        while self.pos < self.src.len() {
+            let rest = &self.src[self.pos..];
+            if let Some(after) = rest.strip_prefix("<!--") {
+                let end = after.find("-->").unwrap_or(after.len());
+                self.keep(doc, NodeData::Comment(after[..end].to_string()));
+                self.pos += 4 + (end + 3).min(after.len());
+            } else if let Some(after) = rest.strip_prefix("<!") {
+                let end = after.find('>').unwrap_or(after.len());
+                self.keep(doc, NodeData::Declaration(after[..end].to_string()));
+                self.pos += 2 + (end + 1).min(after.len());
+            } else if rest.starts_with("<?") {
+                self.pos += rest.find('>').map(|i| i + 1).unwrap_or(rest.len());
+            } else if rest.starts_with("</") && rest[2..].starts_with(|c: char| c.is_ascii_alphabetic()) {
+                let end = rest.find('>').map(|i| i + 1).unwrap_or(rest.len());
+                let name = tag_name_prefix(&rest[2..]).to_ascii_lowercase();
+                self.pos += end;
+                self.close(doc, &name);
+            } else if rest.starts_with('<') && rest[1..].starts_with(|c: char| c.is_ascii_alphabetic()) {
+                self.start_tag(doc);
+            } else {
+                // Always consume the first char, which may be a stray `<` or multi-byte
+                let first = rest.chars().next().map_or(1, char::len_utf8);
+                let end = rest[first..].find('<').map(|i| i + first).unwrap_or(rest.len());
+                self.text(doc, &decode_entities(&rest[..end]));
+                self.pos += end;
+            }
+        }
+    }
+
+    fn current(&self) -> NodeId { *self.stack.last().expect("root never popped") }
+
+    /// Append a comment or declaration node if this parse keeps them.
+    fn keep(&mut self, doc: &mut Document, data: NodeData) {
+        if !self.keep_comments { return; }
+        let id = doc.push(data);
+        doc.append_child(self.current(), id);
+    }
+
+    fn text(&mut self, doc: &mut Document, text: &str) {
+        if text.is_empty() { return; }
+        let parent = self.current();
+        // Merge with a preceding text sibling so "a &amp; b" stays one node
+        if let Some(&last) = doc.children(parent).last() {
+            if let NodeData::Text(t) = &mut doc.nodes[last.0].data {
+                t.push_str(text);
+                return;
+            }
+        }
+        let id = doc.create_text(text);
+        doc.append_child(parent, id);
+    }
+
+    fn start_tag(&mut self, doc: &mut Document) {
+        let rest = &self.src[self.pos + 1..];
+        let name = tag_name_prefix(rest).to_ascii_lowercase();
+        let mut i = self.pos + 1 + name.len();
+        let bytes = self.src.as_bytes();
+        let mut attrs: Vec<(String, String)> = Vec::new();
+        let mut self_closing = false;
+        loop {
+            while i < bytes.len() && bytes[i].is_ascii_whitespace() { i += 1; }
+            if i >= bytes.len() { break; }
+            match bytes[i] {
+                b'>' => { i += 1; break; }
+                b'/' => { self_closing = true; i += 1; continue; }
+                _ => {}
+            }
+            self_closing = false;
+            let start = i;
+            while i < bytes.len() && !bytes[i].is_ascii_whitespace() && !matches!(bytes[i], b'=' | b'>' | b'/') { i += 1; }
+            if i == start { i += 1; continue; }
+            let key = self.src[start..i].to_ascii_lowercase();
+            while i < bytes.len() && bytes[i].is_ascii_whitespace() { i += 1; }
+            let mut value = String::new();
+            if i < bytes.len() && bytes[i] == b'=' {
+                i += 1;
+                while i < bytes.len() && bytes[i].is_ascii_whitespace() { i += 1; }
+                if i < bytes.len() && (bytes[i] == b'"' || bytes[i] == b'\'') {
+                    let quote = bytes[i];
+                    let vstart = i + 1;
+                    let vend = self.src[vstart..].bytes().position(|b| b == quote).map(|p| vstart + p).unwrap_or(bytes.len());
+                    value = decode_entities(&self.src[vstart..vend]);
+                    i = (vend + 1).min(bytes.len());
+                } else {
+                    let vstart = i;
+                    while i < bytes.len() && !bytes[i].is_ascii_whitespace() && bytes[i] != b'>' { i += 1; }
+                    value = decode_entities(&self.src[vstart..i]);
+                }
+            }
+            // First occurrence wins, as in the HTML spec
+            if !attrs.iter().any(|(k, _)| *k == key) { attrs.push((key, value)); }
+        }
+        self.pos = i;
+
+        self.implicit_close(doc, &name);
+        let id = doc.create_element(&name, attrs);
+        doc.append_child(self.current(), id);
+        if VOID.contains(&name.as_str()) || self_closing && !RAW_TEXT.contains(&name.as_str()) {
+            return;
+        }
+        if RAW_TEXT.contains(&name.as_str()) {
+            let rest = &self.src[self.pos..];
+            let end = find_ascii_ci(rest, &format!("</{name}")).unwrap_or(rest.len());
+            let raw = &rest[..end];
+            if !raw.is_empty() {
+                let text = if name == "script" || name == "style" { raw.to_string() } else { decode_entities(raw) };
+                let t = doc.create_text(&text);
+                doc.append_child(id, t);
+            }
+            self.pos += end;
+            let rest = &self.src[self.pos..];
+            self.pos += rest.find('>').map(|i| i + 1).unwrap_or(rest.len());
+            return;
+        }
+        self.stack.push(id);
+    }
+
+    fn implicit_close(&mut self, doc: &Document, name: &str) {
+        if CLOSES_P.contains(&name) && doc.tag_name(self.current()) == Some("p") {
+            self.stack.pop();
+        }
+        if name == "li" {
+            // Close an open <li> belonging to the nearest list
+            if let Some(pos) = self.stack.iter().rposition(|&n| matches!(doc.tag_name(n), Some("li" | "ul" | "ol"))) {
+                if doc.tag_name(self.stack[pos]) == Some("li") { self.stack.truncate(pos); }
+            }
+        }
+    }
+
+    fn close(&mut self, doc: &Document, name: &str) {
+        // Stray end tags without a matching open element are ignored
+        if let Some(pos) = self.stack.iter().rposition(|&n| doc.tag_name(n) == Some(name)) {
+            self.stack.truncate(pos.max(1));
+        }
+    }
+}
+
+fn tag_name_prefix(s: &str) -> &str {
+    let end = s.find(|c: char| c.is_ascii_whitespace() || c == '>' || c == '/').unwrap_or(s.len());
+    &s[..end]
+}
+
+fn find_ascii_ci(hay: &str, needle: &str) -> Option<usize> {
+    let (h, n) = (hay.as_bytes(), needle.as_bytes());
+    if n.len() > h.len() { return None; }
+    (0..=h.len() - n.len()).find(|&i| h[i..i + n.len()].eq_ignore_ascii_case(n))
+}
+
+fn escape_html(out: &mut String, s: &str, attr: bool) {
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' if attr => out.push_str("&quot;"),
+            '\u{a0}' => out.push_str("&nbsp;"),
+            c => out.push(c),
+        }
+    }
+}
+
+/// Decode the common named references plus numeric ones; unknown references pass through.
+pub fn decode_entities(s: &str) -> String {
+    if !s.contains('&') { return s.to_string(); }
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find('&') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let end = rest[1..].find(|c: char| !(c.is_ascii_alphanumeric() || c == '#')).map(|e| e + 1).unwrap_or(rest.len());
+        let name = &rest[1..end];
+        let decoded = match name {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{a0}'),
+            _ if name.starts_with("#x") || name.starts_with("#X") => u32::from_str_radix(&name[2..], 16).ok().and_then(char::from_u32),
+            _ if name.starts_with('#') => name[1..].parse::<u32>().ok().and_then(char::from_u32),
+            _ => None,
+        };
+        match decoded {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[end..];
+                if rest.starts_with(';') { rest = &rest[1..]; }
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selectors() {
+        let doc = Document::parse("<div id=a class='x y'><p>1</p><span><p class=y>2</p></span></div><p data-k=v>3</p>");
+        let texts = |sel: &str| doc.select(doc.root(), sel).into_iter().map(|n| doc.text_content(n)).collect::<Vec<_>>();
+        assert_eq!(texts("p"), vec!["1", "2", "3"]);
+        assert_eq!(texts("#a > p"), vec!["1"]);
+        assert_eq!(texts("div.x p.y"), vec!["2"]);
+        assert_eq!(texts("[data-k=v], div > span"), vec!["2", "3"]);
+        assert!(texts("p >").is_empty() && texts("p:hover").is_empty());
+    }
+
+    #[test]
+    fn parses_nested_elements_and_attrs() {
+        let doc = Document::parse("<div id=a class='x y'><p>Hi &amp; bye<img src=\"i.png\" alt=pic></div>");
+        let div = doc.elements_by_tag("div").next().unwrap();
+        assert_eq!(doc.attr(div, "id"), Some("a"));
+        assert_eq!(doc.attr(div, "CLASS"), Some("x y"));
+        let img = doc.elements_by_tag("img").next().unwrap();
+        assert_eq!(doc.tag_name(doc.parent(img).unwrap()), Some("p"));
+        assert_eq!(doc.text_content(div), "Hi & bye");
+        assert!(!doc.has_dirty());
+    }
+
+    #[test]
+    fn mutations_mark_dirty() {
+        let mut doc = Document::parse("<div><p>a</p></div>");
+        let p = doc.elements_by_tag("p").next().unwrap();
+        doc.set_text(p, "b");
+        doc.set_attr(p, "class", "x");
+        assert_eq!(doc.text_content(p), "b");
+        assert!(doc.take_dirty().contains(&p));
+        let div = doc.parent(p).unwrap();
+        doc.detach(p);
+        assert!(!doc.is_connected(p));
+        assert_eq!(doc.take_dirty().into_iter().collect::<Vec<_>>(), vec![div]);
+    }
+
+    #[test]
+    fn implicit_close_and_raw_text() {
+        let doc = Document::parse("<ul><li>a<li>b</ul><p>x<p>y<script>if (a<b) {}</script>");
+        let ul = doc.elements_by_tag("ul").next().unwrap();
+        assert_eq!(doc.children(ul).len(), 2);
+        assert_eq!(doc.elements_by_tag("p").count(), 2);
+        let script = doc.elements_by_tag("script").next().unwrap();
+        assert_eq!(doc.text_content(script), "if (a<b) {}");
+    }
+
+    #[test]
+    fn non_ascii_text() {
+        let doc = Document::parse("<p>é</p><b>x</b>é<<é");
+        let p = doc.elements_by_tag("p").next().unwrap();
+        assert_eq!(doc.text_content(p), "é");
+        assert_eq!(doc.text_content(doc.root()), "éxé<<é");
+    }
+
+    #[test]
+    fn serializes_back_to_html() {
+        let src = "<div class=\"a&amp;b\">x &lt; y<br><p>one</p><script>if (a<b) {}</script></div>tail";
+        let doc = Document::parse(src);
+        assert_eq!(doc.to_html(doc.root()), src);
+        let p = doc.elements_by_tag("p").next().unwrap();
+        assert_eq!(doc.to_html(p), "<p>one</p>");
+        assert_eq!(Document::parse(&doc.to_html(doc.root())).to_html(doc.root()), src);
+
+        let src = "<!DOCTYPE html>\n<html><!-- note --><body>a<!---->b<script>// <!-- not a comment</script></body></html>";
+        let doc = Document::parse_with_comments(src);
+        assert_eq!(doc.to_html(doc.root()), src);
+        assert_eq!(doc.text_content(doc.root()), "\nab// <!-- not a comment");
+        assert_eq!(Document::parse(src).to_html(doc.root()), "\n<html><body>ab<script>// <!-- not a comment</script></body></html>");
+    }
+}
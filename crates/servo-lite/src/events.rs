@@ -0,0 +1,218 @@
+//! DOM events: hit testing, listener registry and capture/target/bubble dispatch.
+
+use crate::dom::{Document, NodeId};
+use crate::layout::{self, LayoutBox, Rect, HEADER_H};
+use crate::scroll::ScrollLayers;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventPhase {
+    None,
+    Capturing,
+    AtTarget,
+    Bubbling,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventDetail {
+    None,
+    Mouse { x: i32, y: i32, button: u8 },
+    Key { key: String, code: u32 },
+    Wheel { delta_x: f32, delta_y: f32 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    /// DOM event type, e.g. "click" or "keydown".
+    pub kind: String,
+    pub target: NodeId,
+    pub current_target: NodeId,
+    pub phase: EventPhase,
+    pub bubbles: bool,
+    pub detail: EventDetail,
+    propagation_stopped: bool,
+    default_prevented: bool,
+}
+
+impl Event {
+    pub fn new(kind: &str, target: NodeId, detail: EventDetail) -> Self {
+        let bubbles = !matches!(kind, "focus" | "blur" | "mouseenter" | "mouseleave" | "load" | "error");
+        Self { kind: kind.to_string(), target, current_target: target, phase: EventPhase::None, bubbles, detail, propagation_stopped: false, default_prevented: false }
+    }
+
+    pub fn stop_propagation(&mut self) { self.propagation_stopped = true; }
+
+    pub fn prevent_default(&mut self) { self.default_prevented = true; }
+
+    pub fn default_prevented(&self) -> bool { self.default_prevented }
+}
+
+/// Listener callback; it may mutate the DOM, which the next page update picks up.
+pub type Listener = Box<dyn FnMut(&mut Event, &mut Document)>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ListenerId(u64);
+
+struct Entry {
+    id: ListenerId,
+    node: NodeId,
+    kind: String,
+    capture: bool,
+    listener: Option<Listener>,
+}
+
+#[derive(Default)]
+pub struct EventListeners {
+    next_id: u64,
+    entries: Vec<Entry>,
+}
+
+impl EventListeners {
+    pub fn new() -> Self { Self::default() }
+
+    pub fn add(&mut self, node: NodeId, kind: &str, capture: bool, listener: Listener) -> ListenerId {
+        self.next_id += 1;
+        let id = ListenerId(self.next_id);
+        self.entries.push(Entry { id, node, kind: kind.to_string(), capture, listener: Some(listener) });
+        id
+    }
+
+    pub fn remove(&mut self, id: ListenerId) -> bool {
+        let before = self.entries.len();
+        self.entries.retain(|e| e.id != id);
+        self.entries.len() != before
+    }
+
+    /// Dispatch `ev` along the path from the document root to `ev.target`:
+    /// capture listeners top-down, then the target, then bubble listeners bottom-up.
+    pub fn dispatch(&mut self, doc: &mut Document, mut ev: Event) -> Event {
+        let mut path: Vec<NodeId> = std::iter::successors(Some(ev.target), |&n| doc.parent(n)).collect();
+        path.reverse();
+        let (&target, ancestors) = path.split_last().expect("path contains target");
+
+        for &node in ancestors {
+            self.invoke(doc, &mut ev, node, EventPhase::Capturing);
+            if ev.propagation_stopped { return finish(ev); }
+        }
+        self.invoke(doc, &mut ev, target, EventPhase::AtTarget);
+        if ev.bubbles {
+            for &node in ancestors.iter().rev() {
+                if ev.propagation_stopped { break; }
+                self.invoke(doc, &mut ev, node, EventPhase::Bubbling);
+            }
+        }
+        finish(ev)
+    }
+
+    fn invoke(&mut self, doc: &mut Document, ev: &mut Event, node: NodeId, phase: EventPhase) {
+        ev.current_target = node;
+        ev.phase = phase;
+        // At the target, capture listeners run before bubble listeners
+        let wanted: &[bool] = match phase {
+            EventPhase::Capturing => &[true],
+            EventPhase::Bubbling => &[false],
+            _ => &[true, false],
+        };
+        for &capture in wanted {
+            let ids: Vec<ListenerId> = self.entries.iter()
+                .filter(|e| e.node == node && e.capture == capture && e.kind == ev.kind)
+                .map(|e| e.id)
+                .collect();
+            for id in ids {
+                let Some(entry) = self.entries.iter_mut().find(|e| e.id == id) else { continue };
+                let Some(mut f) = entry.listener.take() else { continue };
+                f(ev, doc);
+                if let Some(entry) = self.entries.iter_mut().find(|e| e.id == id) { entry.listener = Some(f); }
+            }
+        }
+    }
+}
+
+fn finish(mut ev: Event) -> Event {
+    ev.phase = EventPhase::None;
+    ev.current_target = ev.target;
+    ev
+}
+
+/// Result of mapping a point to the box tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HitTestResult {
+    /// Element (or the document) the point belongs to; text hits resolve to their parent element.
+    pub node: NodeId,
+    /// Node of the topmost layout box under the point.
+    pub box_node: NodeId,
+    /// Layout-space rect of that box (before scrolling).
+    pub rect: Rect,
+}
+
+/// Topmost (last painted, deepest) box containing the viewport point (x, y),
+/// taking the scroll offset and clip of every enclosing layer into account.
+pub fn hit_test(doc: &Document, root: &LayoutBox, scroll: &ScrollLayers, x: i32, y: i32) -> Option<HitTestResult> {
+    let b = topmost(doc, scroll, root, x, y)?;
+    let node = if doc.text(b.node).is_some() { doc.parent(b.node).unwrap_or(b.node) } else { b.node };
+    Some(HitTestResult { node, box_node: b.node, rect: b.rect })
+}
+
+fn topmost<'a>(doc: &Document, scroll: &ScrollLayers, b: &'a LayoutBox, x: i32, y: i32) -> Option<&'a LayoutBox> {
+    // Children may overflow their parent, so search them regardless of the parent's rect,
+    // unless the parent is a layer clipping them (the document clips to below the header)
+    let inner = if b.node == doc.root() {
+        y >= HEADER_H
+    } else {
+        !layout::overflow(doc, b.node).is_layer() || b.rect.contains(x, y)
+    };
+    let (sx, sy) = scroll.offset(b.node);
+    inner.then(|| b.children.iter().rev().find_map(|c| topmost(doc, scroll, c, x + sx, y + sy)))
+        .flatten()
+        .or_else(|| b.rect.contains(x, y).then_some(b))
+}
+
+/// Elements that take focus from clicks and Tab navigation.
+pub fn is_focusable(doc: &Document, node: NodeId) -> bool {
+    let Some(tag) = doc.tag_name(node) else { return false };
+    if doc.attr(node, "disabled").is_some() { return false; }
+    if let Some(t) = doc.attr(node, "tabindex") { return t.trim().parse::<i32>().is_ok_and(|t| t >= 0); }
+    match tag {
+        "button" | "input" | "select" | "textarea" => true,
+        "a" => doc.attr(node, "href").is_some(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn capture_target_bubble_order_and_stop() {
+        let mut doc = Document::parse("<div><p><span>x</span></p></div>");
+        let (div, p, span) = (doc.elements_by_tag("div").next().unwrap(), doc.elements_by_tag("p").next().unwrap(), doc.elements_by_tag("span").next().unwrap());
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut ls = EventListeners::new();
+        for (node, name, capture) in [(div, "div-bubble", false), (div, "div-capture", true), (span, "span", false), (p, "p-bubble", false)] {
+            let log = log.clone();
+            ls.add(node, "click", capture, Box::new(move |ev, _| log.borrow_mut().push((name, ev.phase))));
+        }
+        ls.dispatch(&mut doc, Event::new("click", span, EventDetail::None));
+        assert_eq!(*log.borrow(), vec![
+            ("div-capture", EventPhase::Capturing),
+            ("span", EventPhase::AtTarget),
+            ("p-bubble", EventPhase::Bubbling),
+            ("div-bubble", EventPhase::Bubbling),
+        ]);
+
+        log.borrow_mut().clear();
+        ls.add(p, "click", false, Box::new(|ev, _| ev.stop_propagation()));
+        let ev = ls.dispatch(&mut doc, Event::new("click", span, EventDetail::None));
+        assert!(!log.borrow().iter().any(|(n, _)| *n == "div-bubble"));
+        assert_eq!(ev.phase, EventPhase::None);
+    }
+
+    #[test]
+    fn focusable_elements() {
+        let doc = Document::parse("<a href=x>a</a><a>b</a><button disabled>c</button><div tabindex=0>d</div><input>");
+        let focusable: Vec<_> = doc.descendants(doc.root()).filter(|&n| is_focusable(&doc, n)).filter_map(|n| doc.tag_name(n)).collect();
+        assert_eq!(focusable, vec!["a", "div", "input"]);
+    }
+}
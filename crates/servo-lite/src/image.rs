@@ -0,0 +1,364 @@
+//! Image subsystem: fetch, decode (PNG/JPEG/GIF/WebP) on worker threads and
+//! keep decoded bitmaps in a byte-budgeted LRU cache shared with layout/paint.
+
+use bytes::Bytes;
+use ::image::{AnimationDecoder, ImageFormat};
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// Reject images whose decoded size would exceed this many pixels (decompression bombs).
+const MAX_PIXELS: u64 = 64 * 1024 * 1024;
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum ImageError {
+    #[error("fetch failed: {0}")] Fetch(String),
+    #[error("unsupported image format")] Unsupported,
+    #[error("decode failed: {0}")] Decode(String),
+    #[error("image too large: {0}x{1}")] TooLarge(u32, u32),
+}
+
+/// One frame of straight-alpha RGBA8 pixels.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageFrame {
+    pub pixels: Bytes,
+    pub delay_ms: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedImage {
+    pub width: u32,
+    pub height: u32,
+    pub frames: Vec<ImageFrame>,
+}
+
+impl DecodedImage {
+    pub fn is_animated(&self) -> bool { self.frames.len() > 1 }
+
+    /// Bytes charged against the cache budget.
+    pub fn byte_size(&self) -> usize { self.frames.iter().map(|f| f.pixels.len()).sum() }
+
+    /// Frame to show `elapsed_ms` after the animation started (loops forever).
+    pub fn frame_at(&self, elapsed_ms: u64) -> &ImageFrame {
+        let total: u64 = self.frames.iter().map(|f| f.delay_ms.max(1) as u64).sum();
+        if self.frames.len() == 1 || total == 0 { return &self.frames[0]; }
+        let mut t = elapsed_ms % total;
+        for f in &self.frames {
+            let d = f.delay_ms.max(1) as u64;
+            if t < d { return f; }
+            t -= d;
+        }
+        &self.frames[0]
+    }
+}
+
+/// Decode an encoded image, sniffing the format from its magic bytes.
+pub fn decode(data: &[u8]) -> Result<DecodedImage, ImageError> {
+    let format = ::image::guess_format(data).map_err(|_| ImageError::Unsupported)?;
+    let frames = match format {
+        ImageFormat::Gif => {
+            let dec = ::image::codecs::gif::GifDecoder::new(Cursor::new(data)).map_err(decode_err)?;
+            collect_frames(dec)?
+        }
+        ImageFormat::WebP => {
+            let dec = ::image::codecs::webp::WebPDecoder::new(Cursor::new(data)).map_err(decode_err)?;
+            if dec.has_animation() { collect_frames(dec)? } else { still(data, format)? }
+        }
+        ImageFormat::Png | ImageFormat::Jpeg => still(data, format)?,
+        _ => return Err(ImageError::Unsupported),
+    };
+    let (width, height) = frames.first().map(|f| (f.0, f.1)).ok_or_else(|| ImageError::Decode("no frames".into()))?;
+    Ok(DecodedImage { width, height, frames: frames.into_iter().map(|f| f.2).collect() })
+}
+
+fn decode_err(e: ::image::ImageError) -> ImageError {
+    match e {
+        ::image::ImageError::Limits(_) => ImageError::TooLarge(0, 0),
+        e => ImageError::Decode(e.to_string()),
+    }
+}
+
+fn check_size(w: u32, h: u32) -> Result<(), ImageError> {
+    if w == 0 || h == 0 || w as u64 * h as u64 > MAX_PIXELS { return Err(ImageError::TooLarge(w, h)); }
+    Ok(())
+}
+
+fn still(data: &[u8], format: ImageFormat) -> Result<Vec<(u32, u32, ImageFrame)>, ImageError> {
+    let mut reader = ::image::ImageReader::with_format(Cursor::new(data), format);
+    let mut limits = ::image::Limits::default();
+    limits.max_alloc = Some(MAX_PIXELS * 4);
+    reader.limits(limits);
+    let img = reader.decode().map_err(decode_err)?.into_rgba8();
+    check_size(img.width(), img.height())?;
+    Ok(vec![(img.width(), img.height(), ImageFrame { pixels: Bytes::from(img.into_raw()), delay_ms: 0 })])
+}
+
+fn collect_frames<'a>(dec: impl AnimationDecoder<'a>) -> Result<Vec<(u32, u32, ImageFrame)>, ImageError> {
+    let mut out = Vec::new();
+    for frame in dec.into_frames() {
+        let frame = frame.map_err(decode_err)?;
+        let (num, den) = frame.delay().numer_denom_ms();
+        let delay_ms = num.checked_div(den).unwrap_or(0);
+        let buf = frame.into_buffer();
+        check_size(buf.width(), buf.height())?;
+        out.push((buf.width(), buf.height(), ImageFrame { pixels: Bytes::from(buf.into_raw()), delay_ms }));
+    }
+    Ok(out)
+}
+
+/// Load state of one image URL as seen by layout and paint.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImageState {
+    Pending,
+    Ready(Arc<DecodedImage>),
+    /// Fetch or decode failed; paint a broken-image placeholder.
+    Broken,
+}
+
+struct CacheEntry {
+    state: ImageState,
+    last_used: u64,
+}
+
+/// Decoded images keyed by URL, evicting least-recently-used bitmaps once the byte budget is exceeded.
+pub struct ImageCache {
+    budget: usize,
+    used: usize,
+    tick: u64,
+    entries: HashMap<String, CacheEntry>,
+}
+
+impl ImageCache {
+    pub fn new(budget_bytes: usize) -> Self {
+        Self { budget: budget_bytes, used: 0, tick: 0, entries: HashMap::new() }
+    }
+
+    pub fn used_bytes(&self) -> usize { self.used }
+
+    pub fn budget_bytes(&self) -> usize { self.budget }
+
+    pub fn get(&mut self, url: &str) -> Option<ImageState> {
+        self.tick += 1;
+        let e = self.entries.get_mut(url)?;
+        e.last_used = self.tick;
+        Some(e.state.clone())
+    }
+
+    pub fn insert(&mut self, url: &str, state: ImageState) {
+        self.tick += 1;
+        if let Some(old) = self.entries.remove(url) { self.used -= cost(&old.state); }
+        self.used += cost(&state);
+        self.entries.insert(url.to_string(), CacheEntry { state, last_used: self.tick });
+        self.evict(url);
+    }
+
+    fn pending(&self) -> usize { self.entries.values().filter(|e| e.state == ImageState::Pending).count() }
+
+    fn evict(&mut self, keep: &str) {
+        while self.used > self.budget {
+            let victim = self.entries.iter()
+                .filter(|(k, e)| k.as_str() != keep && cost(&e.state) > 0)
+                .min_by_key(|(_, e)| e.last_used)
+                .map(|(k, _)| k.clone());
+            let Some(victim) = victim else { break };
+            if let Some(e) = self.entries.remove(&victim) { self.used -= cost(&e.state); }
+        }
+    }
+}
+
+fn cost(state: &ImageState) -> usize {
+    match state {
+        ImageState::Ready(img) => img.byte_size(),
+        _ => 0,
+    }
+}
+
+/// Source of encoded image bytes.
+pub trait ImageFetcher: Send + Sync {
+    fn fetch(&self, url: &str) -> Result<Vec<u8>, ImageError>;
+}
+
+/// Fetch images through network-srv, driving its async client on the given runtime.
+pub struct NetworkFetcher {
+    net: network_srv::Network,
+    rt: tokio::runtime::Handle,
+}
+
+impl NetworkFetcher {
+    pub fn new(net: network_srv::Network, rt: tokio::runtime::Handle) -> Self { Self { net, rt } }
+}
+
+impl ImageFetcher for NetworkFetcher {
+    fn fetch(&self, url: &str) -> Result<Vec<u8>, ImageError> {
+        let req = message_defs::HttpRequest { url: url.to_string() };
+        let resp = self.rt.block_on(self.net.fetch(req)).map_err(|e| ImageError::Fetch(e.to_string()))?;
+        Ok(resp.body.to_vec())
+    }
+}
+
+struct Shared {
+    cache: Mutex<ImageCache>,
+    idle: Condvar,
+    fetcher: Arc<dyn ImageFetcher>,
+    generation: std::sync::atomic::AtomicU64,
+}
+
+/// Asynchronous image loader: `request` never blocks, worker threads fetch and
+/// decode, and results land in the shared cache for the next layout pass.
+pub struct ImagePipeline {
+    shared: Arc<Shared>,
+    tx: Option<mpsc::Sender<String>>,
+    workers: Vec<JoinHandle<()>>,
+    started: Instant,
+}
+
+impl ImagePipeline {
+    pub fn new(fetcher: Arc<dyn ImageFetcher>, budget_bytes: usize, workers: usize) -> Self {
+        let shared = Arc::new(Shared {
+            cache: Mutex::new(ImageCache::new(budget_bytes)),
+            idle: Condvar::new(),
+            fetcher,
+            generation: Default::default(),
+        });
+        let (tx, rx) = mpsc::channel::<String>();
+        let rx = Arc::new(Mutex::new(rx));
+        let workers = (0..workers.max(1))
+            .map(|i| {
+                let (shared, rx) = (shared.clone(), rx.clone());
+                std::thread::Builder::new()
+                    .name(format!("image-decode-{i}"))
+                    .spawn(move || worker(shared, rx))
+                    .expect("spawn image worker")
+            })
+            .collect();
+        Self { shared, tx: Some(tx), workers, started: Instant::now() }
+    }
+
+    /// Current state of `url`, scheduling a load on first sight (or after eviction).
+    pub fn request(&self, url: &str) -> ImageState {
+        let mut cache = self.shared.cache.lock().unwrap();
+        if let Some(state) = cache.get(url) { return state; }
+        cache.insert(url, ImageState::Pending);
+        drop(cache);
+        if let Some(tx) = &self.tx { let _ = tx.send(url.to_string()); }
+        ImageState::Pending
+    }
+
+    /// Current state without scheduling a load.
+    pub fn state(&self, url: &str) -> Option<ImageState> { self.shared.cache.lock().unwrap().get(url) }
+
+    /// Block until no loads are pending or `timeout` elapses. Returns true when idle.
+    pub fn wait_idle(&self, timeout: Duration) -> bool {
+        let cache = self.shared.cache.lock().unwrap();
+        let (cache, _) = self.shared.idle.wait_timeout_while(cache, timeout, |c| c.pending() > 0).unwrap();
+        cache.pending() == 0
+    }
+
+    /// Bumped whenever a load completes; layout can compare it to decide whether to rerun.
+    pub fn generation(&self) -> u64 { self.shared.generation.load(std::sync::atomic::Ordering::Acquire) }
+
+    /// Milliseconds since the pipeline started, used as the animation clock.
+    pub fn elapsed_ms(&self) -> u64 { self.started.elapsed().as_millis() as u64 }
+
+    pub fn used_bytes(&self) -> usize { self.shared.cache.lock().unwrap().used_bytes() }
+}
+
+impl Drop for ImagePipeline {
+    fn drop(&mut self) {
+        self.tx.take();
+        for w in self.workers.drain(..) { let _ = w.join(); }
+    }
+}
+
+fn worker(shared: Arc<Shared>, rx: Arc<Mutex<mpsc::Receiver<String>>>) {
+    loop {
+        let job = rx.lock().unwrap().recv();
+        let Ok(url) = job else { return };
+        let state = match shared.fetcher.fetch(&url).and_then(|data| decode(&data)) {
+            Ok(img) => ImageState::Ready(Arc::new(img)),
+            Err(_) => ImageState::Broken,
+        };
+        shared.cache.lock().unwrap().insert(&url, state);
+        shared.generation.fetch_add(1, std::sync::atomic::Ordering::AcqRel);
+        shared.idle.notify_all();
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) struct MapFetcher(pub HashMap<String, Vec<u8>>);
+
+    impl ImageFetcher for MapFetcher {
+        fn fetch(&self, url: &str) -> Result<Vec<u8>, ImageError> {
+            self.0.get(url).cloned().ok_or_else(|| ImageError::Fetch(format!("404 {url}")))
+        }
+    }
+
+    pub(crate) fn png(w: u32, h: u32, rgba: [u8; 4]) -> Vec<u8> {
+        let img = ::image::RgbaImage::from_pixel(w, h, ::image::Rgba(rgba));
+        let mut out = Vec::new();
+        img.write_to(&mut Cursor::new(&mut out), ImageFormat::Png).unwrap();
+        out
+    }
+
+    fn gif_two_frames() -> Vec<u8> {
+        use ::image::codecs::gif::GifEncoder;
+        let mut out = Vec::new();
+        {
+            let mut enc = GifEncoder::new(&mut out);
+            for c in [[255, 0, 0, 255], [0, 0, 255, 255]] {
+                let buf = ::image::RgbaImage::from_pixel(4, 4, ::image::Rgba(c));
+                let frame = ::image::Frame::from_parts(buf, 0, 0, ::image::Delay::from_numer_denom_ms(100, 1));
+                enc.encode_frame(frame).unwrap();
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn decodes_png_and_animated_gif() {
+        let img = decode(&png(3, 2, [1, 2, 3, 255])).unwrap();
+        assert_eq!((img.width, img.height), (3, 2));
+        assert_eq!(&img.frames[0].pixels[..4], &[1, 2, 3, 255]);
+
+        let gif = decode(&gif_two_frames()).unwrap();
+        assert!(gif.is_animated());
+        assert_eq!(&gif.frame_at(0).pixels[..3], &[255, 0, 0]);
+        assert_eq!(&gif.frame_at(150).pixels[..3], &[0, 0, 255]);
+        assert!(matches!(decode(b"not an image"), Err(ImageError::Unsupported)));
+    }
+
+    #[test]
+    fn cache_evicts_lru_over_budget() {
+        let one = Arc::new(decode(&png(4, 4, [0; 4])).unwrap());
+        let mut cache = ImageCache::new(one.byte_size() * 2);
+        cache.insert("a", ImageState::Ready(one.clone()));
+        cache.insert("b", ImageState::Ready(one.clone()));
+        assert!(cache.get("a").is_some()); // touch a so b is the LRU victim
+        cache.insert("c", ImageState::Ready(one));
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("a").is_some() && cache.get("c").is_some());
+        assert!(cache.used_bytes() <= cache.budget_bytes());
+    }
+
+    #[test]
+    fn pipeline_loads_off_thread_and_marks_broken() {
+        let mut map = HashMap::new();
+        map.insert("ok.png".to_string(), png(2, 2, [0, 255, 0, 255]));
+        map.insert("bad.png".to_string(), b"\x89PNG garbage".to_vec());
+        let p = ImagePipeline::new(Arc::new(MapFetcher(map)), 1 << 20, 2);
+        assert_eq!(p.request("ok.png"), ImageState::Pending);
+        p.request("bad.png");
+        p.request("missing.png");
+        assert!(p.wait_idle(Duration::from_secs(5)));
+        assert!(matches!(p.request("ok.png"), ImageState::Ready(img) if img.width == 2));
+        assert_eq!(p.request("bad.png"), ImageState::Broken);
+        assert_eq!(p.request("missing.png"), ImageState::Broken);
+        assert_eq!(p.generation(), 3);
+    }
+}
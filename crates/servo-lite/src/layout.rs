@@ -0,0 +1,414 @@
+//! Block/inline flow layout over the DOM. Metrics are fixed (monospace text,
+//! one line height) since servo-lite has no font stack; images use their
+//! intrinsic size unless `width`/`height` attributes say otherwise.
+
+use crate::dom::{Document, NodeId};
+use crate::image::{ImagePipeline, ImageState};
+use std::cell::Cell;
+use std::collections::BTreeSet;
+
+pub const HEADER_H: i32 = 48;
+pub const CHAR_W: i32 = 8;
+pub const LINE_H: i32 = 16;
+const BODY_MARGIN: i32 = 8;
+const LIST_INDENT: i32 = 24;
+/// Size of the broken-image placeholder when the element gives no dimensions.
+pub const BROKEN_IMAGE_SIZE: i32 = 20;
+
+const BLOCK: &[&str] = &[
+    "address", "article", "aside", "blockquote", "body", "dd", "div", "dl", "dt", "fieldset", "figcaption", "figure", "footer",
+    "form", "h1", "h2", "h3", "h4", "h5", "h6", "header", "hr", "html", "li", "main", "nav", "ol", "p", "pre", "section", "table",
+    "tbody", "td", "th", "thead", "tr", "ul",
+];
+const HIDDEN: &[&str] = &["head", "link", "meta", "noscript", "script", "style", "template", "title"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Hash)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub w: i32,
+    pub h: i32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, w: i32, h: i32) -> Self { Self { x, y, w, h } }
+
+    pub fn right(&self) -> i32 { self.x + self.w }
+
+    pub fn bottom(&self) -> i32 { self.y + self.h }
+
+    pub fn is_empty(&self) -> bool { self.w <= 0 || self.h <= 0 }
+
+    pub fn contains(&self, x: i32, y: i32) -> bool { x >= self.x && x < self.right() && y >= self.y && y < self.bottom() }
+
+    pub fn intersects(&self, o: &Rect) -> bool {
+        !self.is_empty() && !o.is_empty() && self.x < o.right() && o.x < self.right() && self.y < o.bottom() && o.y < self.bottom()
+    }
+
+    /// Overlapping part of both, if any.
+    pub fn intersection(&self, o: &Rect) -> Option<Rect> {
+        let (x, y) = (self.x.max(o.x), self.y.max(o.y));
+        let r = Rect::new(x, y, self.right().min(o.right()) - x, self.bottom().min(o.bottom()) - y);
+        (!r.is_empty()).then_some(r)
+    }
+
+    pub fn translate(&self, dx: i32, dy: i32) -> Rect { Rect::new(self.x + dx, self.y + dy, self.w, self.h) }
+
+    /// Bounding box of both; an empty rect does not contribute.
+    pub fn union(&self, o: &Rect) -> Rect {
+        if self.is_empty() { return *o; }
+        if o.is_empty() { return *self; }
+        let (x, y) = (self.x.min(o.x), self.y.min(o.y));
+        Rect::new(x, y, self.right().max(o.right()) - x, self.bottom().max(o.bottom()) - y)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BoxKind {
+    Block,
+    Inline,
+    Text,
+    Image { src: String, state: ImageState },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayoutBox {
+    pub node: NodeId,
+    pub kind: BoxKind,
+    pub rect: Rect,
+    pub children: Vec<LayoutBox>,
+}
+
+impl LayoutBox {
+    /// Pre-order walk over this box and its descendants.
+    pub fn walk<'a>(&'a self, f: &mut impl FnMut(&'a LayoutBox)) {
+        f(self);
+        for c in &self.children { c.walk(f); }
+    }
+
+    /// Shift this subtree vertically without relaying it out.
+    pub fn translate_y(&mut self, dy: i32) {
+        self.rect.y += dy;
+        for c in &mut self.children { c.translate_y(dy); }
+    }
+
+    /// Child-index path to the box generated for `node` with the given kind filter.
+    fn path_to(&self, node: NodeId, block_only: bool) -> Option<Vec<usize>> {
+        if self.node == node && (!block_only || self.kind == BoxKind::Block) { return Some(Vec::new()); }
+        for (i, c) in self.children.iter().enumerate() {
+            if let Some(mut p) = c.path_to(node, block_only) {
+                p.insert(0, i);
+                return Some(p);
+            }
+        }
+        None
+    }
+
+    pub fn find(&self, node: NodeId) -> Option<&LayoutBox> {
+        let path = self.path_to(node, false)?;
+        Some(path.iter().fold(self, |b, &i| &b.children[i]))
+    }
+}
+
+pub struct LayoutContext<'a> {
+    pub viewport: (u32, u32),
+    /// Base for resolving relative `src` attributes.
+    pub base_url: Option<&'a str>,
+    /// When present, images are requested from the pipeline and sized from decoded data.
+    pub images: Option<&'a ImagePipeline>,
+}
+
+/// CSS `overflow` of an element, from its inline `style` attribute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    Visible,
+    Hidden,
+    Scroll,
+    Auto,
+}
+
+impl Overflow {
+    /// Clips its content into its own scroll layer.
+    pub fn is_layer(self) -> bool { self != Overflow::Visible }
+
+    /// Scrolls in response to wheel and keyboard input (`hidden` only scrolls programmatically).
+    pub fn is_user_scrollable(self) -> bool { matches!(self, Overflow::Scroll | Overflow::Auto) }
+}
+
+/// Value of `prop` in the element's inline `style="a: b; c: d"` declarations (last one wins).
+pub fn style_value<'a>(doc: &'a Document, node: NodeId, prop: &str) -> Option<&'a str> {
+    doc.attr(node, "style")?
+        .rsplit(';')
+        .filter_map(|decl| decl.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case(prop))
+        .map(|(_, value)| value.trim())
+}
+
+pub fn overflow(doc: &Document, node: NodeId) -> Overflow {
+    match style_value(doc, node, "overflow").map(str::to_ascii_lowercase).as_deref() {
+        Some("hidden" | "clip") => Overflow::Hidden,
+        Some("scroll") => Overflow::Scroll,
+        Some("auto") => Overflow::Auto,
+        _ => Overflow::Visible,
+    }
+}
+
+/// Parse a `px` length such as `120px` or `120`; other units are ignored.
+fn px_length(v: &str) -> Option<i32> {
+    let v = v.trim();
+    v.strip_suffix("px").unwrap_or(v).trim().parse::<f32>().ok().filter(|v| *v >= 0.0).map(|v| v as i32)
+}
+
+pub fn is_hidden(doc: &Document, node: NodeId) -> bool { doc.tag_name(node).is_some_and(|t| HIDDEN.contains(&t)) }
+
+pub fn is_block(doc: &Document, node: NodeId) -> bool { doc.tag_name(node).is_some_and(|t| BLOCK.contains(&t)) }
+
+/// Lay out the whole document below the header bar.
+pub fn layout(doc: &Document, ctx: &LayoutContext) -> LayoutBox { layout_counted(doc, ctx).0 }
+
+/// Full layout that also reports how many boxes were generated.
+pub fn layout_counted(doc: &Document, ctx: &LayoutContext) -> (LayoutBox, usize) {
+    let vw = ctx.viewport.0 as i32;
+    let engine = Engine { doc, ctx, boxes: Cell::new(0) };
+    let mut root = engine.block(doc.root(), BODY_MARGIN, HEADER_H + BODY_MARGIN, (vw - 2 * BODY_MARGIN).max(0));
+    root.rect = Rect::new(0, 0, vw, root.rect.bottom() + BODY_MARGIN);
+    (root, engine.boxes.get())
+}
+
+/// Incrementally update `root` for the `dirty` nodes and return the number of boxes regenerated.
+///
+/// Each dirty node is mapped to its containing block, which is laid out again at
+/// its old position and width; if its height changed, following siblings are
+/// translated and ancestor heights adjusted instead of being laid out again.
+/// Falls back to a full layout when the containing block is the document itself.
+pub fn relayout(doc: &Document, ctx: &LayoutContext, root: &mut LayoutBox, dirty: &BTreeSet<NodeId>) -> usize {
+    let mut blocks: BTreeSet<NodeId> = BTreeSet::new();
+    for &node in dirty {
+        if !doc.is_connected(node) { continue; }
+        let mut cur = Some(node);
+        while let Some(n) = cur {
+            if root.path_to(n, true).is_some() { blocks.insert(n); break; }
+            cur = doc.parent(n);
+        }
+    }
+    // Drop blocks nested inside another dirty block; the outer relayout covers them
+    let outer: Vec<NodeId> = blocks.iter().copied()
+        .filter(|&b| !std::iter::successors(doc.parent(b), |&p| doc.parent(p)).any(|a| blocks.contains(&a)))
+        .collect();
+    if outer.contains(&doc.root()) {
+        let (new_root, n) = layout_counted(doc, ctx);
+        *root = new_root;
+        return n;
+    }
+
+    let engine = Engine { doc, ctx, boxes: Cell::new(0) };
+    for block in outer {
+        let Some(path) = root.path_to(block, true) else { continue };
+        let (&last, parents) = path.split_last().expect("root handled above");
+        let parent = path_mut(root, parents);
+        let old = parent.children[last].rect;
+        let new = engine.block(block, old.x, old.y, old.w);
+        let dy = new.rect.h - old.h;
+        parent.children[last] = new;
+        if dy == 0 { continue; }
+        // The resized block pushes its later siblings down and every ancestor grows by dy
+        for depth in (0..path.len()).rev() {
+            shift_after(path_mut(root, &path[..depth]), path[depth], dy);
+        }
+    }
+    engine.boxes.get()
+}
+
+fn path_mut<'a>(root: &'a mut LayoutBox, path: &[usize]) -> &'a mut LayoutBox {
+    path.iter().fold(root, |b, &i| &mut b.children[i])
+}
+
+/// Grow `parent` by `dy` and translate its children after index `i`.
+fn shift_after(parent: &mut LayoutBox, i: usize, dy: i32) {
+    parent.rect.h += dy;
+    for c in parent.children.iter_mut().skip(i + 1) { c.translate_y(dy); }
+}
+
+struct Engine<'a> {
+    doc: &'a Document,
+    ctx: &'a LayoutContext<'a>,
+    boxes: Cell<usize>,
+}
+
+/// One inline formatting context: a cursor that wraps atoms onto new lines.
+struct Line {
+    x0: i32,
+    width: i32,
+    cx: i32,
+    top: i32,
+    height: i32,
+}
+
+impl Line {
+    fn new(x0: i32, top: i32, width: i32) -> Self { Self { x0, width, cx: x0, top, height: 0 } }
+
+    fn place(&mut self, w: i32, h: i32) -> (i32, i32) {
+        if self.cx + w > self.x0 + self.width && self.cx > self.x0 { self.newline(); }
+        let pos = (self.cx, self.top);
+        self.cx += w;
+        self.height = self.height.max(h);
+        pos
+    }
+
+    fn newline(&mut self) {
+        self.top += self.height.max(LINE_H);
+        self.cx = self.x0;
+        self.height = 0;
+    }
+
+    fn finish(self) -> i32 { self.top + self.height }
+}
+
+impl Engine<'_> {
+    fn block(&self, node: NodeId, x: i32, y: i32, w: i32) -> LayoutBox {
+        self.boxes.set(self.boxes.get() + 1);
+        let indent = if matches!(self.doc.tag_name(node), Some("ul" | "ol")) { LIST_INDENT } else { 0 };
+        let (cx, cw) = (x + indent, (w - indent).max(0));
+        let mut children = Vec::new();
+        let mut cursor = y;
+        let mut line: Option<Line> = None;
+        for &child in self.doc.children(node) {
+            if is_hidden(self.doc, child) { continue; }
+            if is_block(self.doc, child) {
+                if let Some(l) = line.take() { cursor = l.finish(); }
+                let b = self.block(child, cx, cursor, cw);
+                cursor = b.rect.bottom() + margin_bottom(self.doc, child);
+                children.push(b);
+            } else {
+                let l = line.get_or_insert_with(|| Line::new(cx, cursor, cw));
+                if let Some(b) = self.inline(child, l) { children.push(b); }
+            }
+        }
+        if let Some(l) = line.take() { cursor = l.finish(); }
+        // An explicit height fixes the box; content past it overflows (and may scroll)
+        let h = style_value(self.doc, node, "height").and_then(px_length).unwrap_or(cursor - y);
+        LayoutBox { node, kind: BoxKind::Block, rect: Rect::new(x, y, w, h), children }
+    }
+
+    fn inline(&self, node: NodeId, line: &mut Line) -> Option<LayoutBox> {
+        self.boxes.set(self.boxes.get() + 1);
+        if let Some(text) = self.doc.text(node) {
+            let mut rect = Rect::default();
+            for word in text.split_whitespace() {
+                let ww = word.chars().count() as i32 * CHAR_W;
+                if line.cx > line.x0 && line.cx + CHAR_W + ww > line.x0 + line.width { line.newline(); }
+                let gap = if line.cx > line.x0 { CHAR_W } else { 0 };
+                let (x, y) = line.place(gap + ww, LINE_H);
+                rect = rect.union(&Rect::new(x + gap, y, ww, LINE_H));
+            }
+            return (!rect.is_empty()).then_some(LayoutBox { node, kind: BoxKind::Text, rect, children: Vec::new() });
+        }
+        match self.doc.tag_name(node)? {
+            "br" => { line.newline(); None }
+            "img" => Some(self.image(node, line)),
+            _ => {
+                let children: Vec<LayoutBox> = self.doc.children(node).iter()
+                    .filter(|&&c| !is_hidden(self.doc, c))
+                    .filter_map(|&c| self.inline(c, line))
+                    .collect();
+                let rect = children.iter().fold(Rect::new(line.cx, line.top, 0, 0), |r, c| r.union(&c.rect));
+                Some(LayoutBox { node, kind: BoxKind::Inline, rect, children })
+            }
+        }
+    }
+
+    fn image(&self, node: NodeId, line: &mut Line) -> LayoutBox {
+        let src = self.doc.attr(node, "src").map(|s| resolve_url(self.ctx.base_url, s)).unwrap_or_default();
+        let state = match self.ctx.images {
+            _ if src.is_empty() => ImageState::Broken,
+            Some(p) => p.request(&src),
+            None => ImageState::Pending,
+        };
+        let (iw, ih) = match &state {
+            ImageState::Ready(img) => (img.width as i32, img.height as i32),
+            ImageState::Broken => (BROKEN_IMAGE_SIZE, BROKEN_IMAGE_SIZE),
+            ImageState::Pending => (0, 0),
+        };
+        let (aw, ah) = (dimension_attr(self.doc, node, "width"), dimension_attr(self.doc, node, "height"));
+        let (w, h) = match (aw, ah) {
+            (Some(w), Some(h)) => (w, h),
+            (Some(w), None) if iw > 0 => (w, w * ih / iw),
+            (None, Some(h)) if ih > 0 => (h * iw / ih, h),
+            (Some(w), None) => (w, ih),
+            (None, Some(h)) => (iw, h),
+            (None, None) => (iw, ih),
+        };
+        let (x, y) = line.place(w, h);
+        LayoutBox { node, kind: BoxKind::Image { src, state }, rect: Rect::new(x, y, w, h), children: Vec::new() }
+    }
+}
+
+fn margin_bottom(doc: &Document, node: NodeId) -> i32 {
+    match doc.tag_name(node) {
+        Some("p" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "ul" | "ol" | "blockquote" | "pre" | "figure") => LINE_H / 2,
+        _ => 0,
+    }
+}
+
+/// Parse `width="120"` / `height="40px"`; percentages and garbage are ignored.
+fn dimension_attr(doc: &Document, node: NodeId, name: &str) -> Option<i32> {
+    let v = doc.attr(node, name)?.trim();
+    v.strip_suffix("px").unwrap_or(v).trim().parse::<u32>().ok().map(|v| v.min(i32::MAX as u32) as i32)
+}
+
+/// Resolve `src` against `base`, leaving it untouched if either is not a valid URL.
+pub fn resolve_url(base: Option<&str>, src: &str) -> String {
+    let src = src.trim();
+    base.and_then(|b| url::Url::parse(b).ok())
+        .and_then(|b| b.join(src).ok())
+        .map(|u| u.to_string())
+        .unwrap_or_else(|| src.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ctx() -> LayoutContext<'static> { LayoutContext { viewport: (800, 600), base_url: None, images: None } }
+
+    #[test]
+    fn blocks_stack_vertically() {
+        let doc = Document::parse("<div>one</div><div>two</div>");
+        let root = layout(&doc, &ctx());
+        let mut divs = Vec::new();
+        root.walk(&mut |b| if doc.tag_name(b.node) == Some("div") { divs.push(b.rect) });
+        assert_eq!(divs.len(), 2);
+        assert_eq!(divs[0].y, HEADER_H + BODY_MARGIN);
+        assert_eq!(divs[1].y, divs[0].bottom());
+        assert_eq!(divs[0].h, LINE_H);
+    }
+
+    #[test]
+    fn image_attrs_size_pending_image() {
+        let doc = Document::parse("<p><img src=a.png width=30 height=10><img></p>");
+        let root = layout(&doc, &ctx());
+        let mut imgs = Vec::new();
+        root.walk(&mut |b| if let BoxKind::Image { state, .. } = &b.kind { imgs.push((b.rect, state.clone())) });
+        assert_eq!((imgs[0].0.w, imgs[0].0.h), (30, 10));
+        assert_eq!(imgs[0].1, ImageState::Pending);
+        assert_eq!(imgs[1].1, ImageState::Broken);
+        assert_eq!(imgs[1].0.w, BROKEN_IMAGE_SIZE);
+    }
+
+    #[test]
+    fn style_height_and_overflow() {
+        let doc = Document::parse("<div style='color: red; overflow: AUTO; height: 40px'><p>a</p><p>b</p><p>c</p></div>");
+        let div = doc.elements_by_tag("div").next().unwrap();
+        assert_eq!(overflow(&doc, div), Overflow::Auto);
+        let root = layout(&doc, &ctx());
+        let b = root.find(div).unwrap();
+        assert_eq!(b.rect.h, 40);
+        assert!(b.children.last().unwrap().rect.bottom() > b.rect.bottom());
+    }
+
+    #[test]
+    fn resolves_relative_src() {
+        assert_eq!(resolve_url(Some("https://a.com/dir/page.html"), "img/x.png"), "https://a.com/dir/img/x.png");
+        assert_eq!(resolve_url(None, "x.png"), "x.png");
+    }
+}
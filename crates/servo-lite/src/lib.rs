@@ -1,32 +1,57 @@
 //! M10 servo-lite: extremely small layout engine producing a DisplayList
 
-use message_defs::{DisplayList, DrawCmd};
+pub mod a11y;
+#[cfg(feature = "atspi")]
+pub mod atspi;
+pub mod diff;
+pub mod dom;
+pub mod events;
+pub mod image;
+pub mod layout;
+pub mod page;
+pub mod paint;
+#[cfg(feature = "js")]
+pub mod script;
+pub mod scroll;
+
+pub use page::Page;
+
+use message_defs::DisplayList;
 use thiserror::Error;
 
+use crate::image::ImagePipeline;
+use crate::layout::LayoutContext;
+
 #[derive(Debug, Error)]
 pub enum LayoutError {
     #[error("empty html")] Empty,
 }
 
-/// Very small parser: places a header rect if <h1> is present (case-insensitive)
-/// and otherwise still draws a default header to avoid a blank look.
-/// This is not a real layout engine; it only serves Phase-1 integration.
+/// Parse, lay out and paint `html` without loading images: `<img>` elements
+/// reserve their attribute size but draw nothing. Always paints the white
+/// background and the Phase-1 header bar (darker if an `<h1>` is present).
 pub fn html_to_display_list(html: &str, viewport: (u32, u32)) -> Result<DisplayList, LayoutError> {
-    if html.trim().is_empty() { return Err(LayoutError::Empty); }
-    let (w, h) = viewport;
-
-    // Background: white
-    let mut items = Vec::new();
-    items.push(DrawCmd::Rect { x: 0, y: 0, w, h, rgba: rgba_u32(255, 255, 255, 255) });
+    render(html, &LayoutContext { viewport, base_url: None, images: None }, 0)
+}
 
-    // Header bar: always draw, darker if <h1> exists (case-insensitive)
-    let lower = html.to_lowercase();
-    let has_h1 = lower.contains("<h1") && lower.contains("</h1>");
-    let header_h = 48u32;
-    let (r, g, b) = if has_h1 { (32, 32, 32) } else { (200, 200, 200) };
-    items.push(DrawCmd::Rect { x: 0, y: 0, w, h: header_h, rgba: rgba_u32(r, g, b, 255) });
+/// Like [`html_to_display_list`], but requests `<img>` sources from `images`
+/// (resolved against `base_url`). Loads happen off-thread; images not decoded
+/// yet are left out, so callers re-render when `images.generation()` changes.
+/// Failed loads paint a broken-image placeholder instead of failing the page.
+pub fn html_to_display_list_with_images(
+    html: &str,
+    viewport: (u32, u32),
+    base_url: Option<&str>,
+    images: &ImagePipeline,
+) -> Result<DisplayList, LayoutError> {
+    render(html, &LayoutContext { viewport, base_url, images: Some(images) }, images.elapsed_ms())
+}
 
-    Ok(DisplayList { items })
+fn render(html: &str, ctx: &LayoutContext, anim_ms: u64) -> Result<DisplayList, LayoutError> {
+    if html.trim().is_empty() { return Err(LayoutError::Empty); }
+    let doc = dom::Document::parse(html);
+    let root = layout::layout(&doc, ctx);
+    Ok(paint::paint(&doc, &root, ctx.viewport, anim_ms, &scroll::ScrollLayers::new()))
 }
 
 #[inline]
@@ -50,5 +75,26 @@ mod tests {
         let dl = html_to_display_list("<h1>Example</h1>", (800, 600)).unwrap();
         assert!(dl.items.len() >= 2, "expect bg + header");
     }
+
+    #[test]
+    fn img_decoded_into_display_list_or_placeholder() {
+        use crate::image::tests::{png, MapFetcher};
+        use message_defs::DrawCmd;
+        use std::sync::Arc;
+        let mut map = std::collections::HashMap::new();
+        map.insert("https://a.com/ok.png".to_string(), png(8, 4, [0, 0, 255, 255]));
+        let images = ImagePipeline::new(Arc::new(MapFetcher(map)), 1 << 20, 1);
+        let html = "<p><img src=ok.png><img src=broken.png width=30 height=30></p>";
+        html_to_display_list_with_images(html, (800, 600), Some("https://a.com/"), &images).unwrap();
+        assert!(images.wait_idle(std::time::Duration::from_secs(5)));
+        let dl = html_to_display_list_with_images(html, (800, 600), Some("https://a.com/"), &images).unwrap();
+        let img = dl.items.iter().find_map(|c| match c {
+            DrawCmd::Image { w, h, src_w, .. } => Some((*w, *h, *src_w)),
+            _ => None,
+        });
+        assert_eq!(img, Some((8, 4, 8)));
+        // bg + header + document layer push/pop + image + placeholder frame, fill and mark
+        assert_eq!(dl.items.len(), 8);
+    }
 }
 
@@ -0,0 +1,579 @@
+//! Stateful page: keeps the DOM, layout tree and last DisplayList so that DOM
+//! mutations only relayout dirty subtrees and each update yields damage rects.
+
+use crate::a11y::{self, AccessNode};
+use crate::diff;
+use crate::dom::{Document, NodeId};
+use crate::events::{self, Event, EventDetail, EventListeners, HitTestResult, Listener, ListenerId};
+use crate::image::{ImagePipeline, ImageState};
+use crate::layout::{self, BoxKind, LayoutBox, LayoutContext, Rect};
+use crate::paint;
+use crate::scroll::{self, ScrollBehavior, ScrollLayers, ScrollState};
+#[cfg(feature = "js")]
+use crate::script::{ConsoleMessage, ScriptBudget, ScriptEngine, ScriptError};
+use crate::LayoutError;
+use event_packet::InputEvent;
+use message_defs::DisplayList;
+#[cfg(feature = "js")]
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::sync::Arc;
+
+/// Dynamic pseudo-class state of an element (`:hover`, `:active`, `:focus`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ElementState {
+    pub hover: bool,
+    pub active: bool,
+    pub focus: bool,
+}
+
+/// Outcome of routing one [`InputEvent`] into the page.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputResult {
+    /// Node the primary DOM event was dispatched to.
+    pub target: Option<NodeId>,
+    pub default_prevented: bool,
+    /// Damage from relayout/repaint caused by listeners or state changes.
+    pub damage: Vec<Rect>,
+}
+
+pub struct Page {
+    doc: Document,
+    viewport: (u32, u32),
+    base_url: Option<String>,
+    images: Option<Arc<ImagePipeline>>,
+    image_generation: u64,
+    root: LayoutBox,
+    display_list: DisplayList,
+    needs_full_layout: bool,
+    last_layout_boxes: usize,
+    listeners: EventListeners,
+    hover: Option<NodeId>,
+    active: Option<NodeId>,
+    focus: Option<NodeId>,
+    pressed: Option<NodeId>,
+    scroll: ScrollLayers,
+    #[cfg(feature = "js")]
+    scripts: Option<Scripts>,
+}
+
+#[cfg(feature = "js")]
+struct Scripts {
+    engine: Rc<RefCell<ScriptEngine>>,
+    /// Page listeners installed for script listeners, keyed by (node, type, capture, callback)
+    /// so that registering the same listener twice is a no-op, as in the DOM.
+    listeners: HashMap<(NodeId, String, bool, usize), ListenerId>,
+}
+
+impl Page {
+    pub fn new(html: &str, viewport: (u32, u32)) -> Result<Self, LayoutError> { Self::build(html, viewport, None, None) }
+
+    /// Page whose `<img>` elements load through `images`, resolved against `base_url`.
+    pub fn with_images(html: &str, viewport: (u32, u32), base_url: Option<&str>, images: Arc<ImagePipeline>) -> Result<Self, LayoutError> {
+        Self::build(html, viewport, base_url.map(str::to_string), Some(images))
+    }
+
+    fn build(html: &str, viewport: (u32, u32), base_url: Option<String>, images: Option<Arc<ImagePipeline>>) -> Result<Self, LayoutError> {
+        if html.trim().is_empty() { return Err(LayoutError::Empty); }
+        let mut page = Self {
+            doc: Document::parse(html),
+            viewport,
+            base_url,
+            images,
+            image_generation: 0,
+            root: LayoutBox { node: crate::dom::NodeId(0), kind: BoxKind::Block, rect: Rect::default(), children: Vec::new() },
+            display_list: DisplayList { items: Vec::new() },
+            needs_full_layout: true,
+            last_layout_boxes: 0,
+            listeners: EventListeners::new(),
+            hover: None,
+            active: None,
+            focus: None,
+            pressed: None,
+            scroll: ScrollLayers::new(),
+            #[cfg(feature = "js")]
+            scripts: None,
+        };
+        page.update();
+        Ok(page)
+    }
+
+    pub fn document(&self) -> &Document { &self.doc }
+
+    /// Mutable DOM access; changes are picked up by the next [`Page::update`].
+    pub fn document_mut(&mut self) -> &mut Document { &mut self.doc }
+
+    pub fn layout_root(&self) -> &LayoutBox { &self.root }
+
+    pub fn display_list(&self) -> &DisplayList { &self.display_list }
+
+    pub fn viewport(&self) -> (u32, u32) { self.viewport }
+
+    pub fn set_viewport(&mut self, viewport: (u32, u32)) {
+        if viewport != self.viewport {
+            self.viewport = viewport;
+            self.needs_full_layout = true;
+        }
+    }
+
+    /// Boxes regenerated by the most recent layout pass (full or incremental).
+    pub fn last_layout_boxes(&self) -> usize { self.last_layout_boxes }
+
+    /// Relayout whatever changed since the last update, repaint, and return the
+    /// damaged regions of the viewport (empty when nothing visible changed).
+    pub fn update(&mut self) -> Vec<Rect> {
+        self.mark_loaded_images();
+        let ctx = LayoutContext { viewport: self.viewport, base_url: self.base_url.as_deref(), images: self.images.as_deref() };
+        let dirty = self.doc.take_dirty();
+        if self.needs_full_layout {
+            (self.root, self.last_layout_boxes) = layout::layout_counted(&self.doc, &ctx);
+            self.needs_full_layout = false;
+            self.scroll.sync(&self.doc, &self.root, self.viewport);
+        } else if !dirty.is_empty() {
+            self.last_layout_boxes = layout::relayout(&self.doc, &ctx, &mut self.root, &dirty);
+            self.scroll.sync(&self.doc, &self.root, self.viewport);
+        } else {
+            self.last_layout_boxes = 0;
+        }
+        let anim_ms = self.images.as_ref().map(|p| p.elapsed_ms()).unwrap_or(0);
+        let dl = paint::paint(&self.doc, &self.root, self.viewport, anim_ms, &self.scroll);
+        let clip = Rect::new(0, 0, self.viewport.0 as i32, self.viewport.1 as i32);
+        let damage = diff::damage(&self.display_list, &dl, clip);
+        self.display_list = dl;
+        damage
+    }
+
+    /// Topmost box and DOM node under a viewport point.
+    pub fn hit_test(&self, x: i32, y: i32) -> Option<HitTestResult> { events::hit_test(&self.doc, &self.root, &self.scroll, x, y) }
+
+    /// Accessibility tree of the current layout, with focus and scroll offsets applied.
+    pub fn accessibility_tree(&self) -> AccessNode { a11y::build(&self.doc, &self.root, &self.scroll, self.focus) }
+
+    pub fn scroll_layers(&self) -> &ScrollLayers { &self.scroll }
+
+    /// Scroll offset of the document (`document().root()`) or an `overflow` container.
+    pub fn scroll_offset(&self, node: NodeId) -> (i32, i32) { self.scroll.offset(node) }
+
+    /// Scroll a layer; the next [`Page::update`] repaints it without relayout.
+    pub fn scroll_to(&mut self, node: NodeId, x: i32, y: i32, behavior: ScrollBehavior) -> bool {
+        self.scroll.scroll_to(node, x, y, behavior)
+    }
+
+    pub fn scroll_by(&mut self, node: NodeId, dx: i32, dy: i32, behavior: ScrollBehavior) -> bool {
+        self.scroll.scroll_by(node, dx, dy, behavior)
+    }
+
+    /// Advance smooth scrolling and script timers by `dt_ms` and update; call once
+    /// per frame while `scroll_layers().is_animating()` or timers are pending.
+    pub fn tick(&mut self, dt_ms: u64) -> Vec<Rect> {
+        self.scroll.tick(dt_ms);
+        #[cfg(feature = "js")]
+        self.run_timers(dt_ms);
+        self.update()
+    }
+
+    pub fn add_event_listener(&mut self, node: NodeId, kind: &str, capture: bool, listener: Listener) -> ListenerId {
+        self.listeners.add(node, kind, capture, listener)
+    }
+
+    pub fn remove_event_listener(&mut self, id: ListenerId) -> bool { self.listeners.remove(id) }
+
+    /// Dispatch a synthetic DOM event; returns it after propagation.
+    pub fn dispatch_event(&mut self, ev: Event) -> Event {
+        let ev = self.listeners.dispatch(&mut self.doc, ev);
+        #[cfg(feature = "js")]
+        self.apply_listener_changes();
+        ev
+    }
+
+    pub fn focused(&self) -> Option<NodeId> { self.focus }
+
+    /// Move focus, firing `blur` on the old and `focus` on the new element.
+    pub fn set_focus(&mut self, node: Option<NodeId>) {
+        if node == self.focus { return; }
+        if let Some(old) = self.focus.take() { self.dispatch_event(Event::new("blur", old, EventDetail::None)); }
+        self.focus = node;
+        if let Some(new) = node { self.dispatch_event(Event::new("focus", new, EventDetail::None)); }
+    }
+
+    pub fn element_state(&self, node: NodeId) -> ElementState {
+        let in_chain = |leaf: Option<NodeId>| {
+            std::iter::successors(leaf, |&n| self.doc.parent(n)).any(|n| n == node)
+        };
+        ElementState { hover: in_chain(self.hover), active: in_chain(self.active), focus: self.focus == Some(node) }
+    }
+
+    /// Route a host input event to its DOM target: hit test pointer events,
+    /// send keys to the focused element, keep hover/active/focus state, fire
+    /// synthesized events (mouseover/out, click, focus/blur), then update.
+    pub fn handle_input(&mut self, input: &InputEvent) -> InputResult {
+        let mut result = InputResult { target: None, default_prevented: false, damage: Vec::new() };
+        let mut record = |ev: Event| {
+            result.target = Some(ev.target);
+            result.default_prevented = ev.default_prevented();
+        };
+        match input {
+            InputEvent::MouseMove { x, y } => {
+                let (x, y) = (*x as i32, *y as i32);
+                let target = self.target_at(x, y);
+                self.set_hover(target, x, y);
+                record(self.dispatch_event(Event::new("mousemove", target, EventDetail::Mouse { x, y, button: 0 })));
+            }
+            InputEvent::MouseDown { button, x, y } => {
+                let (x, y) = (*x as i32, *y as i32);
+                let target = self.target_at(x, y);
+                self.set_hover(target, x, y);
+                let ev = self.dispatch_event(Event::new("mousedown", target, EventDetail::Mouse { x, y, button: *button }));
+                if *button == 0 {
+                    self.active = Some(target);
+                    self.pressed = Some(target);
+                    if !ev.default_prevented() {
+                        let focusable = std::iter::successors(Some(target), |&n| self.doc.parent(n)).find(|&n| events::is_focusable(&self.doc, n));
+                        self.set_focus(focusable);
+                    }
+                }
+                record(ev);
+            }
+            InputEvent::MouseUp { button, x, y } => {
+                let (x, y) = (*x as i32, *y as i32);
+                let target = self.target_at(x, y);
+                let detail = EventDetail::Mouse { x, y, button: *button };
+                let ev = self.dispatch_event(Event::new("mouseup", target, detail.clone()));
+                if *button == 0 {
+                    self.active = None;
+                    // click goes to the nearest common ancestor of press and release targets
+                    if let Some(pressed) = self.pressed.take() {
+                        let down: Vec<NodeId> = std::iter::successors(Some(pressed), |&n| self.doc.parent(n)).collect();
+                        if let Some(common) = std::iter::successors(Some(target), |&n| self.doc.parent(n)).find(|n| down.contains(n)) {
+                            self.dispatch_event(Event::new("click", common, detail));
+                        }
+                    }
+                }
+                record(ev);
+            }
+            InputEvent::Wheel { delta_x, delta_y } => {
+                let target = self.hover.unwrap_or(self.doc.root());
+                let ev = self.dispatch_event(Event::new("wheel", target, EventDetail::Wheel { delta_x: *delta_x, delta_y: *delta_y }));
+                if !ev.default_prevented() {
+                    let delta = (delta_x.round() as i32, delta_y.round() as i32);
+                    self.scroll_chain(target, delta, |_| 1, ScrollBehavior::Instant);
+                }
+                record(ev);
+            }
+            InputEvent::KeyDown { key, code } => {
+                let target = self.focus.unwrap_or(self.doc.root());
+                let ev = self.dispatch_event(Event::new("keydown", target, EventDetail::Key { key: key.clone(), code: *code }));
+                if !ev.default_prevented() {
+                    if key == "Tab" { self.focus_next(); } else { self.scroll_for_key(key, target); }
+                }
+                record(ev);
+            }
+            InputEvent::KeyUp { key, code } => {
+                let target = self.focus.unwrap_or(self.doc.root());
+                record(self.dispatch_event(Event::new("keyup", target, EventDetail::Key { key: key.clone(), code: *code })));
+            }
+            InputEvent::Resize { w, h } => self.set_viewport((*w, *h)),
+        }
+        result.damage = self.update();
+        result
+    }
+
+    fn target_at(&self, x: i32, y: i32) -> NodeId { self.hit_test(x, y).map(|h| h.node).unwrap_or(self.doc.root()) }
+
+    fn set_hover(&mut self, target: NodeId, x: i32, y: i32) {
+        if self.hover == Some(target) { return; }
+        let detail = EventDetail::Mouse { x, y, button: 0 };
+        if let Some(old) = self.hover.take() { self.dispatch_event(Event::new("mouseout", old, detail.clone())); }
+        self.hover = Some(target);
+        self.dispatch_event(Event::new("mouseover", target, detail));
+    }
+
+    /// Scroll the nearest user-scrollable layer around `from` (falling back to the
+    /// document) that can still move along `dir`, by `dir` times `step(layer)`.
+    fn scroll_chain(&mut self, from: NodeId, dir: (i32, i32), step: impl Fn(&ScrollState) -> i32, behavior: ScrollBehavior) -> bool {
+        let root = self.doc.root();
+        let layer = std::iter::successors(Some(from), |&n| self.doc.parent(n))
+            .filter(|&n| n == root || layout::overflow(&self.doc, n).is_user_scrollable())
+            .find_map(|n| self.scroll.get(n).filter(|s| s.can_scroll(dir.0, dir.1)).map(|s| (n, step(s))));
+        let Some((layer, step)) = layer else { return false };
+        self.scroll.scroll_by(layer, dir.0.saturating_mul(step), dir.1.saturating_mul(step), behavior)
+    }
+
+    /// Arrow, page, Home/End and space keys scroll smoothly unless a text control has focus.
+    fn scroll_for_key(&mut self, key: &str, from: NodeId) {
+        if matches!(self.doc.tag_name(from), Some("input" | "textarea" | "select")) { return; }
+        let dir = match key {
+            "ArrowDown" | "PageDown" | " " | "End" => (0, 1),
+            "ArrowUp" | "PageUp" | "Home" => (0, -1),
+            "ArrowRight" => (1, 0),
+            "ArrowLeft" => (-1, 0),
+            _ => return,
+        };
+        let step = |s: &ScrollState| match key {
+            "PageDown" | "PageUp" | " " => (s.clip.h - scroll::LINE_STEP).max(scroll::LINE_STEP),
+            "Home" | "End" => i32::MAX,
+            _ => scroll::LINE_STEP,
+        };
+        self.scroll_chain(from, dir, step, ScrollBehavior::Smooth);
+    }
+
+    /// Tab navigation: next focusable element in document order, wrapping around.
+    fn focus_next(&mut self) {
+        let order: Vec<NodeId> = self.doc.descendants(self.doc.root()).filter(|&n| events::is_focusable(&self.doc, n)).collect();
+        if order.is_empty() { return; }
+        let next = match self.focus.and_then(|f| order.iter().position(|&n| n == f)) {
+            Some(i) => order[(i + 1) % order.len()],
+            None => order[0],
+        };
+        self.set_focus(Some(next));
+    }
+
+    /// Dirty every `<img>` whose load state changed since it was laid out.
+    fn mark_loaded_images(&mut self) {
+        let Some(images) = &self.images else { return };
+        let generation = images.generation();
+        if generation == self.image_generation { return; }
+        self.image_generation = generation;
+        let mut changed = Vec::new();
+        self.root.walk(&mut |b| {
+            if let BoxKind::Image { src, state } = &b.kind {
+                if !src.is_empty() && !same_state(state, &images.request(src)) { changed.push(b.node); }
+            }
+        });
+        for n in changed { self.doc.mark_dirty(n); }
+    }
+}
+
+fn same_state(a: &ImageState, b: &ImageState) -> bool {
+    match (a, b) {
+        (ImageState::Ready(x), ImageState::Ready(y)) => Arc::ptr_eq(x, y),
+        _ => std::mem::discriminant(a) == std::mem::discriminant(b),
+    }
+}
+
+#[cfg(feature = "js")]
+impl Page {
+    /// Start scripting and run the page's inline classic `<script>`s in document order.
+    /// Each failure is logged to the console and returned; later scripts still run.
+    pub fn enable_scripts(&mut self, budget: ScriptBudget) -> Vec<ScriptError> {
+        if self.scripts.is_none() {
+            self.scripts = Some(Scripts { engine: Rc::new(RefCell::new(ScriptEngine::new(budget))), listeners: HashMap::new() });
+        }
+        let classic = |t: Option<&str>| t.is_none_or(|t| t.trim().is_empty() || t.trim().eq_ignore_ascii_case("text/javascript") || t.trim().eq_ignore_ascii_case("application/javascript"));
+        let sources: Vec<String> = self.doc.elements_by_tag("script")
+            .filter(|&s| self.doc.attr(s, "src").is_none() && classic(self.doc.attr(s, "type")))
+            .map(|s| self.doc.text_content(s))
+            .collect();
+        sources.iter().filter_map(|src| self.eval_script(src).err()).collect()
+    }
+
+    /// Run `source` in the page's script context; DOM changes show up on the next update.
+    pub fn eval_script(&mut self, source: &str) -> Result<(), ScriptError> {
+        let engine = self.scripts.as_ref().ok_or(ScriptError::Disabled)?.engine.clone();
+        let result = engine.borrow_mut().eval(&mut self.doc, source);
+        self.apply_listener_changes();
+        result
+    }
+
+    pub fn console_messages(&self) -> Vec<ConsoleMessage> {
+        self.scripts.as_ref().map(|s| s.engine.borrow().console()).unwrap_or_default()
+    }
+
+    fn run_timers(&mut self, dt_ms: u64) {
+        let Some(engine) = self.scripts.as_ref().map(|s| s.engine.clone()) else { return };
+        // An exhausted budget is already reported on the console
+        let _ = engine.borrow_mut().advance_timers(&mut self.doc, dt_ms);
+        self.apply_listener_changes();
+    }
+
+    /// Mirror `addEventListener`/`removeEventListener` calls made by scripts into the page's listeners.
+    fn apply_listener_changes(&mut self) {
+        let Some(scripts) = &mut self.scripts else { return };
+        let changes = scripts.engine.borrow_mut().take_listener_changes();
+        for c in changes {
+            let key = (c.node, c.kind.clone(), c.capture, c.callback);
+            if !c.add {
+                if let Some(id) = scripts.listeners.remove(&key) { self.listeners.remove(id); }
+                continue;
+            }
+            if scripts.listeners.contains_key(&key) { continue; }
+            let engine = scripts.engine.clone();
+            let listener = self.listeners.add(c.node, &c.kind, c.capture, Box::new(move |ev, doc| engine.borrow_mut().call_listener(doc, c.callback, ev)));
+            scripts.listeners.insert(key, listener);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::{HEADER_H, LINE_H};
+
+    fn rects(root: &LayoutBox) -> Vec<Rect> {
+        let mut out = Vec::new();
+        root.walk(&mut |b| out.push(b.rect));
+        out
+    }
+
+    const HTML: &str = "<div id=a><p>first</p></div><div id=b><p>one</p><p>two</p><p>three</p></div><div id=c>tail<img width=10 height=10></div>";
+
+    #[test]
+    fn text_change_relayouts_only_containing_block() {
+        let mut page = Page::new(HTML, (800, 600)).unwrap();
+        let full = page.last_layout_boxes();
+        let p = page.document().elements_by_tag("p").next().unwrap();
+        let text = page.document().children(p)[0];
+        page.document_mut().set_text(text, "changed");
+        page.update();
+        assert!(page.last_layout_boxes() < full / 2, "{} vs {}", page.last_layout_boxes(), full);
+        let fresh = Page::new(&HTML.replace("first", "changed"), (800, 600)).unwrap();
+        assert_eq!(page.layout_root(), fresh.layout_root());
+    }
+
+    #[test]
+    fn growing_block_shifts_following_siblings() {
+        let mut page = Page::new(HTML, (800, 600)).unwrap();
+        let c = page.document().descendants(page.document().root()).find(|&n| page.document().attr(n, "id") == Some("c")).unwrap();
+        let before = page.layout_root().find(c).unwrap().rect;
+        let doc = page.document_mut();
+        let p = doc.create_element("p", Vec::new());
+        let t = doc.create_text("extra");
+        doc.append_child(p, t);
+        let a = doc.descendants(doc.root()).find(|&n| doc.attr(n, "id") == Some("a")).unwrap();
+        doc.append_child(a, p);
+        let damage = page.update();
+        let after = page.layout_root().find(c).unwrap().rect;
+        assert_eq!(after.y, before.y + LINE_H + LINE_H / 2);
+        // The placeholder image in #c moved, so its old and new positions are damaged
+        assert_eq!(damage.len(), 2);
+        let fresh = Page::new(&HTML.replace("<p>first</p>", "<p>first</p><p>extra</p>"), (800, 600)).unwrap();
+        assert_eq!(rects(page.layout_root()), rects(fresh.layout_root()));
+    }
+
+    #[test]
+    fn no_change_means_no_damage_and_no_layout() {
+        let mut page = Page::new(HTML, (800, 600)).unwrap();
+        assert!(page.update().is_empty());
+        assert_eq!(page.last_layout_boxes(), 0);
+    }
+
+    #[test]
+    fn hit_test_finds_topmost_element() {
+        let page = Page::new("<div id=outer><p>hello <b>bold</b></p></div>", (800, 600)).unwrap();
+        let doc = page.document();
+        let b = doc.elements_by_tag("b").next().unwrap();
+        let rect = page.layout_root().find(b).unwrap().rect;
+        let hit = page.hit_test(rect.x + 1, rect.y + 1).unwrap();
+        assert_eq!(hit.node, b);
+        assert_ne!(hit.box_node, b, "text box is topmost, element is its parent");
+        // Below the content nothing is hit; input routing falls back to the document
+        assert!(page.hit_test(790, 590).is_none());
+    }
+
+    #[test]
+    fn input_routing_click_focus_hover_and_keys() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+        let mut page = Page::new("<div id=box><button>go</button></div><input>", (800, 600)).unwrap();
+        let button = page.document().elements_by_tag("button").next().unwrap();
+        let input = page.document().elements_by_tag("input").next().unwrap();
+        let div = page.document().parent(button).unwrap();
+        let log = Rc::new(RefCell::new(Vec::new()));
+        for kind in ["click", "keydown", "mouseover", "focus"] {
+            let log = log.clone();
+            page.add_event_listener(div, kind, true, Box::new(move |ev, _| log.borrow_mut().push(ev.kind.clone())));
+        }
+        // Clicking mutates the DOM; the page relayouts and reports damage
+        page.add_event_listener(button, "click", false, Box::new(|ev, doc| doc.set_text(ev.target, "clicked button")));
+
+        let r = page.layout_root().find(button).unwrap().rect;
+        let (x, y) = ((r.x + 2) as f32, (r.y + 2) as f32);
+        page.handle_input(&InputEvent::MouseMove { x, y });
+        assert!(page.element_state(div).hover && page.element_state(button).hover);
+        page.handle_input(&InputEvent::MouseDown { button: 0, x, y });
+        assert!(page.element_state(button).active);
+        assert_eq!(page.focused(), Some(button));
+        let res = page.handle_input(&InputEvent::MouseUp { button: 0, x, y });
+        assert!(!page.element_state(button).active);
+        assert_eq!(page.document().text_content(button), "clicked button");
+        assert_eq!(res.target, Some(button));
+
+        let res = page.handle_input(&InputEvent::KeyDown { key: "Tab".into(), code: 9 });
+        assert_eq!(res.target, Some(button));
+        assert_eq!(page.focused(), Some(input));
+        // focus does not bubble, so the div capture listener saw it only while the button was targeted
+        assert_eq!(*log.borrow(), vec!["mouseover", "focus", "click", "keydown"]);
+    }
+
+    #[test]
+    fn repainting_damage_matches_full_raster() {
+        let (w, h) = (200, 120);
+        let mut page = Page::new("<p><img width=20 height=20 src=''></p><p>x</p>", (w, h)).unwrap();
+        let mut frame = gpu_srv::cpu::rasterize_rgba8(w, h, page.display_list());
+        let img = page.document().elements_by_tag("img").next().unwrap();
+        page.document_mut().set_attr(img, "width", "40");
+        let damage = page.update();
+        assert_eq!(damage.len(), 1);
+        for r in &damage {
+            gpu_srv::cpu::repaint_region_rgba8(&mut frame, w, h, page.display_list(), (r.x as u32, r.y as u32, r.w as u32, r.h as u32));
+        }
+        assert_eq!(frame, gpu_srv::cpu::rasterize_rgba8(w, h, page.display_list()));
+    }
+
+    #[test]
+    fn wheel_scrolls_document_pixels_without_relayout() {
+        let (w, h) = (100, 300);
+        let html = "<p style='height:100px'>a</p><img width=40 height=40><p style='height:1000px'>b</p>";
+        let mut page = Page::new(html, (w, h)).unwrap();
+        let before = gpu_srv::cpu::rasterize_rgba8(w, h, page.display_list());
+        let row = |buf: &[u8], y: u32| buf[(y * w * 4) as usize..((y + 1) * w * 4) as usize].to_vec();
+        let img_top = page.layout_root().find(page.document().elements_by_tag("img").next().unwrap()).unwrap().rect.y as u32;
+        assert!(row(&before, img_top).iter().any(|&c| c != 255), "placeholder is painted");
+
+        page.handle_input(&InputEvent::MouseMove { x: 50.0, y: 200.0 });
+        let res = page.handle_input(&InputEvent::Wheel { delta_x: 0.0, delta_y: 40.0 });
+        assert_eq!(page.scroll_offset(page.document().root()), (0, 40));
+        assert_eq!(page.last_layout_boxes(), 0);
+        let after = gpu_srv::cpu::rasterize_rgba8(w, h, page.display_list());
+        for y in HEADER_H as u32 + 40..h {
+            assert_eq!(row(&before, y), row(&after, y - 40), "row {y} moved up by the scroll offset");
+        }
+        // Repainting the damage from the old frame gives the new frame
+        let mut frame = before;
+        for r in &res.damage {
+            gpu_srv::cpu::repaint_region_rgba8(&mut frame, w, h, page.display_list(), (r.x as u32, r.y as u32, r.w as u32, r.h as u32));
+        }
+        assert_eq!(frame, after);
+    }
+
+    #[test]
+    fn scroll_containers_chain_to_document_and_keys_scroll_smoothly() {
+        let html = "<div style='overflow:auto;height:60px'><p style='height:200px'>x</p><button>b</button></div><p style='height:1000px'>y</p>";
+        let mut page = Page::new(html, (200, 300)).unwrap();
+        let (root, div) = (page.document().root(), page.document().elements_by_tag("div").next().unwrap());
+        let button = page.document().elements_by_tag("button").next().unwrap();
+        let div_rect = page.layout_root().find(div).unwrap().rect;
+        let (x, y) = ((div_rect.x + 5) as f32, (div_rect.y + 5) as f32);
+        page.handle_input(&InputEvent::MouseMove { x, y });
+        page.handle_input(&InputEvent::Wheel { delta_x: 0.0, delta_y: 30.0 });
+        assert_eq!((page.scroll_offset(div), page.scroll_offset(root)), ((0, 30), (0, 0)));
+        page.handle_input(&InputEvent::Wheel { delta_x: 0.0, delta_y: 1000.0 });
+        let max = page.scroll_layers().get(div).unwrap().max_y;
+        assert_eq!(page.scroll_offset(div), (0, max));
+        // The button scrolled into view inside the container and hit testing follows it
+        let b = page.layout_root().find(button).unwrap().rect;
+        assert_eq!(page.hit_test(b.x + 2, b.y - max + 2).unwrap().node, button);
+        // At its end the container hands the wheel to the document
+        page.handle_input(&InputEvent::Wheel { delta_x: 0.0, delta_y: 10.0 });
+        assert_eq!(page.scroll_offset(root), (0, 10));
+
+        page.handle_input(&InputEvent::KeyDown { key: "PageDown".into(), code: 34 });
+        assert_eq!(page.scroll_offset(root), (0, 10), "smooth scroll starts on the next tick");
+        page.tick(scroll::SMOOTH_SCROLL_MS / 3);
+        assert!(page.scroll_layers().is_animating());
+        page.tick(scroll::SMOOTH_SCROLL_MS);
+        assert_eq!(page.scroll_offset(root), (0, 10 + 300 - HEADER_H - scroll::LINE_STEP));
+        page.handle_input(&InputEvent::KeyDown { key: "Home".into(), code: 36 });
+        page.tick(scroll::SMOOTH_SCROLL_MS);
+        assert_eq!(page.scroll_offset(root), (0, 0));
+    }
+}
@@ -0,0 +1,85 @@
+//! Turn a layout tree into a DisplayList.
+
+use crate::dom::Document;
+use crate::image::ImageState;
+use crate::layout::{self, BoxKind, LayoutBox, Rect, HEADER_H};
+use crate::rgba_u32;
+use crate::scroll::{self, ScrollLayers};
+use message_defs::{DisplayList, DrawCmd};
+
+/// Paint background, the Phase-1 header bar and every visible image.
+/// `anim_ms` picks the current frame of animated images. The document and each
+/// `overflow` container are wrapped in a layer carrying its offset from `scroll`.
+pub fn paint(doc: &Document, root: &LayoutBox, viewport: (u32, u32), anim_ms: u64, scroll: &ScrollLayers) -> DisplayList {
+    let (w, h) = viewport;
+    let mut items = vec![DrawCmd::Rect { x: 0, y: 0, w, h, rgba: rgba_u32(255, 255, 255, 255) }];
+
+    // Header bar: always drawn, darker if the document has an <h1>
+    let has_h1 = doc.elements_by_tag("h1").next().is_some();
+    let (r, g, b) = if has_h1 { (32, 32, 32) } else { (200, 200, 200) };
+    items.push(DrawCmd::Rect { x: 0, y: 0, w, h: HEADER_H as u32, rgba: rgba_u32(r, g, b, 255) });
+
+    let mut painter = Painter { doc, scroll, anim_ms, items };
+    let clip = scroll::document_clip(viewport);
+    painter.layer(root, clip, clip);
+    DisplayList { items: painter.items }
+}
+
+struct Painter<'a> {
+    doc: &'a Document,
+    scroll: &'a ScrollLayers,
+    anim_ms: u64,
+    items: Vec<DrawCmd>,
+}
+
+impl Painter<'_> {
+    /// Paint `b`'s subtree inside a layer clipped to `clip`; only content meeting
+    /// `visible` is emitted. Both are in the enclosing layer's coordinates.
+    fn layer(&mut self, b: &LayoutBox, clip: Rect, visible: Rect) {
+        let (sx, sy) = self.scroll.offset(b.node);
+        let (x, y, w, h) = (clip.x.max(0) as u32, clip.y.max(0) as u32, clip.w.max(0) as u32, clip.h.max(0) as u32);
+        self.items.push(DrawCmd::PushLayer { clip_x: x, clip_y: y, clip_w: w, clip_h: h, scroll_x: sx, scroll_y: sy });
+        self.paint_box(b, visible.translate(sx, sy));
+        self.items.push(DrawCmd::PopLayer);
+    }
+
+    /// Paint `b` and its descendants whose bounds meet `visible` (in the current layer's coordinates).
+    fn paint_box(&mut self, b: &LayoutBox, visible: Rect) {
+        if let BoxKind::Image { state, .. } = &b.kind {
+            if !b.rect.intersects(&visible) { return; }
+            match state {
+                ImageState::Ready(img) => self.items.push(DrawCmd::Image {
+                    x: b.rect.x as u32,
+                    y: b.rect.y as u32,
+                    w: b.rect.w as u32,
+                    h: b.rect.h as u32,
+                    src_w: img.width,
+                    src_h: img.height,
+                    pixels: img.frame_at(self.anim_ms).pixels.clone(),
+                }),
+                ImageState::Broken => broken_image(&mut self.items, b.rect),
+                ImageState::Pending => {}
+            }
+        }
+        for c in &b.children {
+            if !layout::overflow(self.doc, c.node).is_layer() {
+                self.paint_box(c, visible);
+            } else if let Some(v) = c.rect.intersection(&visible) {
+                self.layer(c, c.rect, v);
+            }
+        }
+    }
+}
+
+/// Grey framed box with a red mark, standing in for an image that failed to load.
+fn broken_image(items: &mut Vec<DrawCmd>, r: Rect) {
+    let (x, y, w, h) = (r.x as u32, r.y as u32, r.w as u32, r.h as u32);
+    items.push(DrawCmd::Rect { x, y, w, h, rgba: rgba_u32(128, 128, 128, 255) });
+    if w > 2 && h > 2 {
+        items.push(DrawCmd::Rect { x: x + 1, y: y + 1, w: w - 2, h: h - 2, rgba: rgba_u32(240, 240, 240, 255) });
+    }
+    let m = w.min(h) / 3;
+    if m > 0 {
+        items.push(DrawCmd::Rect { x: x + (w - m) / 2, y: y + (h - m) / 2, w: m, h: m, rgba: rgba_u32(200, 40, 40, 255) });
+    }
+}
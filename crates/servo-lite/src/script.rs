@@ -0,0 +1,921 @@
+//! JavaScript for servo-lite pages on the boa engine: `document` and element
+//! bindings over [`Document`], `console`, timers and event listeners.
+//!
+//! Scripts run as tasks (an inline `<script>`, a timer callback, an event
+//! listener) and the page lends its DOM to the engine for the duration of each
+//! task. Every page runs under a [`ScriptBudget`]: runaway loops and recursion
+//! throw, and once the page has used up its total time its scripts are disabled.
+//! The deadline is checked while a task runs, every [`CHECK_EVERY`] VM cycles
+//! and between promise jobs, so the task that overruns is cut short. Code that a
+//! builtin calls back into (an `Array.prototype.forEach` callback, a promise
+//! reaction) runs on boa's synchronous path, bounded by the loop and recursion
+//! limits, and the deadline is checked again once it returns.
+//! boa has no heap accounting, so memory is bounded by what scripts can allocate
+//! on the host side (DOM nodes, timers, console lines) plus the VM stack limit.
+
+use crate::dom::{Document, NodeId};
+use crate::events::{Event, EventDetail, EventPhase};
+use boa_engine::builtins::function::BoundFunction;
+use boa_engine::job::{Job, JobExecutor};
+use boa_engine::native_function::NativeFunctionPointer;
+use boa_engine::object::builtins::{JsArray, JsFunction};
+use boa_engine::object::{FunctionObjectBuilder, ObjectInitializer};
+use boa_engine::property::{Attribute, PropertyDescriptor};
+use boa_engine::{js_string, Context, JsArgs, JsData, JsError, JsNativeError, JsObject, JsResult, JsString, JsValue, NativeFunction, Script, Source};
+use boa_gc::{Finalize, Trace};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::future::Future;
+use std::pin::pin;
+use std::rc::Rc;
+use std::task::{Poll, Waker};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// Shortest `setInterval` period, as browsers clamp nested timers.
+const MIN_INTERVAL_MS: u64 = 4;
+
+/// VM "cycles" (instruction costs) between deadline checks while a task runs.
+const CHECK_EVERY: u32 = 4096;
+
+/// Global the engine binds a timer or listener callback to, so it runs as a budgeted script.
+const TASK_SLOT: &str = "__servoTask";
+
+/// Per-page limits on script execution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScriptBudget {
+    /// Wall-clock time all of a page's script tasks may use in total.
+    pub time: Duration,
+    /// Iterations a single loop may run before it throws.
+    pub loop_iterations: u64,
+    pub recursion: usize,
+    /// VM stack slots.
+    pub stack_size: usize,
+    /// Largest document (in nodes) scripts may grow the DOM to.
+    pub max_nodes: usize,
+    pub max_timers: usize,
+    /// Console messages kept; later ones are dropped.
+    pub max_console: usize,
+}
+
+impl Default for ScriptBudget {
+    fn default() -> Self {
+        Self {
+            time: Duration::from_millis(500),
+            loop_iterations: 1_000_000,
+            recursion: 256,
+            stack_size: 10 * 1024,
+            max_nodes: 50_000,
+            max_timers: 256,
+            max_console: 1000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ScriptError {
+    #[error("uncaught {0}")] Uncaught(String),
+    #[error("script time budget exhausted")] BudgetExhausted,
+    #[error("scripting is not enabled")] Disabled,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsoleLevel {
+    Log,
+    Info,
+    Warn,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConsoleMessage {
+    pub level: ConsoleLevel,
+    pub text: String,
+}
+
+/// `addEventListener`/`removeEventListener` call, applied by the page after the task.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ListenerChange {
+    pub add: bool,
+    pub node: NodeId,
+    pub kind: String,
+    pub capture: bool,
+    /// Index of the JS function in the engine's callback table.
+    pub callback: usize,
+}
+
+pub(crate) struct ScriptEngine {
+    ctx: Context,
+    host: Rc<RefCell<Host>>,
+    /// `__servoTask()`, which invokes the callback bound to [`TASK_SLOT`].
+    call_task: Script,
+}
+
+/// Host state reachable from native functions through the context.
+struct Host {
+    /// The page's DOM while a task runs; an empty document otherwise.
+    doc: Document,
+    budget: ScriptBudget,
+    used: Duration,
+    /// When the running task must stop.
+    deadline: Instant,
+    exhausted: bool,
+    console: Vec<ConsoleMessage>,
+    now_ms: u64,
+    next_timer: u32,
+    timers: BTreeMap<u32, Timer>,
+    callbacks: Vec<JsObject>,
+    changes: Vec<ListenerChange>,
+    /// One wrapper per node so that `a.firstChild === a.firstChild`.
+    wrappers: HashMap<NodeId, JsObject>,
+    node_proto: Option<JsObject>,
+}
+
+struct Timer {
+    due_ms: u64,
+    interval: Option<u64>,
+    callback: JsObject,
+}
+
+struct HostHandle(Rc<RefCell<Host>>);
+
+/// Payload of the JS object wrapping a DOM node.
+#[derive(Debug, Trace, Finalize, JsData)]
+struct NodeRef(#[unsafe_ignore_trace] NodeId);
+
+impl Host {
+    fn log(&mut self, level: ConsoleLevel, text: String) {
+        if self.console.len() < self.budget.max_console { self.console.push(ConsoleMessage { level, text }); }
+    }
+}
+
+impl ScriptEngine {
+    pub(crate) fn new(budget: ScriptBudget) -> Self {
+        let mut ctx = Context::builder().job_executor(Rc::new(Microtasks::default())).build().expect("default context builds");
+        let limits = ctx.runtime_limits_mut();
+        limits.set_loop_iteration_limit(budget.loop_iterations);
+        limits.set_recursion_limit(budget.recursion);
+        limits.set_stack_size_limit(budget.stack_size);
+        let host = Rc::new(RefCell::new(Host {
+            doc: Document::new(),
+            budget,
+            used: Duration::ZERO,
+            deadline: Instant::now(),
+            exhausted: false,
+            console: Vec::new(),
+            now_ms: 0,
+            next_timer: 0,
+            timers: BTreeMap::new(),
+            callbacks: Vec::new(),
+            changes: Vec::new(),
+            wrappers: HashMap::new(),
+            node_proto: None,
+        }));
+        ctx.insert_data(HostHandle(host.clone()));
+        install_globals(&mut ctx).expect("fresh context accepts globals");
+        let call_task = Script::parse(Source::from_bytes(&format!("{TASK_SLOT}()")), None, &mut ctx).expect("task call parses");
+        Self { ctx, host, call_task }
+    }
+
+    /// Evaluate a classic script against `doc`.
+    pub(crate) fn eval(&mut self, doc: &mut Document, source: &str) -> Result<(), ScriptError> {
+        self.task(doc, |ctx| {
+            let script = Script::parse(Source::from_bytes(source), None, ctx)?;
+            run_until_deadline(ctx, &script).map(drop)
+        })
+    }
+
+    /// Advance the timer clock by `dt_ms` and run every timer that came due, in due order.
+    /// Errors in callbacks are logged; only an exhausted budget stops the loop.
+    pub(crate) fn advance_timers(&mut self, doc: &mut Document, dt_ms: u64) -> Result<(), ScriptError> {
+        let now = {
+            let mut host = self.host.borrow_mut();
+            host.now_ms += dt_ms;
+            host.now_ms
+        };
+        loop {
+            let callback = {
+                let mut host = self.host.borrow_mut();
+                let Some((id, due)) = host.timers.iter().map(|(&id, t)| (id, t.due_ms)).filter(|&(_, due)| due <= now).min_by_key(|&(id, due)| (due, id)) else { break };
+                // Reschedule or drop before running, so the callback may clear its own timer
+                let timer = host.timers.get_mut(&id).expect("found above");
+                let callback = timer.callback.clone();
+                match timer.interval {
+                    Some(interval) => timer.due_ms = due + interval,
+                    None => { host.timers.remove(&id); }
+                }
+                callback
+            };
+            let call_task = self.call_task.clone();
+            match self.task(doc, |ctx| call(ctx, &call_task, callback, JsValue::undefined(), Vec::new()).map(drop)) {
+                Err(ScriptError::BudgetExhausted) => return Err(ScriptError::BudgetExhausted),
+                _ => continue,
+            }
+        }
+        Ok(())
+    }
+
+    /// Invoke JS listener `callback` for `ev`, copying back `preventDefault()` / `stopPropagation()`.
+    pub(crate) fn call_listener(&mut self, doc: &mut Document, callback: usize, ev: &mut Event) {
+        let Some(f) = self.host.borrow().callbacks.get(callback).cloned() else { return };
+        let snapshot = ev.clone();
+        let call_task = self.call_task.clone();
+        let flags = self.task(doc, |ctx| {
+            let obj = event_object(ctx, &snapshot);
+            let this = wrap(ctx, snapshot.current_target);
+            call(ctx, &call_task, f, this, vec![obj.clone().into()])?;
+            Ok((obj.get(js_string!("defaultPrevented"), ctx)?.to_boolean(), obj.get(js_string!("cancelBubble"), ctx)?.to_boolean()))
+        });
+        if let Ok((prevented, stopped)) = flags {
+            if prevented { ev.prevent_default(); }
+            if stopped { ev.stop_propagation(); }
+        }
+    }
+
+    pub(crate) fn take_listener_changes(&mut self) -> Vec<ListenerChange> { std::mem::take(&mut self.host.borrow_mut().changes) }
+
+    pub(crate) fn console(&self) -> Vec<ConsoleMessage> { self.host.borrow().console.clone() }
+
+    /// Run one task with the DOM lent to the engine, then charge its time to the budget.
+    /// A task still running at the deadline is interrupted and fails with `BudgetExhausted`.
+    fn task<R>(&mut self, doc: &mut Document, f: impl FnOnce(&mut Context) -> JsResult<R>) -> Result<R, ScriptError> {
+        let start = Instant::now();
+        {
+            let mut host = self.host.borrow_mut();
+            if host.exhausted { return Err(ScriptError::BudgetExhausted); }
+            host.doc = std::mem::take(doc);
+            host.deadline = start + host.budget.time.saturating_sub(host.used);
+        }
+        let result = f(&mut self.ctx).and_then(|r| self.ctx.run_jobs().map(|()| r));
+        // An interrupted task leaves its frames on the VM; nothing runs on it again.
+        let interrupted = self.host.borrow().exhausted;
+        let result = if interrupted { Err(String::new()) } else { result.map_err(|e| error_message(e, &mut self.ctx)) };
+        let mut host = self.host.borrow_mut();
+        *doc = std::mem::take(&mut host.doc);
+        host.used += start.elapsed();
+        if let (Err(msg), false) = (&result, interrupted) { host.log(ConsoleLevel::Error, format!("Uncaught {msg}")); }
+        if interrupted || host.used > host.budget.time {
+            host.exhausted = true;
+            host.timers.clear();
+            host.log(ConsoleLevel::Error, "script time budget exhausted; scripts disabled".to_string());
+        }
+        if interrupted { return Err(ScriptError::BudgetExhausted); }
+        result.map_err(ScriptError::Uncaught)
+    }
+}
+
+/// Promise job queue that checks the task deadline between jobs, so an endless
+/// `then` chain is interrupted. Pages have no host timers or async natives on the
+/// boa side, so only promise and generic jobs are queued.
+#[derive(Default)]
+struct Microtasks(RefCell<VecDeque<Job>>);
+
+impl JobExecutor for Microtasks {
+    fn enqueue_job(self: Rc<Self>, job: Job, _: &mut Context) {
+        if matches!(job, Job::PromiseJob(_) | Job::GenericJob(_)) { self.0.borrow_mut().push_back(job); }
+    }
+
+    fn run_jobs(self: Rc<Self>, ctx: &mut Context) -> JsResult<()> {
+        loop {
+            let Some(job) = self.0.borrow_mut().pop_front() else { break };
+            let result = match job {
+                _ if past_deadline(ctx) => Err(interrupt(ctx)),
+                Job::PromiseJob(job) => job.call(ctx),
+                Job::GenericJob(job) => job.call(ctx),
+                _ => Ok(JsValue::undefined()),
+            };
+            if let Err(e) = result {
+                self.0.borrow_mut().clear();
+                return Err(e);
+            }
+        }
+        ctx.clear_kept_objects();
+        Ok(())
+    }
+}
+
+fn past_deadline(ctx: &Context) -> bool { Instant::now() >= host(ctx).borrow().deadline }
+
+/// Stop the running task: mark the budget exhausted and throw out of the VM.
+fn interrupt(ctx: &Context) -> JsError {
+    host(ctx).borrow_mut().exhausted = true;
+    JsNativeError::error().with_message("script time budget exhausted").into()
+}
+
+/// Evaluate `script`, checking the task deadline every [`CHECK_EVERY`] VM cycles.
+fn run_until_deadline(ctx: &mut Context, script: &Script) -> JsResult<JsValue> {
+    let deadline = host(ctx).borrow().deadline;
+    let mut cx = std::task::Context::from_waker(Waker::noop());
+    {
+        let mut run = pin!(script.evaluate_async_with_budget(ctx, CHECK_EVERY));
+        loop {
+            if let Poll::Ready(result) = run.as_mut().poll(&mut cx) { return result; }
+            if Instant::now() >= deadline { break; }
+        }
+    }
+    Err(interrupt(ctx))
+}
+
+/// Call `f` as a budgeted task: bound to [`TASK_SLOT`] and invoked from `call_task`, it
+/// runs on the same VM loop as the script instead of boa's synchronous call path.
+fn call(ctx: &mut Context, call_task: &Script, f: JsObject, this: JsValue, args: Vec<JsValue>) -> JsResult<JsValue> {
+    let bound = BoundFunction::create(f, this, args, ctx)?;
+    let slot = PropertyDescriptor::builder().value(bound).writable(false).enumerable(false).configurable(true);
+    ctx.global_object().define_property_or_throw(js_string!(TASK_SLOT), slot, ctx)?;
+    run_until_deadline(ctx, call_task)
+}
+
+/// `TypeError: message` style text for an uncaught error, without the backtrace.
+fn error_message(err: JsError, ctx: &mut Context) -> String {
+    let fallback = err.to_string().lines().next().unwrap_or_default().to_string();
+    err.into_opaque(ctx).and_then(|v| v.to_string(ctx)).map(|s| s.to_std_string_escaped()).unwrap_or(fallback)
+}
+
+fn host(ctx: &Context) -> Rc<RefCell<Host>> { ctx.get_data::<HostHandle>().expect("installed with the engine").0.clone() }
+
+fn with_doc<R>(ctx: &Context, f: impl FnOnce(&mut Document) -> R) -> R { f(&mut host(ctx).borrow_mut().doc) }
+
+fn js_str(s: &str) -> JsValue { JsString::from(s).into() }
+
+fn type_error(msg: &str) -> JsError { JsNativeError::typ().with_message(msg.to_string()).into() }
+
+fn arg_string(args: &[JsValue], i: usize, ctx: &mut Context) -> JsResult<String> {
+    Ok(args.get_or_undefined(i).to_string(ctx)?.to_std_string_escaped())
+}
+
+fn node_of(v: &JsValue) -> JsResult<NodeId> {
+    v.as_object().and_then(|o| o.downcast_ref::<NodeRef>().map(|n| n.0)).ok_or_else(|| type_error("not a DOM node"))
+}
+
+/// The (cached) JS object for `node`.
+fn wrap(ctx: &mut Context, node: NodeId) -> JsValue {
+    let host = host(ctx);
+    let mut host = host.borrow_mut();
+    if let Some(o) = host.wrappers.get(&node) { return o.clone().into(); }
+    let obj = JsObject::from_proto_and_data(host.node_proto.clone(), NodeRef(node));
+    host.wrappers.insert(node, obj.clone());
+    obj.into()
+}
+
+fn wrap_opt(ctx: &mut Context, node: Option<NodeId>) -> JsValue { node.map(|n| wrap(ctx, n)).unwrap_or(JsValue::null()) }
+
+fn wrap_all(ctx: &mut Context, nodes: Vec<NodeId>) -> JsValue {
+    let values: Vec<JsValue> = nodes.into_iter().map(|n| wrap(ctx, n)).collect();
+    JsArray::from_iter(values, ctx).into()
+}
+
+fn function(ctx: &mut Context, name: &str, f: NativeFunctionPointer, length: usize) -> JsFunction {
+    FunctionObjectBuilder::new(ctx.realm(), NativeFunction::from_fn_ptr(f)).name(JsString::from(name)).length(length).build()
+}
+
+type Accessor = (&'static str, NativeFunctionPointer, Option<NativeFunctionPointer>);
+type Method = (&'static str, NativeFunctionPointer, usize);
+
+/// Object with the given accessors and methods, for use as a prototype.
+fn prototype(ctx: &mut Context, accessors: &[Accessor], methods: &[Method]) -> JsObject {
+    let built: Vec<_> = accessors.iter()
+        .map(|&(name, get, set)| (name, function(ctx, name, get, 0), set.map(|s| function(ctx, name, s, 1))))
+        .collect();
+    let mut init = ObjectInitializer::new(ctx);
+    for (name, get, set) in built {
+        init.accessor(JsString::from(name), Some(get), set, Attribute::CONFIGURABLE | Attribute::ENUMERABLE);
+    }
+    for &(name, f, len) in methods { init.function(NativeFunction::from_fn_ptr(f), JsString::from(name), len); }
+    init.build()
+}
+
+fn install_globals(ctx: &mut Context) -> JsResult<()> {
+    let node_proto = prototype(ctx, NODE_ACCESSORS, NODE_METHODS);
+    host(ctx).borrow_mut().node_proto = Some(node_proto.clone());
+
+    let document_proto = prototype(ctx, DOCUMENT_ACCESSORS, DOCUMENT_METHODS);
+    document_proto.set_prototype(Some(node_proto));
+    let root = Document::new().root();
+    let document = JsObject::from_proto_and_data(document_proto, NodeRef(root));
+    host(ctx).borrow_mut().wrappers.insert(root, document.clone());
+    ctx.register_global_property(js_string!("document"), document, Attribute::all())?;
+
+    let console = prototype(ctx, &[], &[("log", console_log, 0), ("debug", console_log, 0), ("info", console_info, 0), ("warn", console_warn, 0), ("error", console_error, 0)]);
+    ctx.register_global_property(js_string!("console"), console, Attribute::all())?;
+
+    for (name, f, len) in [("setTimeout", set_timeout as NativeFunctionPointer, 2), ("setInterval", set_interval, 2), ("clearTimeout", clear_timer, 1), ("clearInterval", clear_timer, 1)] {
+        ctx.register_global_callable(JsString::from(name), len, NativeFunction::from_fn_ptr(f))?;
+    }
+    let global = ctx.global_object();
+    ctx.register_global_property(js_string!("window"), global, Attribute::all())?;
+    Ok(())
+}
+
+// ---- Node bindings ----
+
+const NODE_ACCESSORS: &[Accessor] = &[
+    ("nodeType", get_node_type, None),
+    ("nodeName", get_tag_name, None),
+    ("tagName", get_tag_name, None),
+    ("textContent", get_text_content, Some(set_text_content)),
+    ("id", get_id, Some(set_id)),
+    ("className", get_class_name, Some(set_class_name)),
+    ("value", get_value, Some(set_value)),
+    ("parentNode", get_parent, None),
+    ("parentElement", get_parent_element, None),
+    ("childNodes", get_child_nodes, None),
+    ("children", get_children, None),
+    ("firstChild", get_first_child, None),
+    ("lastChild", get_last_child, None),
+    ("previousSibling", get_previous_sibling, None),
+    ("nextSibling", get_next_sibling, None),
+];
+
+const NODE_METHODS: &[Method] = &[
+    ("getAttribute", get_attribute, 1),
+    ("setAttribute", set_attribute, 2),
+    ("removeAttribute", remove_attribute, 1),
+    ("hasAttribute", has_attribute, 1),
+    ("appendChild", append_child, 1),
+    ("insertBefore", insert_before, 2),
+    ("removeChild", remove_child, 1),
+    ("remove", remove, 0),
+    ("contains", contains, 1),
+    ("querySelector", query_selector, 1),
+    ("querySelectorAll", query_selector_all, 1),
+    ("getElementsByTagName", get_elements_by_tag_name, 1),
+    ("addEventListener", add_event_listener, 2),
+    ("removeEventListener", remove_event_listener, 2),
+];
+
+const DOCUMENT_ACCESSORS: &[Accessor] = &[
+    ("documentElement", get_document_element, None),
+    ("body", get_body, None),
+    ("head", get_head, None),
+];
+
+const DOCUMENT_METHODS: &[Method] = &[
+    ("getElementById", get_element_by_id, 1),
+    ("createElement", create_element, 1),
+    ("createTextNode", create_text_node, 1),
+];
+
+fn get_node_type(this: &JsValue, _: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let node = node_of(this)?;
+    let kind = with_doc(ctx, |d| if node == d.root() { 9 } else if d.text(node).is_some() { 3 } else { 1 });
+    Ok(kind.into())
+}
+
+fn get_tag_name(this: &JsValue, _: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let node = node_of(this)?;
+    let name = with_doc(ctx, |d| match d.tag_name(node) {
+        Some(t) => t.to_ascii_uppercase(),
+        None if node == d.root() => "#document".to_string(),
+        None => "#text".to_string(),
+    });
+    Ok(js_str(&name))
+}
+
+fn get_text_content(this: &JsValue, _: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let node = node_of(this)?;
+    Ok(js_str(&with_doc(ctx, |d| d.text_content(node))))
+}
+
+fn set_text_content(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let node = node_of(this)?;
+    let text = arg_string(args, 0, ctx)?;
+    with_doc(ctx, |d| d.set_text(node, &text));
+    Ok(JsValue::undefined())
+}
+
+/// Getter/setter pair reflecting a content attribute, e.g. `el.id` <-> `id="..."`.
+macro_rules! reflect_attr {
+    ($get:ident, $set:ident, $attr:literal) => {
+        fn $get(this: &JsValue, _: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+            let node = node_of(this)?;
+            Ok(js_str(&with_doc(ctx, |d| d.attr(node, $attr).unwrap_or_default().to_string())))
+        }
+
+        fn $set(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+            let node = node_of(this)?;
+            let value = arg_string(args, 0, ctx)?;
+            with_doc(ctx, |d| d.set_attr(node, $attr, &value));
+            Ok(JsValue::undefined())
+        }
+    };
+}
+
+reflect_attr!(get_id, set_id, "id");
+reflect_attr!(get_class_name, set_class_name, "class");
+reflect_attr!(get_value, set_value, "value");
+
+/// Getter returning the node picked by `$pick` from the document and this node.
+macro_rules! node_getter {
+    ($name:ident, |$d:ident, $n:ident| $pick:expr) => {
+        fn $name(this: &JsValue, _: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+            let $n = node_of(this)?;
+            let picked = with_doc(ctx, |$d| $pick);
+            Ok(wrap_opt(ctx, picked))
+        }
+    };
+}
+
+node_getter!(get_parent, |d, n| d.parent(n));
+node_getter!(get_parent_element, |d, n| d.parent(n).filter(|&p| d.tag_name(p).is_some()));
+node_getter!(get_first_child, |d, n| d.children(n).first().copied());
+node_getter!(get_last_child, |d, n| d.children(n).last().copied());
+node_getter!(get_previous_sibling, |d, n| sibling(d, n, -1));
+node_getter!(get_next_sibling, |d, n| sibling(d, n, 1));
+node_getter!(get_document_element, |d, n| d.children(n).iter().copied().find(|&c| d.tag_name(c).is_some()));
+node_getter!(get_body, |d, _n| d.elements_by_tag("body").next());
+node_getter!(get_head, |d, _n| d.elements_by_tag("head").next());
+
+fn sibling(doc: &Document, node: NodeId, step: isize) -> Option<NodeId> {
+    let siblings = doc.children(doc.parent(node)?);
+    let i = siblings.iter().position(|&c| c == node)?;
+    siblings.get(i.checked_add_signed(step)?).copied()
+}
+
+fn get_child_nodes(this: &JsValue, _: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let node = node_of(this)?;
+    let nodes = with_doc(ctx, |d| d.children(node).to_vec());
+    Ok(wrap_all(ctx, nodes))
+}
+
+fn get_children(this: &JsValue, _: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let node = node_of(this)?;
+    let nodes = with_doc(ctx, |d| d.children(node).iter().copied().filter(|&c| d.tag_name(c).is_some()).collect());
+    Ok(wrap_all(ctx, nodes))
+}
+
+fn get_attribute(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let (node, name) = (node_of(this)?, arg_string(args, 0, ctx)?);
+    Ok(with_doc(ctx, |d| d.attr(node, &name.to_ascii_lowercase()).map(js_str)).unwrap_or(JsValue::null()))
+}
+
+fn set_attribute(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let (node, name, value) = (node_of(this)?, arg_string(args, 0, ctx)?, arg_string(args, 1, ctx)?);
+    if name.is_empty() || name.contains(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | '>' | '/' | '=')) {
+        return Err(type_error("invalid attribute name"));
+    }
+    with_doc(ctx, |d| d.set_attr(node, &name, &value));
+    Ok(JsValue::undefined())
+}
+
+fn remove_attribute(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let (node, name) = (node_of(this)?, arg_string(args, 0, ctx)?);
+    with_doc(ctx, |d| d.remove_attr(node, &name));
+    Ok(JsValue::undefined())
+}
+
+fn has_attribute(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let (node, name) = (node_of(this)?, arg_string(args, 0, ctx)?);
+    Ok(with_doc(ctx, |d| d.attr(node, &name.to_ascii_lowercase()).is_some()).into())
+}
+
+/// Reject insertions that would make a node its own ancestor or move the document.
+fn check_insert(doc: &Document, parent: NodeId, child: NodeId) -> JsResult<()> {
+    if child == doc.root() || std::iter::successors(Some(parent), |&p| doc.parent(p)).any(|a| a == child) {
+        return Err(type_error("HierarchyRequestError: the new child is an ancestor of the parent"));
+    }
+    if doc.text(parent).is_some() { return Err(type_error("HierarchyRequestError: text nodes have no children")); }
+    Ok(())
+}
+
+fn append_child(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let (parent, child) = (node_of(this)?, node_of(args.get_or_undefined(0))?);
+    with_doc(ctx, |d| check_insert(d, parent, child).map(|()| d.append_child(parent, child)))?;
+    Ok(args.get_or_undefined(0).clone())
+}
+
+fn insert_before(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let (parent, child) = (node_of(this)?, node_of(args.get_or_undefined(0))?);
+    let reference = args.get_or_undefined(1);
+    let reference = if reference.is_null_or_undefined() { None } else { Some(node_of(reference)?) };
+    with_doc(ctx, |d| {
+        check_insert(d, parent, child)?;
+        match reference {
+            Some(r) if d.parent(r) != Some(parent) => return Err(type_error("NotFoundError: reference is not a child of this node")),
+            Some(r) => d.insert_before(parent, child, r),
+            None => d.append_child(parent, child),
+        }
+        Ok(())
+    })?;
+    Ok(args.get_or_undefined(0).clone())
+}
+
+fn remove_child(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let (parent, child) = (node_of(this)?, node_of(args.get_or_undefined(0))?);
+    with_doc(ctx, |d| {
+        if d.parent(child) != Some(parent) { return Err(type_error("NotFoundError: not a child of this node")); }
+        d.detach(child);
+        Ok(())
+    })?;
+    Ok(args.get_or_undefined(0).clone())
+}
+
+fn remove(this: &JsValue, _: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let node = node_of(this)?;
+    with_doc(ctx, |d| d.detach(node));
+    Ok(JsValue::undefined())
+}
+
+fn contains(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let node = node_of(this)?;
+    let Ok(other) = node_of(args.get_or_undefined(0)) else { return Ok(false.into()) };
+    Ok(with_doc(ctx, |d| std::iter::successors(Some(other), |&p| d.parent(p)).any(|a| a == node)).into())
+}
+
+fn query_selector(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let (node, sel) = (node_of(this)?, arg_string(args, 0, ctx)?);
+    let found = with_doc(ctx, |d| d.select(node, &sel).first().copied());
+    Ok(wrap_opt(ctx, found))
+}
+
+fn query_selector_all(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let (node, sel) = (node_of(this)?, arg_string(args, 0, ctx)?);
+    let found = with_doc(ctx, |d| d.select(node, &sel));
+    Ok(wrap_all(ctx, found))
+}
+
+fn get_elements_by_tag_name(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let (node, tag) = (node_of(this)?, arg_string(args, 0, ctx)?);
+    let found = with_doc(ctx, |d| {
+        d.descendants(node).skip(1).filter(|&n| d.tag_name(n).is_some_and(|t| tag == "*" || t.eq_ignore_ascii_case(&tag))).collect()
+    });
+    Ok(wrap_all(ctx, found))
+}
+
+fn get_element_by_id(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let id = arg_string(args, 0, ctx)?;
+    let found = with_doc(ctx, |d| d.descendants(d.root()).find(|&n| d.attr(n, "id") == Some(id.as_str())));
+    Ok(wrap_opt(ctx, found))
+}
+
+/// Fail with a RangeError once the document holds as many nodes as the budget allows.
+fn check_node_budget(ctx: &Context) -> JsResult<()> {
+    let host = host(ctx);
+    let host = host.borrow();
+    if host.doc.len() >= host.budget.max_nodes {
+        return Err(JsNativeError::range().with_message("DOM node budget exhausted").into());
+    }
+    Ok(())
+}
+
+fn create_element(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let name = arg_string(args, 0, ctx)?;
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return Err(type_error("InvalidCharacterError: invalid tag name"));
+    }
+    check_node_budget(ctx)?;
+    let node = with_doc(ctx, |d| d.create_element(&name, Vec::new()));
+    Ok(wrap(ctx, node))
+}
+
+fn create_text_node(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let text = arg_string(args, 0, ctx)?;
+    check_node_budget(ctx)?;
+    let node = with_doc(ctx, |d| d.create_text(&text));
+    Ok(wrap(ctx, node))
+}
+
+// ---- Events ----
+
+/// `capture` from the third `addEventListener` argument: a boolean or `{ capture }`.
+fn capture_flag(v: &JsValue, ctx: &mut Context) -> JsResult<bool> {
+    match v.as_object() {
+        Some(o) => Ok(o.get(js_string!("capture"), ctx)?.to_boolean()),
+        None => Ok(v.to_boolean()),
+    }
+}
+
+fn listener_change(this: &JsValue, args: &[JsValue], ctx: &mut Context, add: bool) -> JsResult<JsValue> {
+    let (node, kind) = (node_of(this)?, arg_string(args, 0, ctx)?);
+    // A null listener is ignored, as in the DOM
+    let Some(f) = args.get_or_undefined(1).as_callable() else { return Ok(JsValue::undefined()) };
+    let capture = capture_flag(args.get_or_undefined(2), ctx)?;
+    let host = host(ctx);
+    let mut host = host.borrow_mut();
+    let callback = match host.callbacks.iter().position(|c| JsObject::equals(c, &f)) {
+        Some(i) => i,
+        None if add => {
+            host.callbacks.push(f);
+            host.callbacks.len() - 1
+        }
+        None => return Ok(JsValue::undefined()),
+    };
+    host.changes.push(ListenerChange { add, node, kind, capture, callback });
+    Ok(JsValue::undefined())
+}
+
+fn add_event_listener(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> { listener_change(this, args, ctx, true) }
+
+fn remove_event_listener(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> { listener_change(this, args, ctx, false) }
+
+/// JS view of `ev`; `defaultPrevented`/`cancelBubble` are read back after the listener ran.
+fn event_object(ctx: &mut Context, ev: &Event) -> JsObject {
+    let (target, current) = (wrap(ctx, ev.target), wrap(ctx, ev.current_target));
+    let phase = match ev.phase {
+        EventPhase::None => 0,
+        EventPhase::Capturing => 1,
+        EventPhase::AtTarget => 2,
+        EventPhase::Bubbling => 3,
+    };
+    let mut init = ObjectInitializer::new(ctx);
+    init.property(js_string!("type"), js_str(&ev.kind), Attribute::ENUMERABLE)
+        .property(js_string!("target"), target, Attribute::ENUMERABLE)
+        .property(js_string!("currentTarget"), current, Attribute::ENUMERABLE)
+        .property(js_string!("eventPhase"), phase, Attribute::ENUMERABLE)
+        .property(js_string!("bubbles"), ev.bubbles, Attribute::ENUMERABLE)
+        .property(js_string!("defaultPrevented"), ev.default_prevented(), Attribute::all())
+        .property(js_string!("cancelBubble"), false, Attribute::all())
+        .function(NativeFunction::from_fn_ptr(prevent_default), js_string!("preventDefault"), 0)
+        .function(NativeFunction::from_fn_ptr(stop_propagation), js_string!("stopPropagation"), 0);
+    match &ev.detail {
+        EventDetail::None => {}
+        EventDetail::Mouse { x, y, button } => {
+            init.property(js_string!("clientX"), *x, Attribute::ENUMERABLE)
+                .property(js_string!("clientY"), *y, Attribute::ENUMERABLE)
+                .property(js_string!("button"), *button, Attribute::ENUMERABLE);
+        }
+        EventDetail::Key { key, code } => {
+            init.property(js_string!("key"), js_str(key), Attribute::ENUMERABLE).property(js_string!("keyCode"), *code, Attribute::ENUMERABLE);
+        }
+        EventDetail::Wheel { delta_x, delta_y } => {
+            init.property(js_string!("deltaX"), *delta_x, Attribute::ENUMERABLE).property(js_string!("deltaY"), *delta_y, Attribute::ENUMERABLE);
+        }
+    }
+    init.build()
+}
+
+fn set_flag(this: &JsValue, name: JsString, ctx: &mut Context) -> JsResult<JsValue> {
+    if let Some(o) = this.as_object() { o.set(name, true, false, ctx)?; }
+    Ok(JsValue::undefined())
+}
+
+fn prevent_default(this: &JsValue, _: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> { set_flag(this, js_string!("defaultPrevented"), ctx) }
+
+fn stop_propagation(this: &JsValue, _: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> { set_flag(this, js_string!("cancelBubble"), ctx) }
+
+// ---- console and timers ----
+
+fn console(args: &[JsValue], ctx: &mut Context, level: ConsoleLevel) -> JsResult<JsValue> {
+    let text = args.iter()
+        .map(|a| a.as_string().map(|s| s.to_std_string_escaped()).unwrap_or_else(|| a.display().to_string()))
+        .collect::<Vec<_>>()
+        .join(" ");
+    host(ctx).borrow_mut().log(level, text);
+    Ok(JsValue::undefined())
+}
+
+fn console_log(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> { console(args, ctx, ConsoleLevel::Log) }
+
+fn console_info(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> { console(args, ctx, ConsoleLevel::Info) }
+
+fn console_warn(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> { console(args, ctx, ConsoleLevel::Warn) }
+
+fn console_error(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> { console(args, ctx, ConsoleLevel::Error) }
+
+fn add_timer(args: &[JsValue], ctx: &mut Context, repeat: bool) -> JsResult<JsValue> {
+    let callback = args.get_or_undefined(0).as_callable().ok_or_else(|| type_error("timer handler must be a function"))?;
+    let delay = args.get_or_undefined(1).to_number(ctx)?;
+    let delay = if delay.is_finite() && delay > 0.0 { delay as u64 } else { 0 };
+    let host = host(ctx);
+    let mut host = host.borrow_mut();
+    if host.timers.len() >= host.budget.max_timers {
+        return Err(JsNativeError::range().with_message("timer budget exhausted").into());
+    }
+    host.next_timer += 1;
+    let id = host.next_timer;
+    let due_ms = host.now_ms + delay;
+    host.timers.insert(id, Timer { due_ms, interval: repeat.then_some(delay.max(MIN_INTERVAL_MS)), callback });
+    Ok(id.into())
+}
+
+fn set_timeout(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> { add_timer(args, ctx, false) }
+
+fn set_interval(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> { add_timer(args, ctx, true) }
+
+fn clear_timer(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let id = args.get_or_undefined(0).to_number(ctx)?;
+    if id.is_finite() && id >= 0.0 { host(ctx).borrow_mut().timers.remove(&(id as u32)); }
+    Ok(JsValue::undefined())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Page;
+    use event_packet::InputEvent;
+
+    fn console_texts(page: &Page) -> Vec<String> { page.console_messages().into_iter().map(|m| m.text).collect() }
+
+    #[test]
+    fn dom_bindings_mutate_the_page() {
+        let mut page = Page::new("<ul id=list></ul><p class=note>old</p><script>console.log('boot', 1 + 1)</script>", (800, 600)).unwrap();
+        assert_eq!(page.eval_script("1"), Err(ScriptError::Disabled));
+        assert!(page.enable_scripts(ScriptBudget::default()).is_empty());
+        page.eval_script(r#"
+            const list = document.getElementById('list');
+            for (const t of ['a', 'b']) { const li = document.createElement('li'); li.textContent = t; list.appendChild(li); }
+            document.querySelector('p.note').textContent = 'new';
+            list.setAttribute('data-n', String(list.children.length));
+            console.warn(list.firstChild === list.children[0], list.firstChild.parentNode.id, list.lastChild.tagName);
+        "#).unwrap();
+        let doc = page.document();
+        let list = doc.select(doc.root(), "#list")[0];
+        assert_eq!(doc.text_content(list), "ab");
+        assert_eq!(doc.attr(list, "data-n"), Some("2"));
+        assert_eq!(doc.text_content(doc.select(doc.root(), ".note")[0]), "new");
+        page.update();
+        assert!(page.last_layout_boxes() > 0, "script mutations are laid out");
+        assert_eq!(page.console_messages()[1], ConsoleMessage { level: ConsoleLevel::Warn, text: "true list LI".into() });
+
+        let err = page.eval_script("list.appendChild(list)").unwrap_err();
+        assert!(matches!(&err, ScriptError::Uncaught(m) if m.starts_with("TypeError: HierarchyRequestError")), "{err}");
+        assert_eq!(page.console_messages().last().unwrap().level, ConsoleLevel::Error);
+    }
+
+    #[test]
+    fn script_listeners_receive_input_events() {
+        let html = "<button id=b>0</button><script>
+            let clicks = 0;
+            const b = document.getElementById('b');
+            function onClick(e) { clicks++; b.textContent = String(clicks); console.log(e.type, e.target === b, e.eventPhase); }
+            b.addEventListener('click', onClick);
+            b.addEventListener('click', onClick);
+            b.addEventListener('mousedown', e => e.preventDefault());
+            document.addEventListener('click', () => console.log('doc'), { capture: true });
+        </script>";
+        let mut page = Page::new(html, (800, 600)).unwrap();
+        assert!(page.enable_scripts(ScriptBudget::default()).is_empty());
+        let b = page.document().select(page.document().root(), "#b")[0];
+        let click = |page: &mut Page| {
+            let r = page.layout_root().find(b).unwrap().rect;
+            let (x, y) = ((r.x + 1) as f32, (r.y + 1) as f32);
+            page.handle_input(&InputEvent::MouseDown { button: 0, x, y });
+            page.handle_input(&InputEvent::MouseUp { button: 0, x, y });
+        };
+        click(&mut page);
+        assert_eq!(page.document().text_content(b), "1", "duplicate registration is ignored");
+        assert_eq!(page.focused(), None, "mousedown default was prevented");
+        assert_eq!(console_texts(&page), vec!["doc", "click true 2"]);
+
+        page.eval_script("b.removeEventListener('click', onClick)").unwrap();
+        click(&mut page);
+        assert_eq!(page.document().text_content(b), "1");
+    }
+
+    #[test]
+    fn timers_run_on_page_ticks() {
+        let mut page = Page::new("<p>x</p>", (800, 600)).unwrap();
+        page.enable_scripts(ScriptBudget::default());
+        page.eval_script("let n = 0; const id = setInterval(() => { if (++n == 3) clearInterval(id); }, 10); setTimeout(() => console.log('timeout', n), 25);").unwrap();
+        page.tick(9);
+        page.eval_script("console.log(n)").unwrap();
+        page.tick(1);
+        page.eval_script("console.log(n)").unwrap();
+        page.tick(100);
+        page.eval_script("console.log(n)").unwrap();
+        assert_eq!(console_texts(&page), vec!["0", "1", "timeout 2", "3"]);
+    }
+
+    #[test]
+    fn budgets_stop_runaway_scripts() {
+        let mut page = Page::new("<p>x</p>", (800, 600)).unwrap();
+        let max_nodes = page.document().len() + 2;
+        page.enable_scripts(ScriptBudget { loop_iterations: 1000, max_nodes, ..Default::default() });
+        assert!(matches!(page.eval_script("while (true) {}"), Err(ScriptError::Uncaught(_))));
+        let err = page.eval_script("for (let i = 0; i < 5; i++) document.createElement('div')").unwrap_err();
+        assert!(matches!(&err, ScriptError::Uncaught(m) if m.contains("RangeError")), "{err}");
+        assert_eq!(page.document().len(), max_nodes);
+        page.eval_script("1").unwrap();
+
+        let mut page = Page::new("<p>x</p>", (800, 600)).unwrap();
+        page.enable_scripts(ScriptBudget { time: Duration::ZERO, ..Default::default() });
+        page.eval_script("setTimeout(() => console.log('late'), 0)").unwrap();
+        assert_eq!(page.eval_script("1"), Err(ScriptError::BudgetExhausted));
+        page.tick(10);
+        assert_eq!(console_texts(&page), vec!["script time budget exhausted; scripts disabled"]);
+    }
+
+    #[test]
+    fn long_tasks_are_interrupted_at_the_deadline() {
+        let time = Duration::from_millis(100);
+        let budget = ScriptBudget { time, loop_iterations: u64::MAX, ..Default::default() };
+        // Each call stays under the loop limit; only the deadline stops the outer loop
+        let busy = "function spin() { for (let i = 0; i < 1e5; i++) {} } for (;;) spin();";
+        let mut page = Page::new("<p>x</p>", (800, 600)).unwrap();
+        page.enable_scripts(budget);
+        let start = Instant::now();
+        assert_eq!(page.eval_script(busy), Err(ScriptError::BudgetExhausted));
+        assert!(start.elapsed() < time * 3, "stopped after {:?}", start.elapsed());
+        assert_eq!(page.eval_script("1"), Err(ScriptError::BudgetExhausted));
+
+        // Timer callbacks and endless promise chains are interrupted too
+        for script in [format!("setTimeout(() => {{ {busy} }}, 0)"), "function again() { Promise.resolve().then(again) } again()".to_string()] {
+            let mut page = Page::new("<p>x</p>", (800, 600)).unwrap();
+            page.enable_scripts(budget);
+            let start = Instant::now();
+            let _ = page.eval_script(&script);
+            page.tick(1);
+            assert!(start.elapsed() < time * 3, "stopped after {:?}", start.elapsed());
+            assert_eq!(page.eval_script("1"), Err(ScriptError::BudgetExhausted));
+        }
+    }
+}
@@ -0,0 +1,190 @@
+//! Scroll layers: the document and every `overflow` container keep their own
+//! scroll offset. Scrolling only changes offsets, so the layout tree is reused
+//! and paint just emits the new offsets on the layer commands.
+
+use crate::dom::{Document, NodeId};
+use crate::layout::{self, LayoutBox, Rect, HEADER_H};
+use std::collections::BTreeMap;
+
+/// Distance scrolled by one arrow key press.
+pub const LINE_STEP: i32 = 40;
+/// Duration of a smooth scroll animation.
+pub const SMOOTH_SCROLL_MS: u64 = 150;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrollBehavior {
+    Instant,
+    Smooth,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ScrollState {
+    pub x: i32,
+    pub y: i32,
+    /// Largest offsets that still keep content inside the clip.
+    pub max_x: i32,
+    pub max_y: i32,
+    /// Visible area of the layer in its parent's coordinates.
+    pub clip: Rect,
+    anim: Option<Animation>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Animation {
+    from: (i32, i32),
+    to: (i32, i32),
+    elapsed_ms: u64,
+}
+
+impl ScrollState {
+    /// Offset the layer is heading to: the animation end, or the current offset.
+    pub fn target(&self) -> (i32, i32) { self.anim.map(|a| a.to).unwrap_or((self.x, self.y)) }
+
+    pub fn is_animating(&self) -> bool { self.anim.is_some() }
+
+    /// Whether a scroll by (dx, dy) would move the layer at all.
+    pub fn can_scroll(&self, dx: i32, dy: i32) -> bool {
+        let (x, y) = self.target();
+        (dx < 0 && x > 0) || (dx > 0 && x < self.max_x) || (dy < 0 && y > 0) || (dy > 0 && y < self.max_y)
+    }
+
+    fn clamp(&self, (x, y): (i32, i32)) -> (i32, i32) { (x.clamp(0, self.max_x), y.clamp(0, self.max_y)) }
+}
+
+#[derive(Debug, Default)]
+pub struct ScrollLayers {
+    layers: BTreeMap<NodeId, ScrollState>,
+}
+
+impl ScrollLayers {
+    pub fn new() -> Self { Self::default() }
+
+    /// Current offset of `node`'s layer; (0, 0) for nodes that are not layers.
+    pub fn offset(&self, node: NodeId) -> (i32, i32) { self.layers.get(&node).map(|s| (s.x, s.y)).unwrap_or((0, 0)) }
+
+    pub fn get(&self, node: NodeId) -> Option<&ScrollState> { self.layers.get(&node) }
+
+    pub fn is_animating(&self) -> bool { self.layers.values().any(ScrollState::is_animating) }
+
+    /// Rebuild the layer set after layout: recompute clips and scroll ranges,
+    /// clamp existing offsets and forget layers whose boxes disappeared.
+    pub fn sync(&mut self, doc: &Document, root: &LayoutBox, viewport: (u32, u32)) {
+        let mut found = BTreeMap::new();
+        let clip = document_clip(viewport);
+        found.insert(root.node, range(clip, root.rect.union(&extent(doc, root))));
+        root.walk(&mut |b| {
+            if b.node != root.node && layout::overflow(doc, b.node).is_layer() {
+                found.insert(b.node, range(b.rect, extent(doc, b)));
+            }
+        });
+        for (node, state) in found.iter_mut() {
+            let Some(old) = self.layers.get(node) else { continue };
+            (state.x, state.y) = state.clamp((old.x, old.y));
+            state.anim = old.anim.map(|a| Animation { to: state.clamp(a.to), ..a });
+        }
+        self.layers = found;
+    }
+
+    /// Scroll `node`'s layer to (x, y), clamped to its range. Returns false if
+    /// `node` is not a layer or the target did not change.
+    pub fn scroll_to(&mut self, node: NodeId, x: i32, y: i32, behavior: ScrollBehavior) -> bool {
+        let Some(s) = self.layers.get_mut(&node) else { return false };
+        let to = s.clamp((x, y));
+        if to == s.target() { return false; }
+        match behavior {
+            ScrollBehavior::Instant => {
+                (s.x, s.y) = to;
+                s.anim = None;
+            }
+            ScrollBehavior::Smooth => s.anim = Some(Animation { from: (s.x, s.y), to, elapsed_ms: 0 }),
+        }
+        true
+    }
+
+    /// Scroll relative to the current target, so repeated smooth scrolls accumulate.
+    pub fn scroll_by(&mut self, node: NodeId, dx: i32, dy: i32, behavior: ScrollBehavior) -> bool {
+        let Some((x, y)) = self.layers.get(&node).map(ScrollState::target) else { return false };
+        self.scroll_to(node, x.saturating_add(dx), y.saturating_add(dy), behavior)
+    }
+
+    /// Advance smooth scrolls by `dt_ms`; true if any offset moved.
+    pub fn tick(&mut self, dt_ms: u64) -> bool {
+        let mut moved = false;
+        for s in self.layers.values_mut() {
+            let Some(mut a) = s.anim else { continue };
+            a.elapsed_ms += dt_ms;
+            let t = (a.elapsed_ms as f32 / SMOOTH_SCROLL_MS as f32).min(1.0);
+            // Ease-out cubic: fast start, gentle landing
+            let p = 1.0 - (1.0 - t).powi(3);
+            let lerp = |from: i32, to: i32| from + ((to - from) as f32 * p).round() as i32;
+            let pos = (lerp(a.from.0, a.to.0), lerp(a.from.1, a.to.1));
+            moved |= pos != (s.x, s.y);
+            (s.x, s.y) = pos;
+            s.anim = (t < 1.0).then_some(a);
+        }
+        moved
+    }
+}
+
+/// Viewport area the document scrolls in: everything below the header bar.
+pub fn document_clip(viewport: (u32, u32)) -> Rect {
+    Rect::new(0, HEADER_H, viewport.0 as i32, (viewport.1 as i32 - HEADER_H).max(0))
+}
+
+fn range(clip: Rect, content: Rect) -> ScrollState {
+    ScrollState { max_x: (content.right() - clip.right()).max(0), max_y: (content.bottom() - clip.bottom()).max(0), clip, ..Default::default() }
+}
+
+/// Bounds of everything `b` lays out, without looking inside nested layers.
+fn extent(doc: &Document, b: &LayoutBox) -> Rect {
+    b.children.iter().fold(Rect::default(), |r, c| {
+        let inner = if layout::overflow(doc, c.node).is_layer() { Rect::default() } else { extent(doc, c) };
+        r.union(&c.rect).union(&inner)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::{layout, LayoutContext};
+
+    fn layers(html: &str) -> (Document, ScrollLayers) {
+        let doc = Document::parse(html);
+        let root = layout(&doc, &LayoutContext { viewport: (200, 148), base_url: None, images: None });
+        let mut layers = ScrollLayers::new();
+        layers.sync(&doc, &root, (200, 148));
+        (doc, layers)
+    }
+
+    #[test]
+    fn ranges_clamping_and_nested_layers() {
+        let (doc, mut l) = layers("<div style='overflow:scroll;height:50px'><p style='height:200px'>x</p></div><p style='height:400px'>y</p>");
+        let div = doc.elements_by_tag("div").next().unwrap();
+        assert_eq!(l.get(div).unwrap().max_y, 150);
+        // body margin, div, p plus its margin, body margin below the header; 148px viewport
+        let doc_max = l.get(doc.root()).unwrap().max_y;
+        assert_eq!(doc_max, HEADER_H + 8 + 50 + 400 + 8 + 8 - 148);
+        assert!(l.scroll_by(div, 0, 1000, ScrollBehavior::Instant));
+        assert_eq!(l.offset(div), (0, 150));
+        assert!(!l.scroll_by(div, 0, 10, ScrollBehavior::Instant));
+        assert!(!l.get(div).unwrap().can_scroll(0, 1));
+        assert!(!l.scroll_by(doc.elements_by_tag("p").next().unwrap(), 0, 10, ScrollBehavior::Instant), "not a layer");
+    }
+
+    #[test]
+    fn smooth_scroll_eases_to_target() {
+        let (doc, mut l) = layers("<p style='height:1000px'>y</p>");
+        let root = doc.root();
+        assert!(l.scroll_by(root, 0, 100, ScrollBehavior::Smooth));
+        assert_eq!(l.offset(root), (0, 0));
+        assert!(l.tick(SMOOTH_SCROLL_MS / 2));
+        let mid = l.offset(root).1;
+        assert!(mid > 50 && mid < 100, "ease-out is past halfway at half time: {mid}");
+        // A second smooth scroll continues from the pending target
+        l.scroll_by(root, 0, 100, ScrollBehavior::Smooth);
+        l.tick(SMOOTH_SCROLL_MS);
+        assert_eq!(l.offset(root), (0, 200));
+        assert!(!l.is_animating());
+        assert!(!l.tick(16));
+    }
+}
@@ -1,21 +1,61 @@
+use std::time::Duration;
+
 pub type TabId = u64;
 
+/// Whether a tab's surface can be seen, as reported by the compositor.
+#[derive(Debug, Clone, Copy, Default, serde::Serialize, serde::Deserialize, PartialEq, Eq, Hash)]
+pub enum Visibility {
+    #[default]
+    Visible,
+    /// On screen but fully covered by opaque surfaces above it.
+    Occluded,
+    /// Not on screen at all, e.g. a background tab.
+    Hidden,
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
 pub struct TabState {
     pub url: String,
     pub title: String,
     pub frozen: bool,
+    #[serde(default)]
+    pub visibility: Visibility,
+}
+
+/// How tabs that can't be seen are slowed down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThrottlePolicy {
+    pub foreground_hz: u32,
+    /// Frame rate for occluded and hidden tabs.
+    pub background_hz: u32,
+    /// Freeze a tab once it has been out of sight this long.
+    pub freeze_after: Duration,
+}
+
+impl Default for ThrottlePolicy {
+    fn default() -> Self { Self { foreground_hz: 60, background_hz: 1, freeze_after: Duration::from_secs(300) } }
 }
 
 #[derive(Default)]
 pub struct TabManager {
     next_id: TabId,
     tabs: std::collections::BTreeMap<TabId, TabState>,
+    policy: ThrottlePolicy,
+    /// When each tab that is out of sight stopped being visible.
+    hidden_since: std::collections::BTreeMap<TabId, Duration>,
+    /// Tabs frozen by [`TabManager::tick`] rather than [`TabManager::freeze_tab`];
+    /// only these thaw when they become visible again.
+    frozen_by_visibility: std::collections::BTreeSet<TabId>,
 }
 
 impl TabManager {
     pub fn new() -> Self {
-        Self { next_id: 1, tabs: Default::default() }
+        Self { next_id: 1, ..Default::default() }
+    }
+
+    pub fn with_policy(mut self, policy: ThrottlePolicy) -> Self {
+        self.policy = policy;
+        self
     }
 
     pub fn len(&self) -> usize { self.tabs.len() }
@@ -23,12 +63,14 @@ impl TabManager {
     pub fn new_tab(&mut self, url: &str) -> TabId {
         let id = self.next_id;
         self.next_id = self.next_id.wrapping_add(1).max(1);
-        self.tabs.insert(id, TabState { url: url.to_string(), title: String::new(), frozen: false });
+        self.tabs.insert(id, TabState { url: url.to_string(), title: String::new(), frozen: false, visibility: Visibility::Visible });
         id
     }
 
     pub fn close_tab(&mut self, id: TabId) -> anyhow::Result<()> {
         if self.tabs.remove(&id).is_some() {
+            self.hidden_since.remove(&id);
+            self.frozen_by_visibility.remove(&id);
             Ok(())
         } else {
             Err(anyhow::anyhow!("tab not found: {}", id))
@@ -38,10 +80,48 @@ impl TabManager {
     pub fn freeze_tab(&mut self, id: TabId) -> anyhow::Result<TabState> {
         let st = self.tabs.get_mut(&id).ok_or_else(|| anyhow::anyhow!("tab not found: {}", id))?;
         st.frozen = true;
+        self.frozen_by_visibility.remove(&id);
         Ok(st.clone())
     }
 
     pub fn get(&self, id: TabId) -> Option<&TabState> { self.tabs.get(&id) }
+
+    /// Record what the compositor reported at time `now`. A tab that becomes
+    /// visible again is thawed if it was frozen for being out of sight; an
+    /// explicit [`TabManager::freeze_tab`] stays in force.
+    pub fn set_visibility(&mut self, id: TabId, visibility: Visibility, now: Duration) -> anyhow::Result<()> {
+        let st = self.tabs.get_mut(&id).ok_or_else(|| anyhow::anyhow!("tab not found: {}", id))?;
+        st.visibility = visibility;
+        if visibility == Visibility::Visible {
+            if self.frozen_by_visibility.remove(&id) { st.frozen = false; }
+            self.hidden_since.remove(&id);
+        } else {
+            self.hidden_since.entry(id).or_insert(now);
+        }
+        Ok(())
+    }
+
+    /// Frame rate `id` should produce at: 0 when frozen or unknown.
+    pub fn frame_rate(&self, id: TabId) -> u32 {
+        match self.tabs.get(&id) {
+            Some(st) if st.frozen => 0,
+            Some(st) if st.visibility == Visibility::Visible => self.policy.foreground_hz,
+            Some(_) => self.policy.background_hz,
+            None => 0,
+        }
+    }
+
+    /// Freeze tabs that have been out of sight for the policy's timeout;
+    /// returns the ones frozen by this call.
+    pub fn tick(&mut self, now: Duration) -> Vec<TabId> {
+        let due: Vec<TabId> = self.hidden_since.iter()
+            .filter(|(id, since)| now.saturating_sub(**since) >= self.policy.freeze_after && self.tabs.get(id).is_some_and(|t| !t.frozen))
+            .map(|(id, _)| *id)
+            .collect();
+        let frozen: Vec<TabId> = due.into_iter().filter(|&id| self.freeze_tab(id).is_ok()).collect();
+        self.frozen_by_visibility.extend(&frozen);
+        frozen
+    }
 }
 
 #[cfg(test)]
@@ -65,11 +145,50 @@ mod tests {
 
     #[test]
     fn id_wraps_but_not_zero() {
-        let mut tm = TabManager { next_id: u64::MAX, tabs: Default::default() };
+        let mut tm = TabManager { next_id: u64::MAX, ..Default::default() };
         let id1 = tm.new_tab("x");
         let id2 = tm.new_tab("y");
         assert_eq!(id1, u64::MAX);
         assert_eq!(id2, 1);
     }
-}
 
+    #[test]
+    fn background_tabs_are_throttled_then_frozen() {
+        let secs = Duration::from_secs;
+        let mut tm = TabManager::new().with_policy(ThrottlePolicy { freeze_after: secs(60), ..Default::default() });
+        let (a, b) = (tm.new_tab("https://a.com"), tm.new_tab("https://b.com"));
+        assert_eq!(tm.frame_rate(a), 60);
+        tm.set_visibility(a, Visibility::Hidden, secs(10)).unwrap();
+        tm.set_visibility(b, Visibility::Occluded, secs(40)).unwrap();
+        // Reporting again doesn't restart the timeout
+        tm.set_visibility(a, Visibility::Occluded, secs(30)).unwrap();
+        assert_eq!((tm.frame_rate(a), tm.frame_rate(b)), (1, 1));
+        assert_eq!(tm.tick(secs(69)), Vec::<TabId>::new());
+        assert_eq!(tm.tick(secs(70)), vec![a]);
+        assert_eq!(tm.tick(secs(200)), vec![b]);
+        assert_eq!(tm.frame_rate(a), 0);
+
+        tm.set_visibility(a, Visibility::Visible, secs(201)).unwrap();
+        assert_eq!((tm.get(a).unwrap().frozen, tm.frame_rate(a)), (false, 60));
+        assert!(tm.tick(secs(1000)).is_empty());
+        assert!(tm.set_visibility(99, Visibility::Hidden, secs(0)).is_err());
+    }
+
+    #[test]
+    fn showing_a_tab_keeps_an_explicit_freeze() {
+        let secs = Duration::from_secs;
+        let mut tm = TabManager::new().with_policy(ThrottlePolicy { freeze_after: secs(60), ..Default::default() });
+        let (a, b) = (tm.new_tab("https://a.com"), tm.new_tab("https://b.com"));
+        tm.freeze_tab(a).unwrap();
+        tm.set_visibility(a, Visibility::Hidden, secs(0)).unwrap();
+        tm.set_visibility(a, Visibility::Visible, secs(1)).unwrap();
+        assert_eq!((tm.get(a).unwrap().frozen, tm.frame_rate(a)), (true, 0));
+
+        // Frozen for being hidden, then frozen on purpose while still hidden
+        tm.set_visibility(b, Visibility::Hidden, secs(0)).unwrap();
+        assert_eq!(tm.tick(secs(60)), vec![b]);
+        tm.freeze_tab(b).unwrap();
+        tm.set_visibility(b, Visibility::Visible, secs(61)).unwrap();
+        assert!(tm.get(b).unwrap().frozen);
+    }
+}
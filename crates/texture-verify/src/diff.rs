@@ -0,0 +1,170 @@
+//! Per-pixel comparison. Pixels whose channels differ by more than the
+//! tolerance count as different, except where the difference looks like
+//! anti-aliasing: an edge pixel between a darker and a brighter neighbour,
+//! in the style of pixelmatch. Either kind is marked in the diff image.
+
+use crate::image::Image;
+use crate::VerifyError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiffOptions {
+    /// Largest per-channel difference still treated as equal.
+    pub tolerance: u8,
+    /// Don't count differences that look like anti-aliased edges.
+    pub ignore_antialiasing: bool,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self { Self { tolerance: 2, ignore_antialiasing: true } }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mark { Same, Antialiased, Different }
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diff {
+    pub width: u32,
+    pub height: u32,
+    /// Pixels that differ beyond the tolerance and aren't anti-aliasing.
+    pub different: usize,
+    /// Pixels that differ but were put down to anti-aliasing.
+    pub antialiased: usize,
+    /// Largest channel difference anywhere.
+    pub max_delta: u8,
+    marks: Vec<Mark>,
+}
+
+impl Diff {
+    pub fn is_match(&self) -> bool { self.different == 0 }
+
+    /// Share of pixels that are different, 0.0 to 1.0.
+    pub fn ratio(&self) -> f64 {
+        let n = self.width as usize * self.height as usize;
+        if n == 0 { 0.0 } else { self.different as f64 / n as f64 }
+    }
+
+    /// `expected` faded to grey, with differences in red and anti-aliasing in yellow.
+    pub fn image(&self, expected: &Image) -> Image {
+        let mut rgba = Vec::with_capacity(self.marks.len() * 4);
+        for (px, mark) in expected.rgba.chunks_exact(4).zip(&self.marks) {
+            rgba.extend_from_slice(&match mark {
+                Mark::Different => [255, 0, 0, 255],
+                Mark::Antialiased => [255, 255, 0, 255],
+                Mark::Same => {
+                    let y = 255 - ((255 - luma(px) as u32) / 10) as u8;
+                    [y, y, y, 255]
+                }
+            });
+        }
+        Image { width: self.width, height: self.height, rgba }
+    }
+}
+
+/// BT.601 luma blended over white by alpha.
+fn luma(px: &[u8]) -> f64 {
+    let y = 0.299 * px[0] as f64 + 0.587 * px[1] as f64 + 0.114 * px[2] as f64;
+    let a = px[3] as f64 / 255.0;
+    255.0 + (y - 255.0) * a
+}
+
+fn neighbours(img: &Image, x: u32, y: u32) -> impl Iterator<Item = (u32, u32)> {
+    let (w, h) = (img.width as i64, img.height as i64);
+    (-1..=1i64).flat_map(move |dy| (-1..=1i64).map(move |dx| (x as i64 + dx, y as i64 + dy)))
+        .filter(move |&(nx, ny)| (nx, ny) != (x as i64, y as i64) && nx >= 0 && ny >= 0 && nx < w && ny < h)
+        .map(|(nx, ny)| (nx as u32, ny as u32))
+}
+
+/// At least three neighbours identical to the pixel itself: part of a flat area.
+fn has_many_siblings(img: &Image, x: u32, y: u32) -> bool {
+    let px = img.pixel(x, y);
+    neighbours(img, x, y).filter(|&(nx, ny)| img.pixel(nx, ny) == px).count() > 2
+}
+
+/// Whether `(x, y)` in `img` sits on an anti-aliased edge: few equal
+/// neighbours, and both a darker and a brighter neighbour, one of which is
+/// part of a flat area in both images.
+fn is_antialiased(img: &Image, other: &Image, x: u32, y: u32) -> bool {
+    let center = luma(&img.pixel(x, y));
+    let (mut equal, mut min, mut max) = (0, 0.0, 0.0);
+    let (mut darkest, mut brightest) = ((x, y), (x, y));
+    for (nx, ny) in neighbours(img, x, y) {
+        let delta = luma(&img.pixel(nx, ny)) - center;
+        if delta == 0.0 {
+            equal += 1;
+            if equal > 2 { return false; }
+        } else if delta < min {
+            min = delta;
+            darkest = (nx, ny);
+        } else if delta > max {
+            max = delta;
+            brightest = (nx, ny);
+        }
+    }
+    if min == 0.0 || max == 0.0 { return false; }
+    let flat = |(px, py)| has_many_siblings(img, px, py) && has_many_siblings(other, px, py);
+    flat(darkest) || flat(brightest)
+}
+
+pub fn diff(expected: &Image, actual: &Image, opts: DiffOptions) -> Result<Diff, VerifyError> {
+    if (expected.width, expected.height) != (actual.width, actual.height) {
+        return Err(VerifyError::SizeMismatch { expected: (expected.width, expected.height), actual: (actual.width, actual.height) });
+    }
+    let (w, h) = (expected.width, expected.height);
+    let mut d = Diff { width: w, height: h, different: 0, antialiased: 0, max_delta: 0, marks: Vec::with_capacity(w as usize * h as usize) };
+    for y in 0..h {
+        for x in 0..w {
+            let (a, b) = (expected.pixel(x, y), actual.pixel(x, y));
+            let delta = a.iter().zip(&b).map(|(p, q)| p.abs_diff(*q)).max().unwrap_or(0);
+            d.max_delta = d.max_delta.max(delta);
+            let mark = if delta <= opts.tolerance {
+                Mark::Same
+            } else if opts.ignore_antialiasing && (is_antialiased(expected, actual, x, y) || is_antialiased(actual, expected, x, y)) {
+                d.antialiased += 1;
+                Mark::Antialiased
+            } else {
+                d.different += 1;
+                Mark::Different
+            };
+            d.marks.push(mark);
+        }
+    }
+    Ok(d)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// White canvas with a black box from x=2..6, its left edge at `edge` grey.
+    fn boxed(edge: u8) -> Image {
+        let mut img = Image::filled(10, 8, [255; 4]);
+        for y in 2..6 {
+            for x in 2..7 { img.set_pixel(x, y, [0, 0, 0, 255]); }
+            img.set_pixel(2, y, [edge, edge, edge, 255]);
+        }
+        img
+    }
+
+    #[test]
+    fn counts_differences_and_tolerates_antialiasing() {
+        let base = boxed(0);
+        assert!(diff(&base, &base, DiffOptions::default()).unwrap().is_match());
+
+        // A softened edge is anti-aliasing, not a change
+        let soft = diff(&base, &boxed(128), DiffOptions::default()).unwrap();
+        assert_eq!((soft.different, soft.antialiased, soft.max_delta), (0, 4, 128));
+        let strict = diff(&base, &boxed(128), DiffOptions { ignore_antialiasing: false, ..Default::default() }).unwrap();
+        assert_eq!(strict.different, 4);
+
+        let mut moved = base.clone();
+        moved.set_pixel(8, 7, [255, 0, 0, 255]);
+        moved.set_pixel(0, 0, [254, 254, 254, 255]);
+        let d = diff(&base, &moved, DiffOptions::default()).unwrap();
+        assert_eq!((d.different, d.ratio()), (1, 1.0 / 80.0));
+        let img = d.image(&base);
+        assert_eq!((img.pixel(8, 7), img.pixel(3, 3), img.pixel(0, 0)), ([255, 0, 0, 255], [230, 230, 230, 255], [255; 4]));
+        assert_eq!(diff(&base, &boxed(128), DiffOptions::default()).unwrap().image(&base).pixel(2, 3), [255, 255, 0, 255]);
+
+        assert!(matches!(diff(&base, &Image::filled(1, 1, [0; 4]), DiffOptions::default()), Err(VerifyError::SizeMismatch { .. })));
+    }
+}
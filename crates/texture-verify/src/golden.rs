@@ -0,0 +1,93 @@
+//! Golden files: compare a rendered [`Image`] against a PNG on disk. With
+//! `UPDATE_GOLDENS=1` in the environment goldens are (re)written instead.
+//! On a mismatch `<name>.actual.png` and `<name>.diff.png` are written next
+//! to the golden for inspection.
+
+use crate::diff::{diff, DiffOptions};
+use crate::image::Image;
+use crate::ssim::ssim;
+use crate::VerifyError;
+use std::path::{Path, PathBuf};
+
+pub const UPDATE_ENV: &str = "UPDATE_GOLDENS";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GoldenOptions {
+    pub diff: DiffOptions,
+    /// Differing pixels allowed before the comparison fails.
+    pub max_different: usize,
+    /// Lowest SSIM accepted.
+    pub min_ssim: f64,
+    /// Write the golden instead of comparing; defaults to [`UPDATE_ENV`] being set.
+    pub update: bool,
+}
+
+impl Default for GoldenOptions {
+    fn default() -> Self { Self { diff: DiffOptions::default(), max_different: 0, min_ssim: 0.99, update: update_requested() } }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GoldenOutcome {
+    Matched { different: usize, ssim: f64 },
+    /// The golden was written from the actual image.
+    Updated,
+}
+
+/// `UPDATE_ENV` is set to anything but empty, `0` or `false`.
+pub fn update_requested() -> bool {
+    std::env::var(UPDATE_ENV).is_ok_and(|v| !matches!(v.as_str(), "" | "0" | "false"))
+}
+
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    path.with_file_name(format!("{stem}.{suffix}.png"))
+}
+
+pub fn check_golden(path: impl AsRef<Path>, actual: &Image, opts: GoldenOptions) -> Result<GoldenOutcome, VerifyError> {
+    let path = path.as_ref();
+    let (actual_path, diff_path) = (sibling(path, "actual"), sibling(path, "diff"));
+    if opts.update {
+        actual.write_png(path)?;
+        for stale in [&actual_path, &diff_path] { let _ = std::fs::remove_file(stale); }
+        return Ok(GoldenOutcome::Updated);
+    }
+    if !path.exists() { return Err(VerifyError::MissingGolden(path.to_path_buf())); }
+    let expected = Image::read_png(path)?;
+    let d = diff(&expected, actual, opts.diff)?;
+    let score = ssim(&expected, actual)?;
+    if d.different <= opts.max_different && score >= opts.min_ssim {
+        return Ok(GoldenOutcome::Matched { different: d.different, ssim: score });
+    }
+    actual.write_png(&actual_path)?;
+    d.image(&expected).write_png(&diff_path)?;
+    Err(VerifyError::GoldenMismatch { path: path.to_path_buf(), different: d.different, ssim: score, diff_path })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn update_then_compare() {
+        let dir = std::env::temp_dir().join(format!("texture-verify-golden-{}", std::process::id()));
+        let path = dir.join("box.png");
+        let img = Image::filled(16, 16, [0, 128, 255, 255]);
+        let compare = GoldenOptions { update: false, ..Default::default() };
+
+        assert!(matches!(check_golden(&path, &img, compare), Err(VerifyError::MissingGolden(_))));
+        assert_eq!(check_golden(&path, &img, GoldenOptions { update: true, ..compare }).unwrap(), GoldenOutcome::Updated);
+        assert_eq!(check_golden(&path, &img, compare).unwrap(), GoldenOutcome::Matched { different: 0, ssim: 1.0 });
+
+        let mut changed = img.clone();
+        changed.set_pixel(5, 5, [255, 0, 0, 255]);
+        match check_golden(&path, &changed, compare) {
+            Err(VerifyError::GoldenMismatch { different: 1, diff_path, .. }) => {
+                assert_eq!(Image::read_png(&diff_path).unwrap().pixel(5, 5), [255, 0, 0, 255]);
+                assert_eq!(Image::read_png(dir.join("box.actual.png")).unwrap(), changed);
+            }
+            other => panic!("{other:?}"),
+        }
+        assert!(check_golden(&path, &changed, GoldenOptions { max_different: 1, min_ssim: 0.9, ..compare }).is_ok());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
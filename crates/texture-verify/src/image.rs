@@ -0,0 +1,145 @@
+//! RGBA8 frames, rectangular regions and PNG reading/writing.
+
+use crate::VerifyError;
+use std::io::Cursor;
+use std::path::Path;
+
+/// Rectangle in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+
+impl Region {
+    pub fn new(x: u32, y: u32, w: u32, h: u32) -> Self { Self { x, y, w, h } }
+}
+
+/// Tightly packed RGBA8 pixels, rows top to bottom.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+
+impl Image {
+    pub fn new(width: u32, height: u32, rgba: Vec<u8>) -> Result<Self, VerifyError> {
+        let want = width as usize * height as usize * 4;
+        if rgba.len() != want { return Err(VerifyError::BufferSize { width, height, len: rgba.len() }); }
+        Ok(Self { width, height, rgba })
+    }
+
+    /// `width`x`height` filled with `rgba`.
+    pub fn filled(width: u32, height: u32, rgba: [u8; 4]) -> Self { Self { width, height, rgba: rgba.repeat(width as usize * height as usize) } }
+
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let i = (y as usize * self.width as usize + x as usize) * 4;
+        [self.rgba[i], self.rgba[i + 1], self.rgba[i + 2], self.rgba[i + 3]]
+    }
+
+    pub fn set_pixel(&mut self, x: u32, y: u32, px: [u8; 4]) {
+        let i = (y as usize * self.width as usize + x as usize) * 4;
+        self.rgba[i..i + 4].copy_from_slice(&px);
+    }
+
+    fn check(&self, r: Region) -> Result<(), VerifyError> {
+        if r.x as u64 + r.w as u64 > self.width as u64 || r.y as u64 + r.h as u64 > self.height as u64 {
+            return Err(VerifyError::RegionOutOfBounds { region: r, width: self.width, height: self.height });
+        }
+        Ok(())
+    }
+
+    /// Copy of `region`.
+    pub fn crop(&self, region: Region) -> Result<Image, VerifyError> {
+        self.check(region)?;
+        let mut rgba = Vec::with_capacity(region.w as usize * region.h as usize * 4);
+        for y in region.y..region.y + region.h {
+            let start = (y as usize * self.width as usize + region.x as usize) * 4;
+            rgba.extend_from_slice(&self.rgba[start..start + region.w as usize * 4]);
+        }
+        Ok(Image { width: region.w, height: region.h, rgba })
+    }
+
+    /// Check every pixel of `region` is within `tolerance` of `rgba` on each channel.
+    pub fn expect_region(&self, region: Region, rgba: [u8; 4], tolerance: u8) -> Result<(), VerifyError> {
+        self.check(region)?;
+        let mut bad = 0;
+        let mut first = None;
+        for y in region.y..region.y + region.h {
+            for x in region.x..region.x + region.w {
+                let px = self.pixel(x, y);
+                if px.iter().zip(&rgba).any(|(a, b)| a.abs_diff(*b) > tolerance) {
+                    bad += 1;
+                    first.get_or_insert((x, y, px));
+                }
+            }
+        }
+        match first {
+            None => Ok(()),
+            Some((x, y, found)) => Err(VerifyError::RegionMismatch { region, expected: rgba, count: bad, x, y, found }),
+        }
+    }
+
+    pub fn decode_png(bytes: &[u8]) -> Result<Image, VerifyError> {
+        let mut decoder = png::Decoder::new(Cursor::new(bytes));
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info().map_err(|e| VerifyError::Png(e.to_string()))?;
+        let mut buf = vec![0; reader.output_buffer_size().ok_or_else(|| VerifyError::Png("image too large".into()))?];
+        let info = reader.next_frame(&mut buf).map_err(|e| VerifyError::Png(e.to_string()))?;
+        buf.truncate(info.line_size * info.height as usize);
+        let rgba = match info.color_type {
+            png::ColorType::Rgba => buf,
+            png::ColorType::Rgb => buf.chunks_exact(3).flat_map(|p| [p[0], p[1], p[2], 255]).collect(),
+            png::ColorType::GrayscaleAlpha => buf.chunks_exact(2).flat_map(|p| [p[0], p[0], p[0], p[1]]).collect(),
+            png::ColorType::Grayscale => buf.iter().flat_map(|&g| [g, g, g, 255]).collect(),
+            png::ColorType::Indexed => return Err(VerifyError::Png("palette was not expanded".into())),
+        };
+        Image::new(info.width, info.height, rgba)
+    }
+
+    pub fn encode_png(&self) -> Result<Vec<u8>, VerifyError> {
+        let mut out = Vec::new();
+        let mut encoder = png::Encoder::new(&mut out, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(|e| VerifyError::Png(e.to_string()))?;
+        writer.write_image_data(&self.rgba).map_err(|e| VerifyError::Png(e.to_string()))?;
+        writer.finish().map_err(|e| VerifyError::Png(e.to_string()))?;
+        Ok(out)
+    }
+
+    pub fn read_png(path: impl AsRef<Path>) -> Result<Image, VerifyError> { Self::decode_png(&std::fs::read(path)?) }
+
+    /// Write as PNG, creating parent directories.
+    pub fn write_png(&self, path: impl AsRef<Path>) -> Result<(), VerifyError> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() { std::fs::create_dir_all(dir)?; }
+        Ok(std::fs::write(path, self.encode_png()?)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn png_round_trip_and_regions() {
+        let mut img = Image::filled(5, 4, [10, 20, 30, 255]);
+        img.set_pixel(3, 2, [200, 0, 0, 128]);
+        assert_eq!(Image::decode_png(&img.encode_png().unwrap()).unwrap(), img);
+        assert!(matches!(Image::new(2, 2, vec![0; 15]), Err(VerifyError::BufferSize { .. })));
+
+        let crop = img.crop(Region::new(2, 1, 3, 2)).unwrap();
+        assert_eq!((crop.width, crop.height, crop.pixel(1, 1)), (3, 2, [200, 0, 0, 128]));
+        assert!(img.crop(Region::new(4, 0, 2, 1)).is_err());
+
+        img.expect_region(Region::new(0, 0, 5, 2), [12, 18, 30, 255], 2).unwrap();
+        match img.expect_region(Region::new(0, 0, 5, 4), [10, 20, 30, 255], 0) {
+            Err(VerifyError::RegionMismatch { count: 1, x: 3, y: 2, .. }) => {}
+            other => panic!("{other:?}"),
+        }
+    }
+}
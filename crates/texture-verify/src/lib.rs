@@ -1,5 +1,32 @@
 #![forbid(unsafe_code)]
-//! Minimal pixel compare helpers for Phase 0
+//! Pixel compare helpers: RGBA8 images with PNG I/O, per-pixel diffs with
+//! anti-aliasing detection, SSIM, region assertions and golden files.
+
+pub mod diff;
+pub mod golden;
+pub mod image;
+pub mod ssim;
+
+pub use diff::{diff, Diff, DiffOptions};
+pub use golden::{check_golden, GoldenOptions, GoldenOutcome};
+pub use image::{Image, Region};
+pub use ssim::ssim;
+
+use std::path::PathBuf;
+
+#[derive(Debug, thiserror::Error)]
+pub enum VerifyError {
+    #[error("io: {0}")] Io(#[from] std::io::Error),
+    #[error("png: {0}")] Png(String),
+    #[error("{len} bytes is not a {width}x{height} RGBA8 image")] BufferSize { width: u32, height: u32, len: usize },
+    #[error("size mismatch: expected {expected:?}, got {actual:?}")] SizeMismatch { expected: (u32, u32), actual: (u32, u32) },
+    #[error("{region:?} is outside the {width}x{height} image")] RegionOutOfBounds { region: Region, width: u32, height: u32 },
+    #[error("{count} pixels in {region:?} differ from {expected:?}, first at ({x}, {y}): {found:?}")]
+    RegionMismatch { region: Region, expected: [u8; 4], count: usize, x: u32, y: u32, found: [u8; 4] },
+    #[error("golden {0} is missing; set UPDATE_GOLDENS=1 to create it")] MissingGolden(PathBuf),
+    #[error("{path} mismatch: {different} pixels differ, ssim {ssim:.4}; see {diff_path}")]
+    GoldenMismatch { path: PathBuf, different: usize, ssim: f64, diff_path: PathBuf },
+}
 
 /// Returns true if the average RGB is within tolerance of the target color.
 pub fn is_roughly_color_rgba8(pixels: &[u8], color: (u8, u8, u8), tolerance: u8) -> bool {
@@ -15,9 +42,9 @@ pub fn is_roughly_color_rgba8(pixels: &[u8], color: (u8, u8, u8), tolerance: u8)
     if count == 0 { return false; }
     let avg = ((sum.0 / count) as i64, (sum.1 / count) as i64, (sum.2 / count) as i64);
     let target = (color.0 as i64, color.1 as i64, color.2 as i64);
-    (avg.0 - target.0).abs() as u8 <= tolerance &&
-    (avg.1 - target.1).abs() as u8 <= tolerance &&
-    (avg.2 - target.2).abs() as u8 <= tolerance
+    (avg.0 - target.0).unsigned_abs() <= tolerance as u64 &&
+    (avg.1 - target.1).unsigned_abs() <= tolerance as u64 &&
+    (avg.2 - target.2).unsigned_abs() <= tolerance as u64
 }
 
 #[cfg(test)]
@@ -30,4 +57,3 @@ mod tests {
         assert!(!is_roughly_color_rgba8(&pixels, (0, 0, 255), 16));
     }
 }
-
@@ -0,0 +1,89 @@
+//! Structural similarity (SSIM) on luma over 8x8 windows with a stride of 4,
+//! using the usual constants for 8-bit data. 1.0 means identical; values
+//! above about 0.98 are rarely visible.
+
+use crate::image::Image;
+use crate::VerifyError;
+
+const WINDOW: u32 = 8;
+const STRIDE: u32 = 4;
+const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+fn luma(img: &Image) -> Vec<f64> {
+    img.rgba.chunks_exact(4).map(|p| {
+        let a = p[3] as f64 / 255.0;
+        (0.299 * p[0] as f64 + 0.587 * p[1] as f64 + 0.114 * p[2] as f64) * a
+    }).collect()
+}
+
+/// Window origins along one axis; one window covering everything when the image is smaller.
+fn origins(len: u32) -> Vec<u32> {
+    if len <= WINDOW { return vec![0]; }
+    let mut v: Vec<u32> = (0..=len - WINDOW).step_by(STRIDE as usize).collect();
+    if v.last() != Some(&(len - WINDOW)) { v.push(len - WINDOW); }
+    v
+}
+
+/// Mean SSIM of two equally sized images.
+pub fn ssim(a: &Image, b: &Image) -> Result<f64, VerifyError> {
+    if (a.width, a.height) != (b.width, b.height) {
+        return Err(VerifyError::SizeMismatch { expected: (a.width, a.height), actual: (b.width, b.height) });
+    }
+    if a.width == 0 || a.height == 0 { return Ok(1.0); }
+    let (la, lb) = (luma(a), luma(b));
+    let w = a.width as usize;
+    let (ww, wh) = (WINDOW.min(a.width) as usize, WINDOW.min(a.height) as usize);
+    let n = (ww * wh) as f64;
+    let (mut total, mut count) = (0.0, 0);
+    for oy in origins(a.height) {
+        for ox in origins(a.width) {
+            let (mut sa, mut sb, mut saa, mut sbb, mut sab) = (0.0, 0.0, 0.0, 0.0, 0.0);
+            for y in oy as usize..oy as usize + wh {
+                for x in ox as usize..ox as usize + ww {
+                    let (p, q) = (la[y * w + x], lb[y * w + x]);
+                    sa += p;
+                    sb += q;
+                    saa += p * p;
+                    sbb += q * q;
+                    sab += p * q;
+                }
+            }
+            let (ma, mb) = (sa / n, sb / n);
+            let (va, vb, cov) = (saa / n - ma * ma, sbb / n - mb * mb, sab / n - ma * mb);
+            total += ((2.0 * ma * mb + C1) * (2.0 * cov + C2)) / ((ma * ma + mb * mb + C1) * (va + vb + C2));
+            count += 1;
+        }
+    }
+    Ok(total / count as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient(noise: u8) -> Image {
+        let mut img = Image::filled(32, 24, [0; 4]);
+        for y in 0..24 {
+            for x in 0..32 {
+                let v = (x * 8) as u8 ^ if (x + y) % 3 == 0 { noise } else { 0 };
+                img.set_pixel(x, y, [v, v, (y * 10) as u8, 255]);
+            }
+        }
+        img
+    }
+
+    #[test]
+    fn identical_is_one_and_noise_lowers_it() {
+        let base = gradient(0);
+        assert!((ssim(&base, &base).unwrap() - 1.0).abs() < 1e-9);
+        let slight = ssim(&base, &gradient(2)).unwrap();
+        let heavy = ssim(&base, &gradient(96)).unwrap();
+        assert!(slight > 0.98 && heavy < 0.8 && heavy < slight, "{slight} {heavy}");
+        // Inverting keeps the means apart and the structure anti-correlated
+        let mut inverted = base.clone();
+        inverted.rgba.chunks_exact_mut(4).for_each(|p| { p[0] = 255 - p[0]; p[1] = 255 - p[1]; p[2] = 255 - p[2]; });
+        assert!(ssim(&base, &inverted).unwrap() < 0.2);
+        assert_eq!(ssim(&Image::filled(3, 2, [9; 4]), &Image::filled(3, 2, [9; 4])).unwrap(), 1.0);
+    }
+}
@@ -0,0 +1,330 @@
+//! Access control in front of ai-runtime: every call needs the origin's AI
+//! permission, and origins are held to a request rate, a concurrency cap and a
+//! daily token quota. Quota usage is persisted (in pref-store by default) so
+//! restarting the browser does not reset it.
+
+use ai_runtime::stream::{AskStream, StreamEvent, Usage};
+use ai_runtime::AiError;
+use message_defs::AiRequest;
+use permission_manager::{Decision, PermissionKind, PermissionManager};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+use web_time::{Instant, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Error)]
+pub enum WindowAiError {
+    #[error("AI access denied for this origin")] Denied,
+    #[error("AI access needs the user's permission")] PermissionRequired,
+    #[error("too many requests; retry in {}s", .retry_after.as_secs())] RateLimited { retry_after: Duration },
+    #[error("daily token quota of {limit} exhausted")] QuotaExceeded { limit: u64 },
+    #[error("prompt needs about {needed} tokens but only {remaining} remain today")] PromptOverQuota { needed: u64, remaining: u64 },
+    #[error("another request from this origin is still running")] Busy,
+    #[error(transparent)] Runtime(#[from] AiError),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    pub requests_per_minute: usize,
+    /// Prompt plus generated tokens per origin per UTC day.
+    pub tokens_per_day: u64,
+    /// Requests per origin in flight at once.
+    pub max_concurrent: usize,
+    /// Generated tokens per request.
+    pub max_tokens: u32,
+}
+
+impl Default for Limits {
+    fn default() -> Self { Self { requests_per_minute: 10, tokens_per_day: 20_000, max_concurrent: 1, max_tokens: 16 } }
+}
+
+/// Tokens charged to an origin on one day; what gets persisted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct DayUsage { pub day: u64, pub tokens: u64 }
+
+/// Where per-origin quota usage is persisted between runs.
+pub trait UsageStore: Send + Sync {
+    fn load(&self, origin: &str) -> Option<DayUsage>;
+    fn save(&self, origin: &str, usage: DayUsage);
+}
+
+/// The default store: one pref-store key per origin.
+pub struct PrefUsageStore;
+
+impl UsageStore for PrefUsageStore {
+    fn load(&self, origin: &str) -> Option<DayUsage> { pref_store::get(&pref_key(origin)) }
+    fn save(&self, origin: &str, usage: DayUsage) { let _ = pref_store::set(&pref_key(origin), &usage); }
+}
+
+#[derive(Default)]
+struct OriginState {
+    recent: VecDeque<Instant>,
+    in_flight: usize,
+    /// Tokens held back for requests in flight: their estimated prompt plus
+    /// their full generation budget, until they settle.
+    reserved: u64,
+    usage: Option<DayUsage>,
+}
+
+fn today() -> u64 { SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() / 86_400) }
+
+fn pref_key(origin: &str) -> String { format!("window_ai::usage::{}", origin) }
+
+/// Lower bound on the prompt's token count: every tokenizer ai-runtime uses
+/// spends at least one token per word.
+fn estimate_tokens(prompt: &str) -> u64 { prompt.split_whitespace().count() as u64 }
+
+pub struct WindowAi {
+    permissions: Mutex<PermissionManager>,
+    limits: Limits,
+    origins: Mutex<HashMap<String, OriginState>>,
+    store: Box<dyn UsageStore>,
+}
+
+impl WindowAi {
+    pub fn new(permissions: PermissionManager, limits: Limits) -> Self { Self::with_store(permissions, limits, Box::new(PrefUsageStore)) }
+
+    pub fn with_store(permissions: PermissionManager, limits: Limits, store: Box<dyn UsageStore>) -> Self {
+        Self { permissions: Mutex::new(permissions), limits, origins: Mutex::new(HashMap::new()), store }
+    }
+
+    pub fn limits(&self) -> Limits { self.limits }
+
+    /// The permission manager, e.g. to record the user's answer to a prompt.
+    pub fn permissions(&self) -> MutexGuard<'_, PermissionManager> { self.permissions.lock().unwrap_or_else(|e| e.into_inner()) }
+
+    fn origins(&self) -> MutexGuard<'_, HashMap<String, OriginState>> { self.origins.lock().unwrap_or_else(|e| e.into_inner()) }
+
+    /// Tokens the origin may still spend today, less what requests in flight
+    /// have reserved.
+    pub fn remaining_tokens(&self, origin: &str) -> u64 {
+        let mut origins = self.origins();
+        let state = origins.entry(origin.to_string()).or_default();
+        self.remaining(state, origin)
+    }
+
+    fn remaining(&self, state: &mut OriginState, origin: &str) -> u64 {
+        self.limits.tokens_per_day.saturating_sub(self.usage(state, origin).tokens).saturating_sub(state.reserved)
+    }
+
+    fn usage<'s>(&self, state: &'s mut OriginState, origin: &str) -> &'s mut DayUsage {
+        let day = today();
+        let usage = state.usage.get_or_insert_with(|| self.store.load(origin).unwrap_or(DayUsage { day, tokens: 0 }));
+        if usage.day != day { *usage = DayUsage { day, tokens: 0 }; }
+        usage
+    }
+
+    /// Check permission and limits, then claim an in-flight slot for `origin`
+    /// and reserve the request's worst-case cost. Returns the generation
+    /// budget (whatever the quota has left after the prompt, capped at
+    /// `max_tokens`) and the tokens reserved.
+    fn admit(&self, origin: &str, prompt: &str) -> Result<(u32, u64), WindowAiError> {
+        match self.permissions().check(origin, PermissionKind::Ai) {
+            Decision::Allow => {}
+            Decision::Deny => return Err(WindowAiError::Denied),
+            Decision::Prompt => return Err(WindowAiError::PermissionRequired),
+        }
+        let mut origins = self.origins();
+        let state = origins.entry(origin.to_string()).or_default();
+        let now = Instant::now();
+        while state.recent.front().is_some_and(|&t| now.duration_since(t) >= RATE_WINDOW) { state.recent.pop_front(); }
+        if state.in_flight >= self.limits.max_concurrent { return Err(WindowAiError::Busy); }
+        if state.recent.len() >= self.limits.requests_per_minute {
+            let retry_after = state.recent.front().map_or(RATE_WINDOW, |&t| RATE_WINDOW.saturating_sub(now.duration_since(t)));
+            return Err(WindowAiError::RateLimited { retry_after });
+        }
+        let remaining = self.remaining(state, origin);
+        if remaining == 0 { return Err(WindowAiError::QuotaExceeded { limit: self.limits.tokens_per_day }); }
+        // Room for the prompt plus at least one generated token
+        let prompt = estimate_tokens(prompt);
+        if prompt + 1 > remaining { return Err(WindowAiError::PromptOverQuota { needed: prompt + 1, remaining }); }
+        let budget = (remaining - prompt).min(self.limits.max_tokens as u64);
+        state.recent.push_back(now);
+        state.in_flight += 1;
+        state.reserved += prompt + budget;
+        Ok((budget as u32, prompt + budget))
+    }
+
+    /// Give back a request's slot and reservation and charge what it actually used.
+    fn release(&self, origin: &str, reserved: u64, tokens: u64) {
+        let mut origins = self.origins();
+        let Some(state) = origins.get_mut(origin) else { return };
+        state.in_flight = state.in_flight.saturating_sub(1);
+        state.reserved = state.reserved.saturating_sub(reserved);
+        if tokens == 0 { return; }
+        let day = self.usage(state, origin);
+        day.tokens += tokens;
+        self.store.save(origin, *day);
+    }
+
+    /// Streaming ask on behalf of `origin`; tokens are charged when the stream ends.
+    pub fn ask_stream(&self, origin: &str, prompt: &str) -> Result<GatedStream<'_>, WindowAiError> {
+        let (max_tokens, reserved) = self.admit(origin, prompt)?;
+        match ai_runtime::ask_stream(AiRequest { prompt: prompt.to_string(), max_tokens }) {
+            Ok(inner) => Ok(GatedStream { gate: self, origin: origin.to_string(), inner, prompt_tokens: estimate_tokens(prompt), reserved, chunks: 0, usage: None }),
+            Err(e) => {
+                self.release(origin, reserved, 0);
+                Err(e.into())
+            }
+        }
+    }
+
+    pub fn ask(&self, origin: &str, prompt: &str) -> Result<String, WindowAiError> {
+        let mut text = String::new();
+        for ev in self.ask_stream(origin, prompt)? {
+            if let StreamEvent::Token(t) = ev? { text.push_str(&t); }
+        }
+        Ok(text.trim().to_string())
+    }
+}
+
+/// [`AskStream`] that holds its origin's in-flight slot and quota reservation
+/// until dropped, then charges the reported usage against the origin's quota.
+/// A stream dropped before its usage report is charged the estimated prompt
+/// plus one token per chunk received.
+pub struct GatedStream<'a> {
+    gate: &'a WindowAi,
+    origin: String,
+    inner: AskStream,
+    /// Estimated prompt tokens, charged if no usage report arrives.
+    prompt_tokens: u64,
+    reserved: u64,
+    chunks: u64,
+    usage: Option<Usage>,
+}
+
+impl GatedStream<'_> {
+    pub fn cancel(&self) { self.inner.cancel() }
+    pub fn cancel_handle(&self) -> ai_runtime::stream::CancelHandle { self.inner.cancel_handle() }
+}
+
+impl Iterator for GatedStream<'_> {
+    type Item = Result<StreamEvent, AiError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let ev = self.inner.next()?;
+        match &ev {
+            Ok(StreamEvent::Token(_)) => self.chunks += 1,
+            Ok(StreamEvent::Done(u)) => self.usage = Some(*u),
+            Err(_) => {}
+        }
+        Some(ev)
+    }
+}
+
+impl Drop for GatedStream<'_> {
+    fn drop(&mut self) {
+        let tokens = self.usage.map_or(self.prompt_tokens + self.chunks, |u| (u.prompt_tokens + u.generated_tokens) as u64);
+        self.gate.release(&self.origin, self.reserved, tokens);
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    const SITE: &str = "https://example.com:443";
+
+    /// permission-manager persists grants through pref-store; keep them out of
+    /// the user's real prefs file.
+    pub(crate) fn isolate_prefs() {
+        static ONCE: std::sync::Once = std::sync::Once::new();
+        ONCE.call_once(|| std::env::set_var("MONAZITE_PREFS_DIR", std::env::temp_dir().join(format!("window-ai-tests-{}", std::process::id()))));
+    }
+
+    /// In-memory usage store; clones share their contents like a prefs file would.
+    #[derive(Clone, Default)]
+    struct MemoryStore(std::sync::Arc<Mutex<HashMap<String, DayUsage>>>);
+
+    impl UsageStore for MemoryStore {
+        fn load(&self, origin: &str) -> Option<DayUsage> { self.0.lock().unwrap().get(origin).copied() }
+        fn save(&self, origin: &str, usage: DayUsage) { self.0.lock().unwrap().insert(origin.to_string(), usage); }
+    }
+
+    fn gate_with(limits: Limits, store: MemoryStore) -> WindowAi {
+        isolate_prefs();
+        let mut pm = PermissionManager::new();
+        pm.grant(SITE, PermissionKind::Ai);
+        pm.deny("https://evil.test:443", PermissionKind::Ai);
+        WindowAi::with_store(pm, limits, Box::new(store))
+    }
+
+    fn gate(limits: Limits) -> WindowAi { gate_with(limits, MemoryStore::default()) }
+
+    #[test]
+    fn permission_is_required() {
+        let g = gate(Limits::default());
+        assert_eq!(g.ask(SITE, "Example Domain content").unwrap(), "Example Domain");
+        assert!(matches!(g.ask("https://evil.test:443", "hi"), Err(WindowAiError::Denied)));
+        assert!(matches!(g.ask("https://new.test:443", "hi"), Err(WindowAiError::PermissionRequired)));
+        g.permissions().grant("https://new.test:443", PermissionKind::Ai);
+        assert!(g.ask("https://new.test:443", "hi").is_ok());
+        assert!(matches!(g.ask(SITE, " "), Err(WindowAiError::Runtime(AiError::Empty))));
+    }
+
+    #[test]
+    fn rate_concurrency_and_quota_limits() {
+        let g = gate(Limits { requests_per_minute: 3, ..Limits::default() });
+        let held = g.ask_stream(SITE, "one two").unwrap();
+        assert!(matches!(g.ask(SITE, "again"), Err(WindowAiError::Busy)));
+        drop(held);
+        g.ask(SITE, "one").unwrap();
+        g.ask(SITE, "two").unwrap();
+        let Err(WindowAiError::RateLimited { retry_after }) = g.ask(SITE, "three") else { panic!("expected rate limit") };
+        assert!(retry_after <= RATE_WINDOW);
+
+        let g = gate(Limits { tokens_per_day: 10, ..Limits::default() });
+        // The mock counts a word as a token: 4 prompt + 4 generated
+        g.ask(SITE, "alpha beta gamma delta").unwrap();
+        assert_eq!(g.remaining_tokens(SITE), 2);
+        // A prompt bigger than what is left is refused up front
+        assert!(matches!(g.ask(SITE, "alpha beta gamma delta"), Err(WindowAiError::PromptOverQuota { needed: 5, remaining: 2 })));
+        // One prompt token leaves one to generate, then the quota is gone
+        assert_eq!(g.ask(SITE, "alpha beta").unwrap_err().to_string(), "prompt needs about 3 tokens but only 2 remain today");
+        assert_eq!(g.ask(SITE, "alpha").unwrap(), "alpha");
+        assert!(matches!(g.ask(SITE, "x"), Err(WindowAiError::QuotaExceeded { limit: 10 })));
+        assert!(matches!(g.ask("https://other.test:443", "x"), Err(WindowAiError::PermissionRequired)));
+    }
+
+    #[test]
+    fn concurrent_requests_reserve_their_cost() {
+        let g = gate(Limits { tokens_per_day: 12, max_concurrent: 2, max_tokens: 4, ..Limits::default() });
+        // 2 prompt tokens plus a budget of 4 held back while it runs
+        let first = g.ask_stream(SITE, "alpha beta").unwrap();
+        assert_eq!(g.remaining_tokens(SITE), 6);
+        let second = g.ask_stream(SITE, "gamma delta").unwrap();
+        assert!(matches!(g.ask_stream(SITE, "x"), Err(WindowAiError::Busy)));
+        drop(second);
+        // Dropped unread: charged its prompt, the rest of its reservation is freed
+        assert_eq!(g.remaining_tokens(SITE), 12 - 6 - 2);
+        let second = g.ask_stream(SITE, "gamma delta").unwrap();
+        assert!(matches!(g.ask_stream(SITE, "x"), Err(WindowAiError::Busy)));
+        drop(second);
+        // The first settles at what it used: 2 prompt + 2 generated
+        assert_eq!(first.count(), 3);
+        assert_eq!(g.remaining_tokens(SITE), 12 - 2 - 2 - 4);
+
+        let g = gate(Limits { tokens_per_day: 10, max_concurrent: 2, ..Limits::default() });
+        let held = g.ask_stream(SITE, "alpha beta").unwrap();
+        assert!(matches!(g.ask_stream(SITE, "x"), Err(WindowAiError::QuotaExceeded { .. })), "the first request reserved the whole quota");
+        drop(held);
+    }
+
+    #[test]
+    fn quota_usage_survives_a_restart() {
+        let store = MemoryStore::default();
+        let g = gate_with(Limits { tokens_per_day: 10, ..Limits::default() }, store.clone());
+        g.ask(SITE, "alpha beta gamma").unwrap();
+        assert_eq!(store.load(SITE), Some(DayUsage { day: today(), tokens: 6 }));
+
+        let g = gate_with(Limits { tokens_per_day: 10, ..Limits::default() }, store.clone());
+        assert_eq!(g.remaining_tokens(SITE), 4);
+        // Usage from an earlier day no longer counts
+        store.save(SITE, DayUsage { day: today() - 1, tokens: 10 });
+        let g = gate_with(Limits { tokens_per_day: 10, ..Limits::default() }, store);
+        assert_eq!(g.remaining_tokens(SITE), 10);
+    }
+}
@@ -1,16 +1,127 @@
-//! M9 window-ai-api (stub): Provide a Rust API analogous to window.ai.ask()
-//! For Phase-1, this is a Rust stub over ai-runtime; WASM bindings to be added later.
+//! M9 window-ai-api: Rust API analogous to window.ai.ask(), gated per origin
+//! by the AI permission and usage limits, with wasm bindings for pages.
 
-pub fn ask(prompt: &str) -> String {
-    ai_runtime::summarize_text(prompt, 16)
+pub mod gate;
+
+use std::sync::OnceLock;
+
+pub use ai_runtime::stream::{CancelHandle, FinishReason, StreamEvent, Usage};
+pub use gate::{DayUsage, GatedStream, Limits, PrefUsageStore, UsageStore, WindowAi, WindowAiError};
+
+/// Process-wide instance backed by the persisted permission store.
+pub fn window_ai() -> &'static WindowAi {
+    static INSTANCE: OnceLock<WindowAi> = OnceLock::new();
+    INSTANCE.get_or_init(|| WindowAi::new(permission_manager::PermissionManager::new(), Limits::default()))
 }
+
+pub fn ask(origin: &str, prompt: &str) -> Result<String, WindowAiError> { window_ai().ask(origin, prompt) }
+
+/// Streaming ask: an iterator of text chunks ending in a usage report.
+pub fn ask_stream(origin: &str, prompt: &str) -> Result<GatedStream<'static>, WindowAiError> { window_ai().ask_stream(origin, prompt) }
+
+#[cfg(target_arch = "wasm32")]
+use std::{cell::RefCell, rc::Rc};
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
 
 /// wasm-bindgen export: expose ask() to JS as ask_wasm
-#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
-pub fn ask_wasm(prompt: String) -> String {
-    ask(&prompt)
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub fn ask_wasm(origin: String, prompt: String) -> Result<String, JsError> {
+    ask(&origin, &prompt).map_err(|e| JsError::new(&e.to_string()))
+}
+
+/// JS handle for a streaming ask. `next()` (the async iterator protocol) and
+/// `read()` (the ReadableStream reader one) return promises of `{ value, done }`
+/// with string chunks. Each chunk is generated after yielding to the event loop,
+/// so the page stays responsive between tokens. `usage()` is available once the
+/// stream is done.
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub struct AiStream(Rc<RefCell<StreamState>>);
+
+#[cfg(target_arch = "wasm32")]
+struct StreamState {
+    inner: GatedStream<'static>,
+    usage: Option<Usage>,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl StreamState {
+    /// The next text chunk, or `None` once the stream is done.
+    fn chunk(&mut self) -> Result<Option<String>, JsError> {
+        loop {
+            match self.inner.next() {
+                Some(Ok(StreamEvent::Token(t))) => return Ok(Some(t)),
+                Some(Ok(StreamEvent::Done(u))) => self.usage = Some(u),
+                Some(Err(e)) => return Err(JsError::new(&e.to_string())),
+                None => return Ok(None),
+            }
+        }
+    }
+}
+
+/// Resolve on a later macrotask (`setTimeout(0)`), letting the page render and handle input.
+#[cfg(target_arch = "wasm32")]
+async fn yield_to_event_loop() -> Result<(), JsValue> {
+    let tick = js_sys::Promise::new(&mut |resolve, _| {
+        let set_timeout = js_sys::Reflect::get(&js_sys::global(), &"setTimeout".into()).ok().and_then(|f| f.dyn_into::<js_sys::Function>().ok());
+        let _ = match set_timeout {
+            Some(set_timeout) => set_timeout.call1(&JsValue::UNDEFINED, &resolve),
+            None => resolve.call0(&JsValue::UNDEFINED),
+        };
+    });
+    wasm_bindgen_futures::JsFuture::from(tick).await.map(drop)
+}
+
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+impl AiStream {
+    pub fn next(&self) -> js_sys::Promise {
+        let state = self.0.clone();
+        wasm_bindgen_futures::future_to_promise(async move {
+            yield_to_event_loop().await?;
+            let chunk = state.borrow_mut().chunk()?;
+            let result = js_sys::Object::new();
+            let value = chunk.as_deref().map_or(JsValue::UNDEFINED, JsValue::from_str);
+            js_sys::Reflect::set(&result, &"value".into(), &value)?;
+            js_sys::Reflect::set(&result, &"done".into(), &JsValue::from_bool(value.is_undefined()))?;
+            Ok(result.into())
+        })
+    }
+
+    pub fn read(&self) -> js_sys::Promise { self.next() }
+
+    pub fn cancel(&self) { self.0.borrow().inner.cancel() }
+
+    /// `{ promptTokens, generatedTokens, timeToFirstTokenMs, elapsedMs, finish }`, or undefined before the end.
+    pub fn usage(&self) -> JsValue {
+        let Some(u) = self.0.borrow().usage else { return JsValue::UNDEFINED };
+        let o = js_sys::Object::new();
+        let ttft = u.time_to_first_token.map_or(JsValue::NULL, |t| JsValue::from_f64(t.as_secs_f64() * 1000.0));
+        let finish = match u.finish {
+            FinishReason::Stop => "stop",
+            FinishReason::Length => "length",
+            FinishReason::Cancelled => "cancelled",
+        };
+        for (k, v) in [
+            ("promptTokens", JsValue::from_f64(u.prompt_tokens as f64)),
+            ("generatedTokens", JsValue::from_f64(u.generated_tokens as f64)),
+            ("timeToFirstTokenMs", ttft),
+            ("elapsedMs", JsValue::from_f64(u.elapsed.as_secs_f64() * 1000.0)),
+            ("finish", JsValue::from_str(finish)),
+        ] {
+            let _ = js_sys::Reflect::set(&o, &k.into(), &v);
+        }
+        o.into()
+    }
+}
+
+/// wasm-bindgen export: start a streaming ask
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub fn ask_stream_wasm(origin: String, prompt: String) -> Result<AiStream, JsError> {
+    ask_stream(&origin, &prompt).map(|inner| AiStream(Rc::new(RefCell::new(StreamState { inner, usage: None })))).map_err(|e| JsError::new(&e.to_string()))
 }
 
 
@@ -19,13 +130,30 @@ mod tests {
     use super::*;
     use std::thread;
 
+    use permission_manager::PermissionKind;
+
     #[test]
     fn concurrent_calls() {
+        gate::tests::isolate_prefs();
         let mut handles = Vec::new();
-        for _ in 0..10 {
-            handles.push(thread::spawn(|| ask("Example Domain content")));
+        for i in 0..10 {
+            let origin = format!("https://tab{i}.test:443");
+            window_ai().permissions().grant(&origin, PermissionKind::Ai);
+            handles.push(thread::spawn(move || ask(&origin, "Example Domain content")));
         }
-        for h in handles { assert_eq!(h.join().unwrap(), "Example Domain"); }
+        for h in handles { assert_eq!(h.join().unwrap().unwrap(), "Example Domain"); }
+    }
+
+    #[test]
+    fn stream_yields_chunks_then_usage() {
+        gate::tests::isolate_prefs();
+        let origin = "https://stream.test:443";
+        window_ai().permissions().grant(origin, PermissionKind::Ai);
+        let events: Vec<StreamEvent> = ask_stream(origin, "one two three").unwrap().map(Result::unwrap).collect();
+        let text: String = events.iter().filter_map(|e| if let StreamEvent::Token(t) = e { Some(t.as_str()) } else { None }).collect();
+        assert_eq!(text, ask(origin, "one two three").unwrap());
+        assert!(matches!(events.last(), Some(StreamEvent::Done(Usage { generated_tokens: 3, finish: FinishReason::Stop, .. }))));
+        assert!(matches!(ask_stream(origin, " "), Err(WindowAiError::Runtime(ai_runtime::AiError::Empty))));
     }
 }
 
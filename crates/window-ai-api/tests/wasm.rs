@@ -1,14 +1,21 @@
 #![cfg(target_arch = "wasm32")]
 
+use permission_manager::PermissionKind;
 use wasm_bindgen_test::*;
-use window_ai_api::ask;
+use window_ai_api::{ask, window_ai, WindowAiError};
 
 // Run these tests in Node.js
 
+const SITE: &str = "https://example.com:443";
 
 #[wasm_bindgen_test]
 fn ask_returns_example_domain_when_present() {
-    let out = ask("This is the Example Domain page.");
+    window_ai().permissions().grant(SITE, PermissionKind::Ai);
+    let out = ask(SITE, "This is the Example Domain page.").unwrap();
     assert!(out.contains("Example Domain"), "unexpected output: {}", out);
 }
 
+#[wasm_bindgen_test]
+fn ask_needs_permission() {
+    assert!(matches!(ask("https://unknown.test:443", "hi"), Err(WindowAiError::PermissionRequired)));
+}